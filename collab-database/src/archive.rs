@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use collab::preclude::Collab;
+use collab_entity::CollabType;
+use collab_persistence::archive::{ArchiveObject, CollabReferences};
+use uuid::Uuid;
+
+use crate::database::{DATABASE, VIEWS};
+use crate::rows::{meta_id_from_row_id, RowMetaKey, DATA, ROW_ID};
+use crate::user::{DATABASES, DATABASE_RECORD_ID, DATABASE_RECORD_VIEWS};
+use crate::views::ROW_ORDERS;
+
+/// Returns the databases of the workspace database and the ids of their views. Used to export
+/// the workspace with
+/// [ArchiveAction::export_workspace](collab_persistence::archive::ArchiveAction::export_workspace).
+pub fn workspace_database_references(collab: &Collab) -> CollabReferences {
+  let json = collab.to_json_value();
+  let mut references = CollabReferences::default();
+  for record in json[DATABASES].as_array().into_iter().flatten() {
+    if let Some(database_id) = record[DATABASE_RECORD_ID].as_str() {
+      references
+        .objects
+        .push(ArchiveObject::new(database_id, CollabType::Database));
+    }
+    for view_id in record[DATABASE_RECORD_VIEWS]
+      .as_array()
+      .into_iter()
+      .flatten()
+    {
+      if let Some(view_id) = view_id.as_str() {
+        references.ids.push(view_id.to_string());
+      }
+    }
+  }
+  references
+}
+
+/// Returns the rows of the database and the ids of its views.
+pub fn database_references(collab: &Collab) -> CollabReferences {
+  let json = collab.to_json_value();
+  let mut references = CollabReferences::default();
+  for (view_id, view) in json[DATABASE][VIEWS].as_object().into_iter().flatten() {
+    references.ids.push(view_id.clone());
+    for row_order in view[ROW_ORDERS].as_array().into_iter().flatten() {
+      if let Some(row_id) = row_order[ROW_ID].as_str() {
+        let row = ArchiveObject::new(row_id, CollabType::DatabaseRow);
+        if !references.objects.contains(&row) {
+          references.objects.push(row);
+        }
+      }
+    }
+  }
+  references
+}
+
+/// Returns the document of the row. The document only exists if it was opened once, otherwise
+/// it's skipped by the export.
+pub fn database_row_references(collab: &Collab) -> CollabReferences {
+  let json = collab.to_json_value();
+  let mut references = CollabReferences::default();
+  if let Some(row_id) = json[DATA][ROW_ID]
+    .as_str()
+    .and_then(|row_id| Uuid::parse_str(row_id).ok())
+  {
+    let document_id = meta_id_from_row_id(&row_id, RowMetaKey::DocumentId);
+    references
+      .objects
+      .push(ArchiveObject::new(document_id, CollabType::Document));
+  }
+  references
+}
+
+/// The ids of the row meta, such as the id of the row document, are derived from the row id.
+/// Add the new meta ids of the remapped rows to the `ids` returned by
+/// [ArchiveManifest::new_ids](collab_persistence::archive::ArchiveManifest::new_ids), so the
+/// meta of a copied row still matches its new row id.
+pub fn remap_row_meta_ids(ids: &mut HashMap<String, String>) {
+  let mut meta_ids = vec![];
+  for (old_id, new_id) in ids.iter() {
+    if let (Ok(old_row_id), Ok(new_row_id)) = (Uuid::parse_str(old_id), Uuid::parse_str(new_id)) {
+      for key in [
+        RowMetaKey::DocumentId,
+        RowMetaKey::IconId,
+        RowMetaKey::CoverId,
+      ] {
+        meta_ids.push((
+          meta_id_from_row_id(&old_row_id, key.clone()),
+          meta_id_from_row_id(&new_row_id, key),
+        ));
+      }
+    }
+  }
+  ids.extend(meta_ids);
+}
@@ -41,9 +41,9 @@ pub struct Database {
 }
 
 const DATABASE_ID: &str = "id";
-const DATABASE: &str = "database";
+pub(crate) const DATABASE: &str = "database";
 const FIELDS: &str = "fields";
-const VIEWS: &str = "views";
+pub(crate) const VIEWS: &str = "views";
 const METAS: &str = "metas";
 
 pub struct DatabaseContext {
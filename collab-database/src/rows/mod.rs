@@ -2,6 +2,7 @@ pub use cell::*;
 pub use cell_builder::*;
 pub use comment::*;
 pub use row::*;
+pub use row_diff::*;
 pub use row_id::*;
 pub use row_meta::*;
 
@@ -9,5 +10,6 @@ mod cell;
 mod cell_builder;
 mod comment;
 mod row;
+mod row_diff;
 mod row_id;
 mod row_meta;
@@ -20,7 +20,7 @@ use crate::{impl_bool_update, impl_i32_update, impl_i64_update};
 
 pub type BlockId = i64;
 
-const DATA: &str = "data";
+pub(crate) const DATA: &str = "data";
 const META: &str = "meta";
 const COMMENT: &str = "comment";
 pub const LAST_MODIFIED: &str = "last_modified";
@@ -235,6 +235,7 @@ pub struct Row {
   pub modified_at: i64,
 }
 
+#[derive(Clone)]
 pub enum RowMetaKey {
   DocumentId,
   IconId,
@@ -414,7 +415,7 @@ impl<'a, 'b, 'c> RowUpdate<'a, 'b, 'c> {
 pub(crate) const ROW_ID: &str = "id";
 const ROW_VISIBILITY: &str = "visibility";
 const ROW_HEIGHT: &str = "height";
-const ROW_CELLS: &str = "cells";
+pub(crate) const ROW_CELLS: &str = "cells";
 
 /// Return row id and created_at from a [YrsValue]
 pub fn row_id_from_value<T: ReadTxn>(value: YrsValue, txn: &T) -> Option<(String, i64)> {
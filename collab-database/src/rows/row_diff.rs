@@ -0,0 +1,63 @@
+use collab::core::diff::diff_collab;
+use collab::preclude::Collab;
+
+use crate::rows::{cell_from_map_ref, row_id_from_map_ref, Cell, RowId, DATA, ROW_CELLS};
+
+/// The cells of a row that changed between two snapshots of the row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowCellsDiff {
+  pub row_id: RowId,
+  pub cells: Vec<CellDiff>,
+}
+
+/// A [Cell] that was added, removed or updated. The cell is added when the `old_cell` is None
+/// and removed when the `new_cell` is None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellDiff {
+  pub field_id: String,
+  pub old_cell: Option<Cell>,
+  pub new_cell: Option<Cell>,
+}
+
+/// Returns the cells that changed between the `old` and the `new` row. Both [Collab]s must
+/// contain the same row, for example the collabs decoded from two snapshots of the row.
+/// Returns None if the row can't be found in the `new` [Collab].
+pub fn diff_row_cells(old: &Collab, new: &Collab) -> Option<RowCellsDiff> {
+  let mut field_ids: Vec<String> = vec![];
+  for change in diff_collab(old, new) {
+    let field_id = match change.path.get(..2) {
+      Some([data, cells]) if data == DATA && cells == ROW_CELLS => {
+        // The change is made inside the cell, or the whole cell is added or removed.
+        change
+          .path
+          .get(2)
+          .cloned()
+          .or_else(|| change.kind.key().map(|key| key.to_string()))
+      },
+      _ => None,
+    };
+    if let Some(field_id) = field_id {
+      if !field_ids.contains(&field_id) {
+        field_ids.push(field_id);
+      }
+    }
+  }
+
+  let old_txn = old.transact();
+  let new_txn = new.transact();
+  let old_data = old.get_map_with_txn(&old_txn, vec![DATA]);
+  let new_data = new.get_map_with_txn(&new_txn, vec![DATA])?;
+  let row_id = row_id_from_map_ref(&new_txn, &new_data)?;
+  let cells = field_ids
+    .into_iter()
+    .map(|field_id| CellDiff {
+      old_cell: old_data
+        .as_ref()
+        .and_then(|old_data| cell_from_map_ref(old_data, &old_txn, &field_id)),
+      new_cell: cell_from_map_ref(&new_data, &new_txn, &field_id),
+      field_id,
+    })
+    .filter(|cell_diff| cell_diff.old_cell != cell_diff.new_cell)
+    .collect();
+  Some(RowCellsDiff { row_id, cells })
+}
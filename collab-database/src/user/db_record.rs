@@ -8,7 +8,7 @@ use collab::preclude::{
 
 use crate::database::timestamp;
 
-const DATABASES: &str = "databases";
+pub(crate) const DATABASES: &str = "databases";
 
 /// It used to keep track of the databases.
 /// Each record of a database is stored in a [DatabaseWithViews]
@@ -140,10 +140,10 @@ pub struct DatabaseWithViews {
   pub linked_views: HashSet<String>,
 }
 
-const DATABASE_RECORD_ID: &str = "database_id";
+pub(crate) const DATABASE_RECORD_ID: &str = "database_id";
 const DATABASE_RECORD_NAME: &str = "name";
 const DATABASE_RECORD_CREATED_AT: &str = "created_at";
-const DATABASE_RECORD_VIEWS: &str = "views";
+pub(crate) const DATABASE_RECORD_VIEWS: &str = "views";
 
 impl DatabaseWithViews {
   fn fill_map_ref(self, txn: &mut TransactionMut, map_ref: &MapRef) {
@@ -5,8 +5,7 @@ use std::pin::Pin;
 use std::sync::{Arc, Weak};
 
 use collab::core::collab::{CollabRawData, MutexCollab};
-use collab::preclude::updates::decoder::Decode;
-use collab::preclude::{Collab, Update};
+use collab::preclude::Collab;
 use collab_entity::CollabType;
 use collab_persistence::doc::YrsDocAction;
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
@@ -291,7 +290,7 @@ impl WorkspaceDatabase {
     snapshot: CollabSnapshot,
   ) -> Result<Database, DatabaseError> {
     let collab = self.collab_for_database(database_id, CollabRawData::default());
-    let update = Update::decode_v1(&snapshot.data)?;
+    let update = snapshot.encoder_version.decode_update(&snapshot.data)?;
     collab.lock().with_origin_transact_mut(|txn| {
       txn.apply_update(update);
     });
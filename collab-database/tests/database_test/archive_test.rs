@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use collab_database::archive::{database_references, remap_row_meta_ids};
+use collab_database::database::gen_row_id;
+use collab_database::rows::{meta_id_from_row_id, CreateRowParams, RowMetaKey};
+use collab_database::views::CreateViewParams;
+use collab_entity::CollabType;
+use collab_persistence::archive::ArchiveObject;
+use uuid::Uuid;
+
+use crate::database_test::helper::create_database;
+
+#[tokio::test]
+async fn database_references_test() {
+  let database_test = create_database(1, "1").await;
+  database_test
+    .create_linked_view(CreateViewParams {
+      database_id: "1".to_string(),
+      view_id: "v2".to_string(),
+      ..Default::default()
+    })
+    .unwrap();
+  let row_id = gen_row_id();
+  database_test
+    .create_row(CreateRowParams {
+      id: row_id.clone(),
+      ..Default::default()
+    })
+    .unwrap();
+
+  let references = database_references(&database_test.get_mutex_collab().lock());
+  let mut view_ids = references.ids.clone();
+  view_ids.sort();
+  assert_eq!(view_ids, vec!["v1".to_string(), "v2".to_string()]);
+  // The row is shared by the two views, but it's only exported once.
+  assert_eq!(
+    references.objects,
+    vec![ArchiveObject::new(row_id, CollabType::DatabaseRow)]
+  );
+}
+
+#[test]
+fn remap_row_meta_ids_test() {
+  let old_row_id = Uuid::new_v4();
+  let new_row_id = Uuid::new_v4();
+  let mut ids = HashMap::from([
+    (old_row_id.to_string(), new_row_id.to_string()),
+    ("v1".to_string(), "v2".to_string()),
+  ]);
+  remap_row_meta_ids(&mut ids);
+
+  assert_eq!(ids.len(), 5);
+  assert_eq!(
+    ids[&meta_id_from_row_id(&old_row_id, RowMetaKey::DocumentId)],
+    meta_id_from_row_id(&new_row_id, RowMetaKey::DocumentId)
+  );
+}
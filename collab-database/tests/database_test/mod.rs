@@ -1,3 +1,4 @@
+mod archive_test;
 mod block_test;
 mod cell_test;
 mod field_setting_test;
@@ -7,6 +8,7 @@ mod group_test;
 pub mod helper;
 mod layout_test;
 mod restore_test;
+mod row_diff_test;
 mod row_test;
 mod sort_test;
 mod type_option_test;
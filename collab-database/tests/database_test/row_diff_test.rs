@@ -0,0 +1,51 @@
+use std::sync::{Arc, Weak};
+
+use collab::core::collab::MutexCollab;
+use collab::core::origin::CollabOrigin;
+use collab::preclude::Collab;
+use collab_database::rows::{diff_row_cells, CellsBuilder, DatabaseRow, Row, RowId};
+
+use crate::helper::TestTextCell;
+
+#[tokio::test]
+async fn diff_row_cells_test() {
+  let row_id = RowId::from(1);
+  let collab = Arc::new(MutexCollab::new(CollabOrigin::Empty, "1", vec![]));
+  let mut row = Row::new(row_id.clone());
+  row.cells = CellsBuilder::new()
+    .insert_cell("f1", TestTextCell::from("hello"))
+    .insert_cell("f2", TestTextCell::from("world"))
+    .build();
+  let database_row = DatabaseRow::create(row, 1, row_id.clone(), Weak::new(), collab.clone());
+  let old = row_collab(&collab);
+  assert!(diff_row_cells(&old, &old).unwrap().cells.is_empty());
+
+  database_row.update(|row_update| {
+    row_update.update_cells(|cells_update| {
+      cells_update
+        .insert("f1", TestTextCell::from("hello world"))
+        .insert("f3", TestTextCell::from("new cell"));
+    });
+  });
+  let new = row_collab(&collab);
+
+  let diff = diff_row_cells(&old, &new).unwrap();
+  assert_eq!(diff.row_id, row_id);
+  assert_eq!(diff.cells.len(), 2);
+
+  assert_eq!(diff.cells[0].field_id, "f1");
+  let old_cell = TestTextCell::from(diff.cells[0].old_cell.clone().unwrap());
+  let new_cell = TestTextCell::from(diff.cells[0].new_cell.clone().unwrap());
+  assert_eq!(old_cell.0, "hello");
+  assert_eq!(new_cell.0, "hello world");
+
+  assert_eq!(diff.cells[1].field_id, "f3");
+  assert!(diff.cells[1].old_cell.is_none());
+  let new_cell = TestTextCell::from(diff.cells[1].new_cell.clone().unwrap());
+  assert_eq!(new_cell.0, "new cell");
+}
+
+fn row_collab(collab: &MutexCollab) -> Collab {
+  let (doc_state, _) = collab.encode_as_update_v1();
+  Collab::new_with_raw_data(CollabOrigin::Empty, "1", vec![doc_state], vec![]).unwrap()
+}
@@ -18,7 +18,7 @@ use collab_database::user::{
 use collab_database::views::{CreateDatabaseParams, DatabaseLayout};
 use collab_entity::CollabType;
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
-use collab_plugins::local_storage::rocksdb::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::DiskPlugin;
 use collab_plugins::local_storage::CollabPersistenceConfig;
 
 use parking_lot::Mutex;
@@ -81,11 +81,7 @@ impl DatabaseCollabService for TestUserDatabaseCollabBuilderImpl {
     let collab = CollabBuilder::new(uid, object_id)
       .with_device_id("1")
       .with_raw_data(collab_raw_data)
-      .with_plugin(RocksdbDiskPlugin::new_with_config(
-        uid,
-        collab_db,
-        config.clone(),
-      ))
+      .with_plugin(DiskPlugin::new_with_config(uid, collab_db, config.clone()))
       .build()
       .unwrap();
     collab.lock().initialize();
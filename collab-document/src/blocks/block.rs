@@ -11,10 +11,10 @@ pub const EXTERNAL_TYPE_ARRAY: &str = "array";
 pub const EXTERNAL_TYPE_MAP: &str = "map";
 
 const ID: &str = "id";
-const TYPE: &str = "ty";
-const PARENT: &str = "parent";
-const CHILDREN: &str = "children";
-const DATA: &str = "data";
+pub(crate) const TYPE: &str = "ty";
+pub(crate) const PARENT: &str = "parent";
+pub(crate) const CHILDREN: &str = "children";
+pub(crate) const DATA: &str = "data";
 const EXTERNAL_ID: &str = "external_id";
 const EXTERNAL_TYPE: &str = "external_type";
 
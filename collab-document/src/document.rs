@@ -16,22 +16,22 @@ use crate::blocks::{
 };
 use crate::error::DocumentError;
 
-const ROOT: &str = "document";
+pub(crate) const ROOT: &str = "document";
 
 /// The page_id is a reference that points to the block’s id.
 /// The block that is referenced by this page_id is the first block of the document.
 /// Crossing this block, we can build the whole document tree.
 const PAGE_ID: &str = "page_id";
 /// Document's all [Block] Map.
-const BLOCKS: &str = "blocks";
+pub(crate) const BLOCKS: &str = "blocks";
 /// Document's meta data.
-const META: &str = "meta";
+pub(crate) const META: &str = "meta";
 /// [Block]'s relation map. And it's also in [META].
 /// The key is the parent block's children_id, and the value is the children block's id.
-const CHILDREN_MAP: &str = "children_map";
+pub(crate) const CHILDREN_MAP: &str = "children_map";
 /// [Block]'s yText map. And it's also in [META].
 /// The key is the text block's external_id, and the value is the text block's yText.
-const TEXT_MAP: &str = "text_map";
+pub(crate) const TEXT_MAP: &str = "text_map";
 
 pub struct Document {
   inner: Arc<MutexCollab>,
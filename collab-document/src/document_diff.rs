@@ -0,0 +1,189 @@
+use collab::core::diff::diff_collab;
+use collab::core::path_observer::{PathChangeKind, TextDeltaChange};
+use collab::preclude::Collab;
+use serde_json::Value as JsonValue;
+
+use crate::blocks::{CHILDREN, DATA, PARENT, TYPE};
+use crate::document::{BLOCKS, CHILDREN_MAP, META, ROOT, TEXT_MAP};
+
+/// A change of the document between two snapshots.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DocumentChange {
+  BlockInserted {
+    block_id: String,
+    ty: String,
+    parent_id: String,
+  },
+  BlockDeleted {
+    block_id: String,
+    ty: String,
+    parent_id: String,
+  },
+  /// A field of the block was changed. The `data` of the block is stored as a JSON string, so
+  /// its values are decoded before they are compared.
+  BlockUpdated {
+    block_id: String,
+    field: String,
+    old_value: JsonValue,
+    new_value: JsonValue,
+  },
+  /// The block is moved to another parent or to another position under the same parent.
+  BlockMoved {
+    block_id: String,
+    old_parent_id: String,
+    new_parent_id: String,
+    old_index: Option<u32>,
+    new_index: Option<u32>,
+  },
+  /// The text with the given id was changed. A created text is reported as an insert of its
+  /// content and a removed text as a delete of its content.
+  TextChanged {
+    text_id: String,
+    delta: Vec<TextDeltaChange>,
+  },
+}
+
+/// Returns the changes that turn the `old` document into the `new` document. Both [Collab]s
+/// must contain a document, for example the collabs decoded from two snapshots of the same
+/// document.
+pub fn diff_document(old: &Collab, new: &Collab) -> Vec<DocumentChange> {
+  let old_json = old.to_json_value();
+  let new_json = new.to_json_value();
+  let mut document_changes = vec![];
+  let mut moved_block_ids: Vec<String> = vec![];
+
+  for change in diff_collab(old, new) {
+    let path = change.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    match (path.as_slice(), change.kind) {
+      ([ROOT, BLOCKS], PathChangeKind::Inserted { key, value }) => {
+        document_changes.push(DocumentChange::BlockInserted {
+          ty: json_str(&value[TYPE]),
+          parent_id: json_str(&value[PARENT]),
+          block_id: key,
+        });
+      },
+      ([ROOT, BLOCKS], PathChangeKind::Removed { key, .. }) => {
+        let block = &old_json[ROOT][BLOCKS][&key];
+        document_changes.push(DocumentChange::BlockDeleted {
+          ty: json_str(&block[TYPE]),
+          parent_id: json_str(&block[PARENT]),
+          block_id: key,
+        });
+      },
+      ([ROOT, BLOCKS, block_id], PathChangeKind::Updated { key, .. }) if key == PARENT => {
+        moved_block_ids.push(block_id.to_string());
+      },
+      (
+        [ROOT, BLOCKS, block_id],
+        PathChangeKind::Updated {
+          key,
+          old_value,
+          value,
+        },
+      ) => {
+        let (old_value, new_value) = if key == DATA {
+          (decode_data(old_value), decode_data(value))
+        } else {
+          (old_value, value)
+        };
+        if old_value != new_value {
+          document_changes.push(DocumentChange::BlockUpdated {
+            block_id: block_id.to_string(),
+            field: key,
+            old_value,
+            new_value,
+          });
+        }
+      },
+      ([ROOT, META, CHILDREN_MAP, _], PathChangeKind::ArrayInserted { values, .. }) => {
+        moved_block_ids.extend(values.iter().map(json_str));
+      },
+      ([ROOT, META, TEXT_MAP, text_id], PathChangeKind::TextDelta { delta }) => {
+        document_changes.push(DocumentChange::TextChanged {
+          text_id: text_id.to_string(),
+          delta,
+        });
+      },
+      ([ROOT, META, TEXT_MAP], PathChangeKind::Inserted { key, value }) => {
+        let delta = match value.as_str() {
+          Some(text) if !text.is_empty() => vec![TextDeltaChange::Insert {
+            insert: value,
+            attributes: None,
+          }],
+          _ => vec![],
+        };
+        document_changes.push(DocumentChange::TextChanged {
+          text_id: key,
+          delta,
+        });
+      },
+      ([ROOT, META, TEXT_MAP], PathChangeKind::Removed { key, old_value }) => {
+        let len = old_value
+          .as_str()
+          .map(|text| text.encode_utf16().count() as u32)
+          .unwrap_or_default();
+        let delta = if len > 0 {
+          vec![TextDeltaChange::Delete { delete: len }]
+        } else {
+          vec![]
+        };
+        document_changes.push(DocumentChange::TextChanged {
+          text_id: key,
+          delta,
+        });
+      },
+      _ => {},
+    }
+  }
+
+  // A block that is reinserted into the children of a parent is only moved when it exists in
+  // both documents and its position changed.
+  let mut reported = vec![];
+  for block_id in moved_block_ids {
+    if reported.contains(&block_id) {
+      continue;
+    }
+    let old_block = &old_json[ROOT][BLOCKS][&block_id];
+    let new_block = &new_json[ROOT][BLOCKS][&block_id];
+    if old_block.is_null() || new_block.is_null() {
+      continue;
+    }
+
+    let old_parent_id = json_str(&old_block[PARENT]);
+    let new_parent_id = json_str(&new_block[PARENT]);
+    let old_index = child_index(&old_json, &old_parent_id, &block_id);
+    let new_index = child_index(&new_json, &new_parent_id, &block_id);
+    if old_parent_id != new_parent_id || old_index != new_index {
+      document_changes.push(DocumentChange::BlockMoved {
+        block_id: block_id.clone(),
+        old_parent_id,
+        new_parent_id,
+        old_index,
+        new_index,
+      });
+      reported.push(block_id);
+    }
+  }
+  document_changes
+}
+
+fn child_index(document_json: &JsonValue, parent_id: &str, block_id: &str) -> Option<u32> {
+  let document = &document_json[ROOT];
+  let children_id = document[BLOCKS][parent_id][CHILDREN].as_str()?;
+  document[META][CHILDREN_MAP][children_id]
+    .as_array()?
+    .iter()
+    .position(|child| child == block_id)
+    .map(|index| index as u32)
+}
+
+fn decode_data(value: JsonValue) -> JsonValue {
+  match value.as_str().map(serde_json::from_str::<JsonValue>) {
+    Some(Ok(data)) => data,
+    _ => value,
+  }
+}
+
+fn json_str(value: &JsonValue) -> String {
+  value.as_str().unwrap_or_default().to_string()
+}
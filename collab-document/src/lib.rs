@@ -3,4 +3,5 @@ pub use collab_persistence::doc::YrsDocAction;
 pub mod blocks;
 pub mod document;
 pub mod document_data;
+pub mod document_diff;
 pub mod error;
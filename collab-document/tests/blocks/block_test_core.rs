@@ -8,7 +8,7 @@ use collab_document::blocks::{
 };
 use collab_document::document::Document;
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
-use collab_plugins::local_storage::rocksdb::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::DiskPlugin;
 use nanoid::nanoid;
 use serde_json::{json, Value};
 
@@ -26,7 +26,7 @@ impl BlockTestCore {
   pub async fn new() -> Self {
     let db = document_storage();
     let doc_id = "1";
-    let disk_plugin = RocksdbDiskPlugin::new(1, Arc::downgrade(&db));
+    let disk_plugin = DiskPlugin::new(1, Arc::downgrade(&db));
     let collab = CollabBuilder::new(1, doc_id)
       .with_plugin(disk_plugin)
       .with_device_id("1")
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use collab::core::origin::CollabOrigin;
+use collab::core::path_observer::TextDeltaChange;
+use collab::preclude::Collab;
+use collab_document::document_diff::{diff_document, DocumentChange};
+use serde_json::json;
+
+use crate::blocks::block_test_core::{BlockTestCore, TEXT_BLOCK_TYPE};
+
+#[tokio::test]
+async fn diff_document_test() {
+  let test = BlockTestCore::new().await;
+  let page = test.get_page();
+  let block_1 = test.insert_text_block("hello".to_string(), &page.id, None);
+  let block_2 = test.insert_text_block("world".to_string(), &page.id, Some(block_1.id.clone()));
+  let old_index = test
+    .get_block_children(&page.id)
+    .iter()
+    .position(|block| block.id == block_2.id)
+    .map(|index| index as u32);
+  let old = document_collab(&test);
+  assert!(diff_document(&old, &old).is_empty());
+
+  test.apply_text_delta(
+    block_1.external_id.as_ref().unwrap(),
+    json!([{"retain": 5}, {"insert": " there"}]).to_string(),
+  );
+  test.update_block_data(
+    &block_2.id,
+    HashMap::from([("level".to_string(), json!(1))]),
+  );
+  test.move_block(&block_2.id, &block_1.id, None);
+  let block_3 = test.insert_text_block("new".to_string(), &page.id, None);
+  let new = document_collab(&test);
+
+  let changes = diff_document(&old, &new);
+  let expected = vec![
+    DocumentChange::TextChanged {
+      text_id: block_1.external_id.clone().unwrap(),
+      delta: vec![
+        TextDeltaChange::Retain {
+          retain: 5,
+          attributes: None,
+        },
+        TextDeltaChange::Insert {
+          insert: json!(" there"),
+          attributes: None,
+        },
+      ],
+    },
+    DocumentChange::BlockUpdated {
+      block_id: block_2.id.clone(),
+      field: "data".to_string(),
+      old_value: json!({}),
+      new_value: json!({"level": 1}),
+    },
+    DocumentChange::BlockMoved {
+      block_id: block_2.id.clone(),
+      old_parent_id: page.id.clone(),
+      new_parent_id: block_1.id.clone(),
+      old_index,
+      new_index: Some(0),
+    },
+    DocumentChange::BlockInserted {
+      block_id: block_3.id.clone(),
+      ty: TEXT_BLOCK_TYPE.to_string(),
+      parent_id: page.id.clone(),
+    },
+    DocumentChange::TextChanged {
+      text_id: block_3.external_id.clone().unwrap(),
+      delta: vec![TextDeltaChange::Insert {
+        insert: json!("new"),
+        attributes: None,
+      }],
+    },
+  ];
+  assert_eq!(changes.len(), expected.len(), "{:?}", changes);
+  for change in expected {
+    assert!(
+      changes.contains(&change),
+      "{:?} not in {:?}",
+      change,
+      changes
+    );
+  }
+}
+
+fn document_collab(test: &BlockTestCore) -> Collab {
+  let (doc_state, _) = test.collab.lock().encode_as_update_v1();
+  Collab::new_with_raw_data(CollabOrigin::Empty, "1", vec![doc_state], vec![]).unwrap()
+}
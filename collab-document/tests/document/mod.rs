@@ -1,4 +1,5 @@
 mod document_data_test;
+mod document_diff_test;
 mod document_test;
 mod redo_undo_test;
 mod restore_test;
@@ -12,7 +12,7 @@ use collab_document::blocks::{Block, BlockAction, DocumentData, DocumentMeta};
 use collab_document::document::Document;
 use collab_document::error::DocumentError;
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
-use collab_plugins::local_storage::rocksdb::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::DiskPlugin;
 use nanoid::nanoid;
 use serde_json::{json, Value};
 use tempfile::TempDir;
@@ -31,7 +31,7 @@ impl DocumentTest {
   }
 
   pub async fn new_with_db(uid: i64, doc_id: &str, db: Arc<RocksCollabDB>) -> Self {
-    let disk_plugin = RocksdbDiskPlugin::new(uid, Arc::downgrade(&db));
+    let disk_plugin = DiskPlugin::new(uid, Arc::downgrade(&db));
     let collab = CollabBuilder::new(1, doc_id)
       .with_plugin(disk_plugin)
       .with_device_id("1")
@@ -103,7 +103,7 @@ impl Deref for DocumentTest {
 
 pub async fn open_document_with_db(uid: i64, doc_id: &str, db: Arc<RocksCollabDB>) -> Document {
   setup_log();
-  let disk_plugin = RocksdbDiskPlugin::new(uid, Arc::downgrade(&db));
+  let disk_plugin = DiskPlugin::new(uid, Arc::downgrade(&db));
   let collab = CollabBuilder::new(uid, doc_id)
     .with_plugin(disk_plugin)
     .with_device_id("1")
@@ -0,0 +1,243 @@
+use std::io::{Read, Write};
+use std::panic;
+
+use collab::core::collab::{MutexCollab, TransactionMutExt};
+use collab::core::origin::CollabOrigin;
+use collab::core::transaction::TransactionRetry;
+use collab::error::CollabError;
+use collab::preclude::updates::decoder::Decode;
+use collab::preclude::updates::encoder::Encode;
+use collab::preclude::{Collab, Doc, ReadTxn, StateVector, Transact, Update};
+use serde::{Deserialize, Serialize};
+
+use crate::CollabType;
+
+/// The version of the bundle format. It's increased whenever the format changes, so a bundle is
+/// never read by a version that doesn't understand it.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// The first bytes of a bundle.
+const BUNDLE_MAGIC: &[u8; 4] = b"AFCB";
+
+/// Describes the content of a [CollabBundle]. It's stored as JSON, so a bundle can be inspected
+/// without decoding the update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleHeader {
+  pub version: u32,
+  pub object_id: String,
+  pub collab_type: CollabType,
+  /// The encoded [StateVector] the bundle is based on. The update of the bundle contains all the
+  /// changes that are not included in it.
+  pub state_vector: Vec<u8>,
+}
+
+/// The changes of a collab that bring a peer up to date, exchanged as a file between machines
+/// that are never connected, for example, air-gapped deployments.
+///
+/// The peer sends its state vector, [CollabBundleExt::export_bundle] encodes the changes that are
+/// missing and [CollabBundleExt::import_bundle] applies them on the peer. The bundles of all the
+/// objects of a workspace can be written to the same file, see [write_bundles] and
+/// [read_bundles].
+///
+/// A bundle is written as the [BUNDLE_MAGIC] bytes, the [BUNDLE_VERSION], the JSON header and
+/// the update. Each variable length part is prefixed by its length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollabBundle {
+  pub header: BundleHeader,
+  update: Vec<u8>,
+}
+
+impl CollabBundle {
+  /// Encode the changes of the collab that are not included in the given [StateVector].
+  pub fn new(collab: &Collab, collab_type: CollabType, state_vector: &StateVector) -> Self {
+    let update = collab.transact().encode_state_as_update_v1(state_vector);
+    Self {
+      header: BundleHeader {
+        version: BUNDLE_VERSION,
+        object_id: collab.object_id.clone(),
+        collab_type,
+        state_vector: state_vector.encode_v1(),
+      },
+      update,
+    }
+  }
+
+  /// Return the encoded update of the bundle.
+  pub fn update(&self) -> &[u8] {
+    &self.update
+  }
+
+  /// Return the [StateVector] the bundle is based on.
+  pub fn state_vector(&self) -> Result<StateVector, CollabError> {
+    Ok(StateVector::decode_v1(&self.header.state_vector)?)
+  }
+
+  pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), CollabError> {
+    let header = serde_json::to_vec(&self.header)?;
+    writer.write_all(BUNDLE_MAGIC)?;
+    writer.write_all(&self.header.version.to_be_bytes())?;
+    write_chunk(&mut writer, &header)?;
+    write_chunk(&mut writer, &self.update)?;
+    writer.flush()?;
+    Ok(())
+  }
+
+  pub fn read_from<R: Read>(mut reader: R) -> Result<Self, CollabError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != BUNDLE_MAGIC {
+      return Err(CollabError::InvalidBundle(
+        "not a collab bundle".to_string(),
+      ));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_be_bytes(version);
+    if version > BUNDLE_VERSION {
+      return Err(CollabError::InvalidBundle(format!(
+        "unsupported bundle version: {}",
+        version
+      )));
+    }
+
+    let header: BundleHeader = serde_json::from_slice(&read_chunk(&mut reader)?)?;
+    let update = read_chunk(&mut reader)?;
+    Ok(Self { header, update })
+  }
+
+  /// Check that the bundle can be applied to the collab. The collab must have all the changes
+  /// the bundle is based on, otherwise the changes of the bundle can't be integrated. That's the
+  /// case when the bundles of an object are not imported in the order they were exported.
+  ///
+  /// The update is decoded and applied to a copy of the collab first, because yrs panics on some
+  /// malformed updates instead of returning an error. A panic is returned as
+  /// [CollabError::InvalidBundle] and the collab is left untouched.
+  fn validate(&self, collab: &Collab, collab_type: &CollabType) -> Result<Update, CollabError> {
+    if self.header.object_id != collab.object_id {
+      return Err(CollabError::InvalidBundle(format!(
+        "the bundle of {} can't be imported into {}",
+        self.header.object_id, collab.object_id
+      )));
+    }
+    if &self.header.collab_type != collab_type {
+      return Err(CollabError::InvalidBundle(format!(
+        "expect {} bundle, but it's {}",
+        collab_type, self.header.collab_type
+      )));
+    }
+
+    let local_state_vector = collab.transact().state_vector();
+    let missing = self
+      .state_vector()?
+      .iter()
+      .any(|(client, clock)| local_state_vector.get(client) < *clock);
+    if missing {
+      return Err(CollabError::InvalidBundle(format!(
+        "{} misses the changes the bundle is based on",
+        collab.object_id
+      )));
+    }
+
+    let doc_state = collab
+      .transact()
+      .encode_state_as_update_v1(&StateVector::default());
+    match panic::catch_unwind(|| {
+      let doc = Doc::new();
+      let mut txn = doc.transact_mut();
+      txn.apply_update(Update::decode_v1(&doc_state)?);
+      txn.apply_update(Update::decode_v1(&self.update)?);
+      Update::decode_v1(&self.update)
+    }) {
+      Ok(update) => Ok(update?),
+      Err(e) => Err(CollabError::InvalidBundle(format!(
+        "the update of the bundle is malformed: {:?}",
+        e
+      ))),
+    }
+  }
+}
+
+/// Exports and imports the [CollabBundle]s of a collab.
+pub trait CollabBundleExt {
+  /// Return the [CollabBundle] that brings the peer with the given [StateVector] up to date.
+  fn export_bundle(&self, collab_type: CollabType, state_vector: &StateVector) -> CollabBundle;
+
+  /// Validate the bundle and apply its update with the [CollabOrigin::Bundle] origin. The
+  /// changes the collab already has are ignored, so a bundle can be imported more than once.
+  fn import_bundle(
+    &self,
+    collab_type: &CollabType,
+    bundle: &CollabBundle,
+  ) -> Result<(), CollabError>;
+}
+
+impl CollabBundleExt for Collab {
+  fn export_bundle(&self, collab_type: CollabType, state_vector: &StateVector) -> CollabBundle {
+    CollabBundle::new(self, collab_type, state_vector)
+  }
+
+  fn import_bundle(
+    &self,
+    collab_type: &CollabType,
+    bundle: &CollabBundle,
+  ) -> Result<(), CollabError> {
+    let update = bundle.validate(self, collab_type)?;
+    let mut txn = TransactionRetry::new(self.get_doc()).get_write_txn_with(CollabOrigin::Bundle);
+    txn.try_apply_update(update)
+  }
+}
+
+impl CollabBundleExt for MutexCollab {
+  fn export_bundle(&self, collab_type: CollabType, state_vector: &StateVector) -> CollabBundle {
+    self.lock().export_bundle(collab_type, state_vector)
+  }
+
+  fn import_bundle(
+    &self,
+    collab_type: &CollabType,
+    bundle: &CollabBundle,
+  ) -> Result<(), CollabError> {
+    self.lock().import_bundle(collab_type, bundle)
+  }
+}
+
+/// Write the bundles one after another, for example, the bundles of all the objects of a
+/// workspace.
+pub fn write_bundles<W: Write>(mut writer: W, bundles: &[CollabBundle]) -> Result<(), CollabError> {
+  for bundle in bundles {
+    bundle.write_to(&mut writer)?;
+  }
+  Ok(())
+}
+
+/// Read the bundles that were written by [write_bundles]. The bundles are returned in the order
+/// they were written.
+pub fn read_bundles<R: Read>(mut reader: R) -> Result<Vec<CollabBundle>, CollabError> {
+  let mut data = vec![];
+  reader.read_to_end(&mut data)?;
+  let mut remaining = data.as_slice();
+  let mut bundles = vec![];
+  while !remaining.is_empty() {
+    bundles.push(CollabBundle::read_from(&mut remaining)?);
+  }
+  Ok(bundles)
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk: &[u8]) -> Result<(), CollabError> {
+  writer.write_all(&(chunk.len() as u64).to_be_bytes())?;
+  writer.write_all(chunk)?;
+  Ok(())
+}
+
+fn read_chunk<R: Read>(reader: &mut R) -> Result<Vec<u8>, CollabError> {
+  let mut len = [0; 8];
+  reader.read_exact(&mut len)?;
+  let mut chunk = vec![];
+  reader
+    .take(u64::from_be_bytes(len))
+    .read_to_end(&mut chunk)?;
+  if chunk.len() as u64 != u64::from_be_bytes(len) {
+    return Err(CollabError::InvalidBundle("truncated bundle".to_string()));
+  }
+  Ok(chunk)
+}
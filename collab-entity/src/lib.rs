@@ -1,4 +1,5 @@
 pub use collab_object::*;
 
+pub mod collab_bundle;
 mod collab_object;
 pub mod reminder;
@@ -0,0 +1,188 @@
+use std::sync::{Arc, Mutex};
+
+use collab::core::collab::MutexCollab;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::{ReadTxn, StateVector};
+use collab_entity::collab_bundle::{read_bundles, write_bundles, CollabBundle, CollabBundleExt};
+use collab_entity::CollabType;
+use serde_json::json;
+
+#[test]
+fn exchange_bundles_test() {
+  let collab_a = create_collab(1, "1");
+  let collab_b = create_collab(2, "1");
+  collab_a.lock().insert("name", "appflowy");
+
+  // The peer sends its state vector and receives the changes it misses as a file.
+  let state_vector = collab_b.lock().transact().state_vector();
+  let bundle = collab_a.export_bundle(CollabType::Document, &state_vector);
+  assert_eq!(bundle.header.object_id, "1");
+  assert_eq!(bundle.state_vector().unwrap(), state_vector);
+  let mut file = vec![];
+  bundle.write_to(&mut file).unwrap();
+
+  let origins = Arc::new(Mutex::new(vec![]));
+  let cloned_origins = origins.clone();
+  let _subscription = collab_b
+    .lock()
+    .get_doc()
+    .observe_update_v1(move |txn, _| cloned_origins.lock().unwrap().push(CollabOrigin::from(txn)))
+    .unwrap();
+  let bundle = CollabBundle::read_from(file.as_slice()).unwrap();
+  collab_b
+    .import_bundle(&CollabType::Document, &bundle)
+    .unwrap();
+  assert_eq!(collab_b.to_json_value(), json!({"name": "appflowy"}));
+  assert_eq!(origins.lock().unwrap().as_slice(), &[CollabOrigin::Bundle]);
+
+  // The edits of both peers are merged.
+  collab_b.lock().insert("desc", "hello");
+  let state_vector = collab_a.lock().transact().state_vector();
+  let bundle = collab_b.export_bundle(CollabType::Document, &state_vector);
+  collab_a
+    .import_bundle(&CollabType::Document, &bundle)
+    .unwrap();
+  assert_eq!(
+    collab_a.to_json_value(),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+  assert_eq!(collab_a.to_json_value(), collab_b.to_json_value());
+}
+
+#[test]
+fn exchange_workspace_bundles_test() {
+  let objects = vec![
+    ("1", CollabType::Folder),
+    ("2", CollabType::Document),
+    ("3", CollabType::Document),
+  ];
+  let collabs_a = objects
+    .iter()
+    .map(|(object_id, _)| create_collab(1, object_id))
+    .collect::<Vec<_>>();
+  let collabs_b = objects
+    .iter()
+    .map(|(object_id, _)| create_collab(2, object_id))
+    .collect::<Vec<_>>();
+  for (collab, (object_id, _)) in collabs_a.iter().zip(objects.iter()) {
+    collab.lock().insert("id", *object_id);
+  }
+
+  // The bundles of all the objects are written to the same file.
+  let bundles = collabs_a
+    .iter()
+    .zip(collabs_b.iter())
+    .zip(objects.iter())
+    .map(|((collab_a, collab_b), (_, collab_type))| {
+      let state_vector = collab_b.lock().transact().state_vector();
+      collab_a.export_bundle(collab_type.clone(), &state_vector)
+    })
+    .collect::<Vec<_>>();
+  let mut file = vec![];
+  write_bundles(&mut file, &bundles).unwrap();
+
+  let bundles = read_bundles(file.as_slice()).unwrap();
+  assert_eq!(bundles.len(), 3);
+  for bundle in bundles {
+    let index = objects
+      .iter()
+      .position(|(object_id, _)| *object_id == bundle.header.object_id)
+      .unwrap();
+    collabs_b[index]
+      .import_bundle(&bundle.header.collab_type, &bundle)
+      .unwrap();
+  }
+  for (collab, (object_id, _)) in collabs_b.iter().zip(objects.iter()) {
+    assert_eq!(collab.to_json_value(), json!({ "id": object_id }));
+  }
+}
+
+#[test]
+fn import_invalid_bundle_test() {
+  let collab_a = create_collab(1, "1");
+  collab_a.lock().insert("name", "appflowy");
+  let first_bundle = collab_a.export_bundle(CollabType::Document, &StateVector::default());
+  let state_vector = collab_a.lock().transact().state_vector();
+  collab_a.lock().insert("desc", "hello");
+  let second_bundle = collab_a.export_bundle(CollabType::Document, &state_vector);
+
+  // The bundle of another object or type is refused.
+  let collab_b = create_collab(2, "2");
+  assert!(matches!(
+    collab_b.import_bundle(&CollabType::Document, &first_bundle),
+    Err(CollabError::InvalidBundle(_))
+  ));
+  let collab_b = create_collab(2, "1");
+  assert!(matches!(
+    collab_b.import_bundle(&CollabType::Folder, &first_bundle),
+    Err(CollabError::InvalidBundle(_))
+  ));
+
+  // The bundles of an object must be imported in order.
+  assert!(matches!(
+    collab_b.import_bundle(&CollabType::Document, &second_bundle),
+    Err(CollabError::InvalidBundle(_))
+  ));
+  collab_b
+    .import_bundle(&CollabType::Document, &first_bundle)
+    .unwrap();
+  collab_b
+    .import_bundle(&CollabType::Document, &second_bundle)
+    .unwrap();
+  assert_eq!(
+    collab_b.to_json_value(),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+
+  // A truncated file can't be read.
+  let mut file = vec![];
+  first_bundle.write_to(&mut file).unwrap();
+  file.truncate(file.len() - 1);
+  assert!(CollabBundle::read_from(file.as_slice()).is_err());
+  assert!(CollabBundle::read_from(&b"AFWS"[..]).is_err());
+}
+
+#[test]
+fn import_malformed_bundle_test() {
+  let collab_a = create_collab(1, "1");
+  let collab_b = create_collab(2, "1");
+  collab_b.lock().insert("name", "appflowy");
+
+  // An update whose item is inserted into the "name" value of collab_b, which is not a shared
+  // type. yrs panics when it's applied.
+  let mut update = vec![1, 1, 5, 0, 0x28, 0];
+  let mut client_id = collab_b.lock().get_doc().client_id();
+  while client_id >= 0x80 {
+    update.push((client_id as u8 & 0x7f) | 0x80);
+    client_id >>= 7;
+  }
+  update.push(client_id as u8);
+  update.extend([0, 4, b'd', b'e', b's', b'c', 1, 119, 1, b'x', 0]);
+
+  let bundle = collab_a.export_bundle(CollabType::Document, &StateVector::default());
+  let mut file = vec![];
+  bundle.write_to(&mut file).unwrap();
+  file.truncate(file.len() - bundle.update().len() - 8);
+  file.extend((update.len() as u64).to_be_bytes());
+  file.extend(update);
+  let bundle = CollabBundle::read_from(file.as_slice()).unwrap();
+
+  assert!(matches!(
+    collab_b.import_bundle(&CollabType::Document, &bundle),
+    Err(CollabError::InvalidBundle(_))
+  ));
+  assert_eq!(collab_b.to_json_value(), json!({"name": "appflowy"}));
+  collab_b.lock().insert("desc", "hello");
+  assert_eq!(
+    collab_b.to_json_value(),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+}
+
+fn create_collab(uid: i64, object_id: &str) -> MutexCollab {
+  let origin = CollabOrigin::Client(CollabClient::new(uid, "1"));
+  let collab = MutexCollab::new(origin, object_id, vec![]);
+  collab.lock().initialize();
+  collab
+}
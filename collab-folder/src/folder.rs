@@ -44,11 +44,11 @@ impl AsRef<str> for UserId {
   }
 }
 
-const FOLDER: &str = "folder";
-const VIEWS: &str = "views";
+pub(crate) const FOLDER: &str = "folder";
+pub(crate) const VIEWS: &str = "views";
 const TRASH: &str = "trash";
 const META: &str = "meta";
-const VIEW_RELATION: &str = "relation";
+pub(crate) const VIEW_RELATION: &str = "relation";
 const CURRENT_VIEW: &str = "current_view";
 const CURRENT_WORKSPACE: &str = "current_workspace";
 
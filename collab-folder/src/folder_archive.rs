@@ -0,0 +1,32 @@
+use collab::preclude::Collab;
+use collab_entity::CollabType;
+use collab_persistence::archive::{ArchiveObject, CollabReferences};
+
+use crate::folder::{FOLDER, VIEWS};
+use crate::view::{VIEW_LAYOUT, VIEW_PARENT_ID};
+use crate::ViewLayout;
+
+/// Returns what the folder refers to when exporting the workspace with
+/// [ArchiveAction::export_workspace](collab_persistence::archive::ArchiveAction::export_workspace).
+/// The documents are exported together with the folder. The other views are the workspaces and
+/// the views of the databases, which are exported from the workspace database, so only their
+/// ids are returned.
+pub fn folder_references(collab: &Collab) -> CollabReferences {
+  let json = collab.to_json_value();
+  let mut references = CollabReferences::default();
+  if let Some(views) = json[FOLDER][VIEWS].as_object() {
+    for (view_id, view) in views {
+      let layout = view[VIEW_LAYOUT]
+        .as_i64()
+        .and_then(|layout| ViewLayout::try_from(layout).ok());
+      let is_workspace = view[VIEW_PARENT_ID].as_str().unwrap_or_default().is_empty();
+      match layout {
+        Some(ViewLayout::Document) if !is_workspace => references
+          .objects
+          .push(ArchiveObject::new(view_id, CollabType::Document)),
+        _ => references.ids.push(view_id.clone()),
+      }
+    }
+  }
+  references
+}
@@ -0,0 +1,131 @@
+use collab::core::diff::diff_collab;
+use collab::core::path_observer::PathChangeKind;
+use collab::preclude::Collab;
+use serde_json::Value as JsonValue;
+
+use crate::folder::{FOLDER, VIEWS, VIEW_RELATION};
+use crate::view::{VIEW_NAME, VIEW_PARENT_ID};
+
+/// A change of a view between two snapshots of the folder.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FolderViewChange {
+  Created {
+    view_id: String,
+    parent_view_id: String,
+    name: String,
+  },
+  Deleted {
+    view_id: String,
+    parent_view_id: String,
+    name: String,
+  },
+  Renamed {
+    view_id: String,
+    old_name: String,
+    new_name: String,
+  },
+  /// The view is moved to another parent or to another position under the same parent. The
+  /// index is the position of the view in the children of its parent.
+  Moved {
+    view_id: String,
+    old_parent_view_id: String,
+    new_parent_view_id: String,
+    old_index: Option<u32>,
+    new_index: Option<u32>,
+  },
+}
+
+/// Returns the view changes that turn the `old` folder into the `new` folder. Both [Collab]s
+/// must contain a folder, for example the collabs decoded from two snapshots of the same folder.
+pub fn diff_folder(old: &Collab, new: &Collab) -> Vec<FolderViewChange> {
+  let old_json = old.to_json_value();
+  let new_json = new.to_json_value();
+  let mut view_changes = vec![];
+  let mut moved_view_ids: Vec<String> = vec![];
+
+  for change in diff_collab(old, new) {
+    let path = change.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    match (path.as_slice(), change.kind) {
+      ([FOLDER, VIEWS], PathChangeKind::Inserted { key, value }) => {
+        view_changes.push(FolderViewChange::Created {
+          parent_view_id: json_str(&value[VIEW_PARENT_ID]),
+          name: json_str(&value[VIEW_NAME]),
+          view_id: key,
+        });
+      },
+      ([FOLDER, VIEWS], PathChangeKind::Removed { key, .. }) => {
+        // The content of a removed view is not part of the change, so read it from the old
+        // folder.
+        let view = &old_json[FOLDER][VIEWS][&key];
+        view_changes.push(FolderViewChange::Deleted {
+          parent_view_id: json_str(&view[VIEW_PARENT_ID]),
+          name: json_str(&view[VIEW_NAME]),
+          view_id: key,
+        });
+      },
+      (
+        [FOLDER, VIEWS, view_id],
+        PathChangeKind::Updated {
+          key,
+          old_value,
+          value,
+        },
+      ) if key == VIEW_NAME => {
+        view_changes.push(FolderViewChange::Renamed {
+          view_id: view_id.to_string(),
+          old_name: json_str(&old_value),
+          new_name: json_str(&value),
+        });
+      },
+      ([FOLDER, VIEWS, view_id], PathChangeKind::Updated { key, .. }) if key == VIEW_PARENT_ID => {
+        moved_view_ids.push(view_id.to_string());
+      },
+      ([FOLDER, VIEW_RELATION, _], PathChangeKind::ArrayInserted { values, .. }) => {
+        moved_view_ids.extend(values.iter().map(|value| json_str(&value["id"])));
+      },
+      _ => {},
+    }
+  }
+
+  // A view that is reinserted into the children of a parent is only moved when it exists in
+  // both folders and its position changed.
+  let mut reported = vec![];
+  for view_id in moved_view_ids {
+    if reported.contains(&view_id) {
+      continue;
+    }
+    let old_view = &old_json[FOLDER][VIEWS][&view_id];
+    let new_view = &new_json[FOLDER][VIEWS][&view_id];
+    if old_view.is_null() || new_view.is_null() {
+      continue;
+    }
+
+    let old_parent_view_id = json_str(&old_view[VIEW_PARENT_ID]);
+    let new_parent_view_id = json_str(&new_view[VIEW_PARENT_ID]);
+    let old_index = child_index(&old_json, &old_parent_view_id, &view_id);
+    let new_index = child_index(&new_json, &new_parent_view_id, &view_id);
+    if old_parent_view_id != new_parent_view_id || old_index != new_index {
+      view_changes.push(FolderViewChange::Moved {
+        view_id: view_id.clone(),
+        old_parent_view_id,
+        new_parent_view_id,
+        old_index,
+        new_index,
+      });
+      reported.push(view_id);
+    }
+  }
+  view_changes
+}
+
+fn child_index(folder_json: &JsonValue, parent_view_id: &str, view_id: &str) -> Option<u32> {
+  folder_json[FOLDER][VIEW_RELATION][parent_view_id]
+    .as_array()?
+    .iter()
+    .position(|child| child["id"] == view_id)
+    .map(|index| index as u32)
+}
+
+fn json_str(value: &JsonValue) -> String {
+  value.as_str().unwrap_or_default().to_string()
+}
@@ -1,5 +1,7 @@
 pub use entities::*;
 pub use folder::*;
+pub use folder_archive::*;
+pub use folder_diff::*;
 pub use folder_migration::*;
 pub use folder_observe::*;
 pub use relation::*;
@@ -10,6 +12,8 @@ pub use workspace::*;
 
 mod entities;
 mod folder;
+mod folder_archive;
+mod folder_diff;
 mod relation;
 mod section;
 mod trash;
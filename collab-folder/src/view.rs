@@ -16,11 +16,11 @@ use crate::{impl_any_update, impl_i64_update, impl_option_str_update, impl_str_u
 use crate::{subscribe_view_change, RepeatedViewIdentifier, ViewIdentifier, ViewRelations};
 
 const VIEW_ID: &str = "id";
-const VIEW_NAME: &str = "name";
-const VIEW_PARENT_ID: &str = "bid";
+pub(crate) const VIEW_NAME: &str = "name";
+pub(crate) const VIEW_PARENT_ID: &str = "bid";
 const VIEW_DESC: &str = "desc";
 const VIEW_DATABASE_ID: &str = "database_id";
-const VIEW_LAYOUT: &str = "layout";
+pub(crate) const VIEW_LAYOUT: &str = "layout";
 const VIEW_CREATE_AT: &str = "created_at";
 const VIEW_ICON: &str = "icon";
 
@@ -0,0 +1,32 @@
+use collab::core::origin::CollabOrigin;
+use collab::preclude::Collab;
+use collab_entity::CollabType;
+use collab_folder::{folder_references, UserId, ViewLayout};
+use collab_persistence::archive::ArchiveObject;
+
+use crate::util::{create_folder_with_workspace, make_test_view};
+
+#[tokio::test]
+async fn folder_references_test() {
+  let uid = UserId::from(1);
+  let folder_test = create_folder_with_workspace(uid, "w1").await;
+  folder_test.insert_view(make_test_view("v1", "w1", vec![]), None);
+  folder_test.insert_view(make_test_view("v1_1", "v1", vec![]), None);
+  let mut grid = make_test_view("v2", "w1", vec![]);
+  grid.layout = ViewLayout::Grid;
+  folder_test.insert_view(grid, None);
+
+  let (doc_state, _) = folder_test.encode_as_update_v1();
+  let collab =
+    Collab::new_with_raw_data(CollabOrigin::Empty, "w1", vec![doc_state], vec![]).unwrap();
+  let references = folder_references(&collab);
+  assert_eq!(
+    references.objects,
+    vec![
+      ArchiveObject::new("v1", CollabType::Document),
+      ArchiveObject::new("v1_1", CollabType::Document),
+    ]
+  );
+  // The database views are exported from the workspace database.
+  assert_eq!(references.ids, vec!["v2".to_string(), "w1".to_string()]);
+}
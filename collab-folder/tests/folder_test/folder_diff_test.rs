@@ -0,0 +1,85 @@
+use collab::core::origin::CollabOrigin;
+use collab::preclude::Collab;
+use collab_folder::{diff_folder, Folder, FolderViewChange, UserId};
+
+use crate::util::{create_folder_with_workspace, make_test_view};
+
+#[tokio::test]
+async fn diff_folder_views_test() {
+  let uid = UserId::from(1);
+  let folder_test = create_folder_with_workspace(uid.clone(), "w1").await;
+  let mut view_1 = make_test_view("v1", "w1", vec![]);
+  view_1.name = "my view".to_string();
+  folder_test.insert_view(view_1, None);
+  folder_test.insert_view(make_test_view("v1_1", "v1", vec![]), None);
+  folder_test.insert_view(make_test_view("v2", "w1", vec![]), None);
+  folder_test.insert_view(make_test_view("v3", "w1", vec![]), None);
+  let old = folder_collab(&folder_test);
+
+  folder_test
+    .views
+    .update_view("v1", |update| update.set_name("renamed view").done());
+  folder_test.views.delete_views(vec!["v3"]);
+  let mut view_4 = make_test_view("v4", "w1", vec![]);
+  view_4.name = "new view".to_string();
+  folder_test.insert_view(view_4, None);
+  folder_test.move_nested_view("v2", "v1", Some("v1_1".to_string()));
+  let new = folder_collab(&folder_test);
+
+  assert!(diff_folder(&old, &old).is_empty());
+  assert_eq!(
+    diff_folder(&old, &new),
+    vec![
+      FolderViewChange::Renamed {
+        view_id: "v1".to_string(),
+        old_name: "my view".to_string(),
+        new_name: "renamed view".to_string(),
+      },
+      FolderViewChange::Deleted {
+        view_id: "v3".to_string(),
+        parent_view_id: "w1".to_string(),
+        name: "".to_string(),
+      },
+      FolderViewChange::Created {
+        view_id: "v4".to_string(),
+        parent_view_id: "w1".to_string(),
+        name: "new view".to_string(),
+      },
+      FolderViewChange::Moved {
+        view_id: "v2".to_string(),
+        old_parent_view_id: "w1".to_string(),
+        new_parent_view_id: "v1".to_string(),
+        old_index: Some(1),
+        new_index: Some(1),
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn diff_folder_reorder_views_test() {
+  let uid = UserId::from(1);
+  let folder_test = create_folder_with_workspace(uid.clone(), "w1").await;
+  for view_id in ["v1", "v2", "v3"] {
+    folder_test.insert_view(make_test_view(view_id, "w1", vec![]), None);
+  }
+  let old = folder_collab(&folder_test);
+
+  folder_test.move_view("v1", 0, 2);
+  let new = folder_collab(&folder_test);
+  assert_eq!(
+    diff_folder(&old, &new),
+    vec![FolderViewChange::Moved {
+      view_id: "v1".to_string(),
+      old_parent_view_id: "w1".to_string(),
+      new_parent_view_id: "w1".to_string(),
+      old_index: Some(0),
+      new_index: Some(2),
+    }]
+  );
+}
+
+fn folder_collab(folder: &Folder) -> Collab {
+  let (doc_state, _) = folder.encode_as_update_v1();
+  Collab::new_with_raw_data(CollabOrigin::Empty, "w1", vec![doc_state], vec![]).unwrap()
+}
@@ -1,6 +1,8 @@
 mod child_views_test;
 mod custom_section;
 mod favorite_test;
+mod folder_archive_test;
+mod folder_diff_test;
 mod load_disk;
 mod serde_test;
 mod trash_test;
@@ -9,7 +9,7 @@ use collab::preclude::CollabBuilder;
 use collab_folder::*;
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
 
-use collab_plugins::local_storage::rocksdb::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::DiskPlugin;
 use nanoid::nanoid;
 use tempfile::TempDir;
 use tracing_subscriber::fmt::Subscriber;
@@ -53,7 +53,7 @@ pub async fn create_folder_with_data(
 
   let path = tempdir.into_path();
   let db = Arc::new(RocksCollabDB::open(path.clone()).unwrap());
-  let disk_plugin = RocksdbDiskPlugin::new(uid.as_i64(), Arc::downgrade(&db));
+  let disk_plugin = DiskPlugin::new(uid.as_i64(), Arc::downgrade(&db));
   let cleaner: Cleaner = Cleaner::new(path);
 
   let collab = CollabBuilder::new(uid.as_i64(), workspace_id)
@@ -81,7 +81,7 @@ pub async fn create_folder_with_data(
 
 pub async fn open_folder_with_db(uid: UserId, object_id: &str, db_path: PathBuf) -> FolderTest {
   let db = Arc::new(RocksCollabDB::open(db_path.clone()).unwrap());
-  let disk_plugin = RocksdbDiskPlugin::new(uid.as_i64(), Arc::downgrade(&db));
+  let disk_plugin = DiskPlugin::new(uid.as_i64(), Arc::downgrade(&db));
   let cleaner: Cleaner = Cleaner::new(db_path);
   let collab = CollabBuilder::new(1, object_id)
     .with_plugin(disk_plugin)
@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+
+use collab::core::collab::Collab;
+use collab::core::remap::remap_collab;
+use collab_entity::CollabType;
+use serde::{Deserialize, Serialize};
+use yrs::updates::decoder::Decode;
+use yrs::{ReadTxn, StateVector, Update};
+
+use crate::doc::YrsDocAction;
+use crate::kv::KVStore;
+use crate::{PersistenceError, TransactionMutExt};
+
+/// The version of the archive format. It's increased whenever the format changes, so an archive
+/// is never read by a version that doesn't understand it.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// The first bytes of an archive.
+const ARCHIVE_MAGIC: &[u8; 4] = b"AFWS";
+
+/// The device id of the collabs that are created to export and import an archive.
+const ARCHIVE_DEVICE_ID: &str = "archive";
+
+/// An object of the archive. The object id is the id of its collab.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ArchiveObject {
+  pub object_id: String,
+  pub collab_type: CollabType,
+}
+
+impl ArchiveObject {
+  pub fn new<T: ToString>(object_id: T, collab_type: CollabType) -> Self {
+    Self {
+      object_id: object_id.to_string(),
+      collab_type,
+    }
+  }
+}
+
+/// What a collab refers to. It's returned by the resolver of [ArchiveAction::export_workspace]
+/// to walk the workspace.
+#[derive(Debug, Clone, Default)]
+pub struct CollabReferences {
+  /// The objects that are exported together with the collab.
+  pub objects: Vec<ArchiveObject>,
+  /// The ids used by the collab that are not the ids of an object, for example, the ids of the
+  /// database views. They're replaced together with the object ids when the archive is imported
+  /// as a copy.
+  pub ids: Vec<String>,
+}
+
+/// Describes the content of a [WorkspaceArchive]. It's stored as JSON, so it can be inspected
+/// without decoding the collabs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+  pub version: u32,
+  pub workspace_id: String,
+  /// The time the archive was created, in seconds.
+  pub created_at: i64,
+  /// The exported objects, in the order they were visited.
+  pub objects: Vec<ArchiveObject>,
+  pub ids: Vec<String>,
+}
+
+impl ArchiveManifest {
+  /// Return the ids to import the archive as a copy: the workspace id is replaced by
+  /// `new_workspace_id` and the other ids of the manifest by new unique ids.
+  pub fn new_ids(&self, new_workspace_id: &str) -> HashMap<String, String> {
+    let mut ids = HashMap::new();
+    ids.insert(self.workspace_id.clone(), new_workspace_id.to_string());
+    let other_ids = self
+      .objects
+      .iter()
+      .map(|object| &object.object_id)
+      .chain(self.ids.iter());
+    for id in other_ids {
+      if !ids.contains_key(id) {
+        ids.insert(id.clone(), uuid::Uuid::new_v4().to_string());
+      }
+    }
+    ids
+  }
+}
+
+/// The encoded collabs of a workspace together with their [ArchiveManifest].
+///
+/// An archive is written as the [ARCHIVE_MAGIC] bytes, the [ARCHIVE_VERSION], the JSON manifest
+/// and the doc state of each object, in the order of the manifest. Each variable length part is
+/// prefixed by its length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceArchive {
+  pub manifest: ArchiveManifest,
+  doc_states: HashMap<String, Vec<u8>>,
+}
+
+impl WorkspaceArchive {
+  /// Return the encoded doc state of the given object.
+  pub fn get_doc_state(&self, object_id: &str) -> Option<&[u8]> {
+    self
+      .doc_states
+      .get(object_id)
+      .map(|doc_state| doc_state.as_slice())
+  }
+
+  pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), PersistenceError> {
+    let manifest = serde_json::to_vec(&self.manifest)
+      .map_err(|e| PersistenceError::InvalidData(e.to_string()))?;
+    writer.write_all(ARCHIVE_MAGIC)?;
+    writer.write_all(&self.manifest.version.to_be_bytes())?;
+    write_chunk(&mut writer, &manifest)?;
+    for object in self.manifest.objects.iter() {
+      let doc_state = self.doc_states.get(&object.object_id).ok_or_else(|| {
+        PersistenceError::InvalidData(format!("missing doc state of {}", object.object_id))
+      })?;
+      write_chunk(&mut writer, doc_state)?;
+    }
+    writer.flush()?;
+    Ok(())
+  }
+
+  pub fn read_from<R: Read>(mut reader: R) -> Result<Self, PersistenceError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != ARCHIVE_MAGIC {
+      return Err(PersistenceError::InvalidData(
+        "not a workspace archive".to_string(),
+      ));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_be_bytes(version);
+    if version > ARCHIVE_VERSION {
+      return Err(PersistenceError::InvalidData(format!(
+        "unsupported archive version: {}",
+        version
+      )));
+    }
+
+    let manifest: ArchiveManifest = serde_json::from_slice(&read_chunk(&mut reader)?)
+      .map_err(|e| PersistenceError::InvalidData(e.to_string()))?;
+    let mut doc_states = HashMap::with_capacity(manifest.objects.len());
+    for object in manifest.objects.iter() {
+      doc_states.insert(object.object_id.clone(), read_chunk(&mut reader)?);
+    }
+    Ok(Self {
+      manifest,
+      doc_states,
+    })
+  }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk: &[u8]) -> Result<(), PersistenceError> {
+  writer.write_all(&(chunk.len() as u64).to_be_bytes())?;
+  writer.write_all(chunk)?;
+  Ok(())
+}
+
+fn read_chunk<R: Read>(reader: &mut R) -> Result<Vec<u8>, PersistenceError> {
+  let mut len = [0; 8];
+  reader.read_exact(&mut len)?;
+  let mut chunk = vec![];
+  reader
+    .take(u64::from_be_bytes(len))
+    .read_to_end(&mut chunk)?;
+  if chunk.len() as u64 != u64::from_be_bytes(len) {
+    return Err(PersistenceError::InvalidData(
+      "truncated workspace archive".to_string(),
+    ));
+  }
+  Ok(chunk)
+}
+
+impl<'a, T> ArchiveAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Exports the collabs of a workspace to a [WorkspaceArchive] and imports them back, possibly
+/// into another workspace or for another user.
+pub trait ArchiveAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Export the `roots` and all the objects they refer to. Each exported collab is passed to
+  /// `resolve`, which returns what the collab refers to, for example, the documents of the
+  /// folder or the rows of a database. The objects that don't exist in the store are skipped.
+  fn export_workspace<F>(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    roots: Vec<ArchiveObject>,
+    resolve: F,
+  ) -> Result<WorkspaceArchive, PersistenceError>
+  where
+    F: Fn(&ArchiveObject, &Collab) -> CollabReferences,
+  {
+    let mut queue = VecDeque::from(roots);
+    let mut visited = HashSet::new();
+    let mut objects = vec![];
+    let mut ids = vec![];
+    let mut doc_states = HashMap::new();
+    while let Some(object) = queue.pop_front() {
+      if !visited.insert(object.object_id.clone()) {
+        continue;
+      }
+      if !self.is_exist(uid, &object.object_id) {
+        tracing::warn!("🟡skip exporting {:?}, it's not exist", object);
+        continue;
+      }
+
+      let collab = Collab::new(uid, &object.object_id, ARCHIVE_DEVICE_ID, vec![]);
+      {
+        let mut txn = collab.origin_transact_mut();
+        let report = self.load_doc_with_txn(uid, &object.object_id, &mut txn)?;
+        if !report.is_clean() {
+          tracing::warn!("🟡export {:?} with load issues: {:?}", object, report);
+        }
+      }
+      let references = resolve(&object, &collab);
+      queue.extend(references.objects);
+      for id in references.ids {
+        if !ids.contains(&id) {
+          ids.push(id);
+        }
+      }
+      let doc_state = collab
+        .transact()
+        .encode_state_as_update_v1(&StateVector::default());
+      doc_states.insert(object.object_id.clone(), doc_state);
+      objects.push(object);
+    }
+
+    Ok(WorkspaceArchive {
+      manifest: ArchiveManifest {
+        version: ARCHIVE_VERSION,
+        workspace_id: workspace_id.to_string(),
+        created_at: chrono::Utc::now().timestamp(),
+        objects,
+        ids,
+      },
+      doc_states,
+    })
+  }
+
+  /// Import the objects of the archive for the given user and return the manifest of the
+  /// imported objects. The objects of the returned manifest are in the same order as the
+  /// objects of the archive.
+  ///
+  /// Each id of `ids` is replaced by its new id, see [ArchiveManifest::new_ids]. If `ids` is
+  /// empty, the objects keep their ids. The import fails if one of the imported objects already
+  /// exists.
+  ///
+  /// The collabs are decoded before any object is created, but creating an object can still
+  /// fail after others were created. Run the import in a single write transaction, for example
+  /// with [crate::kv::KVTransactionDB::with_write_txn], so the created objects are discarded
+  /// together with the transaction if the import fails.
+  fn import_workspace(
+    &self,
+    uid: i64,
+    archive: &WorkspaceArchive,
+    ids: &HashMap<String, String>,
+  ) -> Result<ArchiveManifest, PersistenceError> {
+    let manifest = &archive.manifest;
+    let new_id = |id: &String| ids.get(id).cloned().unwrap_or_else(|| id.clone());
+
+    let objects = manifest
+      .objects
+      .iter()
+      .map(|object| ArchiveObject::new(new_id(&object.object_id), object.collab_type.clone()))
+      .collect::<Vec<_>>();
+    if let Some(object) = objects
+      .iter()
+      .find(|object| self.is_exist(uid, &object.object_id))
+    {
+      tracing::warn!("🟡can't import {:?}, it already exists", object);
+      return Err(PersistenceError::DocumentAlreadyExist);
+    }
+
+    let mut collabs = Vec::with_capacity(objects.len());
+    for (object, new_object) in manifest.objects.iter().zip(objects.iter()) {
+      let doc_state = archive.get_doc_state(&object.object_id).ok_or_else(|| {
+        PersistenceError::InvalidData(format!("missing doc state of {}", object.object_id))
+      })?;
+      let collab = Collab::new(uid, &object.object_id, ARCHIVE_DEVICE_ID, vec![]);
+      {
+        let mut txn = collab.origin_transact_mut();
+        txn.try_apply_update(Update::decode_v1(doc_state)?)?;
+      }
+      if ids.is_empty() {
+        collabs.push(collab);
+      } else {
+        let new_collab = Collab::new(uid, &new_object.object_id, ARCHIVE_DEVICE_ID, vec![]);
+        remap_collab(&collab, &new_collab, ids);
+        collabs.push(new_collab);
+      }
+    }
+    for (new_object, collab) in objects.iter().zip(collabs.iter()) {
+      self.create_new_doc(uid, &new_object.object_id, &collab.transact())?;
+    }
+
+    Ok(ArchiveManifest {
+      version: manifest.version,
+      workspace_id: new_id(&manifest.workspace_id),
+      created_at: manifest.created_at,
+      objects,
+      ids: manifest.ids.iter().map(new_id).collect(),
+    })
+  }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use yrs::updates::encoder::Encode;
+
+use crate::doc::get_doc_id;
+use crate::encoding::doc_encoder_version;
+use crate::keys::{make_doc_state_key, make_doc_update_key, make_state_vector_key, Clock};
+use crate::kv::{KVEntry, KVStore, KVTransactionDB};
+use crate::PersistenceError;
+
+/// The thresholds that trigger the compaction of a document's update log. The document is
+/// compacted as soon as one of the thresholds is crossed.
+#[derive(Debug, Clone)]
+pub struct CompactionConfig {
+  /// Compact the document when the number of stored updates reaches this value.
+  /// Default is 1000.
+  pub max_update_count: usize,
+  /// Compact the document when the total size of stored updates reaches this value.
+  /// Default is 4MB.
+  pub max_update_bytes: usize,
+}
+
+impl CompactionConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn max_update_count(mut self, max_update_count: usize) -> Self {
+    debug_assert!(max_update_count > 0);
+    self.max_update_count = max_update_count;
+    self
+  }
+
+  pub fn max_update_bytes(mut self, max_update_bytes: usize) -> Self {
+    debug_assert!(max_update_bytes > 0);
+    self.max_update_bytes = max_update_bytes;
+    self
+  }
+
+  /// Return true if the update log exceeds one of the thresholds.
+  pub fn is_exceeded(&self, stats: &UpdateLogStats) -> bool {
+    stats.update_count >= self.max_update_count || stats.update_bytes >= self.max_update_bytes
+  }
+}
+
+impl Default for CompactionConfig {
+  fn default() -> Self {
+    Self {
+      max_update_count: 1000,
+      max_update_bytes: 4 * 1024 * 1024,
+    }
+  }
+}
+
+/// The updates that are stored after the document state.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct UpdateLogStats {
+  pub update_count: usize,
+  pub update_bytes: usize,
+}
+
+/// The result of compacting a single document.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct CompactionResult {
+  /// The number of updates that were merged into the document state.
+  pub merged_updates: usize,
+  /// The size of the document state and the updates before compacting.
+  pub bytes_before: usize,
+  /// The size of the document state after compacting.
+  pub bytes_after: usize,
+}
+
+impl<'a, T> CompactAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+pub trait CompactAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Return the number and the total size of the updates stored for the given document.
+  fn update_log_stats<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<UpdateLogStats, PersistenceError> {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    let start = make_doc_update_key(doc_id, 0);
+    let end = make_doc_update_key(doc_id, Clock::MAX);
+    let mut stats = UpdateLogStats::default();
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      stats.update_count += 1;
+      stats.update_bytes += entry.value().len();
+    }
+    Ok(stats)
+  }
+
+  /// Merge the document state and all the stored updates of the given document into a new
+  /// document state. The document state and the state vector are rewritten and the merged
+  /// updates are removed within this transaction, so they are committed all at once.
+  fn compact_doc<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<CompactionResult, PersistenceError> {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    let encoder_version = doc_encoder_version(self, doc_id)?;
+    let doc_state_key = make_doc_state_key(doc_id);
+    let doc_state = self
+      .get(doc_state_key.as_ref())?
+      .map(|value| value.as_ref().to_vec());
+
+    let start = make_doc_update_key(doc_id, 0);
+    let end = make_doc_update_key(doc_id, Clock::MAX);
+    let mut update_keys = vec![];
+    let mut updates = vec![];
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      update_keys.push(entry.key().to_vec());
+      updates.push(entry.value().to_vec());
+    }
+
+    let doc_state_len = doc_state.as_ref().map(|state| state.len()).unwrap_or(0);
+    if updates.is_empty() {
+      return Ok(CompactionResult {
+        merged_updates: 0,
+        bytes_before: doc_state_len,
+        bytes_after: doc_state_len,
+      });
+    }
+
+    let bytes_before = doc_state_len + updates.iter().map(|update| update.len()).sum::<usize>();
+    let mut encoded_updates: Vec<&[u8]> = Vec::with_capacity(updates.len() + 1);
+    if let Some(doc_state) = doc_state.as_ref() {
+      encoded_updates.push(doc_state);
+    }
+    encoded_updates.extend(updates.iter().map(|update| update.as_slice()));
+    let new_doc_state = encoder_version.merge_updates(&encoded_updates)?;
+    let sv = encoder_version
+      .decode_update(&new_doc_state)?
+      .state_vector()
+      .encode_v1();
+
+    // Only remove the updates that were merged. The updates that are pushed after reading the
+    // range are kept and applied on top of the new doc state.
+    for key in update_keys.iter() {
+      self.remove(key)?;
+    }
+    self.insert(doc_state_key, &new_doc_state)?;
+    self.insert(make_state_vector_key(doc_id), sv)?;
+
+    tracing::trace!(
+      "[🙂Client {}] => [{}:{:?}] compact {} updates: {} -> {} bytes",
+      uid,
+      doc_id,
+      object_id,
+      updates.len(),
+      bytes_before,
+      new_doc_state.len()
+    );
+    Ok(CompactionResult {
+      merged_updates: updates.len(),
+      bytes_before,
+      bytes_after: new_doc_state.len(),
+    })
+  }
+}
+
+/// The database that the [UpdateLogCompactor] runs against. Each call should run in its own
+/// transaction.
+pub trait CompactionStore: Send + Sync + 'static {
+  fn update_log_stats(&self, uid: i64, object_id: &str)
+    -> Result<UpdateLogStats, PersistenceError>;
+
+  fn compact_doc(&self, uid: i64, object_id: &str) -> Result<CompactionResult, PersistenceError>;
+}
+
+impl<DB> CompactionStore for DB
+where
+  DB: KVTransactionDB,
+{
+  fn update_log_stats(
+    &self,
+    uid: i64,
+    object_id: &str,
+  ) -> Result<UpdateLogStats, PersistenceError> {
+    CompactAction::update_log_stats(&self.read_txn(), uid, object_id)
+  }
+
+  fn compact_doc(&self, uid: i64, object_id: &str) -> Result<CompactionResult, PersistenceError> {
+    self.with_write_txn(|w_db_txn| CompactAction::compact_doc(w_db_txn, uid, object_id))
+  }
+}
+
+/// The accumulated statistics of an [UpdateLogCompactor].
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct CompactionStats {
+  /// The number of compactions that succeeded.
+  pub compactions: u64,
+  /// The number of compactions that failed.
+  pub failures: u64,
+  /// The number of updates that were merged into document states.
+  pub merged_updates: u64,
+  /// The total size of the document states and updates before compacting.
+  pub bytes_before: u64,
+  /// The total size of the document states after compacting.
+  pub bytes_after: u64,
+}
+
+impl CompactionStats {
+  fn did_compact(&mut self, result: &CompactionResult) {
+    self.compactions += 1;
+    self.merged_updates += result.merged_updates as u64;
+    self.bytes_before += result.bytes_before as u64;
+    self.bytes_after += result.bytes_after as u64;
+  }
+}
+
+enum CompactionCommand {
+  DidPushUpdate {
+    uid: i64,
+    object_id: String,
+    update_len: usize,
+  },
+  Compact {
+    uid: i64,
+    object_id: String,
+  },
+  Stop,
+}
+
+/// Compact the update log of the documents in the background.
+///
+/// The compactor keeps track of the number and the size of the updates that are pushed for
+/// each document. Once one of the thresholds in [CompactionConfig] is crossed, the updates are
+/// merged into the document state on a background thread. The thread stops when
+/// [UpdateLogCompactor::stop] is called, when the compactor is dropped, or when the store is
+/// dropped.
+pub struct UpdateLogCompactor {
+  sender: UnboundedSender<CompactionCommand>,
+  stats: Arc<RwLock<CompactionStats>>,
+  thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl UpdateLogCompactor {
+  pub fn new<S: CompactionStore>(store: Weak<S>, config: CompactionConfig) -> Self {
+    let (sender, receiver) = unbounded_channel();
+    let stats = Arc::new(RwLock::new(CompactionStats::default()));
+    let cloned_stats = stats.clone();
+    let thread = thread::spawn(move || run_compaction(store, config, receiver, cloned_stats));
+    Self {
+      sender,
+      stats,
+      thread: Mutex::new(Some(thread)),
+    }
+  }
+
+  /// Notify the compactor that an update was pushed for the given document. It should be called
+  /// after the update is committed.
+  pub fn did_push_update(&self, uid: i64, object_id: &str, update_len: usize) {
+    let _ = self.sender.send(CompactionCommand::DidPushUpdate {
+      uid,
+      object_id: object_id.to_string(),
+      update_len,
+    });
+  }
+
+  /// Compact the given document regardless of the thresholds.
+  pub fn compact(&self, uid: i64, object_id: &str) {
+    let _ = self.sender.send(CompactionCommand::Compact {
+      uid,
+      object_id: object_id.to_string(),
+    });
+  }
+
+  pub fn stats(&self) -> CompactionStats {
+    self.stats.read().clone()
+  }
+
+  /// Stop the background thread and wait for it to exit. The commands sent before stopping are
+  /// processed first, so a compaction that was triggered is never abandoned halfway. The
+  /// commands sent after stopping are ignored.
+  pub fn stop(&self) {
+    let _ = self.sender.send(CompactionCommand::Stop);
+    if let Some(thread) = self.thread.lock().take() {
+      if thread.join().is_err() {
+        tracing::error!("🔴compaction thread panicked");
+      }
+    }
+  }
+}
+
+impl Drop for UpdateLogCompactor {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+fn run_compaction<S: CompactionStore>(
+  store: Weak<S>,
+  config: CompactionConfig,
+  mut receiver: UnboundedReceiver<CompactionCommand>,
+  stats: Arc<RwLock<CompactionStats>>,
+) {
+  // The update log of each document since the last compaction.
+  let mut update_logs: HashMap<(i64, String), UpdateLogStats> = HashMap::new();
+  while let Some(command) = receiver.blocking_recv() {
+    let store = match store.upgrade() {
+      None => break,
+      Some(store) => store,
+    };
+
+    let (uid, object_id) = match command {
+      CompactionCommand::DidPushUpdate {
+        uid,
+        object_id,
+        update_len,
+      } => {
+        let key = (uid, object_id);
+        match update_logs.get_mut(&key) {
+          Some(update_log) => {
+            update_log.update_count += 1;
+            update_log.update_bytes += update_len;
+          },
+          None => {
+            // The update is already committed, so it's included in the stored update log.
+            match store.update_log_stats(uid, &key.1) {
+              Ok(update_log) => {
+                update_logs.insert(key.clone(), update_log);
+              },
+              Err(e) => {
+                tracing::error!("🔴read update log of {} failed: {:?}", key.1, e);
+                continue;
+              },
+            }
+          },
+        }
+        if !config.is_exceeded(&update_logs[&key]) {
+          continue;
+        }
+        key
+      },
+      CompactionCommand::Compact { uid, object_id } => (uid, object_id),
+      CompactionCommand::Stop => break,
+    };
+
+    match store.compact_doc(uid, &object_id) {
+      Ok(result) => {
+        tracing::debug!(
+          "compact {}: {} updates, {} -> {} bytes",
+          object_id,
+          result.merged_updates,
+          result.bytes_before,
+          result.bytes_after
+        );
+        stats.write().did_compact(&result);
+      },
+      Err(e) => {
+        tracing::error!("🔴compact {} failed: {:?}", object_id, e);
+        stats.write().failures += 1;
+      },
+    }
+    // Start counting again. If the compaction failed, it will be retried after another
+    // threshold's worth of updates.
+    update_logs.insert((uid, object_id), UpdateLogStats::default());
+  }
+}
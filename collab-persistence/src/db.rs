@@ -3,12 +3,14 @@ use std::io::Write;
 use std::panic;
 use std::panic::AssertUnwindSafe;
 
+use collab::core::encoding::EncoderVersion;
 use smallvec::SmallVec;
 use yrs::{TransactionMut, Update};
 
 use crate::error::PersistenceError;
 use crate::keys::{
   clock_from_key, make_doc_update_key, make_snapshot_update_key, Clock, DocID, Key, SnapshotID,
+  CLOCK_LEN,
 };
 use crate::kv::{KVEntry, KVStore};
 use crate::oid::{DOC_ID_LEN, LOCAL_DOC_ID_GEN, OID};
@@ -19,26 +21,28 @@ pub fn insert_snapshot_update<'a, K, S>(
   snapshot_id: SnapshotID,
   object_id: &K,
   data: Vec<u8>,
+  encoder_version: EncoderVersion,
 ) -> Result<(), PersistenceError>
 where
   K: AsRef<[u8]> + ?Sized + Debug,
   S: KVStore<'a>,
   PersistenceError: From<<S as KVStore<'a>>::Error>,
 {
-  let snapshot = CollabSnapshot::new(data).to_vec();
+  let snapshot = CollabSnapshot::new_with_encoder_version(data, encoder_version).to_vec();
   let update_key = create_update_key(snapshot_id, store, object_id, make_snapshot_update_key)?;
   store.insert(update_key, snapshot)?;
   Ok(())
 }
 
-pub fn insert_doc_update<'a, K, S>(
+pub fn insert_doc_update<'a, K, V, S>(
   db: &S,
   doc_id: DocID,
   object_id: &K,
-  value: Vec<u8>,
+  value: V,
 ) -> Result<Vec<u8>, PersistenceError>
 where
   K: AsRef<[u8]> + ?Sized + Debug,
+  V: AsRef<[u8]>,
   S: KVStore<'a>,
   PersistenceError: From<<S as KVStore<'a>>::Error>,
 {
@@ -66,7 +70,7 @@ where
   Ok(make_update_key(id, last_clock))
 }
 
-fn create_update_key<'a, F, K, S>(
+pub(crate) fn create_update_key<'a, F, K, S>(
   id: OID,
   store: &S,
   _object_id: &K,
@@ -96,11 +100,15 @@ where
   PersistenceError: From<<S as KVStore<'a>>::Error>,
 {
   let max_key = make_update_key(id, Clock::MAX);
-  if let Ok(Some(entry)) = store.next_back_entry(max_key.as_ref()) {
-    let clock_byte = clock_from_key(entry.key());
-    Ok(Clock::from_be_bytes(clock_byte.try_into().unwrap()))
-  } else {
-    Ok(0)
+  // The entry prior to the max key might not be an update of the given id when all of its
+  // updates were removed, for example, after flushing or compacting the document.
+  let prefix = &max_key[..max_key.len() - CLOCK_LEN - 1];
+  match store.next_back_entry(max_key.as_ref()) {
+    Ok(Some(entry)) if entry.key().len() == max_key.len() && entry.key().starts_with(prefix) => {
+      let clock_byte = clock_from_key(entry.key());
+      Ok(Clock::from_be_bytes(clock_byte.try_into().unwrap()))
+    },
+    _ => Ok(0),
   }
 }
 
@@ -1,22 +1,53 @@
 use std::fmt::Debug;
 
-use yrs::updates::decoder::Decode;
+use collab::core::encoding::{convert_update, EncoderVersion};
 use yrs::updates::encoder::Encode;
 use yrs::{Doc, ReadTxn, StateVector, Transact, Transaction, TransactionMut, Update};
 
+use crate::encoding::{doc_encoder_version, insert_doc_encoder_version, EncodingAction};
 use crate::keys::{
-  make_doc_end_key, make_doc_id_key, make_doc_start_key, make_doc_state_key, make_doc_update_key,
-  make_state_vector_key, oid_from_key, Clock, DocID, Key, DOC_SPACE, DOC_SPACE_OBJECT,
-  DOC_SPACE_OBJECT_KEY,
+  clock_from_key, make_doc_end_key, make_doc_id_key, make_doc_start_key, make_doc_state_key,
+  make_doc_update_key, make_state_vector_key, oid_from_key, Clock, DocID, Key, DOC_SPACE,
+  DOC_SPACE_OBJECT, DOC_SPACE_OBJECT_KEY,
 };
 use crate::kv::KVEntry;
 use crate::kv::KVStore;
+use crate::quarantine::{QuarantineAction, QuarantinedUpdate};
 use crate::snapshot::SnapshotAction;
+use crate::txn_meta::TransactionMetaAction;
+use crate::version::VersionAction;
 use crate::{
   get_id_for_key, get_last_update_key, insert_doc_update, make_doc_id_for_key, PersistenceError,
   TransactionMutExt,
 };
 
+/// What happened when loading a document with [YrsDocAction::load_doc_with_txn].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+  /// The number of updates that were applied before the first quarantined update.
+  pub update_count: u32,
+  /// The error of the document state if it couldn't be applied.
+  pub doc_state_error: Option<String>,
+  /// The first update that couldn't be applied and all the updates after it.
+  pub quarantined: Vec<QuarantinedUpdate>,
+}
+
+impl LoadReport {
+  /// Return true if the document state and all the updates were applied.
+  pub fn is_clean(&self) -> bool {
+    self.doc_state_error.is_none() && self.quarantined.is_empty()
+  }
+
+  /// Return the number of quarantined updates that were applied anyway.
+  pub fn recovered_count(&self) -> usize {
+    self
+      .quarantined
+      .iter()
+      .filter(|update| update.error.is_none())
+      .count()
+  }
+}
+
 pub trait DocTransaction: Send + Sync {
   fn doc_transaction(&self) -> Transaction;
   fn doc_transaction_mut(&self) -> TransactionMut;
@@ -42,7 +73,8 @@ pub trait YrsDocAction<'a>: KVStore<'a> + Sized
 where
   PersistenceError: From<<Self as KVStore<'a>>::Error>,
 {
-  /// Create a new document with the given object id.
+  /// Create a new document with the given object id. The document is encoded with the encoder
+  /// version of the store, see [EncodingAction::get_encoder_version].
   fn create_new_doc<K: AsRef<[u8]> + ?Sized + Debug, T: ReadTxn>(
     &self,
     uid: i64,
@@ -61,7 +93,8 @@ where
       object_id,
       doc_id
     );
-    let doc_state = txn.encode_diff_v1(&StateVector::default());
+    let encoder_version = self.get_encoder_version()?;
+    let doc_state = encoder_version.encode_state_as_update(txn, &StateVector::default());
     let sv = txn.state_vector().encode_v1();
     let doc_state_key = make_doc_state_key(doc_id);
     let sv_key = make_state_vector_key(doc_id);
@@ -75,13 +108,15 @@ where
     );
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, sv)?;
+    insert_doc_encoder_version(self, doc_id, encoder_version)?;
 
     Ok(())
   }
 
   /// Load the document from the database and apply the updates to the transaction.
   /// After loading the document, it will delete the document state vec and updates and
-  /// insert the new document state.
+  /// insert the new document state. The new document state is encoded with the encoder version
+  /// of the store.
   fn flush_doc_with_txn<K: AsRef<[u8]> + ?Sized + Debug, T: ReadTxn>(
     &self,
     uid: i64,
@@ -96,7 +131,8 @@ where
       object_id
     );
 
-    let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+    let encoder_version = self.get_encoder_version()?;
+    let doc_state = encoder_version.encode_state_as_update(txn, &StateVector::default());
     let sv = txn.state_vector().encode_v1();
 
     // Remove the updates
@@ -117,6 +153,7 @@ where
     // Insert new doc state and state vector
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, sv)?;
+    insert_doc_encoder_version(self, doc_id, encoder_version)?;
     Ok(())
   }
 
@@ -139,24 +176,30 @@ where
   ///   1. D = document state + updates
   ///   2. D = document state + snapshot + updates
   ///
-  /// Return the number of updates
+  /// If an update can't be decoded or applied, the update and all the updates after it are
+  /// reported in the [LoadReport::quarantined]. The updates after it are still applied one by
+  /// one. Nothing is removed from the database, call [QuarantineAction::quarantine_updates] with
+  /// the report in a write transaction to move them out of the update log.
   fn load_doc_with_txn<K: AsRef<[u8]> + ?Sized + Debug>(
     &self,
     uid: i64,
     object_id: &K,
     txn: &mut TransactionMut,
-  ) -> Result<u32, PersistenceError> {
-    let mut update_count = 0;
+  ) -> Result<LoadReport, PersistenceError> {
+    let mut report = LoadReport::default();
 
     if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let encoder_version = doc_encoder_version(self, doc_id)?;
       let doc_state_key = make_doc_state_key(doc_id);
       if let Some(doc_state) = self.get(doc_state_key.as_ref())? {
         // Load the doc state
-        if let Err(e) = Update::decode_v1(doc_state.as_ref())
+        if let Err(e) = encoder_version
+          .decode_update(doc_state.as_ref())
           .map_err(PersistenceError::Yrs)
           .and_then(|update| txn.try_apply_update(update))
         {
-          tracing::error!("🔴{:?} apply doc state error: {}", object_id, e)
+          tracing::error!("🔴{:?} apply doc state error: {}", object_id, e);
+          report.doc_state_error = Some(e.to_string());
         }
 
         // If the enable_snapshot is true, we will try to load the snapshot.
@@ -166,17 +209,27 @@ where
         // Load the updates
         let encoded_updates = self.range(update_start.as_ref()..update_end.as_ref())?;
         for encoded_update in encoded_updates {
-          // Decode the update and apply it to the transaction. If the update is invalid, we will
-          // remove the update and the following updates.
-          if let Err(e) = Update::decode_v1(encoded_update.value())
+          // Decode the update and apply it to the transaction. Once an update is invalid, it
+          // and the following updates are quarantined.
+          let result = encoder_version
+            .decode_update(encoded_update.value())
             .map_err(PersistenceError::Yrs)
-            .and_then(|update| txn.try_apply_update(update))
-          {
-            tracing::error!("🔴{:?} apply update error: {}", object_id, e);
-            self.remove_range(encoded_update.key().as_ref(), update_end.as_ref())?;
-            break;
+            .and_then(|update| txn.try_apply_update(update));
+          let clock =
+            Clock::from_be_bytes(clock_from_key(encoded_update.key()).try_into().unwrap());
+          match result {
+            Ok(_) if report.quarantined.is_empty() => report.update_count += 1,
+            Ok(_) => report
+              .quarantined
+              .push(QuarantinedUpdate { clock, error: None }),
+            Err(e) => {
+              tracing::error!("🔴{:?} apply update:{} error: {}", object_id, clock, e);
+              report.quarantined.push(QuarantinedUpdate {
+                clock,
+                error: Some(e.to_string()),
+              });
+            },
           }
-          update_count += 1;
         }
       } else {
         tracing::error!(
@@ -185,7 +238,7 @@ where
           object_id
         );
       }
-      Ok(update_count)
+      Ok(report)
     } else {
       tracing::trace!("[🙂Client] => {:?} not exist", object_id);
       Err(PersistenceError::DocumentNotExist)
@@ -197,7 +250,7 @@ where
     uid: i64,
     object_id: &K,
     doc: Doc,
-  ) -> Result<u32, PersistenceError> {
+  ) -> Result<LoadReport, PersistenceError> {
     let mut txn = doc.transact_mut();
     self.load_doc_with_txn(uid, object_id, &mut txn)
   }
@@ -238,7 +291,8 @@ where
   //   Some(snapshot.update_key)
   // }
 
-  /// Push an update to the persistence
+  /// Push an update to the persistence. The update is encoded with the v1 encoding, it's
+  /// converted to the encoder version of the document before being stored.
   fn push_update<K: AsRef<[u8]> + ?Sized + Debug>(
     &self,
     uid: i64,
@@ -253,7 +307,16 @@ where
         );
         Err(PersistenceError::DocumentNotExist)
       },
-      Some(doc_id) => insert_doc_update(self, doc_id, object_id, update.to_vec()),
+      Some(doc_id) => {
+        // The update is stored as it is if the document is encoded with the v1 encoding.
+        match doc_encoder_version(self, doc_id)? {
+          EncoderVersion::V1 => insert_doc_update(self, doc_id, object_id, update),
+          encoder_version => {
+            let update = convert_update(update, EncoderVersion::V1, encoder_version)?;
+            insert_doc_update(self, doc_id, object_id, update)
+          },
+        }
+      },
     }
   }
 
@@ -284,6 +347,8 @@ where
     Ok(())
   }
 
+  /// Replace the document with the given document state, which is encoded with the v1 encoding.
+  /// It's stored with the encoder version of the store.
   fn flush_doc_with<K: AsRef<[u8]> + ?Sized + Debug>(
     &self,
     uid: i64,
@@ -291,6 +356,8 @@ where
     doc_state: &[u8],
     sv: &[u8],
   ) -> Result<(), PersistenceError> {
+    let encoder_version = self.get_encoder_version()?;
+    let doc_state = convert_update(doc_state, EncoderVersion::V1, encoder_version)?;
     let doc_id = get_or_create_did(uid, self, object_id)?;
     let start = make_doc_start_key(doc_id);
     let end = make_doc_end_key(doc_id);
@@ -302,21 +369,28 @@ where
     // Insert new doc state and state vector
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, sv)?;
+    insert_doc_encoder_version(self, doc_id, encoder_version)?;
     Ok(())
   }
 
+  /// Return all the updates of the given document, encoded with the v1 encoding.
   fn get_all_updates<K: AsRef<[u8]> + ?Sized + Debug>(
     &self,
     uid: i64,
     object_id: &K,
   ) -> Result<Vec<Vec<u8>>, PersistenceError> {
     if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let encoder_version = doc_encoder_version(self, doc_id)?;
       let start = make_doc_update_key(doc_id, 0);
       let end = make_doc_update_key(doc_id, Clock::MAX);
       let range = self.range(start.as_ref()..end.as_ref())?;
       let mut updates = vec![];
       for update in range {
-        updates.push(update.value().to_vec());
+        updates.push(convert_update(
+          update.value(),
+          encoder_version,
+          EncoderVersion::V1,
+        )?);
       }
       Ok(updates)
     } else {
@@ -349,6 +423,15 @@ where
 
       // Delete the snapshot
       self.delete_all_snapshots(uid, object_id)?;
+
+      // Delete the versions, they can't be materialized without the document
+      self.delete_all_versions(uid, object_id)?;
+
+      // Delete the quarantined updates
+      self.delete_quarantined_updates(uid, object_id)?;
+
+      // Delete the metadata of the transactions
+      self.delete_all_txn_metas(uid, object_id)?;
     }
     Ok(())
   }
@@ -370,13 +453,14 @@ where
     object_id: &K,
   ) -> Result<Vec<Update>, PersistenceError> {
     if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let encoder_version = doc_encoder_version(self, doc_id)?;
       let start = make_doc_update_key(doc_id, 0);
       let end = make_doc_update_key(doc_id, Clock::MAX);
 
       let mut updates = vec![];
       if let Ok(encoded_updates) = self.range(start.as_ref()..=end.as_ref()) {
         for encoded_update in encoded_updates {
-          updates.push(Update::decode_v1(encoded_update.value())?);
+          updates.push(encoder_version.decode_update(encoded_update.value())?);
         }
       }
       Ok(updates)
@@ -429,7 +513,7 @@ where
   }
 }
 
-fn get_doc_id<'a, K, S>(collab_id: i64, store: &S, object_id: &K) -> Option<DocID>
+pub(crate) fn get_doc_id<'a, K, S>(collab_id: i64, store: &S, object_id: &K) -> Option<DocID>
 where
   S: KVStore<'a>,
   K: AsRef<[u8]> + ?Sized,
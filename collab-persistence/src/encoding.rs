@@ -0,0 +1,177 @@
+use std::fmt::Debug;
+
+use collab::core::encoding::{convert_update, EncoderVersion};
+
+use crate::doc::get_doc_id;
+use crate::keys::{
+  make_doc_encoder_version_key, make_doc_state_key, make_doc_update_key, make_encoder_version_key,
+  Clock, DocID, Key, DOC_SPACE, DOC_SPACE_OBJECT, DOC_SPACE_OBJECT_KEY,
+};
+use crate::kv::{KVEntry, KVStore};
+use crate::quarantine::convert_quarantined_updates;
+use crate::PersistenceError;
+
+impl<'a, T> EncodingAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// The documents of a store can be encoded with different [EncoderVersion]s. The store keeps
+/// the version of each document, so the documents encoded with the v1 encoding are still read
+/// after the encoder version of the store is changed. The new documents and the documents that
+/// are flushed are encoded with the encoder version of the store.
+///
+/// The updates passed to [crate::doc::YrsDocAction::push_update] are always encoded with the v1 encoding.
+/// They are converted to the encoder version of the document before being stored.
+pub trait EncodingAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Return the encoder version used to encode the new documents. It's [EncoderVersion::V1] if
+  /// it was never set.
+  fn get_encoder_version(&self) -> Result<EncoderVersion, PersistenceError> {
+    match self.get(make_encoder_version_key().as_ref())? {
+      None => Ok(EncoderVersion::V1),
+      Some(value) => decode_encoder_version(value.as_ref()),
+    }
+  }
+
+  /// Set the encoder version used to encode the new documents. The existing documents keep
+  /// their encoder version until they are flushed or converted with [EncodingAction::convert_doc].
+  fn set_encoder_version(&self, version: EncoderVersion) -> Result<(), PersistenceError> {
+    self.insert(make_encoder_version_key(), [u8::from(version)])?;
+    Ok(())
+  }
+
+  /// Return the encoder version of the given document.
+  fn get_doc_encoder_version<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<EncoderVersion, PersistenceError> {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    doc_encoder_version(self, doc_id)
+  }
+
+  /// Re-encode the document state, the updates and the quarantined updates of the given document
+  /// with the given version. Return false if the document was already encoded with the version.
+  ///
+  /// The quarantined updates that can't be decoded keep their encoder version, so they can still
+  /// be repaired with [crate::quarantine::QuarantineAction::reimport_quarantined_updates].
+  fn convert_doc<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+    version: EncoderVersion,
+  ) -> Result<bool, PersistenceError> {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    let current = doc_encoder_version(self, doc_id)?;
+    if current == version {
+      return Ok(false);
+    }
+
+    let doc_state_key = make_doc_state_key(doc_id);
+    let mut entries = self
+      .get(doc_state_key.as_ref())?
+      .map(|doc_state| vec![(doc_state_key.to_vec(), doc_state.as_ref().to_vec())])
+      .unwrap_or_default();
+    let start = make_doc_update_key(doc_id, 0);
+    let end = make_doc_update_key(doc_id, Clock::MAX);
+    entries.extend(
+      self
+        .range(start.as_ref()..end.as_ref())?
+        .map(|entry| (entry.key().to_vec(), entry.value().to_vec())),
+    );
+
+    // Convert all the entries before writing them, so the document is left untouched if one of
+    // them can't be decoded.
+    let mut converted = Vec::with_capacity(entries.len());
+    for (key, value) in entries {
+      converted.push((key, convert_update(&value, current, version)?));
+    }
+    for (key, value) in converted {
+      self.insert(key, value)?;
+    }
+    convert_quarantined_updates(self, doc_id, current, version)?;
+    insert_doc_encoder_version(self, doc_id, version)?;
+    tracing::trace!(
+      "[🙂Client {}] => [{}:{:?}] convert doc from {} to {}",
+      uid,
+      doc_id,
+      object_id,
+      current,
+      version
+    );
+    Ok(true)
+  }
+
+  /// Convert all the documents of the given user with [EncodingAction::convert_doc] and return
+  /// the number of converted documents.
+  fn convert_all_docs(&self, uid: i64, version: EncoderVersion) -> Result<usize, PersistenceError> {
+    let uid_bytes = uid.to_be_bytes();
+    let from = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT]);
+    let to = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY]);
+    let object_ids = self
+      .range(from.as_ref()..to.as_ref())?
+      .filter(|entry| entry.key()[2..].starts_with(&uid_bytes))
+      .map(|entry| {
+        let key = entry.key();
+        key[2 + uid_bytes.len()..key.len() - 1].to_vec()
+      })
+      .collect::<Vec<_>>();
+
+    let mut count = 0;
+    for object_id in object_ids {
+      if self.convert_doc(uid, &object_id, version)? {
+        count += 1;
+      }
+    }
+    Ok(count)
+  }
+}
+
+/// Return the encoder version of the document state and the updates of the given document.
+pub(crate) fn doc_encoder_version<'a, S>(
+  store: &S,
+  doc_id: DocID,
+) -> Result<EncoderVersion, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  match store.get(make_doc_encoder_version_key(doc_id).as_ref())? {
+    None => Ok(EncoderVersion::V1),
+    Some(value) => decode_encoder_version(value.as_ref()),
+  }
+}
+
+/// Record the encoder version of the given document. The v1 encoding is the default, so nothing
+/// is stored for it.
+pub(crate) fn insert_doc_encoder_version<'a, S>(
+  store: &S,
+  doc_id: DocID,
+  version: EncoderVersion,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let key = make_doc_encoder_version_key(doc_id);
+  match version {
+    EncoderVersion::V1 => store.remove(key.as_ref())?,
+    _ => store.insert(key, [u8::from(version)])?,
+  }
+  Ok(())
+}
+
+pub(crate) fn decode_encoder_version(value: &[u8]) -> Result<EncoderVersion, PersistenceError> {
+  match value {
+    [version] => Ok(EncoderVersion::try_from(*version)?),
+    _ => Err(PersistenceError::InvalidData(format!(
+      "invalid encoder version: {:?}",
+      value
+    ))),
+  }
+}
@@ -8,9 +8,16 @@ pub enum PersistenceError {
   #[error(transparent)]
   RocksDb(#[from] rocksdb::Error),
 
+  #[cfg(feature = "sqlite_persistence")]
+  #[error(transparent)]
+  Sqlite(#[from] rusqlite::Error),
+
   #[error(transparent)]
   Bincode(#[from] bincode::Error),
 
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+
   #[error("The document is not exist")]
   DocumentNotExist,
 
@@ -32,6 +39,12 @@ pub enum PersistenceError {
   #[error("Can't find the latest update key")]
   LatestUpdateKeyNotExist,
 
+  #[error("The encryption key {key_id} of the workspace {workspace_id} is not found")]
+  EncryptionKeyNotFound { workspace_id: String, key_id: u32 },
+
+  #[error("encryption error: {0}")]
+  Encryption(String),
+
   #[error("Internal failure: {0}")]
   Internal(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
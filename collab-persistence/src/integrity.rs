@@ -0,0 +1,479 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::panic;
+
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, StateVector, Transact, Update};
+
+use collab::core::encoding::EncoderVersion;
+use collab::core::version::CollabVersion;
+
+use crate::compact::CompactAction;
+use crate::doc::LoadReport;
+use crate::encoding::doc_encoder_version;
+use crate::keys::{
+  make_doc_state_key, make_state_vector_key, Clock, DocID, Key, CLOCK_LEN, COLLAB_SPACE,
+  COLLAB_SPACE_OBJECT, DOC_ID_LEN, DOC_SPACE, DOC_SPACE_OBJECT, DOC_SPACE_OBJECT_KEY, DOC_STATE,
+  DOC_STATE_VEC, DOC_UPDATE, DOC_UPDATE_KEY_LEN, QUARANTINE_SPACE, QUARANTINE_SPACE_OBJECT_KEY,
+  SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, SNAPSHOT_UPDATE, SNAPSHOT_UPDATE_KEY_LEN, TERMINATOR,
+  VERSION_SPACE, VERSION_SPACE_OBJECT, VERSION_SPACE_OBJECT_KEY,
+};
+use crate::kv::{KVEntry, KVStore};
+use crate::quarantine::{QuarantineAction, QuarantinedUpdate};
+use crate::snapshot::CollabSnapshot;
+use crate::PersistenceError;
+
+/// The length of the doc state and the state vector keys: [1,1, doc_id, tag].
+const DOC_STATE_KEY_SIZE: usize = 2 + DOC_ID_LEN + 1;
+
+/// The result of [IntegrityAction::verify_integrity].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+  /// The number of documents that were checked.
+  pub checked_docs: usize,
+  pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+  /// Return true if no issue was found.
+  pub fn is_ok(&self) -> bool {
+    self.issues.is_empty()
+  }
+}
+
+/// The result of [IntegrityAction::repair_integrity].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+  pub repaired: Vec<IntegrityIssue>,
+  /// The issues that can't be repaired without losing data. They are left untouched.
+  pub unrepaired: Vec<IntegrityIssue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssue {
+  /// A key whose id is not mapped by any object id. It can't be reached, so it's never read nor
+  /// deleted.
+  OrphanedKey { key: Vec<u8> },
+  /// The id of an object id mapping is not a valid id.
+  InvalidId { key: Vec<u8> },
+  /// The document has no doc state.
+  MissingDocState {
+    uid: i64,
+    object_id: String,
+    doc_id: DocID,
+  },
+  /// The doc state can't be decoded.
+  UndecodableDocState {
+    uid: i64,
+    object_id: String,
+    doc_id: DocID,
+  },
+  /// The document has a doc state, but no state vector.
+  MissingStateVector {
+    uid: i64,
+    object_id: String,
+    doc_id: DocID,
+  },
+  /// The state vector is not the state vector of the doc state.
+  StateVectorMismatch {
+    uid: i64,
+    object_id: String,
+    doc_id: DocID,
+  },
+  /// An update of the update log can't be decoded.
+  UndecodableUpdate {
+    uid: i64,
+    object_id: String,
+    doc_id: DocID,
+    clock: Clock,
+  },
+  /// A snapshot can't be decoded.
+  UndecodableSnapshot { key: Vec<u8> },
+  /// A version can't be decoded.
+  UndecodableVersion { key: Vec<u8> },
+}
+
+impl IntegrityIssue {
+  /// Return true if [IntegrityAction::repair_integrity] can fix the issue. The undecodable doc
+  /// states and the invalid ids are kept, because fixing them would drop the whole document.
+  pub fn is_repairable(&self) -> bool {
+    !matches!(
+      self,
+      IntegrityIssue::UndecodableDocState { .. } | IntegrityIssue::InvalidId { .. }
+    )
+  }
+}
+
+impl<'a, T> IntegrityAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Checks that the different kinds of keys of the store agree with each other. Verifying and
+/// repairing should happen in the same write transaction, so the store doesn't change in between:
+///
+/// ```ignore
+/// db.with_write_txn(|w| {
+///   let report = w.verify_integrity()?;
+///   w.repair_integrity(&report)
+/// })
+/// ```
+pub trait IntegrityAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Scan the whole store and return the issues that were found. The store is not modified.
+  fn verify_integrity(&self) -> Result<IntegrityReport, PersistenceError> {
+    let mut issues = vec![];
+    let docs = read_id_mappings(self, DOC_SPACE, DOC_SPACE_OBJECT, &mut issues)?;
+    let doc_ids = docs
+      .iter()
+      .map(|mapping| (mapping.id, mapping))
+      .collect::<HashMap<_, _>>();
+    // An invalid encoder version is read as the v1 encoding, so the entries of the document are
+    // reported as undecodable.
+    let encoder_versions = docs
+      .iter()
+      .map(|mapping| {
+        let version = doc_encoder_version(self, mapping.id).unwrap_or_default();
+        (mapping.id, version)
+      })
+      .collect::<HashMap<_, _>>();
+
+    // The doc states and the state vectors are checked once all the keys of the documents are
+    // read, so a missing doc state can be told apart from an orphaned one.
+    let mut doc_states = HashMap::new();
+    let mut state_vectors = HashMap::new();
+    let start = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY]);
+    let end = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY + 1]);
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      let key = entry.key();
+      let mapping = match id_from_key(key).and_then(|doc_id| doc_ids.get(&doc_id)) {
+        None => {
+          issues.push(IntegrityIssue::OrphanedKey { key: key.to_vec() });
+          continue;
+        },
+        Some(mapping) => mapping,
+      };
+      match (key.len(), key[2 + DOC_ID_LEN]) {
+        (DOC_STATE_KEY_SIZE, DOC_STATE) => {
+          doc_states.insert(mapping.id, entry.value().to_vec());
+        },
+        (DOC_STATE_KEY_SIZE, DOC_STATE_VEC) => {
+          state_vectors.insert(mapping.id, entry.value().to_vec());
+        },
+        (DOC_UPDATE_KEY_LEN, DOC_UPDATE) => {
+          if !is_valid_update(entry.value(), encoder_versions[&mapping.id]) {
+            issues.push(IntegrityIssue::UndecodableUpdate {
+              uid: mapping.uid,
+              object_id: mapping.object_id.clone(),
+              doc_id: mapping.id,
+              clock: clock_from_update_key(key),
+            });
+          }
+        },
+        _ => {},
+      }
+    }
+
+    for mapping in docs.iter() {
+      let (uid, object_id, doc_id) = (mapping.uid, mapping.object_id.clone(), mapping.id);
+      let doc_state = match doc_states.get(&doc_id) {
+        None => {
+          issues.push(IntegrityIssue::MissingDocState {
+            uid,
+            object_id,
+            doc_id,
+          });
+          continue;
+        },
+        Some(doc_state) => doc_state,
+      };
+      let expected_sv = match decode_update(doc_state, encoder_versions[&doc_id]) {
+        None => {
+          issues.push(IntegrityIssue::UndecodableDocState {
+            uid,
+            object_id,
+            doc_id,
+          });
+          continue;
+        },
+        Some(update) => update.state_vector(),
+      };
+      match state_vectors.get(&doc_id) {
+        None => issues.push(IntegrityIssue::MissingStateVector {
+          uid,
+          object_id,
+          doc_id,
+        }),
+        Some(sv) => {
+          if StateVector::decode_v1(sv).ok().as_ref() != Some(&expected_sv) {
+            issues.push(IntegrityIssue::StateVectorMismatch {
+              uid,
+              object_id,
+              doc_id,
+            });
+          }
+        },
+      }
+    }
+
+    let start = Key::from_const([QUARANTINE_SPACE, QUARANTINE_SPACE_OBJECT_KEY]);
+    let end = Key::from_const([QUARANTINE_SPACE, QUARANTINE_SPACE_OBJECT_KEY + 1]);
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      if id_from_key(entry.key()).map_or(true, |doc_id| !doc_ids.contains_key(&doc_id)) {
+        issues.push(IntegrityIssue::OrphanedKey {
+          key: entry.key().to_vec(),
+        });
+      }
+    }
+
+    verify_snapshots(self, &mut issues)?;
+    verify_versions(self, &mut issues)?;
+
+    // The collab ids are not referenced by any other key, so only their value is checked.
+    let start = Key::from_const([COLLAB_SPACE, COLLAB_SPACE_OBJECT]);
+    let end = Key::from_const([COLLAB_SPACE, COLLAB_SPACE_OBJECT + 1]);
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      if entry.value().len() != DOC_ID_LEN {
+        issues.push(IntegrityIssue::InvalidId {
+          key: entry.key().to_vec(),
+        });
+      }
+    }
+
+    Ok(IntegrityReport {
+      checked_docs: docs.len(),
+      issues,
+    })
+  }
+
+  /// Fix the repairable issues of the report:
+  ///
+  /// * the orphaned keys, the undecodable snapshots and the undecodable versions are removed.
+  /// * the undecodable updates are moved to the quarantine.
+  /// * a missing doc state is rebuilt from the update log, or created empty if there are no
+  ///   updates.
+  /// * a missing or mismatched state vector is rewritten from the doc state.
+  fn repair_integrity(&self, report: &IntegrityReport) -> Result<RepairReport, PersistenceError> {
+    let mut repair_report = RepairReport::default();
+
+    // The undecodable updates are quarantined first, so they are not merged into a rebuilt doc
+    // state.
+    let mut quarantined: BTreeMap<(i64, &str), LoadReport> = BTreeMap::new();
+    for issue in report.issues.iter() {
+      if let IntegrityIssue::UndecodableUpdate {
+        uid,
+        object_id,
+        clock,
+        ..
+      } = issue
+      {
+        quarantined
+          .entry((*uid, object_id.as_str()))
+          .or_default()
+          .quarantined
+          .push(QuarantinedUpdate {
+            clock: *clock,
+            error: Some("undecodable update".to_string()),
+          });
+      }
+    }
+    for ((uid, object_id), load_report) in quarantined.iter() {
+      self.quarantine_updates(*uid, *object_id, load_report)?;
+    }
+
+    for issue in report.issues.iter() {
+      match issue {
+        IntegrityIssue::OrphanedKey { key }
+        | IntegrityIssue::UndecodableSnapshot { key }
+        | IntegrityIssue::UndecodableVersion { key } => {
+          self.remove(key)?;
+        },
+        IntegrityIssue::MissingDocState {
+          uid,
+          object_id,
+          doc_id,
+        } => {
+          self.compact_doc(*uid, object_id)?;
+          if self.get(make_doc_state_key(*doc_id).as_ref())?.is_none() {
+            let doc = Doc::new();
+            let doc_state = doc_encoder_version(self, *doc_id)?
+              .encode_state_as_update(&doc.transact(), &StateVector::default());
+            self.insert(make_doc_state_key(*doc_id), doc_state)?;
+            self.insert(
+              make_state_vector_key(*doc_id),
+              StateVector::default().encode_v1(),
+            )?;
+          }
+        },
+        IntegrityIssue::MissingStateVector { doc_id, .. }
+        | IntegrityIssue::StateVectorMismatch { doc_id, .. } => {
+          let encoder_version = doc_encoder_version(self, *doc_id)?;
+          let sv = self
+            .get(make_doc_state_key(*doc_id).as_ref())?
+            .and_then(|doc_state| decode_update(doc_state.as_ref(), encoder_version))
+            .map(|update| update.state_vector().encode_v1());
+          match sv {
+            None => {
+              repair_report.unrepaired.push(issue.clone());
+              continue;
+            },
+            Some(sv) => self.insert(make_state_vector_key(*doc_id), sv)?,
+          }
+        },
+        IntegrityIssue::UndecodableUpdate { .. } => {},
+        IntegrityIssue::UndecodableDocState { .. } | IntegrityIssue::InvalidId { .. } => {
+          repair_report.unrepaired.push(issue.clone());
+          continue;
+        },
+      }
+      tracing::warn!("🟡repair integrity issue: {:?}", issue);
+      repair_report.repaired.push(issue.clone());
+    }
+    Ok(repair_report)
+  }
+}
+
+/// Return the object id mappings of the given key space. The mappings whose id is not valid
+/// are reported as [IntegrityIssue::InvalidId].
+fn read_id_mappings<'a, S>(
+  store: &S,
+  space: u8,
+  object_space: u8,
+  issues: &mut Vec<IntegrityIssue>,
+) -> Result<Vec<IdMapping>, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let start = Key::from_const([space, object_space]);
+  let end = Key::from_const([space, object_space + 1]);
+  let mut mappings = vec![];
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    match IdMapping::from_entry(entry.key(), entry.value()) {
+      Some(mapping) => mappings.push(mapping),
+      None => issues.push(IntegrityIssue::InvalidId {
+        key: entry.key().to_vec(),
+      }),
+    }
+  }
+  Ok(mappings)
+}
+
+fn verify_snapshots<'a, S>(
+  store: &S,
+  issues: &mut Vec<IntegrityIssue>,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  // The snapshot updates share the key space of the snapshot id mappings, so the entries are
+  // told apart by the shape of their keys.
+  let start = Key::from_const([SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT]);
+  let end = Key::from_const([SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT + 1]);
+  let mut snapshot_ids = HashSet::new();
+  let mut snapshots = vec![];
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    let key = entry.key();
+    match IdMapping::from_entry(key, entry.value()) {
+      Some(mapping) if !is_snapshot_update_key(key) => {
+        snapshot_ids.insert(mapping.id);
+      },
+      _ => snapshots.push((key.to_vec(), entry.value().to_vec())),
+    }
+  }
+
+  for (key, value) in snapshots {
+    if !is_snapshot_update_key(&key) {
+      issues.push(IntegrityIssue::InvalidId { key });
+    } else if id_from_key(&key).map_or(true, |id| !snapshot_ids.contains(&id)) {
+      issues.push(IntegrityIssue::OrphanedKey { key });
+    } else {
+      let is_valid = CollabSnapshot::try_from(value.as_slice())
+        .map(|snapshot| is_valid_update(&snapshot.data, snapshot.encoder_version))
+        .unwrap_or(false);
+      if !is_valid {
+        issues.push(IntegrityIssue::UndecodableSnapshot { key });
+      }
+    }
+  }
+  Ok(())
+}
+
+fn verify_versions<'a, S>(
+  store: &S,
+  issues: &mut Vec<IntegrityIssue>,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let version_ids = read_id_mappings(store, VERSION_SPACE, VERSION_SPACE_OBJECT, issues)?
+    .into_iter()
+    .map(|mapping| mapping.id)
+    .collect::<HashSet<_>>();
+  let start = Key::from_const([VERSION_SPACE, VERSION_SPACE_OBJECT_KEY]);
+  let end = Key::from_const([VERSION_SPACE, VERSION_SPACE_OBJECT_KEY + 1]);
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    let key = entry.key().to_vec();
+    if id_from_key(&key).map_or(true, |id| !version_ids.contains(&id)) {
+      issues.push(IntegrityIssue::OrphanedKey { key });
+    } else if bincode::deserialize::<CollabVersion>(entry.value()).is_err() {
+      issues.push(IntegrityIssue::UndecodableVersion { key });
+    }
+  }
+  Ok(())
+}
+
+/// An object id that is mapped to an id, for example, a [DocID].
+struct IdMapping {
+  uid: i64,
+  object_id: String,
+  id: u64,
+}
+
+impl IdMapping {
+  /// The key is [space, object_space, uid, object_id, TERMINATOR] and the value is the id.
+  fn from_entry(key: &[u8], value: &[u8]) -> Option<Self> {
+    if key.len() <= 2 + 8 || key[key.len() - 1] != TERMINATOR || value.len() != DOC_ID_LEN {
+      return None;
+    }
+    Some(Self {
+      uid: i64::from_be_bytes(key[2..10].try_into().ok()?),
+      object_id: String::from_utf8_lossy(&key[10..key.len() - 1]).to_string(),
+      id: u64::from_be_bytes(value.try_into().ok()?),
+    })
+  }
+}
+
+/// Return the id of a [space, object_key_space, id, ..] key.
+fn id_from_key(key: &[u8]) -> Option<u64> {
+  let bytes = key.get(2..2 + DOC_ID_LEN)?;
+  Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn clock_from_update_key(key: &[u8]) -> Clock {
+  let bytes = &key[key.len() - CLOCK_LEN - 1..key.len() - 1];
+  Clock::from_be_bytes(bytes.try_into().unwrap())
+}
+
+fn is_snapshot_update_key(key: &[u8]) -> bool {
+  key.len() == SNAPSHOT_UPDATE_KEY_LEN
+    && key[2 + DOC_ID_LEN] == SNAPSHOT_UPDATE
+    && key[key.len() - 1] == TERMINATOR
+}
+
+fn is_valid_update(data: &[u8], encoder_version: EncoderVersion) -> bool {
+  decode_update(data, encoder_version).is_some()
+}
+
+/// Decoding a corrupted update might panic in yrs, so the panic is treated as a decoding error.
+fn decode_update(data: &[u8], encoder_version: EncoderVersion) -> Option<Update> {
+  panic::catch_unwind(|| encoder_version.decode_update(data).ok())
+    .ok()
+    .flatten()
+}
@@ -17,10 +17,28 @@ use smallvec::{smallvec, SmallVec};
 //     DOC_SPACE_OBJECT_KEY     doc_id      TERMINATOR_HI_WATERMARK (state end)
 //     DOC_SPACE_OBJECT_KEY     doc_id      DOC_STATE_VEC (state vector)
 //     DOC_SPACE_OBJECT_KEY     doc_id      DOC_UPDATE clock TERMINATOR (update)
+//     DOC_SPACE_OBJECT_KEY     doc_id      DOC_ENCODER_VERSION (encoder version)
 //
 // SNAPSHOT_SPACE
 //     SNAPSHOT_SPACE_OBJECT        object_id       TERMINATOR
 //     SNAPSHOT_SPACE_OBJECT_KEY    snapshot_id     SNAPSHOT_UPDATE(snapshot)
+//
+// VERSION_SPACE
+//     VERSION_SPACE_OBJECT         object_id       TERMINATOR
+//     VERSION_SPACE_OBJECT_KEY     version_id      VERSION_ENTRY clock TERMINATOR (version)
+//
+// QUARANTINE_SPACE
+//     QUARANTINE_SPACE_OBJECT_KEY  doc_id          QUARANTINE_UPDATE clock TERMINATOR (update)
+//     QUARANTINE_SPACE_OBJECT_KEY  doc_id          QUARANTINE_ENCODER_VERSION clock TERMINATOR (encoder version)
+//
+// META_SPACE
+//     META_ENCODER_VERSION (encoder version of the store)
+//
+// SINK_SPACE
+//     SINK_SPACE_MSG    uid    object_id    TERMINATOR    msg_id (pending message)
+//
+// TXN_META_SPACE
+//     TXN_META_SPACE_OBJECT_KEY    doc_id    TXN_META_ENTRY clock TERMINATOR (transaction meta)
 
 /// Prefix byte used for all of the yrs object entries.
 pub const DOC_SPACE: u8 = 1;
@@ -45,6 +63,10 @@ pub const REMOTE_DOC_STATE_VEC: u8 = 2;
 /// Tag byte within [DOC_SPACE_OBJECT_KEY] used to identify object's update entries.
 pub const DOC_UPDATE: u8 = 2;
 
+/// Tag byte within [DOC_SPACE_OBJECT_KEY] used to identify the encoder version of the object's
+/// state and updates. The object is encoded with the v1 encoding if the entry doesn't exist.
+pub const DOC_ENCODER_VERSION: u8 = 3;
+
 /// Prefix byte used for snapshot id -> [SnapshotID] mapping index key space.
 pub const SNAPSHOT_SPACE: u8 = 2;
 
@@ -57,6 +79,53 @@ pub const SNAPSHOT_UPDATE: u8 = 1;
 pub const COLLAB_SPACE: u8 = 3;
 pub const COLLAB_SPACE_OBJECT: u8 = 0;
 
+/// Prefix byte used for all of the version entries.
+pub const VERSION_SPACE: u8 = 4;
+
+/// Prefix byte used for object id -> [VersionID] mapping index key space.
+pub const VERSION_SPACE_OBJECT: u8 = 0;
+/// Prefix byte used for version key space.
+pub const VERSION_SPACE_OBJECT_KEY: u8 = 1;
+
+/// Tag byte within [VERSION_SPACE_OBJECT_KEY] used to identify object's version entries.
+pub const VERSION_ENTRY: u8 = 0;
+
+/// Prefix byte used for the updates that couldn't be applied when loading the document.
+pub const QUARANTINE_SPACE: u8 = 5;
+
+/// Prefix byte used for quarantine key space. The quarantined updates are grouped by [DocID].
+pub const QUARANTINE_SPACE_OBJECT_KEY: u8 = 1;
+
+/// Tag byte within [QUARANTINE_SPACE_OBJECT_KEY] used to identify object's quarantined updates.
+pub const QUARANTINE_UPDATE: u8 = 0;
+
+/// Tag byte within [QUARANTINE_SPACE_OBJECT_KEY] used to identify the encoder version of a
+/// quarantined update. The update is encoded with the encoder version of the object if the entry
+/// doesn't exist.
+pub const QUARANTINE_ENCODER_VERSION: u8 = 1;
+
+/// Prefix byte used for the entries that describe the store itself.
+pub const META_SPACE: u8 = 6;
+
+/// Tag byte within [META_SPACE] used to identify the encoder version of the new documents.
+pub const META_ENCODER_VERSION: u8 = 0;
+
+/// Prefix byte used for the messages that are waiting to be sent to the remote.
+pub const SINK_SPACE: u8 = 7;
+
+/// Prefix byte used for the pending message key space. The messages are grouped by the uid and
+/// the object id.
+pub const SINK_SPACE_MSG: u8 = 0;
+
+/// Prefix byte used for the metadata of the local transactions.
+pub const TXN_META_SPACE: u8 = 8;
+
+/// Prefix byte used for transaction metadata key space. The entries are grouped by [DocID].
+pub const TXN_META_SPACE_OBJECT_KEY: u8 = 1;
+
+/// Tag byte within [TXN_META_SPACE_OBJECT_KEY] used to identify object's transaction metadata.
+pub const TXN_META_ENTRY: u8 = 0;
+
 pub type DocID = u64;
 pub const DOC_ID_LEN: usize = 8;
 pub const DOC_STATE_KEY_LEN: usize = DOC_ID_LEN + 4;
@@ -68,6 +137,13 @@ pub const SNAPSHOT_ID_LEN: usize = 8;
 pub const SNAPSHOT_UPDATE_KEY_LEN: usize = SNAPSHOT_ID_LEN + CLOCK_LEN + 4;
 pub const SNAPSHOT_UPDATE_KEY_PREFIX_LEN: usize = SNAPSHOT_ID_LEN + 4;
 
+pub type VersionID = u64;
+pub const VERSION_ID_LEN: usize = 8;
+pub const VERSION_KEY_LEN: usize = VERSION_ID_LEN + CLOCK_LEN + 4;
+
+/// The length of the pending message keys whose object id is a uuid.
+pub const SINK_MSG_KEY_LEN: usize = 2 + 8 + 36 + 1 + 8;
+
 pub type Clock = u32;
 pub const CLOCK_LEN: usize = 4;
 
@@ -123,6 +199,14 @@ pub fn make_remote_state_vector_key(doc_id: DocID) -> Key<DOC_STATE_KEY_LEN> {
   Key(v)
 }
 
+// [1,1,  0,0,0,0,0,0,0,0,  3]
+pub fn make_doc_encoder_version_key(doc_id: DocID) -> Key<DOC_STATE_KEY_LEN> {
+  let mut v: SmallVec<[u8; DOC_STATE_KEY_LEN]> = smallvec![DOC_SPACE, DOC_SPACE_OBJECT_KEY];
+  v.write_all(&doc_id.to_be_bytes()).unwrap();
+  v.push(DOC_ENCODER_VERSION);
+  Key(v)
+}
+
 // [1,1,  0,0,0,0,0,0,0,0,  2   0,0,0,0,  0]
 pub fn make_doc_update_key(doc_id: DocID, clock: Clock) -> Key<DOC_UPDATE_KEY_LEN> {
   let mut v: SmallVec<[u8; DOC_UPDATE_KEY_LEN]> = smallvec![DOC_SPACE, DOC_SPACE_OBJECT_KEY];
@@ -180,6 +264,79 @@ pub fn make_snapshot_update_key_prefix(
   Key(v)
 }
 
+// [4,0, uid,  object_id,  0]
+pub fn make_version_id_key(uid: &[u8], object_id: &[u8]) -> Key<20> {
+  let mut v: SmallVec<[u8; 20]> = smallvec![VERSION_SPACE, VERSION_SPACE_OBJECT];
+  v.write_all(uid).unwrap();
+  v.write_all(object_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [4,1,  0,0,0,0,0,0,0,0,  0   [0,0,0,0],  0]
+pub fn make_version_key(version_id: VersionID, clock: Clock) -> Key<VERSION_KEY_LEN> {
+  let mut v: SmallVec<[u8; VERSION_KEY_LEN]> = smallvec![VERSION_SPACE, VERSION_SPACE_OBJECT_KEY];
+  v.write_all(&version_id.to_be_bytes()).unwrap();
+  v.push(VERSION_ENTRY);
+  v.write_all(&clock.to_be_bytes()).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [5,1,  0,0,0,0,0,0,0,0,  0   [0,0,0,0],  0]
+pub fn make_quarantine_update_key(doc_id: DocID, clock: Clock) -> Key<DOC_UPDATE_KEY_LEN> {
+  let mut v: SmallVec<[u8; DOC_UPDATE_KEY_LEN]> =
+    smallvec![QUARANTINE_SPACE, QUARANTINE_SPACE_OBJECT_KEY];
+  v.write_all(&doc_id.to_be_bytes()).unwrap();
+  v.push(QUARANTINE_UPDATE);
+  v.write_all(&clock.to_be_bytes()).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [5,1,  0,0,0,0,0,0,0,0,  1   [0,0,0,0],  0]
+pub fn make_quarantine_encoder_version_key(doc_id: DocID, clock: Clock) -> Key<DOC_UPDATE_KEY_LEN> {
+  let mut v: SmallVec<[u8; DOC_UPDATE_KEY_LEN]> =
+    smallvec![QUARANTINE_SPACE, QUARANTINE_SPACE_OBJECT_KEY];
+  v.write_all(&doc_id.to_be_bytes()).unwrap();
+  v.push(QUARANTINE_ENCODER_VERSION);
+  v.write_all(&clock.to_be_bytes()).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [8,1,  0,0,0,0,0,0,0,0,  0   [0,0,0,0],  0]
+pub fn make_txn_meta_key(doc_id: DocID, clock: Clock) -> Key<DOC_UPDATE_KEY_LEN> {
+  let mut v: SmallVec<[u8; DOC_UPDATE_KEY_LEN]> =
+    smallvec![TXN_META_SPACE, TXN_META_SPACE_OBJECT_KEY];
+  v.write_all(&doc_id.to_be_bytes()).unwrap();
+  v.push(TXN_META_ENTRY);
+  v.write_all(&clock.to_be_bytes()).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [6,0]
+pub fn make_encoder_version_key() -> Key<2> {
+  Key::from_const([META_SPACE, META_ENCODER_VERSION])
+}
+
+// [7,0,  0,0,0,0,0,0,0,0,  object_id,  0]
+pub fn make_sink_msg_prefix(uid: i64, object_id: &[u8]) -> Key<SINK_MSG_KEY_LEN> {
+  let mut v: SmallVec<[u8; SINK_MSG_KEY_LEN]> = smallvec![SINK_SPACE, SINK_SPACE_MSG];
+  v.write_all(&uid.to_be_bytes()).unwrap();
+  v.write_all(object_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [7,0,  0,0,0,0,0,0,0,0,  object_id,  0,  0,0,0,0,0,0,0,0]
+pub fn make_sink_msg_key(uid: i64, object_id: &[u8], msg_id: u64) -> Key<SINK_MSG_KEY_LEN> {
+  let mut key = make_sink_msg_prefix(uid, object_id);
+  key.0.write_all(&msg_id.to_be_bytes()).unwrap();
+  key
+}
+
 pub fn make_collab_id_key(object_id: &[u8]) -> Key<20> {
   let mut v: SmallVec<[u8; 20]> = smallvec![COLLAB_SPACE, COLLAB_SPACE_OBJECT];
   v.write_all(object_id).unwrap();
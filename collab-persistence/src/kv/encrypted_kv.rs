@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use parking_lot::{Mutex, RwLock};
+
+use crate::kv::{KVEntry, KVStore, KVTransactionDB};
+use crate::PersistenceError;
+
+/// The id of an [EncryptionKey]. It's stored with each encrypted value, so the values that were
+/// encrypted with different keys can be decrypted.
+pub type KeyId = u32;
+
+/// The version of the layout of the encrypted values:
+/// [version: u8][key id: u32 big endian][nonce: 12 bytes][ciphertext and tag]
+const ENCRYPTED_VALUE_VERSION: u8 = 1;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+
+/// The number of values that are encrypted in one transaction by
+/// [EncryptedCollabDB::rotate_keys] and [EncryptedCollabDB::encrypt_existing].
+pub const REENCRYPT_BATCH_SIZE: usize = 500;
+
+/// A 256-bit key used to encrypt the values with AES-256-GCM.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+  pub fn new(bytes: [u8; 32]) -> Self {
+    Self(bytes)
+  }
+
+  /// Generate a random key with the random number generator of the operating system.
+  pub fn generate() -> Self {
+    Self(Aes256Gcm::generate_key(OsRng).into())
+  }
+
+  pub fn as_bytes(&self) -> &[u8; 32] {
+    &self.0
+  }
+}
+
+impl Debug for EncryptionKey {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("EncryptionKey(***)")
+  }
+}
+
+/// Supply the encryption keys of the workspaces. The keys are usually kept in a keychain or
+/// derived from a secret of the user, and they must not be stored in the same store as the
+/// data they encrypt.
+///
+/// A key must stay available as long as there are values encrypted with it. Call
+/// [EncryptedCollabDB::rotate_keys] after changing the current key to re-encrypt the existing
+/// values before removing the previous keys.
+pub trait KeyProvider: Send + Sync + 'static {
+  /// Return the id of the key used to encrypt the new values of the workspace.
+  fn current_key_id(&self, workspace_id: &str) -> Result<KeyId, PersistenceError>;
+
+  /// Return the key with the given id. Return [PersistenceError::EncryptionKeyNotFound] if the
+  /// key doesn't exist.
+  fn get_key(&self, workspace_id: &str, key_id: KeyId) -> Result<EncryptionKey, PersistenceError>;
+}
+
+/// A [KeyProvider] that keeps the keys in memory.
+#[derive(Default)]
+pub struct MemoryKeyProvider {
+  workspaces: RwLock<HashMap<String, WorkspaceKeys>>,
+}
+
+struct WorkspaceKeys {
+  current_key_id: KeyId,
+  keys: HashMap<KeyId, EncryptionKey>,
+}
+
+impl MemoryKeyProvider {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a key to the workspace and return its id. The key becomes the current key of the
+  /// workspace.
+  pub fn add_key(&self, workspace_id: &str, key: EncryptionKey) -> KeyId {
+    let mut workspaces = self.workspaces.write();
+    match workspaces.get_mut(workspace_id) {
+      None => {
+        workspaces.insert(
+          workspace_id.to_string(),
+          WorkspaceKeys {
+            current_key_id: 1,
+            keys: HashMap::from([(1, key)]),
+          },
+        );
+        1
+      },
+      Some(workspace) => {
+        let key_id = workspace.keys.keys().max().copied().unwrap_or_default() + 1;
+        workspace.keys.insert(key_id, key);
+        workspace.current_key_id = key_id;
+        key_id
+      },
+    }
+  }
+
+  /// Remove a key of the workspace. The current key can't be removed.
+  pub fn remove_key(&self, workspace_id: &str, key_id: KeyId) -> Option<EncryptionKey> {
+    let mut workspaces = self.workspaces.write();
+    let workspace = workspaces.get_mut(workspace_id)?;
+    if workspace.current_key_id == key_id {
+      return None;
+    }
+    workspace.keys.remove(&key_id)
+  }
+}
+
+impl KeyProvider for MemoryKeyProvider {
+  fn current_key_id(&self, workspace_id: &str) -> Result<KeyId, PersistenceError> {
+    self
+      .workspaces
+      .read()
+      .get(workspace_id)
+      .map(|workspace| workspace.current_key_id)
+      .ok_or_else(|| PersistenceError::EncryptionKeyNotFound {
+        workspace_id: workspace_id.to_string(),
+        key_id: 0,
+      })
+  }
+
+  fn get_key(&self, workspace_id: &str, key_id: KeyId) -> Result<EncryptionKey, PersistenceError> {
+    self
+      .workspaces
+      .read()
+      .get(workspace_id)
+      .and_then(|workspace| workspace.keys.get(&key_id).cloned())
+      .ok_or_else(|| PersistenceError::EncryptionKeyNotFound {
+        workspace_id: workspace_id.to_string(),
+        key_id,
+      })
+  }
+}
+
+/// A [KVTransactionDB] that encrypts the values of the wrapped database with AES-256-GCM before
+/// they are written to disk. It can be used in place of any other [KVTransactionDB], for example
+/// with the `DiskPlugin`, so the documents, the updates and the snapshots are encrypted
+/// without depending on the encryption of the operating system.
+///
+/// Each value is encrypted with the current key of the workspace and a random nonce. The id of
+/// the key is stored with the value, so a store that contains values encrypted with different
+/// keys can still be read. The key of the entry is used as associated data, which prevents the
+/// values from being moved to another key.
+///
+/// The keys of the entries are not encrypted. They only contain the uid, the object ids and
+/// the clocks of the updates.
+pub struct EncryptedCollabDB<DB> {
+  db: DB,
+  cipher: Arc<WorkspaceCipher>,
+}
+
+impl<DB> EncryptedCollabDB<DB>
+where
+  DB: KVTransactionDB,
+{
+  /// Wrap the store of the given workspace. The keys are supplied by the [KeyProvider].
+  ///
+  /// The values of a store that was written without encryption can't be read until they are
+  /// encrypted with [EncryptedCollabDB::encrypt_existing].
+  pub fn new(db: DB, workspace_id: &str, key_provider: Arc<dyn KeyProvider>) -> Self {
+    let cipher = Arc::new(WorkspaceCipher::new(workspace_id, key_provider));
+    Self { db, cipher }
+  }
+
+  /// Return the wrapped database. The values read from it are encrypted.
+  pub fn inner(&self) -> &DB {
+    &self.db
+  }
+
+  pub fn into_inner(self) -> DB {
+    self.db
+  }
+
+  /// Re-encrypt all the values that were encrypted with a key other than the current key of the
+  /// workspace, including the document states and the snapshots. Return the number of values
+  /// that were re-encrypted. Once it returns, the previous keys are no longer needed.
+  ///
+  /// The values are re-encrypted in batches of [REENCRYPT_BATCH_SIZE], each in its own
+  /// transaction. If it fails, the values that were re-encrypted are kept and it can be called
+  /// again.
+  pub fn rotate_keys(&self) -> Result<usize, PersistenceError> {
+    let key_id = self.cipher.current_key_id()?;
+    let count = self.reencrypt(|key, value| {
+      if encrypted_key_id(value)? == key_id {
+        return Ok(None);
+      }
+      self.cipher.decrypt(key, value).map(Some)
+    })?;
+    tracing::trace!(
+      "re-encrypt {} values of workspace:{} with key:{}",
+      count,
+      self.cipher.workspace_id,
+      key_id
+    );
+    Ok(count)
+  }
+
+  /// Encrypt the values of a store that was written without encryption, so it can be read
+  /// through the [EncryptedCollabDB]. It must be called once after wrapping such a store, before
+  /// reading from it. Return the number of values that were encrypted.
+  ///
+  /// The values that can be decrypted with the keys of the [KeyProvider] are skipped, so a
+  /// store that was partly encrypted, for example because the migration failed, can be migrated
+  /// again. The values are encrypted in batches of [REENCRYPT_BATCH_SIZE], each in its own
+  /// transaction.
+  pub fn encrypt_existing(&self) -> Result<usize, PersistenceError> {
+    let count = self.reencrypt(|key, value| {
+      // A plaintext value can start like an encrypted value, so only the values that can be
+      // decrypted are considered encrypted.
+      if encrypted_key_id(value).is_ok() && self.cipher.decrypt(key, value).is_ok() {
+        return Ok(None);
+      }
+      Ok(Some(value.to_vec()))
+    })?;
+    tracing::trace!(
+      "encrypt {} values of workspace:{}",
+      count,
+      self.cipher.workspace_id
+    );
+    Ok(count)
+  }
+
+  /// Go through the raw values of the wrapped database in batches, each in its own write
+  /// transaction. The `plaintext` returns the plaintext of the value that should be encrypted with
+  /// the current key, or None to keep the value. Return the number of values that were encrypted.
+  fn reencrypt<F>(&self, plaintext: F) -> Result<usize, PersistenceError>
+  where
+    F: Fn(&[u8], &[u8]) -> Result<Option<Vec<u8>>, PersistenceError>,
+  {
+    let key_id = self.cipher.current_key_id()?;
+    let mut start = vec![];
+    let mut count = 0;
+    loop {
+      // The values of a batch are only counted once the batch is committed.
+      let (batch_count, next_start) = self.db.with_write_txn(|txn| {
+        let entries = txn
+          .range(start.as_slice()..)?
+          .take(REENCRYPT_BATCH_SIZE)
+          .map(|entry| (entry.key().to_vec(), entry.value().to_vec()))
+          .collect::<Vec<_>>();
+        let mut batch_count = 0;
+        for (key, value) in entries.iter() {
+          if let Some(plaintext) = plaintext(key, value)? {
+            txn.insert(key, self.cipher.encrypt(key_id, key, &plaintext)?)?;
+            batch_count += 1;
+          }
+        }
+
+        if entries.len() < REENCRYPT_BATCH_SIZE {
+          return Ok((batch_count, None));
+        }
+        // The range always includes its start, so the next batch starts right after the last key.
+        let next_start = entries.last().map(|(key, _)| {
+          let mut next_start = key.clone();
+          next_start.push(0);
+          next_start
+        });
+        Ok((batch_count, next_start))
+      })?;
+      count += batch_count;
+      match next_start {
+        None => return Ok(count),
+        Some(next_start) => start = next_start,
+      }
+    }
+  }
+}
+
+impl<DB> KVTransactionDB for EncryptedCollabDB<DB>
+where
+  DB: KVTransactionDB,
+{
+  type TransactionAction<'a> = EncryptedKVStoreImpl<DB::TransactionAction<'a>>;
+
+  fn read_txn(&self) -> Self::TransactionAction<'_> {
+    EncryptedKVStoreImpl {
+      store: self.db.read_txn(),
+      cipher: self.cipher.clone(),
+      key_id: Mutex::new(None),
+    }
+  }
+
+  fn write_txn(&self) -> Result<Self::TransactionAction<'_>, PersistenceError> {
+    // Resolve the current key once, so all the values of a transaction are encrypted with the
+    // same key.
+    let key_id = self.cipher.current_key_id()?;
+    Ok(EncryptedKVStoreImpl {
+      store: self.db.write_txn()?,
+      cipher: self.cipher.clone(),
+      key_id: Mutex::new(Some(key_id)),
+    })
+  }
+
+  fn commit_txn(txn: Self::TransactionAction<'_>) -> Result<(), PersistenceError> {
+    DB::commit_txn(txn.store)
+  }
+}
+
+/// Implementation of [KVStore] for [EncryptedCollabDB]. The values are encrypted when they are
+/// inserted and decrypted when they are read.
+pub struct EncryptedKVStoreImpl<S> {
+  store: S,
+  cipher: Arc<WorkspaceCipher>,
+  key_id: Mutex<Option<KeyId>>,
+}
+
+impl<S> EncryptedKVStoreImpl<S> {
+  fn key_id(&self) -> Result<KeyId, PersistenceError> {
+    let mut key_id = self.key_id.lock();
+    match *key_id {
+      Some(key_id) => Ok(key_id),
+      None => {
+        let current = self.cipher.current_key_id()?;
+        *key_id = Some(current);
+        Ok(current)
+      },
+    }
+  }
+}
+
+impl<'a, S> KVStore<'a> for EncryptedKVStoreImpl<S>
+where
+  S: KVStore<'a, Error = PersistenceError>,
+{
+  type Range = std::vec::IntoIter<EncryptedEntry>;
+  type Entry = EncryptedEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    match self.store.get(key.as_ref())? {
+      None => Ok(None),
+      Some(value) => Ok(Some(self.cipher.decrypt(key.as_ref(), value.as_ref())?)),
+    }
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    let value = self
+      .cipher
+      .encrypt(self.key_id()?, key.as_ref(), value.as_ref())?;
+    self.store.insert(key, value)
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    self.store.remove(key)
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    self.store.remove_range(from, to)
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    // Decrypt the entries eagerly, so a value that can't be decrypted is returned as an error
+    // instead of being skipped.
+    let entries = self
+      .store
+      .range(range)?
+      .map(|entry| self.decrypt_entry(entry))
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries.into_iter())
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    match self.store.next_back_entry(key)? {
+      None => Ok(None),
+      Some(entry) => Ok(Some(self.decrypt_entry(entry)?)),
+    }
+  }
+}
+
+impl<S> EncryptedKVStoreImpl<S> {
+  fn decrypt_entry<E: KVEntry>(&self, entry: E) -> Result<EncryptedEntry, PersistenceError> {
+    let value = self.cipher.decrypt(entry.key(), entry.value())?;
+    Ok(EncryptedEntry {
+      key: entry.key().to_vec(),
+      value,
+    })
+  }
+}
+
+/// An entry of [EncryptedKVStoreImpl]. The value is decrypted.
+pub struct EncryptedEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl KVEntry for EncryptedEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
+
+/// Encrypt and decrypt the values of a workspace with AES-256-GCM. The ciphers are cached by key
+/// id, so the [KeyProvider] is only asked once for each key.
+///
+/// The encrypted values contain the id of the key and the nonce. The associated data is
+/// authenticated but not stored, so the same associated data must be passed to decrypt a value.
+pub struct WorkspaceCipher {
+  workspace_id: String,
+  key_provider: Arc<dyn KeyProvider>,
+  ciphers: RwLock<HashMap<KeyId, Arc<Aes256Gcm>>>,
+}
+
+impl WorkspaceCipher {
+  pub fn new(workspace_id: &str, key_provider: Arc<dyn KeyProvider>) -> Self {
+    Self {
+      workspace_id: workspace_id.to_string(),
+      key_provider,
+      ciphers: Default::default(),
+    }
+  }
+
+  pub fn workspace_id(&self) -> &str {
+    &self.workspace_id
+  }
+
+  /// Return the id of the key used to encrypt the new values of the workspace.
+  pub fn current_key_id(&self) -> Result<KeyId, PersistenceError> {
+    self.key_provider.current_key_id(&self.workspace_id)
+  }
+
+  fn cipher(&self, key_id: KeyId) -> Result<Arc<Aes256Gcm>, PersistenceError> {
+    if let Some(cipher) = self.ciphers.read().get(&key_id) {
+      return Ok(cipher.clone());
+    }
+    let key = self.key_provider.get_key(&self.workspace_id, key_id)?;
+    let cipher = Arc::new(Aes256Gcm::new(key.as_bytes().into()));
+    self.ciphers.write().insert(key_id, cipher.clone());
+    Ok(cipher)
+  }
+
+  /// Encrypt the value with the given key and a random nonce.
+  pub fn encrypt(
+    &self,
+    key_id: KeyId,
+    aad: &[u8],
+    value: &[u8],
+  ) -> Result<Vec<u8>, PersistenceError> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let ciphertext = self
+      .cipher(key_id)?
+      .encrypt(&nonce, Payload { msg: value, aad })
+      .map_err(|_| PersistenceError::Encryption("failed to encrypt the value".to_string()))?;
+
+    let mut encrypted = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    encrypted.push(ENCRYPTED_VALUE_VERSION);
+    encrypted.extend_from_slice(&key_id.to_be_bytes());
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+  }
+
+  /// Decrypt a value that was encrypted with [WorkspaceCipher::encrypt]. Return an error if the
+  /// value or the associated data was modified.
+  pub fn decrypt(&self, aad: &[u8], value: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let key_id = encrypted_key_id(value)?;
+    let nonce = Nonce::from_slice(&value[1 + KEY_ID_LEN..HEADER_LEN]);
+    self
+      .cipher(key_id)?
+      .decrypt(
+        nonce,
+        Payload {
+          msg: &value[HEADER_LEN..],
+          aad,
+        },
+      )
+      .map_err(|_| {
+        PersistenceError::Encryption(format!("failed to decrypt the value with key:{}", key_id))
+      })
+  }
+}
+
+/// Return the id of the key that was used to encrypt the value.
+pub fn encrypted_key_id(value: &[u8]) -> Result<KeyId, PersistenceError> {
+  if value.len() < HEADER_LEN || value[0] != ENCRYPTED_VALUE_VERSION {
+    return Err(PersistenceError::Encryption(
+      "the value is not encrypted".to_string(),
+    ));
+  }
+  let mut key_id = [0; KEY_ID_LEN];
+  key_id.copy_from_slice(&value[1..1 + KEY_ID_LEN]);
+  Ok(KeyId::from_be_bytes(key_id))
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::ops;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+use parking_lot::{Mutex, MutexGuard, RwLock};
+
+use crate::kv::{KVEntry, KVStore, KVTransactionDB};
+use crate::PersistenceError;
+
+pub type MemoryCollabDB = MemoryStore;
+
+/// An in-memory [KVStore] that keeps the entries in a [BTreeMap]. All the data is lost when the
+/// store is dropped, so it's suitable for tests and ephemeral sessions.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+  map: Arc<RwLock<BTreeMap<Vec<u8>, Vec<u8>>>>,
+  /// Only one write transaction is allowed at a time.
+  write_lock: Arc<Mutex<()>>,
+}
+
+impl MemoryStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Return a read transaction. Each read sees the latest committed data.
+  pub fn read_txn(&self) -> MemoryKVStoreImpl<'_> {
+    MemoryKVStoreImpl {
+      map: &self.map,
+      pending: None,
+      _write_guard: None,
+    }
+  }
+
+  /// Create a write transaction that accesses the database exclusively.
+  /// The changes are only visible to others after the transaction is committed.
+  pub fn write_txn(&self) -> MemoryKVStoreImpl<'_> {
+    MemoryKVStoreImpl {
+      map: &self.map,
+      pending: Some(Mutex::new(BTreeMap::new())),
+      _write_guard: Some(self.write_lock.lock()),
+    }
+  }
+
+  /// Create a write transaction that accesses the database exclusively.
+  /// The transaction will be committed when the closure [F] returns.
+  pub fn with_write_txn<F, O>(&self, f: F) -> Result<O, PersistenceError>
+  where
+    F: FnOnce(&MemoryKVStoreImpl<'_>) -> Result<O, PersistenceError>,
+  {
+    let store = self.write_txn();
+    let result = f(&store)?;
+    store.commit_transaction()?;
+    Ok(result)
+  }
+}
+
+impl KVTransactionDB for MemoryStore {
+  type TransactionAction<'a> = MemoryKVStoreImpl<'a>;
+
+  fn read_txn(&self) -> Self::TransactionAction<'_> {
+    MemoryStore::read_txn(self)
+  }
+
+  fn write_txn(&self) -> Result<Self::TransactionAction<'_>, PersistenceError> {
+    Ok(MemoryStore::write_txn(self))
+  }
+
+  fn commit_txn(txn: Self::TransactionAction<'_>) -> Result<(), PersistenceError> {
+    txn.commit_transaction()
+  }
+}
+
+/// The changes of a write transaction, where [None] marks a removed key.
+type PendingChanges = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Implementation of [KVStore] for [MemoryStore]. The changes of a write transaction are kept
+/// in memory until the transaction is committed.
+pub struct MemoryKVStoreImpl<'a> {
+  map: &'a RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+  pending: Option<Mutex<PendingChanges>>,
+  _write_guard: Option<MutexGuard<'a, ()>>,
+}
+
+impl<'a> MemoryKVStoreImpl<'a> {
+  pub fn commit_transaction(self) -> Result<(), PersistenceError> {
+    if let Some(pending) = self.pending {
+      let mut map = self.map.write();
+      for (key, value) in pending.into_inner() {
+        match value {
+          None => map.remove(&key),
+          Some(value) => map.insert(key, value),
+        };
+      }
+    }
+    Ok(())
+  }
+
+  fn write_pending(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<(), PersistenceError> {
+    match &self.pending {
+      None => Err(PersistenceError::InvalidData(
+        "can't write in a read transaction".to_string(),
+      )),
+      Some(pending) => {
+        pending.lock().insert(key, value);
+        Ok(())
+      },
+    }
+  }
+
+  /// Return the committed entries merged with the pending changes in the given bounds.
+  fn entries(&self, from: ops::Bound<&[u8]>, to: ops::Bound<&[u8]>) -> Vec<MemoryEntry> {
+    let mut entries = self
+      .map
+      .read()
+      .range::<[u8], _>((from, to))
+      .map(|(key, value)| (key.clone(), Some(value.clone())))
+      .collect::<BTreeMap<_, _>>();
+    if let Some(pending) = &self.pending {
+      for (key, value) in pending.lock().range::<[u8], _>((from, to)) {
+        entries.insert(key.clone(), value.clone());
+      }
+    }
+    entries
+      .into_iter()
+      .flat_map(|(key, value)| value.map(|value| MemoryEntry::new(key, value)))
+      .collect()
+  }
+}
+
+impl<'a> KVStore<'a> for MemoryKVStoreImpl<'a> {
+  type Range = std::vec::IntoIter<MemoryEntry>;
+  type Entry = MemoryEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    if let Some(pending) = &self.pending {
+      if let Some(value) = pending.lock().get(key.as_ref()) {
+        return Ok(value.clone());
+      }
+    }
+    Ok(self.map.read().get(key.as_ref()).cloned())
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    self.write_pending(key.as_ref().to_vec(), Some(value.as_ref().to_vec()))
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    self.write_pending(key.to_vec(), None)
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    let entries = self.entries(ops::Bound::Included(from), ops::Bound::Excluded(to));
+    for entry in entries {
+      self.write_pending(entry.key, None)?;
+    }
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    // Same as the RocksDB implementation, the start bound is always included and the end bound
+    // is always excluded.
+    let from = match range.start_bound() {
+      ops::Bound::Included(start) | ops::Bound::Excluded(start) => {
+        ops::Bound::Included(start.as_ref())
+      },
+      ops::Bound::Unbounded => ops::Bound::Unbounded,
+    };
+    let to = match range.end_bound() {
+      ops::Bound::Included(end) | ops::Bound::Excluded(end) => ops::Bound::Excluded(end.as_ref()),
+      ops::Bound::Unbounded => ops::Bound::Unbounded,
+    };
+    Ok(self.entries(from, to).into_iter())
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    let bounds = (ops::Bound::Unbounded, ops::Bound::Included(key));
+    let map = self.map.read();
+    let pending = self.pending.as_ref().map(|pending| pending.lock());
+    let committed = map
+      .range::<[u8], _>(bounds)
+      .rev()
+      .find(|(key, _)| match &pending {
+        None => true,
+        Some(pending) => !pending.contains_key(*key),
+      });
+    let pending = pending.as_ref().and_then(|pending| {
+      pending
+        .range::<[u8], _>(bounds)
+        .rev()
+        .find_map(|(key, value)| value.as_ref().map(|value| (key, value)))
+    });
+    let entry = match (committed, pending) {
+      (Some(committed), Some(pending)) => Some(std::cmp::max(committed, pending)),
+      (committed, pending) => committed.or(pending),
+    };
+    Ok(entry.map(|(key, value)| MemoryEntry::new(key.clone(), value.clone())))
+  }
+}
+
+pub struct MemoryEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl MemoryEntry {
+  pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+    Self { key, value }
+  }
+}
+
+impl KVEntry for MemoryEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
@@ -4,8 +4,44 @@ use std::sync::Arc;
 
 use crate::PersistenceError;
 
+#[cfg(feature = "encrypted_persistence")]
+pub mod encrypted_kv;
+#[cfg(feature = "memory_persistence")]
+pub mod memory_kv;
 #[cfg(feature = "rocksdb_persistence")]
 pub mod rocks_kv;
+#[cfg(feature = "sqlite_persistence")]
+pub mod sqlite_kv;
+
+/// A database that provides the [KVStore] within transactions. Implement this trait to plug in
+/// a new storage backend.
+pub trait KVTransactionDB: Send + Sync + 'static {
+  type TransactionAction<'a>: KVStore<'a, Error = PersistenceError>
+  where
+    Self: 'a;
+
+  /// Return a read transaction.
+  fn read_txn(&self) -> Self::TransactionAction<'_>;
+
+  /// Create a write transaction. The changes are only persisted after the transaction is passed
+  /// to [KVTransactionDB::commit_txn], otherwise they are discarded when it's dropped.
+  fn write_txn(&self) -> Result<Self::TransactionAction<'_>, PersistenceError>;
+
+  /// Commit a transaction that was created with [KVTransactionDB::write_txn].
+  fn commit_txn(txn: Self::TransactionAction<'_>) -> Result<(), PersistenceError>;
+
+  /// Create a write transaction. The transaction will be committed when the closure [F] returns
+  /// [Ok] and discarded otherwise.
+  fn with_write_txn<'a, F, O>(&'a self, f: F) -> Result<O, PersistenceError>
+  where
+    F: FnOnce(&Self::TransactionAction<'a>) -> Result<O, PersistenceError>,
+  {
+    let txn = self.write_txn()?;
+    let result = f(&txn)?;
+    Self::commit_txn(txn)?;
+    Ok(result)
+  }
+}
 
 pub trait KVStore<'a> {
   type Range: Iterator<Item = Self::Entry>;
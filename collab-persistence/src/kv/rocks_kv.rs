@@ -10,7 +10,7 @@ use rocksdb::{
   TransactionOptions, WriteOptions,
 };
 
-use crate::kv::{KVEntry, KVStore};
+use crate::kv::{KVEntry, KVStore, KVTransactionDB};
 use crate::PersistenceError;
 
 pub type RocksCollabDB = RocksStore;
@@ -122,6 +122,22 @@ impl RocksStore {
   }
 }
 
+impl KVTransactionDB for RocksStore {
+  type TransactionAction<'a> = RocksKVStoreImpl<'a, TransactionDB>;
+
+  fn read_txn(&self) -> Self::TransactionAction<'_> {
+    RocksStore::read_txn(self)
+  }
+
+  fn write_txn(&self) -> Result<Self::TransactionAction<'_>, PersistenceError> {
+    Ok(RocksStore::write_txn(self))
+  }
+
+  fn commit_txn(txn: Self::TransactionAction<'_>) -> Result<(), PersistenceError> {
+    txn.commit_transaction()
+  }
+}
+
 /// Implementation of [KVStore] for [RocksStore]. This is a wrapper around [Transaction].
 // pub struct RocksKVStoreImpl<'a, DB: Send + Sync>(Transaction<'a, DB>);
 pub type RocksKVStoreImpl<'a, DB> = MutexRocksKVStoreImpl<'a, DB>;
@@ -0,0 +1,241 @@
+use std::ops;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::{Mutex, MutexGuard};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use crate::kv::{KVEntry, KVStore, KVTransactionDB};
+use crate::PersistenceError;
+
+pub type SqliteCollabDB = SqliteStore;
+
+const CREATE_TABLE_SQL: &str =
+  "CREATE TABLE IF NOT EXISTS collab_kv (key BLOB PRIMARY KEY NOT NULL, value BLOB NOT NULL) WITHOUT ROWID";
+
+/// A [KVStore] backed by a SQLite database. The entries are stored in a single table ordered by
+/// the key, which is compared byte by byte just like RocksDB.
+#[derive(Clone)]
+pub struct SqliteStore {
+  conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+  /// Open a SQLite database at the given path. The database is created if it doesn't exist.
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+    Self::from_conn(Connection::open(path)?)
+  }
+
+  /// Open a SQLite database that lives in memory.
+  pub fn open_in_memory() -> Result<Self, PersistenceError> {
+    Self::from_conn(Connection::open_in_memory()?)
+  }
+
+  fn from_conn(conn: Connection) -> Result<Self, PersistenceError> {
+    conn.execute_batch(CREATE_TABLE_SQL)?;
+    Ok(Self {
+      conn: Arc::new(Mutex::new(conn)),
+    })
+  }
+
+  /// Return a read transaction. Each read sees the latest committed data.
+  pub fn read_txn(&self) -> SqliteKVStoreImpl<'_> {
+    SqliteKVStoreImpl {
+      conn: &self.conn,
+      write_conn: None,
+    }
+  }
+
+  /// Create a write transaction that accesses the database exclusively. The transaction must be
+  /// committed with [SqliteKVStoreImpl::commit_transaction], otherwise it's rolled back when
+  /// dropped.
+  pub fn write_txn(&self) -> Result<SqliteKVStoreImpl<'_>, PersistenceError> {
+    let conn = self.conn.lock();
+    conn.execute_batch("BEGIN IMMEDIATE")?;
+    Ok(SqliteKVStoreImpl {
+      conn: &self.conn,
+      write_conn: Some(conn),
+    })
+  }
+
+  /// Create a write transaction that accesses the database exclusively.
+  /// The transaction will be committed when the closure [F] returns.
+  pub fn with_write_txn<F, O>(&self, f: F) -> Result<O, PersistenceError>
+  where
+    F: FnOnce(&SqliteKVStoreImpl<'_>) -> Result<O, PersistenceError>,
+  {
+    let store = self.write_txn()?;
+    let result = f(&store)?;
+    store.commit_transaction()?;
+    Ok(result)
+  }
+}
+
+impl KVTransactionDB for SqliteStore {
+  type TransactionAction<'a> = SqliteKVStoreImpl<'a>;
+
+  fn read_txn(&self) -> Self::TransactionAction<'_> {
+    SqliteStore::read_txn(self)
+  }
+
+  fn write_txn(&self) -> Result<Self::TransactionAction<'_>, PersistenceError> {
+    SqliteStore::write_txn(self)
+  }
+
+  fn commit_txn(txn: Self::TransactionAction<'_>) -> Result<(), PersistenceError> {
+    txn.commit_transaction()
+  }
+}
+
+/// Implementation of [KVStore] for [SqliteStore]. A write transaction holds the connection until
+/// it's committed or dropped.
+pub struct SqliteKVStoreImpl<'a> {
+  conn: &'a Mutex<Connection>,
+  write_conn: Option<MutexGuard<'a, Connection>>,
+}
+
+impl<'a> SqliteKVStoreImpl<'a> {
+  pub fn commit_transaction(mut self) -> Result<(), PersistenceError> {
+    if let Some(conn) = self.write_conn.take() {
+      if let Err(e) = conn.execute_batch("COMMIT") {
+        let _ = conn.execute_batch("ROLLBACK");
+        return Err(e.into());
+      }
+    }
+    Ok(())
+  }
+
+  fn with_conn<F, O>(&self, f: F) -> Result<O, PersistenceError>
+  where
+    F: FnOnce(&Connection) -> rusqlite::Result<O>,
+  {
+    let result = match &self.write_conn {
+      Some(conn) => f(conn),
+      None => f(&self.conn.lock()),
+    };
+    Ok(result?)
+  }
+
+  fn query_entries(
+    &self,
+    sql: &str,
+    args: Vec<&[u8]>,
+  ) -> Result<Vec<SqliteEntry>, PersistenceError> {
+    self.with_conn(|conn| {
+      let mut stmt = conn.prepare_cached(sql)?;
+      let rows = stmt.query_map(params_from_iter(args), |row| {
+        Ok(SqliteEntry::new(row.get(0)?, row.get(1)?))
+      })?;
+      rows.collect()
+    })
+  }
+}
+
+impl<'a> Drop for SqliteKVStoreImpl<'a> {
+  fn drop(&mut self) {
+    if let Some(conn) = self.write_conn.take() {
+      if let Err(e) = conn.execute_batch("ROLLBACK") {
+        tracing::error!("🔴rollback sqlite transaction failed: {:?}", e);
+      }
+    }
+  }
+}
+
+impl<'a> KVStore<'a> for SqliteKVStoreImpl<'a> {
+  type Range = std::vec::IntoIter<SqliteEntry>;
+  type Entry = SqliteEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    self.with_conn(|conn| {
+      conn
+        .prepare_cached("SELECT value FROM collab_kv WHERE key = ?1")?
+        .query_row(params![key.as_ref()], |row| row.get(0))
+        .optional()
+    })
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    self.with_conn(|conn| {
+      conn
+        .prepare_cached("INSERT OR REPLACE INTO collab_kv (key, value) VALUES (?1, ?2)")?
+        .execute(params![key.as_ref(), value.as_ref()])
+    })?;
+    Ok(())
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    self.with_conn(|conn| {
+      conn
+        .prepare_cached("DELETE FROM collab_kv WHERE key = ?1")?
+        .execute(params![key])
+    })?;
+    Ok(())
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    self.with_conn(|conn| {
+      conn
+        .prepare_cached("DELETE FROM collab_kv WHERE key >= ?1 AND key < ?2")?
+        .execute(params![from, to])
+    })?;
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    // Same as the RocksDB implementation, the start bound is always included and the end bound
+    // is always excluded.
+    let mut conditions = vec![];
+    let mut args = vec![];
+    match range.start_bound() {
+      ops::Bound::Included(start) | ops::Bound::Excluded(start) => {
+        conditions.push("key >= ?");
+        args.push(start.as_ref());
+      },
+      ops::Bound::Unbounded => {},
+    }
+    match range.end_bound() {
+      ops::Bound::Included(end) | ops::Bound::Excluded(end) => {
+        conditions.push("key < ?");
+        args.push(end.as_ref());
+      },
+      ops::Bound::Unbounded => {},
+    }
+
+    let mut sql = "SELECT key, value FROM collab_kv".to_string();
+    if !conditions.is_empty() {
+      sql.push_str(" WHERE ");
+      sql.push_str(&conditions.join(" AND "));
+    }
+    sql.push_str(" ORDER BY key");
+    Ok(self.query_entries(&sql, args)?.into_iter())
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    let sql = "SELECT key, value FROM collab_kv WHERE key <= ? ORDER BY key DESC LIMIT 1";
+    Ok(self.query_entries(sql, vec![key])?.pop())
+  }
+}
+
+pub struct SqliteEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl SqliteEntry {
+  pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+    Self { key, value }
+  }
+}
+
+impl KVEntry for SqliteEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
@@ -2,11 +2,19 @@ pub use db::*;
 pub use error::*;
 pub use range::*;
 
+pub mod archive;
+pub mod compact;
 mod db;
 pub mod doc;
+pub mod encoding;
 pub mod error;
+pub mod integrity;
 pub mod keys;
 pub mod kv;
 mod oid;
+pub mod quarantine;
 mod range;
+pub mod sink_queue;
 pub mod snapshot;
+pub mod txn_meta;
+pub mod version;
@@ -0,0 +1,230 @@
+use std::fmt::Debug;
+
+use collab::core::encoding::EncoderVersion;
+
+use crate::doc::{get_doc_id, LoadReport};
+use crate::encoding::{decode_encoder_version, doc_encoder_version};
+use crate::keys::{
+  clock_from_key, make_doc_update_key, make_quarantine_encoder_version_key,
+  make_quarantine_update_key, Clock, DocID,
+};
+use crate::kv::KVEntry;
+use crate::kv::KVStore;
+use crate::{create_update_key, insert_doc_update, PersistenceError};
+
+/// An update of the update log that was moved to the quarantine when loading the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedUpdate {
+  /// The clock of the update in the update log.
+  pub clock: Clock,
+  /// The error of decoding or applying the update. It's None if the update could be applied,
+  /// but it follows an update that couldn't.
+  pub error: Option<String>,
+}
+
+/// The result of [QuarantineAction::reimport_quarantined_updates].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReimportReport {
+  /// The number of updates that were moved back to the update log.
+  pub reimported: u32,
+  /// The repaired updates that still can't be decoded, with their clock in the quarantine. They
+  /// are kept in the quarantine.
+  pub rejected: Vec<QuarantinedUpdate>,
+}
+
+impl<'a, T> QuarantineAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Keeps the updates that couldn't be applied when loading a document. The quarantined updates
+/// are removed from the update log, so they don't break the following loads, but they are kept
+/// until they are re-imported or deleted explicitly.
+pub trait QuarantineAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Move the updates of the [LoadReport::quarantined] from the update log to the quarantine.
+  /// The updates keep their order in the quarantine.
+  fn quarantine_updates<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+    report: &LoadReport,
+  ) -> Result<(), PersistenceError> {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    for quarantined in &report.quarantined {
+      let update_key = make_doc_update_key(doc_id, quarantined.clock);
+      if let Some(update) = self.get(update_key.as_ref())? {
+        let update = update.as_ref().to_vec();
+        let key = create_update_key(doc_id, self, object_id, make_quarantine_update_key)?;
+        self.insert(key, update)?;
+        self.remove(update_key.as_ref())?;
+        tracing::warn!(
+          "🟡{:?} quarantine update:{}, error: {:?}",
+          object_id,
+          quarantined.clock,
+          quarantined.error
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Return the quarantined updates of the given object id, from the oldest to the newest.
+  fn get_quarantined_updates<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<Vec<Vec<u8>>, PersistenceError> {
+    let mut updates = vec![];
+    if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let start = make_quarantine_update_key(doc_id, 0);
+      let end = make_quarantine_update_key(doc_id, Clock::MAX);
+      for entry in self.range(start.as_ref()..=end.as_ref())? {
+        updates.push(entry.value().to_vec());
+      }
+    }
+    Ok(updates)
+  }
+
+  /// Move the quarantined updates back to the end of the update log, so they are applied by the
+  /// next load. Each update is passed to `repair` with its encoder version, which might differ
+  /// from the encoder version of the document if the document was converted after the update
+  /// was quarantined. `repair` returns the update to re-import, encoded with the given version,
+  /// or None to keep the update in the quarantine.
+  ///
+  /// The re-imported updates that can't be decoded are kept in the quarantine and reported in
+  /// [ReimportReport::rejected].
+  fn reimport_quarantined_updates<K, F>(
+    &self,
+    uid: i64,
+    object_id: &K,
+    mut repair: F,
+  ) -> Result<ReimportReport, PersistenceError>
+  where
+    K: AsRef<[u8]> + ?Sized + Debug,
+    F: FnMut(&[u8], EncoderVersion) -> Option<Vec<u8>>,
+  {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    let doc_version = doc_encoder_version(self, doc_id)?;
+    let mut report = ReimportReport::default();
+    for (clock, update) in quarantined_update_entries(self, doc_id)? {
+      let update_version = quarantined_update_encoder_version(self, doc_id, clock, doc_version)?;
+      let repaired = match repair(&update, update_version) {
+        None => continue,
+        Some(repaired) => repaired,
+      };
+      // The updates of the update log are encoded with the encoder version of the document.
+      let update = match update_version.decode_update(&repaired) {
+        Ok(_) if update_version == doc_version => repaired,
+        Ok(update) => doc_version.encode_update(&update),
+        Err(e) => {
+          tracing::warn!(
+            "🟡{:?} can't re-import the invalid update:{}, error: {:?}",
+            object_id,
+            clock,
+            e
+          );
+          report.rejected.push(QuarantinedUpdate {
+            clock,
+            error: Some(e.to_string()),
+          });
+          continue;
+        },
+      };
+      insert_doc_update(self, doc_id, object_id, update)?;
+      self.remove(make_quarantine_update_key(doc_id, clock).as_ref())?;
+      self.remove(make_quarantine_encoder_version_key(doc_id, clock).as_ref())?;
+      report.reimported += 1;
+    }
+    Ok(report)
+  }
+
+  /// Delete all the quarantined updates of the given object id.
+  fn delete_quarantined_updates<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<(), PersistenceError> {
+    if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let start = make_quarantine_update_key(doc_id, 0);
+      let end = make_quarantine_encoder_version_key(doc_id, Clock::MAX);
+      self.remove_range(start.as_ref(), end.as_ref())?;
+    }
+    Ok(())
+  }
+}
+
+/// Re-encode the quarantined updates of the given document with the given version. The updates
+/// that can't be decoded keep their encoder version, which is recorded next to them.
+pub(crate) fn convert_quarantined_updates<'a, S>(
+  store: &S,
+  doc_id: DocID,
+  doc_version: EncoderVersion,
+  version: EncoderVersion,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  for (clock, update) in quarantined_update_entries(store, doc_id)? {
+    let update_version = quarantined_update_encoder_version(store, doc_id, clock, doc_version)?;
+    let version_key = make_quarantine_encoder_version_key(doc_id, clock);
+    if update_version == version {
+      store.remove(version_key.as_ref())?;
+      continue;
+    }
+    match update_version.decode_update(&update) {
+      Ok(update) => {
+        store.insert(
+          make_quarantine_update_key(doc_id, clock),
+          version.encode_update(&update),
+        )?;
+        store.remove(version_key.as_ref())?;
+      },
+      Err(_) => store.insert(version_key, [u8::from(update_version)])?,
+    }
+  }
+  Ok(())
+}
+
+/// Return the clocks and the quarantined updates of the given document.
+fn quarantined_update_entries<'a, S>(
+  store: &S,
+  doc_id: DocID,
+) -> Result<Vec<(Clock, Vec<u8>)>, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let start = make_quarantine_update_key(doc_id, 0);
+  let end = make_quarantine_update_key(doc_id, Clock::MAX);
+  let entries = store
+    .range(start.as_ref()..=end.as_ref())?
+    .map(|entry| {
+      let clock = Clock::from_be_bytes(clock_from_key(entry.key()).try_into().unwrap());
+      (clock, entry.value().to_vec())
+    })
+    .collect();
+  Ok(entries)
+}
+
+/// Return the encoder version of the quarantined update with the given clock.
+fn quarantined_update_encoder_version<'a, S>(
+  store: &S,
+  doc_id: DocID,
+  clock: Clock,
+  doc_version: EncoderVersion,
+) -> Result<EncoderVersion, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  match store.get(make_quarantine_encoder_version_key(doc_id, clock).as_ref())? {
+    None => Ok(doc_version),
+    Some(value) => decode_encoder_version(value.as_ref()),
+  }
+}
@@ -0,0 +1,86 @@
+use crate::keys::{
+  make_sink_msg_key, make_sink_msg_prefix, Key, SINK_MSG_KEY_LEN, SINK_SPACE, SINK_SPACE_MSG,
+};
+use crate::kv::{KVEntry, KVStore};
+use crate::PersistenceError;
+
+impl<'a, T> SinkQueueAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Keeps the messages that are waiting to be sent to the remote, so they are not lost if the
+/// app quits before the remote acks them. The messages are grouped by the uid and the object id
+/// and ordered by their message id.
+pub trait SinkQueueAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Insert or replace the pending message with the given message id.
+  fn insert_sink_msg(
+    &self,
+    uid: i64,
+    object_id: &str,
+    msg_id: u64,
+    msg: &[u8],
+  ) -> Result<(), PersistenceError> {
+    self.insert(make_sink_msg_key(uid, object_id.as_bytes(), msg_id), msg)?;
+    Ok(())
+  }
+
+  fn remove_sink_msg(
+    &self,
+    uid: i64,
+    object_id: &str,
+    msg_id: u64,
+  ) -> Result<(), PersistenceError> {
+    self.remove(make_sink_msg_key(uid, object_id.as_bytes(), msg_id).as_ref())?;
+    Ok(())
+  }
+
+  /// Return the pending messages of the given object id with their message ids, ordered by the
+  /// message id.
+  fn get_sink_msgs(
+    &self,
+    uid: i64,
+    object_id: &str,
+  ) -> Result<Vec<(u64, Vec<u8>)>, PersistenceError> {
+    let (start, end) = sink_msg_range(uid, object_id);
+    let mut msgs = vec![];
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      let key = entry.key();
+      let mut msg_id = [0; 8];
+      msg_id.copy_from_slice(&key[key.len() - 8..]);
+      msgs.push((u64::from_be_bytes(msg_id), entry.value().to_vec()));
+    }
+    Ok(msgs)
+  }
+
+  fn remove_all_sink_msgs(&self, uid: i64, object_id: &str) -> Result<(), PersistenceError> {
+    let (start, end) = sink_msg_range(uid, object_id);
+    self.remove_range(start.as_ref(), end.as_ref())?;
+    Ok(())
+  }
+
+  /// Return the number of pending messages of all the objects of the given user.
+  fn count_sink_msgs(&self, uid: i64) -> Result<usize, PersistenceError> {
+    let mut start = vec![SINK_SPACE, SINK_SPACE_MSG];
+    start.extend_from_slice(&uid.to_be_bytes());
+    let mut end = start.clone();
+    end.push(u8::MAX);
+    Ok(self.range(start..end)?.count())
+  }
+}
+
+/// Return the bounds of the pending messages of the given object id. The end bound replaces the
+/// terminator of the prefix, so it's right after the last message.
+fn sink_msg_range(uid: i64, object_id: &str) -> (Key<SINK_MSG_KEY_LEN>, Key<SINK_MSG_KEY_LEN>) {
+  let start = make_sink_msg_prefix(uid, object_id.as_bytes());
+  let mut end = make_sink_msg_prefix(uid, object_id.as_bytes());
+  if let Some(terminator) = end.0.last_mut() {
+    *terminator += 1;
+  }
+  (start, end)
+}
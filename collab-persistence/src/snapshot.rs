@@ -2,10 +2,12 @@ use std::fmt::Debug;
 use std::panic;
 use std::panic::AssertUnwindSafe;
 
+use collab::core::encoding::{convert_update, EncoderVersion};
 use serde::{Deserialize, Serialize};
-use yrs::updates::encoder::{Encoder, EncoderV1};
-use yrs::{ReadTxn, Snapshot};
+use yrs::updates::encoder::{Encoder, EncoderV1, EncoderV2};
+use yrs::{ReadTxn, Snapshot, Update};
 
+use crate::encoding::EncodingAction;
 use crate::keys::{make_snapshot_id_key, make_snapshot_update_key, Clock, Key, SnapshotID};
 use crate::kv::KVEntry;
 use crate::kv::KVStore;
@@ -29,6 +31,8 @@ where
   /// The snapshot contains the updates prior to the given update_key. For example,
   /// if the update_key is 10, the snapshot will contain updates 0-9. So when restoring
   /// the document from a snapshot, it should apply the update from key:10.
+  ///
+  /// The snapshot is encoded with the encoder version of the store.
   fn create_snapshot<K, T>(
     &self,
     uid: i64,
@@ -40,7 +44,8 @@ where
     K: AsRef<[u8]> + ?Sized + Debug,
     T: ReadTxn,
   {
-    match try_encode_snapshot(txn, snapshot) {
+    let encoder_version = self.get_encoder_version()?;
+    match try_encode_snapshot_with_version(txn, snapshot, encoder_version) {
       Ok(data) => {
         if data.is_empty() {
           tracing::warn!("🟡unexpected empty snapshot for object_id: {:?}", object_id);
@@ -48,7 +53,7 @@ where
         }
         tracing::trace!("New snapshot for object:{:?}", object_id);
         let snapshot_id = self.create_snapshot_id(uid, object_id.as_ref())?;
-        insert_snapshot_update(self, snapshot_id, object_id, data)?;
+        insert_snapshot_update(self, snapshot_id, object_id, data, encoder_version)?;
       },
       Err(e) => {
         tracing::error!(
@@ -61,6 +66,8 @@ where
     Ok(())
   }
 
+  /// Create a snapshot with the given data, which is encoded with the v1 encoding. It's stored
+  /// with the encoder version of the store.
   fn create_snapshot_with_data<K>(
     &self,
     uid: i64,
@@ -71,8 +78,10 @@ where
     K: AsRef<[u8]> + ?Sized + Debug,
   {
     tracing::trace!("New snapshot for object:{:?}", object_id);
+    let encoder_version = self.get_encoder_version()?;
+    let snapshot_data = convert_update(&snapshot_data, EncoderVersion::V1, encoder_version)?;
     let snapshot_id = self.create_snapshot_id(uid, object_id.as_ref())?;
-    insert_snapshot_update(self, snapshot_id, object_id, snapshot_data)?;
+    insert_snapshot_update(self, snapshot_id, object_id, snapshot_data, encoder_version)?;
     Ok(())
   }
   /// Return list of snapshots for the given object id.
@@ -170,17 +179,37 @@ where
 pub fn try_encode_snapshot<T: ReadTxn>(
   txn: &T,
   snapshot: Snapshot,
+) -> Result<Vec<u8>, PersistenceError> {
+  try_encode_snapshot_with_version(txn, snapshot, EncoderVersion::V1)
+}
+
+/// Encode the state of the document at the given snapshot with the given encoder version.
+pub fn try_encode_snapshot_with_version<T: ReadTxn>(
+  txn: &T,
+  snapshot: Snapshot,
+  encoder_version: EncoderVersion,
 ) -> Result<Vec<u8>, PersistenceError> {
   let mut encoded_data = vec![];
   match {
     let mut wrapper = AssertUnwindSafe(&mut encoded_data);
     let wrapper_txn = AssertUnwindSafe(txn);
     panic::catch_unwind(move || {
-      let mut encoder = EncoderV1::new();
-      wrapper_txn
-        .encode_state_from_snapshot(&snapshot, &mut encoder)
-        .unwrap();
-      **wrapper = encoder.to_vec();
+      **wrapper = match encoder_version {
+        EncoderVersion::V1 => {
+          let mut encoder = EncoderV1::new();
+          wrapper_txn
+            .encode_state_from_snapshot(&snapshot, &mut encoder)
+            .unwrap();
+          encoder.to_vec()
+        },
+        EncoderVersion::V2 => {
+          let mut encoder = EncoderV2::new();
+          wrapper_txn
+            .encode_state_from_snapshot(&snapshot, &mut encoder)
+            .unwrap();
+          encoder.to_vec()
+        },
+      };
     })
   } {
     Ok(_) => Ok(encoded_data),
@@ -192,12 +221,27 @@ pub fn try_encode_snapshot<T: ReadTxn>(
 pub struct CollabSnapshot {
   pub data: Vec<u8>,
   pub created_at: i64,
+  /// The encoder version of the data.
+  pub encoder_version: EncoderVersion,
 }
 
 impl CollabSnapshot {
   pub fn new(data: Vec<u8>) -> CollabSnapshot {
+    Self::new_with_encoder_version(data, EncoderVersion::V1)
+  }
+
+  pub fn new_with_encoder_version(data: Vec<u8>, encoder_version: EncoderVersion) -> Self {
     let created_at = chrono::Utc::now().timestamp();
-    Self { data, created_at }
+    Self {
+      data,
+      created_at,
+      encoder_version,
+    }
+  }
+
+  /// Decode the data of the snapshot with its encoder version.
+  pub fn decode_update(&self) -> Result<Update, PersistenceError> {
+    Ok(self.encoder_version.decode_update(&self.data)?)
   }
 
   pub fn to_vec(&self) -> Vec<u8> {
@@ -205,11 +249,28 @@ impl CollabSnapshot {
   }
 }
 
+/// The layout of the snapshots that were stored before the snapshots had an encoder version.
+/// Their data is encoded with the v1 encoding.
+#[derive(Deserialize)]
+struct LegacyCollabSnapshot {
+  data: Vec<u8>,
+  created_at: i64,
+}
+
 impl TryFrom<&[u8]> for CollabSnapshot {
   type Error = PersistenceError;
 
   fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-    let value = bincode::deserialize(value)?;
-    Ok(value)
+    match bincode::deserialize::<CollabSnapshot>(value) {
+      Ok(snapshot) => Ok(snapshot),
+      Err(_) => {
+        let legacy = bincode::deserialize::<LegacyCollabSnapshot>(value)?;
+        Ok(Self {
+          data: legacy.data,
+          created_at: legacy.created_at,
+          encoder_version: EncoderVersion::V1,
+        })
+      },
+    }
   }
 }
@@ -0,0 +1,72 @@
+use std::fmt::Debug;
+
+use collab::core::commit::TransactionMeta;
+
+use crate::doc::get_doc_id;
+use crate::keys::{make_txn_meta_key, Clock};
+use crate::kv::KVEntry;
+use crate::kv::KVStore;
+use crate::{create_update_key, PersistenceError};
+
+impl<'a, T> TransactionMetaAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Stores the [TransactionMeta] of the local transactions, for example, to show the activity
+/// feed or the audit log of an object. The metadata is kept when the updates of the document are
+/// flushed into the document state, and it's deleted together with the document.
+pub trait TransactionMetaAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Append the metadata of a transaction to the given object id.
+  fn push_txn_meta<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+    meta: &TransactionMeta,
+  ) -> Result<(), PersistenceError> {
+    let doc_id = get_doc_id(uid, self, object_id).ok_or(PersistenceError::DocumentNotExist)?;
+    let key = create_update_key(doc_id, self, object_id, make_txn_meta_key)?;
+    self.insert(key, bincode::serialize(meta)?)?;
+    Ok(())
+  }
+
+  /// Return the metadata of the transactions of the given object id, from the oldest to the
+  /// newest.
+  fn get_txn_metas<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<Vec<TransactionMeta>, PersistenceError> {
+    let mut metas = vec![];
+    if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let start = make_txn_meta_key(doc_id, 0);
+      let end = make_txn_meta_key(doc_id, Clock::MAX);
+      for entry in self.range(start.as_ref()..=end.as_ref())? {
+        match bincode::deserialize::<TransactionMeta>(entry.value()) {
+          Ok(meta) => metas.push(meta),
+          Err(e) => tracing::error!("🔴decode transaction meta failed: {:?}", e),
+        }
+      }
+    }
+    Ok(metas)
+  }
+
+  /// Delete the metadata of all the transactions of the given object id.
+  fn delete_all_txn_metas<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<(), PersistenceError> {
+    if let Some(doc_id) = get_doc_id(uid, self, object_id) {
+      let start = make_txn_meta_key(doc_id, 0);
+      let end = make_txn_meta_key(doc_id, Clock::MAX);
+      self.remove_range(start.as_ref(), end.as_ref())?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Debug;
+
+use collab::core::version::CollabVersion;
+
+use crate::keys::{make_version_id_key, make_version_key, Clock, VersionID};
+use crate::kv::KVEntry;
+use crate::kv::KVStore;
+use crate::{create_update_key, get_id_for_key, make_doc_id_for_key, PersistenceError};
+
+impl<'a, T> VersionAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Stores the [CollabVersion]s of the objects. Unlike the snapshots, a version doesn't contain
+/// the content of the document. The content is recovered from the document, so the versions of
+/// an object are deleted together with its document.
+pub trait VersionAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Append the version to the version history of the given object id.
+  fn create_version<K>(
+    &self,
+    uid: i64,
+    object_id: &K,
+    version: &CollabVersion,
+  ) -> Result<(), PersistenceError>
+  where
+    K: AsRef<[u8]> + ?Sized + Debug,
+  {
+    tracing::trace!("New version for object:{:?}", object_id);
+    let version_id = self.create_version_id(uid, object_id)?;
+    let key = create_update_key(version_id, self, object_id, make_version_key)?;
+    self.insert(key, bincode::serialize(version)?)?;
+    Ok(())
+  }
+
+  /// Return the version history of the given object id, from the oldest to the newest.
+  fn get_versions<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<Vec<CollabVersion>, PersistenceError> {
+    let mut versions = vec![];
+    if let Some(version_id) = get_version_id(uid, self, object_id) {
+      let start = make_version_key(version_id, 0);
+      let end = make_version_key(version_id, Clock::MAX);
+      for entry in self.range(start.as_ref()..=end.as_ref())? {
+        match bincode::deserialize::<CollabVersion>(entry.value()) {
+          Ok(version) => versions.push(version),
+          Err(e) => tracing::error!("🔴decode version failed: {:?}", e),
+        }
+      }
+    }
+    Ok(versions)
+  }
+
+  /// Delete all versions for the given object id.
+  fn delete_all_versions<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<(), PersistenceError> {
+    if let Some(version_id) = get_version_id(uid, self, object_id) {
+      let key = make_version_id_key(&uid.to_be_bytes(), object_id.as_ref());
+      let _ = self.remove(key.as_ref());
+
+      let start = make_version_key(version_id, 0);
+      let end = make_version_key(version_id, Clock::MAX);
+      self.remove_range(start.as_ref(), end.as_ref())?;
+    }
+    Ok(())
+  }
+
+  /// Create a version id for the given object id.
+  fn create_version_id<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<VersionID, PersistenceError> {
+    if let Some(version_id) = get_version_id(uid, self, object_id) {
+      Ok(version_id)
+    } else {
+      let key = make_version_id_key(&uid.to_be_bytes(), object_id.as_ref());
+      make_doc_id_for_key(self, key)
+    }
+  }
+}
+
+pub fn get_version_id<'a, K, S>(uid: i64, store: &S, object_id: &K) -> Option<VersionID>
+where
+  K: AsRef<[u8]> + ?Sized,
+  S: KVStore<'a>,
+{
+  let key = make_version_id_key(&uid.to_be_bytes(), object_id.as_ref());
+  get_id_for_key(store, key)
+}
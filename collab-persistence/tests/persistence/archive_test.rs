@@ -0,0 +1,199 @@
+use collab::preclude::*;
+use collab_entity::CollabType;
+use collab_persistence::archive::{
+  ArchiveAction, ArchiveObject, CollabReferences, WorkspaceArchive,
+};
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::PersistenceError;
+use serde_json::json;
+use test_case::test_case;
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[test]
+fn export_and_import_workspace_test() {
+  let uid = 1;
+  let source = MemoryCollabDB::new();
+  create_collab(
+    &source,
+    uid,
+    "w1",
+    json!({"children": ["d1", "d2", "d3"], "view": "v1"}),
+  );
+  create_collab(
+    &source,
+    uid,
+    "d1",
+    json!({"parent": "w1", "children": ["d2"]}),
+  );
+  create_collab(&source, uid, "d2", json!({"parent": "d1", "children": []}));
+
+  // d3 doesn't exist, so it's skipped.
+  let archive = source
+    .read_txn()
+    .export_workspace(uid, "w1", vec![document("w1")], resolve)
+    .unwrap();
+  let manifest = &archive.manifest;
+  assert_eq!(manifest.workspace_id, "w1");
+  assert_eq!(
+    manifest.objects,
+    vec![document("w1"), document("d1"), document("d2")]
+  );
+  assert_eq!(manifest.ids, vec!["v1".to_string()]);
+
+  let mut bytes = vec![];
+  archive.write_to(&mut bytes).unwrap();
+  let archive = WorkspaceArchive::read_from(bytes.as_slice()).unwrap();
+  assert_eq!(&archive.manifest, manifest);
+
+  // Import the archive into another store, keeping the ids.
+  let target = SqliteCollabDB::open_in_memory().unwrap();
+  target
+    .with_write_txn(|w| w.import_workspace(uid, &archive, &Default::default()))
+    .unwrap();
+  assert_eq!(
+    load_collab(&target, uid, "d1"),
+    json!({"parent": "w1", "children": ["d2"]})
+  );
+
+  // The objects of the archive already exist in the source store.
+  let result = source.with_write_txn(|w| w.import_workspace(uid, &archive, &Default::default()));
+  assert!(matches!(
+    result,
+    Err(PersistenceError::DocumentAlreadyExist)
+  ));
+
+  // Import a copy of the workspace into the source store.
+  let ids = archive.manifest.new_ids("w2");
+  let new_manifest = source
+    .with_write_txn(|w| w.import_workspace(uid, &archive, &ids))
+    .unwrap();
+  assert_eq!(new_manifest.workspace_id, "w2");
+  assert_eq!(new_manifest.objects[0], document("w2"));
+  let new_d1 = &ids["d1"];
+  let new_d2 = &ids["d2"];
+  let new_v1 = &ids["v1"];
+  assert_eq!(new_manifest.ids, vec![new_v1.clone()]);
+  assert_eq!(
+    load_collab(&source, uid, "w2"),
+    json!({"children": [new_d1, new_d2, "d3"], "view": new_v1})
+  );
+  assert_eq!(
+    load_collab(&source, uid, new_d1),
+    json!({"parent": "w2", "children": [new_d2]})
+  );
+
+  // The original workspace is untouched.
+  assert_eq!(
+    load_collab(&source, uid, "d1"),
+    json!({"parent": "w1", "children": ["d2"]})
+  );
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn rollback_failed_import_test<DB: KVTransactionDB>(db: &DB) {
+  let uid = 1;
+  let source = MemoryCollabDB::new();
+  create_collab(&source, uid, "w1", json!({"children": ["d1"]}));
+  create_collab(&source, uid, "d1", json!({"children": []}));
+  let mut archive = source
+    .read_txn()
+    .export_workspace(uid, "w1", vec![document("w1")], resolve)
+    .unwrap();
+
+  // The second w1 fails the import after w1 and d1 were created.
+  archive.manifest.objects.push(document("w1"));
+  let result = db.with_write_txn(|w| w.import_workspace(uid, &archive, &Default::default()));
+  assert!(matches!(
+    result,
+    Err(PersistenceError::DocumentAlreadyExist)
+  ));
+  assert!(!db.read_txn().is_exist(uid, "w1"));
+  assert!(!db.read_txn().is_exist(uid, "d1"));
+
+  // Nothing was left behind, so the archive can be imported once it's fixed.
+  archive.manifest.objects.pop();
+  db.with_write_txn(|w| w.import_workspace(uid, &archive, &Default::default()))
+    .unwrap();
+  assert_eq!(load_collab(db, uid, "d1"), json!({"children": []}));
+}
+
+#[test]
+fn read_invalid_archive_test() {
+  let result = WorkspaceArchive::read_from(b"hello world".as_slice());
+  assert!(matches!(result, Err(PersistenceError::InvalidData(_))));
+
+  let db = MemoryCollabDB::new();
+  create_collab(&db, 1, "w1", json!({"children": []}));
+  let archive = db
+    .read_txn()
+    .export_workspace(1, "w1", vec![document("w1")], resolve)
+    .unwrap();
+  let mut bytes = vec![];
+  archive.write_to(&mut bytes).unwrap();
+  bytes.truncate(bytes.len() - 1);
+  let result = WorkspaceArchive::read_from(bytes.as_slice());
+  assert!(matches!(result, Err(PersistenceError::InvalidData(_))));
+}
+
+fn document(object_id: &str) -> ArchiveObject {
+  ArchiveObject::new(object_id, CollabType::Document)
+}
+
+fn resolve(_object: &ArchiveObject, collab: &Collab) -> CollabReferences {
+  let json = collab.to_json_value();
+  let objects = json["children"]
+    .as_array()
+    .map(|children| {
+      children
+        .iter()
+        .flat_map(|child| child.as_str())
+        .map(document)
+        .collect()
+    })
+    .unwrap_or_default();
+  let ids = json["view"].as_str().map(|id| vec![id.to_string()]);
+  CollabReferences {
+    objects,
+    ids: ids.unwrap_or_default(),
+  }
+}
+
+fn create_collab<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str, value: JsonValue) {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  collab.with_origin_transact_mut(|txn| {
+    for (key, value) in value.as_object().unwrap() {
+      match value {
+        JsonValue::Array(children) => {
+          let children = children
+            .iter()
+            .map(|child| child.as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+          collab.create_array_with_txn(txn, key, children);
+        },
+        value => {
+          collab.insert_with_txn(txn, key, value.as_str().unwrap());
+        },
+      }
+    }
+  });
+  db.with_write_txn(|w| w.create_new_doc(uid, object_id, &collab.transact()))
+    .unwrap();
+}
+
+fn load_collab<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> JsonValue {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  {
+    let mut txn = collab.origin_transact_mut();
+    db.read_txn()
+      .load_doc_with_txn(uid, object_id, &mut txn)
+      .unwrap();
+  }
+  collab.to_json_value()
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use collab_persistence::compact::{CompactAction, CompactionConfig, UpdateLogCompactor};
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use test_case::test_case;
+use yrs::{Doc, GetString, Text, Transact};
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn compact_doc_test<DB: KVTransactionDB>(db: DB) {
+  let oid = "doc_1";
+  let doc = Doc::new();
+  create_doc(&db, oid, &doc);
+  let mut update_bytes = 0;
+  for i in 0..10 {
+    update_bytes += push_text(&db, oid, &doc, &i.to_string());
+  }
+  let stats = db.read_txn().update_log_stats(1, oid).unwrap();
+  assert_eq!(stats.update_count, 10);
+  assert_eq!(stats.update_bytes, update_bytes);
+
+  let result = db.with_write_txn(|w| w.compact_doc(1, oid)).unwrap();
+  assert_eq!(result.merged_updates, 10);
+  assert!(result.bytes_before > update_bytes);
+  assert!(result.bytes_after < result.bytes_before);
+  assert_eq!(db.read_txn().number_of_updates(1, oid), 0);
+  assert_eq!(
+    db.read_txn().update_log_stats(1, oid).unwrap().update_count,
+    0
+  );
+  assert_eq!(load_text(&db, oid), "9876543210");
+
+  // Nothing is merged if there are no updates, and the doc state is left as it is.
+  let noop = db.with_write_txn(|w| w.compact_doc(1, oid)).unwrap();
+  assert_eq!(noop.merged_updates, 0);
+  assert_eq!(noop.bytes_before, result.bytes_after);
+  assert_eq!(noop.bytes_after, result.bytes_after);
+
+  // The updates pushed after compacting are applied on top of the new doc state.
+  push_text(&db, oid, &doc, "a");
+  assert_eq!(db.read_txn().number_of_updates(1, oid), 1);
+  assert_eq!(load_text(&db, oid), "a9876543210");
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn background_compaction_test<DB: KVTransactionDB>(db: DB) {
+  let db = Arc::new(db);
+  let compactor = UpdateLogCompactor::new(
+    Arc::downgrade(&db),
+    CompactionConfig::new().max_update_count(5),
+  );
+  let oid = "doc_1";
+  let doc = Doc::new();
+  create_doc(db.as_ref(), oid, &doc);
+  for i in 0..12 {
+    let len = push_text(db.as_ref(), oid, &doc, &i.to_string());
+    compactor.did_push_update(1, oid, len);
+    if i % 5 == 4 {
+      wait_for_compactions(&compactor, (i / 5 + 1) as u64);
+    }
+  }
+
+  let stats = compactor.stats();
+  assert_eq!(stats.compactions, 2);
+  assert_eq!(stats.failures, 0);
+  assert_eq!(stats.merged_updates, 10);
+  assert!(stats.bytes_after < stats.bytes_before);
+  assert_eq!(db.read_txn().number_of_updates(1, oid), 2);
+  assert_eq!(load_text(db.as_ref(), oid), "11109876543210");
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn compaction_racing_push_update_test<DB: KVTransactionDB>(db: DB) {
+  let db = Arc::new(db);
+  let compactor = UpdateLogCompactor::new(
+    Arc::downgrade(&db),
+    CompactionConfig::new().max_update_count(3),
+  );
+  let oid = "doc_1";
+  let doc = Doc::new();
+  create_doc(db.as_ref(), oid, &doc);
+
+  // The updates are pushed while the compactor merges the previous ones.
+  for i in 0..50 {
+    let len = push_text(db.as_ref(), oid, &doc, &(i % 10).to_string());
+    compactor.did_push_update(1, oid, len);
+  }
+  // Stopping processes the pending commands first.
+  compactor.stop();
+  let stats = compactor.stats();
+  assert!(stats.compactions > 0);
+  assert_eq!(stats.failures, 0);
+
+  // Each update is either merged exactly once or still in the update log.
+  let remaining = db.read_txn().number_of_updates(1, oid);
+  assert_eq!(stats.merged_updates as usize + remaining, 50);
+  assert_eq!(load_text(db.as_ref(), oid), doc_text(&doc));
+
+  // The commands sent after stopping are ignored.
+  compactor.compact(1, oid);
+  assert_eq!(compactor.stats(), stats);
+}
+
+fn wait_for_compactions(compactor: &UpdateLogCompactor, compactions: u64) {
+  for _ in 0..50 {
+    if compactor.stats().compactions >= compactions {
+      return;
+    }
+    thread::sleep(Duration::from_millis(20));
+  }
+}
+
+fn create_doc<DB: KVTransactionDB>(db: &DB, oid: &str, doc: &Doc) {
+  let txn = doc.transact();
+  db.with_write_txn(|w| w.create_new_doc(1, oid, &txn))
+    .unwrap();
+}
+
+fn push_text<DB: KVTransactionDB>(db: &DB, oid: &str, doc: &Doc, s: &str) -> usize {
+  let text = doc.get_or_insert_text("text");
+  let mut txn = doc.transact_mut();
+  text.insert(&mut txn, 0, s);
+  let update = txn.encode_update_v1();
+  db.with_write_txn(|w| w.push_update(1, oid, &update))
+    .unwrap();
+  update.len()
+}
+
+fn load_text<DB: KVTransactionDB>(db: &DB, oid: &str) -> String {
+  let doc = Doc::new();
+  {
+    let mut txn = doc.transact_mut();
+    db.read_txn().load_doc_with_txn(1, oid, &mut txn).unwrap();
+  }
+  doc_text(&doc)
+}
+
+fn doc_text(doc: &Doc) -> String {
+  let text = doc.get_or_insert_text("text");
+  let txn = doc.transact();
+  text.get_string(&txn)
+}
@@ -0,0 +1,164 @@
+use collab::core::encoding::EncoderVersion;
+use collab::preclude::*;
+use collab_persistence::compact::CompactAction;
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::encoding::EncodingAction;
+use collab_persistence::integrity::IntegrityAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::snapshot::{CollabSnapshot, SnapshotAction};
+use serde_json::json;
+use test_case::test_case;
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn encoding_test<DB: KVTransactionDB>(db: &DB) {
+  let uid = 1;
+  assert_eq!(
+    db.read_txn().get_encoder_version().unwrap(),
+    EncoderVersion::V1
+  );
+  let doc_1 = create_doc(db, uid, "1");
+  push_update(db, uid, "1", &doc_1, "1", "a");
+
+  // The documents that are created after changing the encoder version of the store are encoded
+  // with the v2 encoding. The existing documents keep the v1 encoding.
+  db.with_write_txn(|w| w.set_encoder_version(EncoderVersion::V2))
+    .unwrap();
+  let doc_2 = create_doc(db, uid, "2");
+  push_update(db, uid, "2", &doc_2, "2", "b");
+  push_update(db, uid, "1", &doc_1, "3", "c");
+  assert_eq!(doc_encoder_version(db, uid, "1"), EncoderVersion::V1);
+  assert_eq!(doc_encoder_version(db, uid, "2"), EncoderVersion::V2);
+  assert_eq!(load_doc(db, uid, "1"), json!({"1": "a", "3": "c"}));
+  assert_eq!(load_doc(db, uid, "2"), json!({"2": "b"}));
+
+  // The updates are returned with the v1 encoding.
+  let updates = db.read_txn().get_all_updates(uid, "2").unwrap();
+  assert_eq!(updates.len(), 1);
+  assert!(EncoderVersion::V1.decode_update(&updates[0]).is_ok());
+
+  db.with_write_txn(|w| w.compact_doc(uid, "2")).unwrap();
+  assert_eq!(load_doc(db, uid, "2"), json!({"2": "b"}));
+
+  // Convert the remaining v1 documents.
+  let count = db
+    .with_write_txn(|w| w.convert_all_docs(uid, EncoderVersion::V2))
+    .unwrap();
+  assert_eq!(count, 1);
+  assert_eq!(doc_encoder_version(db, uid, "1"), EncoderVersion::V2);
+  assert_eq!(load_doc(db, uid, "1"), json!({"1": "a", "3": "c"}));
+  assert!(db.read_txn().verify_integrity().unwrap().is_ok());
+
+  // Convert a document back to the v1 encoding.
+  assert!(db
+    .with_write_txn(|w| w.convert_doc(uid, "2", EncoderVersion::V1))
+    .unwrap());
+  assert!(!db
+    .with_write_txn(|w| w.convert_doc(uid, "2", EncoderVersion::V1))
+    .unwrap());
+  assert_eq!(doc_encoder_version(db, uid, "2"), EncoderVersion::V1);
+  assert_eq!(load_doc(db, uid, "2"), json!({"2": "b"}));
+
+  // Flushing a document encodes it with the encoder version of the store.
+  db.with_write_txn(|w| w.flush_doc_with_txn(uid, "2", &doc_2.transact()))
+    .unwrap();
+  assert_eq!(doc_encoder_version(db, uid, "2"), EncoderVersion::V2);
+  assert_eq!(load_doc(db, uid, "2"), json!({"2": "b"}));
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn snapshot_encoding_test<DB: KVTransactionDB>(db: DB) {
+  let uid = 1;
+  db.with_write_txn(|w| w.set_encoder_version(EncoderVersion::V2))
+    .unwrap();
+  let doc = Doc::new();
+  doc
+    .get_or_insert_map("data")
+    .insert(&mut doc.transact_mut(), "1", "a");
+  let data = doc
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  db.with_write_txn(|w| w.create_snapshot_with_data(uid, "1", data))
+    .unwrap();
+
+  let snapshots = db.read_txn().get_snapshots(uid, "1");
+  assert_eq!(snapshots[0].encoder_version, EncoderVersion::V2);
+  let restored = Doc::new();
+  restored
+    .transact_mut()
+    .apply_update(snapshots[0].decode_update().unwrap());
+  assert_eq!(
+    restored
+      .get_or_insert_map("data")
+      .to_json(&restored.transact()),
+    doc.get_or_insert_map("data").to_json(&doc.transact())
+  );
+}
+
+#[test]
+fn decode_legacy_snapshot_test() {
+  // The bincode layout of the snapshots without the encoder version: the length of the data
+  // as u64, the data, and the created_at as i64.
+  let mut bytes = 3u64.to_le_bytes().to_vec();
+  bytes.extend([1, 2, 3]);
+  bytes.extend(10i64.to_le_bytes());
+  let snapshot = CollabSnapshot::try_from(bytes.as_slice()).unwrap();
+  assert_eq!(snapshot.data, vec![1, 2, 3]);
+  assert_eq!(snapshot.created_at, 10);
+  assert_eq!(snapshot.encoder_version, EncoderVersion::V1);
+
+  let snapshot = CollabSnapshot::new_with_encoder_version(vec![4], EncoderVersion::V2);
+  let decoded = CollabSnapshot::try_from(snapshot.to_vec().as_slice()).unwrap();
+  assert_eq!(decoded.data, vec![4]);
+  assert_eq!(decoded.encoder_version, EncoderVersion::V2);
+}
+
+fn create_doc<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> Collab {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  db.with_write_txn(|w| w.create_new_doc(uid, object_id, &collab.transact()))
+    .unwrap();
+  collab
+}
+
+fn push_update<DB: KVTransactionDB>(
+  db: &DB,
+  uid: i64,
+  object_id: &str,
+  collab: &Collab,
+  key: &str,
+  value: &str,
+) {
+  let update = collab.with_origin_transact_mut(|txn| {
+    collab.insert_with_txn(txn, key, value);
+    txn.encode_update_v1()
+  });
+  db.with_write_txn(|w| w.push_update(uid, object_id, &update))
+    .unwrap();
+}
+
+fn doc_encoder_version<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> EncoderVersion {
+  db.read_txn()
+    .get_doc_encoder_version(uid, object_id)
+    .unwrap()
+}
+
+fn load_doc<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> JsonValue {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  {
+    let mut txn = collab.origin_transact_mut();
+    let report = db
+      .read_txn()
+      .load_doc_with_txn(uid, object_id, &mut txn)
+      .unwrap();
+    assert!(report.is_clean());
+  }
+  collab.to_json_value()
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use collab::preclude::*;
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::keys::{make_doc_id_key, make_doc_state_key};
+use collab_persistence::kv::encrypted_kv::{
+  EncryptedCollabDB, EncryptionKey, MemoryKeyProvider, REENCRYPT_BATCH_SIZE,
+};
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::{KVEntry, KVStore, KVTransactionDB};
+use collab_persistence::snapshot::SnapshotAction;
+use collab_persistence::{get_id_for_key, PersistenceError};
+use serde_json::json;
+use test_case::test_case;
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+const WORKSPACE_ID: &str = "w1";
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn encryption_test<DB: KVTransactionDB>(db: DB) {
+  let uid = 1;
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  let first_key_id = key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  let db = EncryptedCollabDB::new(db, WORKSPACE_ID, key_provider.clone());
+
+  let collab_1 = create_doc(&db, uid, "1");
+  push_update(&db, uid, "1", &collab_1, "name", "appflowy");
+  let data = collab_1
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  db.with_write_txn(|w| w.create_snapshot_with_data(uid, "1", data))
+    .unwrap();
+  assert_eq!(load_doc(&db, uid, "1"), json!({"name": "appflowy"}));
+
+  // The values stored in the wrapped database are encrypted.
+  let plaintext = b"appflowy";
+  let raw_values = raw_values(db.inner());
+  assert!(!raw_values.is_empty());
+  assert!(raw_values
+    .iter()
+    .all(|value| !value.windows(plaintext.len()).any(|w| w == plaintext)));
+
+  // The store contains values encrypted with different keys after changing the current key.
+  let second_key_id = key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  assert_ne!(first_key_id, second_key_id);
+  let collab_2 = create_doc(&db, uid, "2");
+  push_update(&db, uid, "2", &collab_2, "name", "collab");
+  push_update(&db, uid, "1", &collab_1, "desc", "hello");
+  assert_eq!(
+    load_doc(&db, uid, "1"),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+  assert_eq!(load_doc(&db, uid, "2"), json!({"name": "collab"}));
+
+  // Re-encrypt the values with the current key. The first key can be removed afterwards.
+  let count = db.rotate_keys().unwrap();
+  assert!(count > 0);
+  assert_eq!(db.rotate_keys().unwrap(), 0);
+  key_provider.remove_key(WORKSPACE_ID, first_key_id).unwrap();
+  let db = EncryptedCollabDB::new(db.into_inner(), WORKSPACE_ID, key_provider);
+  assert_eq!(
+    load_doc(&db, uid, "1"),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+  assert_eq!(load_doc(&db, uid, "2"), json!({"name": "collab"}));
+  let snapshots = db.read_txn().get_snapshots(uid, "1");
+  assert_eq!(snapshots.len(), 1);
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn decrypt_tampered_value_test<DB: KVTransactionDB>(db: DB) {
+  let uid = 1;
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  let db = EncryptedCollabDB::new(db, WORKSPACE_ID, key_provider);
+  create_doc(&db, uid, "1");
+  let doc_id = get_id_for_key(
+    &db.read_txn(),
+    make_doc_id_key(&uid.to_be_bytes(), "1".as_bytes()),
+  )
+  .unwrap();
+  let doc_state_key = make_doc_state_key(doc_id);
+
+  // A value that is modified or moved to another key can't be decrypted.
+  let mut value = db
+    .inner()
+    .read_txn()
+    .get(doc_state_key.as_ref())
+    .unwrap()
+    .unwrap()
+    .as_ref()
+    .to_vec();
+  let last = value.len() - 1;
+  value[last] ^= 1;
+  db.inner()
+    .with_write_txn(|w| w.insert(doc_state_key.as_ref(), &value))
+    .unwrap();
+  assert!(matches!(
+    db.read_txn().get(doc_state_key.as_ref()),
+    Err(PersistenceError::Encryption(_))
+  ));
+
+  // A value that is not encrypted can't be read either.
+  db.inner()
+    .with_write_txn(|w| w.insert(doc_state_key.as_ref(), [1, 2, 3]))
+    .unwrap();
+  assert!(matches!(
+    db.read_txn().get(doc_state_key.as_ref()),
+    Err(PersistenceError::Encryption(_))
+  ));
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn missing_encryption_key_test<DB: KVTransactionDB>(db: DB) {
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  let db = EncryptedCollabDB::new(db, WORKSPACE_ID, key_provider.clone());
+  assert!(matches!(
+    db.with_write_txn(|w| w.insert([1], [1])),
+    Err(PersistenceError::EncryptionKeyNotFound { .. })
+  ));
+
+  // The keys of other workspaces can't decrypt the values.
+  key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  db.with_write_txn(|w| w.insert([1], [1])).unwrap();
+  key_provider.add_key("w2", EncryptionKey::generate());
+  let db = EncryptedCollabDB::new(db.into_inner(), "w2", key_provider);
+  assert!(db.read_txn().get([1]).is_err());
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn encrypt_existing_store_test<DB: KVTransactionDB>(db: DB) {
+  let uid = 1;
+  let collab = create_doc(&db, uid, "1");
+  push_update(&db, uid, "1", &collab, "name", "appflowy");
+
+  // The values of the plaintext store can't be read until they are encrypted.
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  let db = EncryptedCollabDB::new(db, WORKSPACE_ID, key_provider);
+  let doc_id_key = make_doc_id_key(&uid.to_be_bytes(), "1".as_bytes());
+  assert!(matches!(
+    db.read_txn().get(doc_id_key.as_ref()),
+    Err(PersistenceError::Encryption(_))
+  ));
+
+  let count = db.encrypt_existing().unwrap();
+  assert_eq!(count, raw_values(db.inner()).len());
+  assert_eq!(db.encrypt_existing().unwrap(), 0);
+  assert_eq!(load_doc(&db, uid, "1"), json!({"name": "appflowy"}));
+  let plaintext = b"appflowy";
+  assert!(raw_values(db.inner())
+    .iter()
+    .all(|value| !value.windows(plaintext.len()).any(|w| w == plaintext)));
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn rotate_keys_in_batches_test<DB: KVTransactionDB>(db: DB) {
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  let first_key_id = key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  let db = EncryptedCollabDB::new(db, WORKSPACE_ID, key_provider.clone());
+  let len = REENCRYPT_BATCH_SIZE * 2 + 1;
+  db.with_write_txn(|w| {
+    for i in 0..len as u32 {
+      w.insert(i.to_be_bytes(), i.to_be_bytes())?;
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  key_provider.add_key(WORKSPACE_ID, EncryptionKey::generate());
+  assert_eq!(db.rotate_keys().unwrap(), len);
+  assert_eq!(db.rotate_keys().unwrap(), 0);
+  key_provider.remove_key(WORKSPACE_ID, first_key_id).unwrap();
+  for i in 0..len as u32 {
+    assert_eq!(
+      db.read_txn().get(i.to_be_bytes()).unwrap().unwrap(),
+      i.to_be_bytes()
+    );
+  }
+}
+
+fn create_doc<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> Collab {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  db.with_write_txn(|w| w.create_new_doc(uid, object_id, &collab.transact()))
+    .unwrap();
+  collab
+}
+
+fn push_update<DB: KVTransactionDB>(
+  db: &DB,
+  uid: i64,
+  object_id: &str,
+  collab: &Collab,
+  key: &str,
+  value: &str,
+) {
+  let update = collab.with_origin_transact_mut(|txn| {
+    collab.insert_with_txn(txn, key, value);
+    txn.encode_update_v1()
+  });
+  db.with_write_txn(|w| w.push_update(uid, object_id, &update))
+    .unwrap();
+}
+
+fn load_doc<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> JsonValue {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  {
+    let mut txn = collab.origin_transact_mut();
+    let report = db
+      .read_txn()
+      .load_doc_with_txn(uid, object_id, &mut txn)
+      .unwrap();
+    assert!(report.is_clean());
+  }
+  collab.to_json_value()
+}
+
+fn raw_values<DB: KVTransactionDB>(db: &DB) -> Vec<Vec<u8>> {
+  db.read_txn()
+    .range::<Vec<u8>, _>(..)
+    .unwrap()
+    .map(|entry| entry.value().to_vec())
+    .collect()
+}
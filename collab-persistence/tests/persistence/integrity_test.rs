@@ -0,0 +1,169 @@
+use collab::preclude::*;
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::get_id_for_key;
+use collab_persistence::integrity::{IntegrityAction, IntegrityIssue};
+use collab_persistence::keys::{
+  make_doc_id_key, make_doc_state_key, make_doc_update_key, make_snapshot_update_key,
+  make_state_vector_key, make_version_key, DocID,
+};
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::{KVStore, KVTransactionDB};
+use collab_persistence::quarantine::QuarantineAction;
+use collab_persistence::snapshot::SnapshotAction;
+use collab_persistence::version::VersionAction;
+use test_case::test_case;
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn integrity_test<DB: KVTransactionDB>(db: &DB) {
+  let uid = 1;
+  let collab = create_collab(db, uid, "1", "hello");
+  let data = collab
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  db.with_write_txn(|w| {
+    w.create_snapshot_with_data(uid, "1", data)?;
+    w.create_version(uid, "1", &collab.create_version(uid, None))
+  })
+  .unwrap();
+  create_collab(db, uid, "2", "world");
+
+  let report = db.read_txn().verify_integrity().unwrap();
+  assert!(report.is_ok(), "{:?}", report.issues);
+  assert_eq!(report.checked_docs, 2);
+
+  // Corrupt the store.
+  let doc_id_1 = get_doc_id(db, uid, "1");
+  let doc_id_2 = get_doc_id(db, uid, "2");
+  db.with_write_txn(|w| {
+    w.push_update(uid, "1", &[255, 255, 255])?;
+    w.insert(make_state_vector_key(doc_id_1), [1, 2, 3])?;
+    w.remove(make_doc_state_key(doc_id_2).as_ref())?;
+    w.insert(make_doc_update_key(doc_id_2 + 1000, 1), [0, 0])?;
+    w.insert(make_snapshot_update_key(doc_id_2 + 1000, 1), [0, 0])?;
+    w.insert(make_version_key(doc_id_2 + 1000, 1), [0, 0])?;
+    Ok(())
+  })
+  .unwrap();
+
+  let report = db.read_txn().verify_integrity().unwrap();
+  assert_eq!(report.checked_docs, 2);
+  let mut issues = report.issues.iter().map(issue_name).collect::<Vec<_>>();
+  issues.sort();
+  assert_eq!(
+    issues,
+    vec![
+      "missing_doc_state",
+      "orphaned_key",
+      "orphaned_key",
+      "orphaned_key",
+      "state_vector_mismatch",
+      "undecodable_update",
+    ]
+  );
+  assert!(report.issues.iter().all(|issue| issue.is_repairable()));
+
+  let repair_report = db
+    .with_write_txn(|w| {
+      let report = w.verify_integrity()?;
+      w.repair_integrity(&report)
+    })
+    .unwrap();
+  assert_eq!(repair_report.repaired.len(), 6);
+  assert!(repair_report.unrepaired.is_empty());
+  let report = db.read_txn().verify_integrity().unwrap();
+  assert!(report.is_ok(), "{:?}", report.issues);
+
+  // The undecodable update is kept in the quarantine and the missing doc state is rebuilt from
+  // the update log.
+  assert_eq!(
+    db.read_txn().get_quarantined_updates(uid, "1").unwrap(),
+    vec![vec![255, 255, 255]]
+  );
+  assert_eq!(load_collab(db, uid, "1").to_json_value()["title"], "hello");
+  assert_eq!(load_collab(db, uid, "2").to_json_value()["title"], "world");
+  assert_eq!(db.read_txn().get_snapshots(uid, "1").len(), 1);
+  assert_eq!(db.read_txn().get_versions(uid, "1").unwrap().len(), 1);
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn undecodable_doc_state_is_not_repaired_test<DB: KVTransactionDB>(db: DB) {
+  let uid = 1;
+  create_collab(&db, uid, "1", "hello");
+  let doc_id = get_doc_id(&db, uid, "1");
+  db.with_write_txn(|w| w.insert(make_doc_state_key(doc_id), [255, 255, 255]))
+    .unwrap();
+
+  let report = db.read_txn().verify_integrity().unwrap();
+  assert_eq!(
+    report.issues,
+    vec![IntegrityIssue::UndecodableDocState {
+      uid,
+      object_id: "1".to_string(),
+      doc_id,
+    }]
+  );
+  let repair_report = db.with_write_txn(|w| w.repair_integrity(&report)).unwrap();
+  assert!(repair_report.repaired.is_empty());
+  assert_eq!(repair_report.unrepaired, report.issues);
+  assert_eq!(
+    db.read_txn()
+      .get(make_doc_state_key(doc_id).as_ref())
+      .unwrap()
+      .unwrap()
+      .as_ref(),
+    [255, 255, 255]
+  );
+}
+
+fn create_collab<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str, title: &str) -> Collab {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  db.with_write_txn(|w| w.create_new_doc(uid, object_id, &collab.transact()))
+    .unwrap();
+  let update = collab.with_origin_transact_mut(|txn| {
+    collab.insert_with_txn(txn, "title", title);
+    txn.encode_update_v1()
+  });
+  db.with_write_txn(|w| w.push_update(uid, object_id, &update))
+    .unwrap();
+  collab
+}
+
+fn load_collab<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> Collab {
+  let collab = Collab::new(uid, object_id, "1", vec![]);
+  {
+    let mut txn = collab.origin_transact_mut();
+    let report = db
+      .read_txn()
+      .load_doc_with_txn(uid, object_id, &mut txn)
+      .unwrap();
+    assert!(report.is_clean());
+  }
+  collab
+}
+
+fn get_doc_id<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> DocID {
+  let key = make_doc_id_key(&uid.to_be_bytes(), object_id.as_bytes());
+  get_id_for_key(&db.read_txn(), key).unwrap()
+}
+
+fn issue_name(issue: &IntegrityIssue) -> &'static str {
+  match issue {
+    IntegrityIssue::OrphanedKey { .. } => "orphaned_key",
+    IntegrityIssue::InvalidId { .. } => "invalid_id",
+    IntegrityIssue::MissingDocState { .. } => "missing_doc_state",
+    IntegrityIssue::UndecodableDocState { .. } => "undecodable_doc_state",
+    IntegrityIssue::MissingStateVector { .. } => "missing_state_vector",
+    IntegrityIssue::StateVectorMismatch { .. } => "state_vector_mismatch",
+    IntegrityIssue::UndecodableUpdate { .. } => "undecodable_update",
+    IntegrityIssue::UndecodableSnapshot { .. } => "undecodable_snapshot",
+    IntegrityIssue::UndecodableVersion { .. } => "undecodable_version",
+  }
+}
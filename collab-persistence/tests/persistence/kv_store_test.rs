@@ -0,0 +1,98 @@
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::{KVStore, KVTransactionDB};
+use collab_persistence::snapshot::SnapshotAction;
+use collab_persistence::PersistenceError;
+use test_case::test_case;
+use yrs::{Doc, GetString, Text, Transact};
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+use crate::util::sqlite_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&sqlite_db().1 ; "sqlite")]
+fn doc_test<DB: KVTransactionDB>(db: &DB) {
+  let doc_1 = Doc::new();
+  let doc_2 = Doc::new();
+  create_doc(db, "doc_1", &doc_1);
+  create_doc(db, "doc_2", &doc_2);
+  for i in 0..10 {
+    push_text(db, "doc_1", &doc_1, &i.to_string());
+  }
+  push_text(db, "doc_2", &doc_2, "hello");
+
+  assert_eq!(db.read_txn().number_of_updates(1, "doc_1"), 10);
+  assert_eq!(db.read_txn().number_of_updates(1, "doc_2"), 1);
+  assert_eq!(load_text(db, "doc_1"), "9876543210");
+  assert_eq!(load_text(db, "doc_2"), "hello");
+  let mut oids = db.read_txn().get_all_docs().unwrap().collect::<Vec<_>>();
+  oids.sort();
+  assert_eq!(oids, vec!["doc_1".to_string(), "doc_2".to_string()]);
+
+  db.with_write_txn(|w| w.create_snapshot_with_data(1, "doc_1", vec![1, 2, 3]))
+    .unwrap();
+  assert_eq!(db.read_txn().get_snapshots(1, "doc_1").len(), 1);
+
+  db.with_write_txn(|w| w.delete_doc(1, "doc_1")).unwrap();
+  assert!(!db.read_txn().is_exist(1, "doc_1"));
+  assert!(db.read_txn().get_snapshots(1, "doc_1").is_empty());
+  assert_eq!(load_text(db, "doc_2"), "hello");
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn rollback_test<DB: KVTransactionDB>(db: &DB) {
+  let result = db.with_write_txn(|w| {
+    w.insert([1, 2, 3], [4, 5, 6])?;
+    assert_eq!(w.get([1, 2, 3])?.unwrap().as_ref(), &[4, 5, 6]);
+    Err::<(), _>(PersistenceError::InvalidData("rollback".to_string()))
+  });
+  assert!(result.is_err());
+  assert!(db.read_txn().get([1, 2, 3]).unwrap().is_none());
+
+  db.with_write_txn(|w| w.insert([1, 2, 3], [4, 5, 6]))
+    .unwrap();
+  assert!(db.read_txn().get([1, 2, 3]).unwrap().is_some());
+}
+
+#[test]
+fn sqlite_store_reopen_test() {
+  let (path, db) = sqlite_db();
+  let doc = Doc::new();
+  create_doc(&db, "doc_1", &doc);
+  push_text(&db, "doc_1", &doc, "hello");
+  drop(db);
+
+  let db = SqliteCollabDB::open(&path).unwrap();
+  assert_eq!(load_text(&db, "doc_1"), "hello");
+}
+
+fn create_doc<DB: KVTransactionDB>(db: &DB, oid: &str, doc: &Doc) {
+  let txn = doc.transact();
+  db.with_write_txn(|w| w.create_new_doc(1, oid, &txn))
+    .unwrap();
+}
+
+fn push_text<DB: KVTransactionDB>(db: &DB, oid: &str, doc: &Doc, s: &str) {
+  let text = doc.get_or_insert_text("text");
+  let mut txn = doc.transact_mut();
+  text.insert(&mut txn, 0, s);
+  let update = txn.encode_update_v1();
+  db.with_write_txn(|w| w.push_update(1, oid, &update))
+    .unwrap();
+}
+
+fn load_text<DB: KVTransactionDB>(db: &DB, oid: &str) -> String {
+  let doc = Doc::new();
+  {
+    let mut txn = doc.transact_mut();
+    db.read_txn().load_doc_with_txn(1, oid, &mut txn).unwrap();
+  }
+  let text = doc.get_or_insert_text("text");
+  let txn = doc.transact();
+  text.get_string(&txn)
+}
@@ -1,4 +1,14 @@
+mod archive_test;
+mod compact_test;
+mod encoding_test;
+mod encryption_test;
+mod integrity_test;
+mod kv_store_test;
+mod quarantine_test;
 mod range_test;
 mod restore_test;
 mod rocksdb_cf_test;
+mod sink_queue_test;
+mod txn_meta_test;
 mod util;
+mod version_test;
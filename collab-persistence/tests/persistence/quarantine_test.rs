@@ -0,0 +1,217 @@
+use collab::core::encoding::EncoderVersion;
+use collab_persistence::doc::{LoadReport, YrsDocAction};
+use collab_persistence::encoding::EncodingAction;
+use collab_persistence::integrity::IntegrityAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::quarantine::QuarantineAction;
+use test_case::test_case;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, GetString, Text, Transact, Update};
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn quarantine_test<DB: KVTransactionDB>(db: &DB) {
+  let doc = Doc::new();
+  {
+    let txn = doc.transact();
+    db.with_write_txn(|w| w.create_new_doc(1, "1", &txn))
+      .unwrap();
+  }
+  push_text(db, &doc, "a");
+  push_text(db, &doc, "b");
+  let bad_update = edit_text(&doc, "c");
+  // Corrupt the update by truncating it.
+  let corrupted_update = bad_update[..bad_update.len() / 2].to_vec();
+  assert!(Update::decode_v1(&corrupted_update).is_err());
+  db.with_write_txn(|w| w.push_update(1, "1", &corrupted_update))
+    .unwrap();
+  // The updates after the corrupted update are still applied.
+  let text = doc.get_or_insert_text("text");
+  let after_update = {
+    let mut txn = doc.transact_mut();
+    text.insert(&mut txn, 1, "d");
+    txn.encode_update_v1()
+  };
+  db.with_write_txn(|w| w.push_update(1, "1", &after_update))
+    .unwrap();
+
+  let (loaded, report) = load_doc(db);
+  assert!(!report.is_clean());
+  assert_eq!(report.update_count, 2);
+  assert_eq!(report.quarantined.len(), 2);
+  assert!(report.quarantined[0].error.is_some());
+  assert!(report.quarantined[1].error.is_none());
+  assert_eq!(report.recovered_count(), 1);
+  // The update after the corrupted one depends on it, so only the first updates are visible.
+  assert_eq!(get_text(&loaded), "ba");
+
+  // Loading doesn't change the database until the updates are quarantined.
+  assert_eq!(db.read_txn().number_of_updates(1, "1"), 4);
+  db.with_write_txn(|w| w.quarantine_updates(1, "1", &report))
+    .unwrap();
+  assert_eq!(db.read_txn().number_of_updates(1, "1"), 2);
+  assert_eq!(
+    db.read_txn().get_quarantined_updates(1, "1").unwrap(),
+    vec![corrupted_update.clone(), after_update.clone()]
+  );
+  let (loaded, report) = load_doc(db);
+  assert!(report.is_clean());
+  assert_eq!(get_text(&loaded), "ba");
+
+  // Re-import the quarantined updates after repairing the corrupted one.
+  let report = db
+    .with_write_txn(|w| {
+      w.reimport_quarantined_updates(1, "1", |update, version| {
+        assert_eq!(version, EncoderVersion::V1);
+        if update == corrupted_update.as_slice() {
+          Some(bad_update.clone())
+        } else {
+          Some(update.to_vec())
+        }
+      })
+    })
+    .unwrap();
+  assert_eq!(report.reimported, 2);
+  assert!(report.rejected.is_empty());
+  assert!(db
+    .read_txn()
+    .get_quarantined_updates(1, "1")
+    .unwrap()
+    .is_empty());
+  let (loaded, report) = load_doc(db);
+  assert!(report.is_clean());
+  assert_eq!(report.update_count, 4);
+  assert_eq!(get_text(&loaded), get_text(&doc));
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn keep_invalid_update_in_quarantine_test<DB: KVTransactionDB>(db: DB) {
+  let doc = Doc::new();
+  {
+    let txn = doc.transact();
+    db.with_write_txn(|w| w.create_new_doc(1, "1", &txn))
+      .unwrap();
+  }
+  push_text(&db, &doc, "a");
+  db.with_write_txn(|w| w.push_update(1, "1", &[255, 255, 255]))
+    .unwrap();
+
+  let (_, report) = load_doc(&db);
+  assert_eq!(report.quarantined.len(), 1);
+  db.with_write_txn(|w| w.quarantine_updates(1, "1", &report))
+    .unwrap();
+
+  // The update is still invalid, so it stays in the quarantine.
+  let report = db
+    .with_write_txn(|w| w.reimport_quarantined_updates(1, "1", |update, _| Some(update.to_vec())))
+    .unwrap();
+  assert_eq!(report.reimported, 0);
+  assert_eq!(report.rejected.len(), 1);
+  assert!(report.rejected[0].error.is_some());
+  assert_eq!(
+    db.read_txn().get_quarantined_updates(1, "1").unwrap().len(),
+    1
+  );
+
+  // The quarantined updates are deleted together with the document.
+  db.with_write_txn(|w| w.delete_doc(1, "1")).unwrap();
+  assert!(db
+    .read_txn()
+    .get_quarantined_updates(1, "1")
+    .unwrap()
+    .is_empty());
+}
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(rocks_db().1 ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn reimport_quarantined_updates_after_conversion_test<DB: KVTransactionDB>(db: DB) {
+  let doc = Doc::new();
+  {
+    let txn = doc.transact();
+    db.with_write_txn(|w| w.create_new_doc(1, "1", &txn))
+      .unwrap();
+  }
+  push_text(&db, &doc, "a");
+  let bad_update = edit_text(&doc, "b");
+  let corrupted_update = bad_update[..bad_update.len() / 2].to_vec();
+  db.with_write_txn(|w| w.push_update(1, "1", &corrupted_update))
+    .unwrap();
+  let after_update = edit_text(&doc, "c");
+  db.with_write_txn(|w| w.push_update(1, "1", &after_update))
+    .unwrap();
+  let (_, report) = load_doc(&db);
+  assert_eq!(report.quarantined.len(), 2);
+  db.with_write_txn(|w| w.quarantine_updates(1, "1", &report))
+    .unwrap();
+
+  // The decodable quarantined update is converted with the document. The corrupted one can't be
+  // decoded, so it keeps the v1 encoding.
+  assert!(db
+    .with_write_txn(|w| w.convert_doc(1, "1", EncoderVersion::V2))
+    .unwrap());
+  let quarantined = db.read_txn().get_quarantined_updates(1, "1").unwrap();
+  assert_eq!(quarantined[0], corrupted_update);
+  assert!(EncoderVersion::V2.decode_update(&quarantined[1]).is_ok());
+
+  let mut versions = vec![];
+  let report = db
+    .with_write_txn(|w| {
+      w.reimport_quarantined_updates(1, "1", |update, version| {
+        versions.push(version);
+        if update == corrupted_update.as_slice() {
+          Some(bad_update.clone())
+        } else {
+          Some(update.to_vec())
+        }
+      })
+    })
+    .unwrap();
+  assert_eq!(versions, vec![EncoderVersion::V1, EncoderVersion::V2]);
+  assert_eq!(report.reimported, 2);
+  assert!(db
+    .read_txn()
+    .get_quarantined_updates(1, "1")
+    .unwrap()
+    .is_empty());
+  let (loaded, report) = load_doc(&db);
+  assert!(report.is_clean());
+  assert_eq!(get_text(&loaded), get_text(&doc));
+  assert!(db.read_txn().verify_integrity().unwrap().is_ok());
+}
+
+fn edit_text(doc: &Doc, s: &str) -> Vec<u8> {
+  let text = doc.get_or_insert_text("text");
+  let mut txn = doc.transact_mut();
+  text.insert(&mut txn, 0, s);
+  txn.encode_update_v1()
+}
+
+fn push_text<DB: KVTransactionDB>(db: &DB, doc: &Doc, s: &str) {
+  let update = edit_text(doc, s);
+  db.with_write_txn(|w| w.push_update(1, "1", &update))
+    .unwrap();
+}
+
+fn load_doc<DB: KVTransactionDB>(db: &DB) -> (Doc, LoadReport) {
+  let doc = Doc::new();
+  let report = {
+    let mut txn = doc.transact_mut();
+    db.read_txn().load_doc_with_txn(1, "1", &mut txn).unwrap()
+  };
+  (doc, report)
+}
+
+fn get_text(doc: &Doc) -> String {
+  let text = doc.get_or_insert_text("text");
+  let txn = doc.transact();
+  text.get_string(&txn)
+}
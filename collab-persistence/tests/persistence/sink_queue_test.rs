@@ -0,0 +1,49 @@
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::sink_queue::SinkQueueAction;
+use test_case::test_case;
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn sink_queue_test<DB: KVTransactionDB>(db: &DB) {
+  db.with_write_txn(|w| {
+    w.insert_sink_msg(1, "1", 3, &[3])?;
+    w.insert_sink_msg(1, "1", 1, &[1])?;
+    w.insert_sink_msg(1, "1", 2, &[2])?;
+    // The object id "10" starts with "1", but its messages are not mixed up.
+    w.insert_sink_msg(1, "10", 1, &[10])?;
+    w.insert_sink_msg(2, "1", 1, &[20])
+  })
+  .unwrap();
+  assert_eq!(
+    db.read_txn().get_sink_msgs(1, "1").unwrap(),
+    vec![(1, vec![1]), (2, vec![2]), (3, vec![3])]
+  );
+  assert_eq!(db.read_txn().count_sink_msgs(1).unwrap(), 4);
+
+  // A merged message replaces the message with the same message id.
+  db.with_write_txn(|w| {
+    w.insert_sink_msg(1, "1", 1, &[1, 2])?;
+    w.remove_sink_msg(1, "1", 2)
+  })
+  .unwrap();
+  assert_eq!(
+    db.read_txn().get_sink_msgs(1, "1").unwrap(),
+    vec![(1, vec![1, 2]), (3, vec![3])]
+  );
+
+  db.with_write_txn(|w| w.remove_all_sink_msgs(1, "1"))
+    .unwrap();
+  assert!(db.read_txn().get_sink_msgs(1, "1").unwrap().is_empty());
+  assert_eq!(
+    db.read_txn().get_sink_msgs(1, "10").unwrap(),
+    vec![(1, vec![10])]
+  );
+  assert_eq!(db.read_txn().count_sink_msgs(1).unwrap(), 1);
+  assert_eq!(db.read_txn().count_sink_msgs(2).unwrap(), 1);
+}
@@ -0,0 +1,70 @@
+use collab::core::commit::TransactionMeta;
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::txn_meta::TransactionMetaAction;
+use collab_persistence::PersistenceError;
+use test_case::test_case;
+use yrs::{Doc, Transact};
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn txn_meta_test<DB: KVTransactionDB>(db: &DB) {
+  // The metadata can't be stored before the document is created.
+  let result = db.with_write_txn(|w| w.push_txn_meta(1, "1", &txn_meta(1, "rename view")));
+  assert!(matches!(result, Err(PersistenceError::DocumentNotExist)));
+
+  let doc = Doc::new();
+  {
+    let txn = doc.transact();
+    db.with_write_txn(|w| {
+      w.create_new_doc(1, "1", &txn)?;
+      w.create_new_doc(1, "2", &txn)
+    })
+    .unwrap();
+  }
+  db.with_write_txn(|w| {
+    w.push_txn_meta(1, "1", &txn_meta(1, "rename view"))?;
+    w.push_txn_meta(1, "1", &txn_meta(2, "paste"))?;
+    w.push_txn_meta(1, "2", &txn_meta(3, "paste"))
+  })
+  .unwrap();
+  assert_eq!(
+    db.read_txn().get_txn_metas(1, "1").unwrap(),
+    vec![txn_meta(1, "rename view"), txn_meta(2, "paste")]
+  );
+
+  // The metadata is kept when the updates are flushed.
+  {
+    let txn = doc.transact();
+    db.with_write_txn(|w| w.flush_doc_with_txn(1, "1", &txn))
+      .unwrap();
+  }
+  assert_eq!(db.read_txn().get_txn_metas(1, "1").unwrap().len(), 2);
+
+  // The metadata is deleted together with the document.
+  db.with_write_txn(|w| w.delete_doc(1, "1")).unwrap();
+  assert!(db.read_txn().get_txn_metas(1, "1").unwrap().is_empty());
+  assert_eq!(
+    db.read_txn().get_txn_metas(1, "2").unwrap(),
+    vec![txn_meta(3, "paste")]
+  );
+}
+
+fn txn_meta(timestamp: i64, action: &str) -> TransactionMeta {
+  let mut meta = TransactionMeta {
+    uid: Some(1),
+    timestamp: Some(timestamp),
+    action: Some(action.to_string()),
+    ..Default::default()
+  };
+  meta
+    .attributes
+    .insert("device".to_string(), "desktop".to_string());
+  meta
+}
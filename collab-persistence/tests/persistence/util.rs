@@ -1,10 +1,15 @@
 use std::path::PathBuf;
+#[cfg(feature = "rocksdb_persistence")]
 use std::sync::Once;
 
+#[cfg(feature = "rocksdb_persistence")]
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
 use tempfile::TempDir;
+#[cfg(feature = "rocksdb_persistence")]
 use tracing_subscriber::{fmt::Subscriber, util::SubscriberInitExt, EnvFilter};
 
+#[cfg(feature = "rocksdb_persistence")]
 pub fn rocks_db() -> (PathBuf, RocksCollabDB) {
   setup_log();
 
@@ -14,6 +19,16 @@ pub fn rocks_db() -> (PathBuf, RocksCollabDB) {
   (path, RocksCollabDB::open(cloned_path).unwrap())
 }
 
+/// Open a sqlite store backed by a file. Use [SqliteCollabDB::open_in_memory] if the test
+/// doesn't need the file.
+pub fn sqlite_db() -> (PathBuf, SqliteCollabDB) {
+  let tempdir = TempDir::new().unwrap();
+  let path = tempdir.into_path().join("collab.db");
+  let cloned_path = path.clone();
+  (path, SqliteCollabDB::open(cloned_path).unwrap())
+}
+
+#[cfg(feature = "rocksdb_persistence")]
 fn setup_log() {
   static START: Once = Once::new();
   START.call_once(|| {
@@ -0,0 +1,56 @@
+use collab::preclude::*;
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::version::VersionAction;
+use test_case::test_case;
+
+#[cfg(feature = "rocksdb_persistence")]
+use crate::util::rocks_db;
+
+#[cfg_attr(feature = "rocksdb_persistence", test_case(&rocks_db().1 ; "rocksdb"))]
+#[test_case(&MemoryCollabDB::new() ; "memory")]
+#[test_case(&SqliteCollabDB::open_in_memory().unwrap() ; "sqlite")]
+fn version_test<DB: KVTransactionDB>(db: &DB) {
+  let uid = 1;
+  let collab = Collab::new(uid, "1", "1", vec![]);
+  db.with_write_txn(|w| w.create_new_doc(uid, "1", &collab.transact()))
+    .unwrap();
+
+  for title in ["hello", "world", "hello world"] {
+    let update = collab.with_origin_transact_mut(|txn| {
+      collab.insert_with_txn(txn, "title", title);
+      txn.encode_update_v1()
+    });
+    db.with_write_txn(|w| w.push_update(uid, "1", &update))
+      .unwrap();
+    let version = collab.create_version(uid, Some(title.to_string()));
+    db.with_write_txn(|w| w.create_version(uid, "1", &version))
+      .unwrap();
+  }
+
+  // Reopen the document and materialize the versions.
+  let reopened = Collab::new(uid, "1", "1", vec![]);
+  {
+    let mut txn = reopened.origin_transact_mut();
+    db.read_txn().load_doc_with_txn(uid, "1", &mut txn).unwrap();
+  }
+  let versions = db.read_txn().get_versions(uid, "1").unwrap();
+  assert_eq!(versions.len(), 3);
+  for (version, title) in versions.iter().zip(["hello", "world", "hello world"]) {
+    assert_eq!(version.label.as_deref(), Some(title));
+    let materialized = reopened.materialize_version(version).unwrap();
+    assert_eq!(materialized.to_json_value()["title"], title);
+  }
+
+  // Restore the first version. The versions after it are kept.
+  reopened.restore_version(&versions[0]).unwrap();
+  assert_eq!(reopened.to_json_value()["title"], "hello");
+  let materialized = reopened.materialize_version(&versions[2]).unwrap();
+  assert_eq!(materialized.to_json_value()["title"], "hello world");
+
+  // The versions are deleted together with the document.
+  db.with_write_txn(|w| w.delete_doc(uid, "1")).unwrap();
+  assert!(db.read_txn().get_versions(uid, "1").unwrap().is_empty());
+}
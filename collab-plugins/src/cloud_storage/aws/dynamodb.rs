@@ -12,6 +12,7 @@ use aws_sdk_dynamodb::types::{
 };
 use aws_sdk_dynamodb::Client;
 use collab::core::collab::MutexCollab;
+use collab::core::commit::TransactionMeta;
 use collab::core::origin::CollabOrigin;
 use collab_sync_client::client::sink::{MsgId, SinkConfig, SinkStrategy};
 
@@ -91,8 +92,8 @@ impl AWSDynamoDB {
     let _ = self.remote_collab.sync(local_collab).await;
   }
 
-  pub fn push_update(&self, update: &[u8]) {
-    self.remote_collab.push_update(update);
+  pub fn push_update(&self, update: &[u8], meta: &TransactionMeta) {
+    self.remote_collab.push_update(update, meta);
   }
 }
 
@@ -28,7 +28,7 @@ pub struct AWSDynamoDBPlugin {
   local_collab: Weak<MutexCollab>,
   aws_dynamodb: Arc<RwLock<Option<AWSDynamoDB>>>,
   state: Arc<RwLock<LoadingState>>,
-  pending_updates: Arc<RwLock<Vec<Vec<u8>>>>,
+  pending_updates: Arc<RwLock<Vec<(Vec<u8>, TransactionMeta)>>>,
 }
 
 impl AWSDynamoDBPlugin {
@@ -97,8 +97,8 @@ impl AWSDynamoDBPlugin {
             weak_pending_updates.upgrade(),
           ) {
             dynamodb.start_sync(local_collab).await;
-            for update in &*pending_updates.read() {
-              dynamodb.push_update(update);
+            for (update, meta) in &*pending_updates.read() {
+              dynamodb.push_update(update, meta);
             }
             *aws_dynamodb.write() = Some(dynamodb);
             *state.write() = LoadingState::Loaded;
@@ -117,11 +117,20 @@ impl CollabPlugin for AWSDynamoDBPlugin {
     }
   }
 
-  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+  fn receive_local_update_with_meta(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    update: &[u8],
+    meta: &TransactionMeta,
+  ) {
     if let Some(aws_dynamodb) = self.aws_dynamodb.write().as_ref() {
-      aws_dynamodb.push_update(update);
+      aws_dynamodb.push_update(update, meta);
     } else {
-      self.pending_updates.write().push(update.to_vec());
+      self
+        .pending_updates
+        .write()
+        .push((update.to_vec(), meta.clone()));
     }
   }
 }
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use collab::core::commit::TransactionMeta;
+use collab::core::encoding::EncoderVersion;
+use collab_entity::CollabObject;
+use collab_persistence::kv::encrypted_kv::{KeyProvider, WorkspaceCipher};
+use tokio::spawn;
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::cloud_storage::msg::MsgId;
+use crate::cloud_storage::{
+  RemoteCollabSnapshot, RemoteCollabState, RemoteCollabStorage, RemoteUpdateReceiver,
+};
+
+/// A [RemoteCollabStorage] that encrypts the updates and the snapshots on the client before they
+/// are sent to the wrapped storage, and decrypts the ones that are received from it. The server
+/// only stores the ciphertext, so it can't read, merge or compact the updates.
+///
+/// The keys are shared by the members of a workspace and supplied by the [KeyProvider]. The id
+/// of the object is used as associated data, so a payload can't be moved to another object.
+pub struct EncryptedRemoteCollabStorage<S> {
+  storage: S,
+  cipher: Arc<WorkspaceCipher>,
+}
+
+impl<S> EncryptedRemoteCollabStorage<S>
+where
+  S: RemoteCollabStorage,
+{
+  pub fn new(storage: S, workspace_id: &str, key_provider: Arc<dyn KeyProvider>) -> Self {
+    let cipher = Arc::new(WorkspaceCipher::new(workspace_id, key_provider));
+    Self { storage, cipher }
+  }
+
+  fn encrypt(&self, object_id: &str, payload: &[u8]) -> Result<Vec<u8>, Error> {
+    let key_id = self.cipher.current_key_id()?;
+    Ok(self.cipher.encrypt(key_id, object_id.as_bytes(), payload)?)
+  }
+
+  fn decrypt(&self, object_id: &str, payload: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(self.cipher.decrypt(object_id.as_bytes(), payload)?)
+  }
+}
+
+#[async_trait]
+impl<S> RemoteCollabStorage for EncryptedRemoteCollabStorage<S>
+where
+  S: RemoteCollabStorage,
+{
+  fn is_enable(&self) -> bool {
+    self.storage.is_enable()
+  }
+
+  async fn get_all_updates(&self, object: &CollabObject) -> Result<Vec<Vec<u8>>, Error> {
+    self
+      .storage
+      .get_all_updates(object)
+      .await?
+      .iter()
+      .map(|update| self.decrypt(&object.object_id, update))
+      .collect()
+  }
+
+  async fn get_snapshots(&self, object_id: &str, limit: usize) -> Vec<RemoteCollabSnapshot> {
+    self
+      .storage
+      .get_snapshots(object_id, limit)
+      .await
+      .into_iter()
+      .flat_map(|snapshot| match self.decrypt(object_id, &snapshot.blob) {
+        Ok(blob) => Some(RemoteCollabSnapshot { blob, ..snapshot }),
+        Err(e) => {
+          tracing::error!("🔴Failed to decrypt snapshot {}: {:?}", snapshot.sid, e);
+          None
+        },
+      })
+      .collect()
+  }
+
+  async fn get_collab_state(&self, object_id: &str) -> Result<Option<RemoteCollabState>, Error> {
+    self.storage.get_collab_state(object_id).await
+  }
+
+  async fn create_snapshot(&self, object: &CollabObject, snapshot: Vec<u8>) -> Result<i64, Error> {
+    let snapshot = self.encrypt(&object.object_id, &snapshot)?;
+    self.storage.create_snapshot(object, snapshot).await
+  }
+
+  async fn send_update(
+    &self,
+    object: &CollabObject,
+    id: MsgId,
+    update: Vec<u8>,
+  ) -> Result<(), Error> {
+    let update = self.encrypt(&object.object_id, &update)?;
+    self.storage.send_update(object, id, update).await
+  }
+
+  /// The [TransactionMeta]s are dropped. They contain the author, the time and the action of the
+  /// edits in plaintext, which must not be readable by the server either.
+  async fn send_update_with_meta(
+    &self,
+    object: &CollabObject,
+    id: MsgId,
+    update: Vec<u8>,
+    _txn_metas: Vec<TransactionMeta>,
+  ) -> Result<(), Error> {
+    self.send_update(object, id, update).await
+  }
+
+  async fn send_init_sync(
+    &self,
+    object: &CollabObject,
+    id: MsgId,
+    init_update: Vec<u8>,
+  ) -> Result<(), Error> {
+    let init_update = self.encrypt(&object.object_id, &init_update)?;
+    self.storage.send_init_sync(object, id, init_update).await
+  }
+
+  /// The received updates are decrypted in the background. The updates that can't be decrypted
+  /// are dropped.
+  fn subscribe_remote_updates(&self, object: &CollabObject) -> Option<RemoteUpdateReceiver> {
+    let mut encrypted_rx = self.storage.subscribe_remote_updates(object)?;
+    let (tx, rx) = unbounded_channel();
+    let cipher = self.cipher.clone();
+    let object_id = object.object_id.clone();
+    spawn(async move {
+      while let Some(update) = encrypted_rx.recv().await {
+        match cipher.decrypt(object_id.as_bytes(), &update) {
+          Ok(update) => {
+            if tx.send(update).is_err() {
+              break;
+            }
+          },
+          Err(e) => tracing::error!(
+            "🔴Failed to decrypt remote update of {}: {:?}",
+            object_id,
+            e
+          ),
+        }
+      }
+    });
+    Some(rx)
+  }
+
+  fn encoder_version(&self) -> EncoderVersion {
+    self.storage.encoder_version()
+  }
+}
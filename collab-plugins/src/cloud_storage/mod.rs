@@ -1,3 +1,5 @@
+#[cfg(feature = "encryption_plugin")]
+pub use encrypted::EncryptedRemoteCollabStorage;
 pub use remote_collab::{
   RemoteCollabSnapshot, RemoteCollabState, RemoteCollabStorage, RemoteUpdateReceiver,
   RemoteUpdateSender,
@@ -11,6 +13,8 @@ pub mod postgres;
 pub mod network_state;
 
 mod channel;
+#[cfg(feature = "encryption_plugin")]
+mod encrypted;
 mod error;
 mod msg;
 mod remote_collab;
@@ -37,6 +37,19 @@ where
   pub(crate) fn push_msg(&mut self, msg_id: MsgId, msg: Msg) {
     self.queue.push(PendingMessage::new(msg, msg_id));
   }
+
+  /// Set the state of the message with the given [MsgId]. The message is not necessarily the
+  /// first one in the queue. Return false if the message is not in the queue.
+  pub(crate) fn set_msg_state(&mut self, msg_id: MsgId, state: MessageState) -> bool {
+    let mut msgs = std::mem::take(&mut self.queue).into_vec();
+    let msg = msgs.iter_mut().find(|msg| msg.msg_id() == msg_id);
+    let found = msg.is_some();
+    if let Some(msg) = msg {
+      msg.set_state(state);
+    }
+    self.queue = BinaryHeap::from(msgs);
+    found
+  }
 }
 
 impl<Msg> Deref for PendingMsgQueue<Msg>
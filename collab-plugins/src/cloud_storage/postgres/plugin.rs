@@ -7,12 +7,13 @@ use std::time::Duration;
 use collab::core::collab::MutexCollab;
 use collab::core::collab_plugin::CollabPluginType;
 use collab::core::collab_state::SnapshotState;
+use collab::core::commit::TransactionMeta;
 use collab::core::origin::CollabOrigin;
 use collab::preclude::{Collab, CollabPlugin};
 use collab::sync_protocol::awareness::Awareness;
 use collab_entity::CollabObject;
 use collab_persistence::doc::YrsDocAction;
-use collab_persistence::kv::rocks_kv::RocksCollabDB;
+use collab_persistence::kv::KVTransactionDB;
 use collab_persistence::TransactionMutExt;
 use parking_lot::RwLock;
 use tokio_retry::strategy::FibonacciBackoff;
@@ -27,25 +28,72 @@ use crate::cloud_storage::remote_collab::{
 };
 use crate::cloud_storage::sink::{SinkConfig, SinkStrategy};
 
-pub struct SupabaseDBPlugin {
+/// The local updates and their metadata that are received before the first sync is done.
+type PendingUpdates = RwLock<Vec<(Vec<u8>, TransactionMeta)>>;
+
+/// Sync the collab with the remote storage. The local collab storage can be any
+/// [KVTransactionDB], the one that the collab is persisted to.
+pub struct SupabaseDBPlugin<DB> {
   uid: i64,
   object: CollabObject,
   local_collab: Weak<MutexCollab>,
-  local_collab_storage: Weak<RocksCollabDB>,
+  local_collab_storage: Weak<DB>,
   remote_collab: Arc<RemoteCollab>,
   remote_collab_storage: Arc<dyn RemoteCollabStorage>,
-  pending_updates: Arc<RwLock<Vec<Vec<u8>>>>,
+  pending_updates: Arc<PendingUpdates>,
   is_first_sync_done: Arc<AtomicBool>,
 }
 
-impl SupabaseDBPlugin {
+impl<DB: KVTransactionDB> SupabaseDBPlugin<DB> {
   pub fn new(
     uid: i64,
     object: CollabObject,
     local_collab: Weak<MutexCollab>,
     sync_per_secs: u64,
     remote_collab_storage: Arc<dyn RemoteCollabStorage>,
-    local_collab_storage: Weak<RocksCollabDB>,
+    local_collab_storage: Weak<DB>,
+  ) -> Self {
+    Self::new_with_options(
+      uid,
+      object,
+      local_collab,
+      sync_per_secs,
+      remote_collab_storage,
+      local_collab_storage,
+      false,
+    )
+  }
+
+  /// Same as [SupabaseDBPlugin::new], but the local changes that are waiting to be sent to the
+  /// remote are persisted in the `local_collab_storage`. The changes that were not acked by the
+  /// remote before the app quit are sent again when the plugin is created.
+  pub fn new_with_durable_sink(
+    uid: i64,
+    object: CollabObject,
+    local_collab: Weak<MutexCollab>,
+    sync_per_secs: u64,
+    remote_collab_storage: Arc<dyn RemoteCollabStorage>,
+    local_collab_storage: Weak<DB>,
+  ) -> Self {
+    Self::new_with_options(
+      uid,
+      object,
+      local_collab,
+      sync_per_secs,
+      remote_collab_storage,
+      local_collab_storage,
+      true,
+    )
+  }
+
+  fn new_with_options(
+    uid: i64,
+    object: CollabObject,
+    local_collab: Weak<MutexCollab>,
+    sync_per_secs: u64,
+    remote_collab_storage: Arc<dyn RemoteCollabStorage>,
+    local_collab_storage: Weak<DB>,
+    durable_sink: bool,
   ) -> Self {
     let pending_updates = Arc::new(RwLock::new(Vec::new()));
     let is_first_sync_done = Arc::new(AtomicBool::new(false));
@@ -55,12 +103,23 @@ impl SupabaseDBPlugin {
       .with_strategy(SinkStrategy::FixInterval(Duration::from_secs(
         sync_per_secs,
       )));
-    let remote_collab = Arc::new(RemoteCollab::new(
-      object.clone(),
-      remote_collab_storage.clone(),
-      config,
-      local_collab.clone(),
-    ));
+    let remote_collab = if durable_sink {
+      RemoteCollab::new_with_durable_sink(
+        object.clone(),
+        remote_collab_storage.clone(),
+        config,
+        local_collab.clone(),
+        local_collab_storage.clone(),
+      )
+    } else {
+      RemoteCollab::new(
+        object.clone(),
+        remote_collab_storage.clone(),
+        config,
+        local_collab.clone(),
+      )
+    };
+    let remote_collab = Arc::new(remote_collab);
 
     // Subscribe the sync state from the remote collab
     let remote_sync_state = remote_collab.subscribe_sync_state();
@@ -87,7 +146,19 @@ impl SupabaseDBPlugin {
   }
 }
 
-impl CollabPlugin for SupabaseDBPlugin {
+impl<DB> SupabaseDBPlugin<DB> {
+  /// Return the number of local changes that are not acked by the remote yet.
+  pub fn pending_changes_count(&self) -> usize {
+    let mut count = self.remote_collab.pending_msg_count();
+    // The updates are kept in memory until the first sync is done.
+    if !self.is_first_sync_done.load(Ordering::SeqCst) {
+      count += self.pending_updates.read().len();
+    }
+    count
+  }
+}
+
+impl<DB: KVTransactionDB> CollabPlugin for SupabaseDBPlugin<DB> {
   fn did_init(&self, _awareness: &Awareness, _object_id: &str) {
     // TODO(nathan): retry action might take a long time even if the network is ready or enable of
     // the [RemoteCollabStorage] is true
@@ -108,11 +179,20 @@ impl CollabPlugin for SupabaseDBPlugin {
     });
   }
 
-  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+  fn receive_local_update_with_meta(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    update: &[u8],
+    meta: &TransactionMeta,
+  ) {
     if self.is_first_sync_done.load(Ordering::SeqCst) {
-      self.remote_collab.push_update(update);
+      self.remote_collab.push_update(update, meta);
     } else {
-      self.pending_updates.write().push(update.to_vec());
+      self
+        .pending_updates
+        .write()
+        .push((update.to_vec(), meta.clone()));
     }
   }
 
@@ -129,12 +209,12 @@ impl CollabPlugin for SupabaseDBPlugin {
 /// Create a snapshot for the object if need
 /// If the remote_update is empty which means the object is not sync. So crate a snapshot for it.
 /// If the remote_update is not empty, check the [RemoteCollabState] to decide whether create a snapshot.
-fn create_snapshot_if_need(
+fn create_snapshot_if_need<DB: KVTransactionDB>(
   uid: i64,
   object: CollabObject,
   remote_update: Vec<u8>,
   weak_local_collab: Weak<MutexCollab>,
-  weak_local_collab_storage: Weak<RocksCollabDB>,
+  weak_local_collab_storage: Weak<DB>,
   weak_remote_collab_storage: Weak<dyn RemoteCollabStorage>,
 ) {
   tokio::spawn(async move {
@@ -162,6 +242,7 @@ fn create_snapshot_if_need(
 
       tracing::trace!("Create remote snapshot for {}", object.object_id);
       let cloned_object = object.clone();
+      let encoder_version = remote_collab_storage.encoder_version();
       if let Ok(Ok(doc_state)) = tokio::task::spawn_blocking(move || {
         let local = Collab::new(uid, object.object_id.clone(), &object.device_id, vec![]);
         let mut txn = local.origin_transact_mut();
@@ -188,7 +269,9 @@ fn create_snapshot_if_need(
         }
 
         let txn = local.transact();
-        Ok::<Vec<_>, anyhow::Error>(txn.encode_state_as_update_v1(&StateVector::default()))
+        Ok::<Vec<_>, anyhow::Error>(
+          encoder_version.encode_state_as_update(&txn, &StateVector::default()),
+        )
       })
       .await
       {
@@ -212,18 +295,18 @@ fn create_snapshot_if_need(
   });
 }
 
-struct InitSyncAction {
+struct InitSyncAction<DB> {
   uid: i64,
   object: CollabObject,
   remote_collab: Weak<RemoteCollab>,
   local_collab: Weak<MutexCollab>,
-  local_collab_storage: Weak<RocksCollabDB>,
+  local_collab_storage: Weak<DB>,
   remote_collab_storage: Weak<dyn RemoteCollabStorage>,
-  pending_updates: Weak<RwLock<Vec<Vec<u8>>>>,
+  pending_updates: Weak<PendingUpdates>,
   is_first_sync_done: Weak<AtomicBool>,
 }
 
-impl Action for InitSyncAction {
+impl<DB: KVTransactionDB> Action for InitSyncAction<DB> {
   type Future = Pin<Box<dyn Future<Output = Result<Self::Item, Self::Error>> + Send>>;
   type Item = ();
   type Error = anyhow::Error;
@@ -261,8 +344,8 @@ impl Action for InitSyncAction {
           weak_remote_collab_storage,
         );
 
-        for update in &*pending_updates.read() {
-          remote_collab.push_update(update);
+        for (update, meta) in &*pending_updates.read() {
+          remote_collab.push_update(update, meta);
         }
 
         is_first_sync_done.store(true, Ordering::SeqCst);
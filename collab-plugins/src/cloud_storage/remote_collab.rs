@@ -8,8 +8,15 @@ use anyhow::Error;
 use async_trait::async_trait;
 use collab::core::collab::{MutexCollab, TransactionMutExt};
 use collab::core::collab_state::SyncState;
+use collab::core::commit::TransactionMeta;
+use collab::core::encoding::{convert_update, EncoderVersion};
 use collab::core::origin::CollabOrigin;
 use collab_entity::{CollabObject, CollabType};
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::sink_queue::SinkQueueAction;
+use collab_persistence::PersistenceError;
+use lib0::decoding::{Cursor, Read};
+use lib0::encoding::Write;
 use parking_lot::Mutex;
 use rand::Rng;
 use serde::Deserialize;
@@ -19,12 +26,15 @@ use tokio::sync::watch;
 use tokio_stream::wrappers::WatchStream;
 use tokio_stream::StreamExt;
 use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
 use yrs::{merge_updates_v1, ReadTxn, Transact, Update};
 
 use crate::cloud_storage::channel::TokioUnboundedSink;
+use crate::cloud_storage::error::SyncError;
 use crate::cloud_storage::msg::{CollabSinkMessage, MsgId};
 use crate::cloud_storage::sink::{
-  CollabSink, CollabSinkRunner, MsgIdCounter, SinkConfig, SinkState,
+  CollabSink, CollabSinkRunner, CollabSinkStorage, MsgIdCounter, SinkConfig, SinkState,
+  SinkStorageChange,
 };
 
 /// The [RemoteCollab] is used to sync the local collab to the remote.
@@ -55,6 +65,38 @@ impl RemoteCollab {
     storage: Arc<dyn RemoteCollabStorage>,
     config: SinkConfig,
     local_collab: Weak<MutexCollab>,
+  ) -> Self {
+    Self::new_with_sink_storage(object, storage, config, local_collab, None)
+  }
+
+  /// Create a new remote collab whose pending messages are persisted in the local `db`. The
+  /// messages that were not acked by the server before the app quit are sent again.
+  pub fn new_with_durable_sink<DB: KVTransactionDB>(
+    object: CollabObject,
+    storage: Arc<dyn RemoteCollabStorage>,
+    config: SinkConfig,
+    local_collab: Weak<MutexCollab>,
+    db: Weak<DB>,
+  ) -> Self {
+    let sink_storage = KVSinkStorage {
+      object: object.clone(),
+      db,
+    };
+    Self::new_with_sink_storage(
+      object,
+      storage,
+      config,
+      local_collab,
+      Some(Arc::new(sink_storage)),
+    )
+  }
+
+  fn new_with_sink_storage(
+    object: CollabObject,
+    storage: Arc<dyn RemoteCollabStorage>,
+    config: SinkConfig,
+    local_collab: Weak<MutexCollab>,
+    sink_storage: Option<Arc<dyn CollabSinkStorage<Message>>>,
   ) -> Self {
     let is_init_sync_finish = Arc::new(AtomicBool::new(false));
     let sync_state = Arc::new(watch::channel(SyncState::SyncInitStart).0);
@@ -67,18 +109,23 @@ impl RemoteCollab {
     let weak_storage = Arc::downgrade(&storage);
     let (notifier, notifier_rx) = watch::channel(false);
     let (sync_state_tx, sink_state_rx) = watch::channel(SinkState::Init);
-    let collab_sink = Arc::new(CollabSink::new(
+    let mut collab_sink = CollabSink::new(
       object.uid,
       TokioUnboundedSink(sink),
       notifier,
       sync_state_tx,
       RngMsgIdCounter::new(),
       config,
-    ));
+    );
+    if let Some(sink_storage) = sink_storage {
+      collab_sink = collab_sink.with_storage(sink_storage);
+    }
+    let collab_sink = Arc::new(collab_sink);
 
     // spawns an asynchronous task to continuously listen to the updates stream
     // and process them as they come in.
     let cloned_is_init_sync_finish = is_init_sync_finish.clone();
+    let encoder_version = storage.encoder_version();
     if let Some(mut collab_stream) = storage.subscribe_remote_updates(&object) {
       spawn(async move {
         while let Some(update) = collab_stream.recv().await {
@@ -88,7 +135,7 @@ impl RemoteCollab {
           if let Some(local_collab) = local_collab.upgrade() {
             if let Some(collab) = local_collab.try_lock_for(Duration::from_secs(1)) {
               if let Ok(mut txn) = collab.try_transaction_mut() {
-                match Update::decode_v1(&update) {
+                match encoder_version.decode_update(&update) {
                   Ok(update) => {
                     if let Err(e) = txn.try_apply_update(update) {
                       tracing::error!("apply remote update failed: {:?}", e);
@@ -138,8 +185,8 @@ impl RemoteCollab {
             continue;
           }
           let is_init_msg = message.is_init_msg();
-          match message.split() {
-            Ok((object, msg_id, payload)) => {
+          match message.split(storage.encoder_version()) {
+            Ok((object, msg_id, payload, txn_metas)) => {
               // If the message is init message, it will flush all the updates to the remote.
               if is_init_msg {
                 tracing::trace!("send init sync {}:{}", object, msg_id);
@@ -161,7 +208,10 @@ impl RemoteCollab {
                 }
               } else {
                 tracing::trace!("send update {}:{}", object, msg_id);
-                match storage.send_update(&object, msg_id, payload).await {
+                match storage
+                  .send_update_with_meta(&object, msg_id, payload, txn_metas)
+                  .await
+                {
                   Ok(_) => {
                     tracing::debug!("ack update {}:{}", object, msg_id);
                     if let Some(collab_sink) = weak_collab_sink.upgrade() {
@@ -204,9 +254,11 @@ impl RemoteCollab {
 
   /// Return the update of the remote collab.
   /// If the remote collab contains any updates, it will return None.
-  /// Otherwise, it will merge the updates into one and return the merged update.
+  /// Otherwise, it will merge the updates into one and return the merged update. The returned
+  /// update is encoded with the v1 encoding.
   pub async fn sync(&self, local_collab: Arc<MutexCollab>) -> Result<Vec<u8>, Error> {
     let mut remote_update = vec![];
+    let encoder_version = self.storage.encoder_version();
     // It would be better if creating a edge function that calculate the diff between the local and remote.
     // The local only need to send its state vector to the remote. In this way, the local does not need to
     // get all the updates from remote.
@@ -219,21 +271,20 @@ impl RemoteCollab {
         .map(|update| update.as_ref())
         .collect::<Vec<&[u8]>>();
 
-      if let Ok(update) = merge_updates_v1(&updates) {
+      if let Ok(update) = encoder_version.merge_updates(&updates) {
         tracing::trace!("{}: sync remote updates:{}", self.object, update.len());
         // Restore the remote collab state from updates
         {
           let remote_collab = self.collab.lock();
           let mut txn = remote_collab.origin_transact_mut();
-          if let Ok(update) = Update::decode_v1(&update) {
+          if let Ok(update) = encoder_version.decode_update(&update) {
+            remote_update = update.encode_v1();
             if let Err(e) = txn.try_apply_update(update) {
               tracing::error!("apply update failed: {:?}", e);
             }
           } else {
             tracing::error!("🔴decode update failed");
           }
-
-          remote_update = update;
         }
       }
 
@@ -290,12 +341,15 @@ impl RemoteCollab {
         object: self.object.clone(),
         payloads: vec![encode_update],
         meta: MessageMeta::Init { msg_id },
+        txn_metas: vec![],
       });
     }
     Ok(remote_update)
   }
 
-  pub fn push_update(&self, update: &[u8]) {
+  /// Push a local update to the remote. The [TransactionMeta] of the update is sent together
+  /// with it, see [RemoteCollabStorage::send_update_with_meta].
+  pub fn push_update(&self, update: &[u8], txn_meta: &TransactionMeta) {
     if let Ok(decode_update) = Update::decode_v1(update) {
       self.collab.lock().with_origin_transact_mut(|txn| {
         txn.apply_update(decode_update);
@@ -305,6 +359,7 @@ impl RemoteCollab {
         object: self.object.clone(),
         payloads: vec![update.to_vec()],
         meta: MessageMeta::Update { msg_id },
+        txn_metas: vec![txn_meta.clone()],
       });
     }
   }
@@ -312,6 +367,11 @@ impl RemoteCollab {
   pub fn clear(&self) {
     self.sink.remove_all_pending_msgs();
   }
+
+  /// Return the number of local changes that are not acked by the server yet.
+  pub fn pending_msg_count(&self) -> usize {
+    self.sink.pending_msg_count()
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -382,6 +442,19 @@ pub trait RemoteCollabStorage: Send + Sync + 'static {
     update: Vec<u8>,
   ) -> Result<(), anyhow::Error>;
 
+  /// Send the update together with the [TransactionMeta] of the local transactions it's merged
+  /// from, in the order they were committed. Override it to store the metadata, for example, for
+  /// the audit log. By default, the metadata is dropped.
+  async fn send_update_with_meta(
+    &self,
+    object: &CollabObject,
+    id: MsgId,
+    update: Vec<u8>,
+    _txn_metas: Vec<TransactionMeta>,
+  ) -> Result<(), anyhow::Error> {
+    self.send_update(object, id, update).await
+  }
+
   /// The init sync is used to send the initial state of the remote collab to the remote storage.
   /// The init_update contains all the missing updates of the remote collab compared to the local.
   async fn send_init_sync(
@@ -393,6 +466,13 @@ pub trait RemoteCollabStorage: Send + Sync + 'static {
 
   /// Subscribe the remote updates.
   fn subscribe_remote_updates(&self, object: &CollabObject) -> Option<RemoteUpdateReceiver>;
+
+  /// The encoder version of the updates and the snapshots that are stored in the remote storage.
+  /// The updates are converted to this version before being sent, and the updates received from
+  /// the remote storage are decoded with it.
+  fn encoder_version(&self) -> EncoderVersion {
+    EncoderVersion::V1
+  }
 }
 
 pub type RemoteUpdateSender = tokio::sync::mpsc::UnboundedSender<Vec<u8>>;
@@ -432,6 +512,18 @@ where
     (**self).send_update(object, id, update).await
   }
 
+  async fn send_update_with_meta(
+    &self,
+    object: &CollabObject,
+    id: MsgId,
+    update: Vec<u8>,
+    txn_metas: Vec<TransactionMeta>,
+  ) -> Result<(), Error> {
+    (**self)
+      .send_update_with_meta(object, id, update, txn_metas)
+      .await
+  }
+
   async fn send_init_sync(
     &self,
     object: &CollabObject,
@@ -444,6 +536,10 @@ where
   fn subscribe_remote_updates(&self, object: &CollabObject) -> Option<RemoteUpdateReceiver> {
     (**self).subscribe_remote_updates(object)
   }
+
+  fn encoder_version(&self) -> EncoderVersion {
+    (**self).encoder_version()
+  }
 }
 
 #[derive(Clone, Debug)]
@@ -471,6 +567,8 @@ struct Message {
   object: CollabObject,
   meta: MessageMeta,
   payloads: Vec<Vec<u8>>,
+  /// The metadata of the local transactions of the payloads.
+  txn_metas: Vec<TransactionMeta>,
 }
 
 impl Message {
@@ -478,15 +576,63 @@ impl Message {
     self.payloads.iter().map(|p| p.len()).sum()
   }
 
-  fn split(self) -> Result<(CollabObject, MsgId, Vec<u8>), anyhow::Error> {
+  /// Merge the payloads, which are encoded with the v1 encoding, into one update that is
+  /// encoded with the given version.
+  fn split(
+    self,
+    encoder_version: EncoderVersion,
+  ) -> Result<(CollabObject, MsgId, Vec<u8>, Vec<TransactionMeta>), anyhow::Error> {
     let updates = self
       .payloads
       .iter()
       .map(|update| update.as_ref())
       .collect::<Vec<&[u8]>>();
     let update = merge_updates_v1(&updates)?;
+    let update = convert_update(&update, EncoderVersion::V1, encoder_version)?;
     let msg_id = *self.meta.msg_id();
-    Ok((self.object, msg_id, update))
+    Ok((self.object, msg_id, update, self.txn_metas))
+  }
+}
+
+impl Message {
+  /// Encode the kind, the payloads and the transaction metadata of the message. The object and
+  /// the [MsgId] are stored with the key of the message.
+  fn to_vec(&self) -> Result<Vec<u8>, SyncError> {
+    let mut buf = vec![];
+    buf.write_u8(u8::from(self.meta.is_init()));
+    buf.write_var(self.payloads.len());
+    for payload in &self.payloads {
+      buf.write_buf(payload);
+    }
+    buf.write_buf(serde_json::to_vec(&self.txn_metas)?);
+    Ok(buf)
+  }
+
+  fn from_slice(object: CollabObject, msg_id: MsgId, data: &[u8]) -> Result<Self, SyncError> {
+    let mut cursor = Cursor::new(data);
+    let meta = match cursor.read_u8()? {
+      0 => MessageMeta::Update { msg_id },
+      _ => MessageMeta::Init { msg_id },
+    };
+    let len: usize = cursor.read_var()?;
+    let mut payloads = Vec::with_capacity(len);
+    for _ in 0..len {
+      payloads.push(cursor.read_buf()?.to_vec());
+    }
+    // The messages that were saved before the transaction metadata was added don't have it.
+    let txn_metas = match cursor.has_content() {
+      false => vec![],
+      true => match cursor.read_buf()? {
+        [] => vec![],
+        txn_metas => serde_json::from_slice(txn_metas)?,
+      },
+    };
+    Ok(Self {
+      object,
+      meta,
+      payloads,
+      txn_metas,
+    })
   }
 }
 
@@ -508,6 +654,7 @@ impl CollabSinkMessage for Message {
 
   fn merge(&mut self, other: Self) -> bool {
     self.payloads.extend(other.payloads);
+    self.txn_metas.extend(other.txn_metas);
     true
   }
 
@@ -567,6 +714,77 @@ impl Display for Message {
   }
 }
 
+/// Persist the pending [Message]s of a [RemoteCollab] in the local database.
+struct KVSinkStorage<DB> {
+  object: CollabObject,
+  db: Weak<DB>,
+}
+
+impl<DB: KVTransactionDB> KVSinkStorage<DB> {
+  fn with_write_txn<F>(&self, f: F) -> Result<(), SyncError>
+  where
+    F: FnOnce(&DB::TransactionAction<'_>) -> Result<(), PersistenceError>,
+  {
+    if let Some(db) = self.db.upgrade() {
+      db.with_write_txn(f).map_err(internal_error)?;
+    }
+    Ok(())
+  }
+}
+
+impl<DB: KVTransactionDB> CollabSinkStorage<Message> for KVSinkStorage<DB> {
+  fn load_msgs(&self) -> Result<Vec<(MsgId, Message)>, SyncError> {
+    let db = match self.db.upgrade() {
+      None => return Ok(vec![]),
+      Some(db) => db,
+    };
+    let msgs = db
+      .read_txn()
+      .get_sink_msgs(self.object.uid, &self.object.object_id)
+      .map_err(internal_error)?;
+    msgs
+      .into_iter()
+      .map(|(msg_id, data)| {
+        let msg = Message::from_slice(self.object.clone(), msg_id, &data)?;
+        Ok((msg_id, msg))
+      })
+      .collect()
+  }
+
+  fn apply_changes(&self, changes: &[SinkStorageChange<Message>]) -> Result<(), SyncError> {
+    // Encode the messages first, so a message that can't be encoded doesn't leave the
+    // transaction half done.
+    let changes = changes
+      .iter()
+      .map(|change| {
+        Ok(match change {
+          SinkStorageChange::Save(msg_id, msg) => SinkStorageChange::Save(*msg_id, msg.to_vec()?),
+          SinkStorageChange::Remove(msg_id) => SinkStorageChange::Remove(*msg_id),
+          SinkStorageChange::RemoveAll => SinkStorageChange::RemoveAll,
+        })
+      })
+      .collect::<Result<Vec<_>, SyncError>>()?;
+
+    let (uid, object_id) = (self.object.uid, &self.object.object_id);
+    self.with_write_txn(|txn| {
+      for change in changes {
+        match change {
+          SinkStorageChange::Save(msg_id, msg) => {
+            txn.insert_sink_msg(uid, object_id, msg_id, &msg)?
+          },
+          SinkStorageChange::Remove(msg_id) => txn.remove_sink_msg(uid, object_id, msg_id)?,
+          SinkStorageChange::RemoveAll => txn.remove_all_sink_msgs(uid, object_id)?,
+        }
+      }
+      Ok(())
+    })
+  }
+}
+
+fn internal_error(e: PersistenceError) -> SyncError {
+  SyncError::Internal(Box::new(e))
+}
+
 #[derive(Debug, thiserror::Error)]
 enum CollabError {
   #[error("Internal error")]
@@ -39,6 +39,12 @@ pub struct CollabSink<Sink, Msg> {
   /// The [PendingMsgQueue] is used to queue the messages that are waiting to be sent to the
   /// remote. It will merge the messages if possible.
   pending_msg_queue: Arc<parking_lot::Mutex<PendingMsgQueue<Msg>>>,
+  /// Persist the pending messages if it's not None. Check out the [CollabSink::with_storage].
+  storage: Option<Arc<dyn CollabSinkStorage<Msg>>>,
+  /// The changes of the pending messages that are not written to the [CollabSinkStorage] yet.
+  storage_changes: parking_lot::Mutex<Vec<SinkStorageChange<Msg>>>,
+  /// Held while writing the changes, so they are written in the order they were made.
+  storage_write: Mutex<()>,
   msg_id_counter: Arc<dyn MsgIdCounter>,
 
   /// The [watch::Sender] is used to notify the [CollabSinkRunner] to process the pending messages.
@@ -59,6 +65,13 @@ pub struct CollabSink<Sink, Msg> {
 impl<Sink, Msg> Drop for CollabSink<Sink, Msg> {
   fn drop(&mut self) {
     let _ = self.notifier.send(true);
+    // Write the changes that were made after the last message was sent.
+    let changes = std::mem::take(self.storage_changes.get_mut());
+    if let (Some(storage), false) = (&self.storage, changes.is_empty()) {
+      if let Err(e) = storage.apply_changes(&changes) {
+        tracing::error!("🔴Failed to persist pending messages: {:?}", e);
+      }
+    }
   }
 }
 
@@ -98,6 +111,9 @@ where
       uid,
       sender,
       pending_msg_queue,
+      storage: None,
+      storage_changes: Default::default(),
+      storage_write: Default::default(),
       msg_id_counter,
       notifier,
       state_notifier,
@@ -107,6 +123,34 @@ where
     }
   }
 
+  /// Persist the pending messages with the given [CollabSinkStorage]. The messages that were
+  /// persisted before, for example before the app was restarted, are put back into the queue
+  /// with their [MsgId]s, so they are sent again.
+  pub fn with_storage(mut self, storage: Arc<dyn CollabSinkStorage<Msg>>) -> Self {
+    match storage.load_msgs() {
+      Ok(msgs) => {
+        let mut pending_msgs = self.pending_msg_queue.lock();
+        for (msg_id, msg) in msgs {
+          pending_msgs.push_msg(msg_id, msg);
+        }
+        trace!("reload {} pending messages", pending_msgs.len());
+      },
+      Err(e) => tracing::error!("🔴Failed to load pending messages: {:?}", e),
+    }
+    self.storage = Some(storage);
+    self
+  }
+
+  /// Return the number of messages that are not acked by the remote yet.
+  pub fn pending_msg_count(&self) -> usize {
+    self
+      .pending_msg_queue
+      .lock()
+      .iter()
+      .filter(|msg| !msg.state().is_done())
+      .count()
+  }
+
   /// Put the message into the queue and notify the sink to process the next message.
   /// After the [Msg] was pushed into the [PendingMsgQueue]. The queue will pop the next msg base on
   /// its priority. And the message priority is determined by the [Msg] that implement the [Ord] and
@@ -117,6 +161,7 @@ where
       let mut pending_msgs = self.pending_msg_queue.lock();
       let msg_id = self.msg_id_counter.next();
       let msg = f(msg_id);
+      self.record_change(|| SinkStorageChange::Save(msg_id, msg.clone()));
       pending_msgs.push_msg(msg_id, msg);
       drop(pending_msgs);
     }
@@ -125,13 +170,20 @@ where
   }
 
   pub fn remove_all_pending_msgs(&self) {
-    self.pending_msg_queue.lock().clear();
+    let mut pending_msgs = self.pending_msg_queue.lock();
+    pending_msgs.clear();
+    if self.storage.is_some() {
+      let mut storage_changes = self.storage_changes.lock();
+      storage_changes.clear();
+      storage_changes.push(SinkStorageChange::RemoveAll);
+    }
   }
 
   /// Notify the sink to process the next message and mark the current message as done.
   pub async fn ack_msg(&self, object_id: &str, msg_id: MsgId) {
     trace!("receive {} message:{}", object_id, msg_id);
-    if let Some(mut pending_msg) = self.pending_msg_queue.lock().peek_mut() {
+    let mut pending_msgs = self.pending_msg_queue.lock();
+    if let Some(pending_msg) = pending_msgs.peek() {
       // In most cases, the msg_id of the pending_msg is the same as the passed-in msg_id. However,
       // due to network issues, the client might send multiple messages with the same msg_id.
       // Therefore, the msg_id might not always match the msg_id of the pending_msg.
@@ -142,15 +194,21 @@ where
         pending_msg.msg_id(),
         msg_id
       );
-      if pending_msg.msg_id() == msg_id {
-        debug!("{} message:{} was sent", object_id, msg_id);
-        pending_msg.set_state(MessageState::Done);
-        self.notify();
-      }
+    }
+
+    // The acked message is not the first one if a message with a higher priority was queued
+    // while sending it. For example, the init message of a reopened collab is queued after the
+    // reloaded messages.
+    if pending_msgs.set_msg_state(msg_id, MessageState::Done) {
+      debug!("{} message:{} was sent", object_id, msg_id);
+      self.record_change(|| SinkStorageChange::Remove(msg_id));
+      self.notify();
     }
   }
 
   async fn process_next_msg(&self) -> Result<(), SyncError> {
+    self.write_storage_changes().await;
+
     // Check if the next message can be deferred. If not, try to send the message immediately. The
     // default value is true.
     let deferrable = self
@@ -194,7 +252,7 @@ where
 
   async fn try_send_msg_immediately(&self) -> Option<()> {
     let (tx, rx) = oneshot::channel();
-    let collab_msg = {
+    let (msg_id, collab_msg) = {
       let (mut pending_msg_queue, mut sending_msg) = match self.pending_msg_queue.try_lock() {
         None => {
           // If acquire the lock failed, try to notify again after 100ms
@@ -217,20 +275,36 @@ where
         return None;
       }
 
-      // Do nothing if the message is still processing.
+      // Do nothing if the message is still processing. Put it back, so it's removed from the
+      // queue when it's acked.
       if sending_msg.state().is_processing() {
+        pending_msg_queue.push(sending_msg);
         return None;
       }
 
       // If the message can merge other messages, try to merge the next message until the
       // message is not mergeable.
       if sending_msg.is_mergeable() {
+        let mut merged_msg_ids = vec![];
         while let Some(pending_msg) = pending_msg_queue.pop() {
+          if pending_msg.state().is_done() {
+            continue;
+          }
           debug!("Try merge collab message: {}", pending_msg.get_msg());
-
+          let msg_id = pending_msg.msg_id();
           if !sending_msg.merge(pending_msg) {
             break;
           }
+          merged_msg_ids.push(msg_id);
+        }
+
+        // The merged message replaces the messages it contains in the same transaction.
+        if !merged_msg_ids.is_empty() {
+          let msg_id = sending_msg.msg_id();
+          self.record_change(|| SinkStorageChange::Save(msg_id, sending_msg.get_msg().clone()));
+          for merged_msg_id in merged_msg_ids {
+            self.record_change(|| SinkStorageChange::Remove(merged_msg_id));
+          }
         }
       }
 
@@ -239,9 +313,10 @@ where
       if !sending_msg.is_init() {
         let _ = self.state_notifier.send(SinkState::Syncing);
       }
+      let msg_id = sending_msg.msg_id();
       let collab_msg = sending_msg.get_msg().clone();
       pending_msg_queue.push(sending_msg);
-      collab_msg
+      (msg_id, collab_msg)
     };
 
     let mut sender = self.sender.lock().await;
@@ -252,7 +327,12 @@ where
     match tokio::time::timeout(self.config.timeout, rx).await {
       Ok(_) => {
         if let Some(mut pending_msgs) = self.pending_msg_queue.try_lock() {
-          let pending_msg = pending_msgs.pop();
+          // A message with a higher priority might be queued while waiting for the ack. It
+          // must not be removed, so the message is only removed if it's done.
+          let pending_msg = match pending_msgs.peek() {
+            Some(msg) if msg.state().is_done() => pending_msgs.pop(),
+            _ => None,
+          };
           trace!(
             "{} was sent, current pending messages: {}",
             pending_msg
@@ -269,15 +349,47 @@ where
         self.notify()
       },
       Err(_) => {
-        if let Some(mut pending_msg) = self.pending_msg_queue.lock().peek_mut() {
-          pending_msg.set_state(MessageState::Timeout);
-        }
+        self
+          .pending_msg_queue
+          .lock()
+          .set_msg_state(msg_id, MessageState::Timeout);
         self.notify();
       },
     }
     None
   }
 
+  /// Record the change of the pending messages if the sink has a [CollabSinkStorage]. It must be
+  /// called with the lock of the [PendingMsgQueue] held, so the changes are recorded in the
+  /// order they are made to the queue.
+  fn record_change<F>(&self, f: F)
+  where
+    F: FnOnce() -> SinkStorageChange<Msg>,
+  {
+    if self.storage.is_some() {
+      self.storage_changes.lock().push(f());
+    }
+  }
+
+  /// Write the recorded changes to the [CollabSinkStorage] in one transaction. The transaction
+  /// runs on a blocking thread, so the runtime is not blocked by the disk IO.
+  async fn write_storage_changes(&self) {
+    let storage = match &self.storage {
+      None => return,
+      Some(storage) => storage.clone(),
+    };
+    let _write_guard = self.storage_write.lock().await;
+    let changes = std::mem::take(&mut *self.storage_changes.lock());
+    if changes.is_empty() {
+      return;
+    }
+    match tokio::task::spawn_blocking(move || storage.apply_changes(&changes)).await {
+      Ok(Ok(_)) => {},
+      Ok(Err(e)) => tracing::error!("🔴Failed to persist pending messages: {:?}", e),
+      Err(e) => tracing::error!("🔴Failed to persist pending messages: {:?}", e),
+    }
+  }
+
   /// Notify the sink to process the next message.
   pub(crate) fn notify(&self) {
     let _ = self.notifier.send(false);
@@ -325,6 +437,29 @@ impl<Msg> CollabSinkRunner<Msg> {
   }
 }
 
+/// Persist the pending messages of a [CollabSink], so the messages that were not acked by the
+/// remote are not lost if the app quits. The merged messages are saved with the [MsgId] of the
+/// message that the others were merged into.
+///
+/// The changes of the pending messages are batched. They are written before the sink sends the
+/// next message, and when the sink is dropped.
+pub trait CollabSinkStorage<Msg>: Send + Sync {
+  /// Return the persisted messages with their [MsgId]s.
+  fn load_msgs(&self) -> Result<Vec<(MsgId, Msg)>, SyncError>;
+
+  /// Apply the changes in order, in one write transaction.
+  fn apply_changes(&self, changes: &[SinkStorageChange<Msg>]) -> Result<(), SyncError>;
+}
+
+/// A change of the pending messages of a [CollabSink].
+#[derive(Clone, Debug)]
+pub enum SinkStorageChange<Msg> {
+  /// Insert or replace the message with the given [MsgId].
+  Save(MsgId, Msg),
+  Remove(MsgId),
+  RemoveAll,
+}
+
 pub struct SinkConfig {
   /// `timeout` is the time to wait for the remote to ack the message. If the remote
   /// does not ack the message in time, the message will be sent again.
@@ -8,3 +8,6 @@ pub mod cloud_storage;
 
 #[cfg(feature = "snapshot_plugin")]
 pub mod snapshot;
+
+#[cfg(feature = "sync_plugin")]
+pub mod sync_plugin;
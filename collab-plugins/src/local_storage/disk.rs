@@ -0,0 +1,365 @@
+use std::ops::Deref;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Weak};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use collab::core::collab_plugin::AsyncCollabPlugin;
+use collab::core::commit::TransactionMeta;
+use collab::core::origin::CollabOrigin;
+use collab::preclude::CollabPlugin;
+use collab::sync_protocol::awareness::Awareness;
+use collab_persistence::compact::UpdateLogCompactor;
+use collab_persistence::doc::{LoadReport, YrsDocAction};
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::quarantine::QuarantineAction;
+use collab_persistence::txn_meta::TransactionMetaAction;
+use parking_lot::RwLock;
+use yrs::{Doc, Transact, TransactionMut};
+
+use crate::local_storage::CollabPersistenceConfig;
+
+/// Persist the updates of the collab to the disk. It works with any database that implements
+/// [KVTransactionDB], for example, RocksDB, SQLite or the in-memory store.
+///
+/// Enable the `encryption_plugin` feature and wrap the database with
+/// `collab_persistence::kv::encrypted_kv::EncryptedCollabDB` to encrypt the data on disk.
+///
+/// By default, the updates are written in the [CollabPlugin] hooks and the errors are logged.
+/// Call [DiskPlugin::with_async_write] to write them in the [AsyncCollabPlugin] hooks instead,
+/// so the errors are returned by `Collab::subscribe_plugin_error`.
+pub struct DiskPlugin<DB> {
+  uid: i64,
+  db: Weak<DB>,
+  did_load: Arc<AtomicBool>,
+  /// the number of updates on disk when opening the document
+  initial_update_count: Arc<AtomicU32>,
+  update_count: Arc<AtomicU32>,
+  config: CollabPersistenceConfig,
+  compactor: Option<Arc<UpdateLogCompactor>>,
+  /// the report of loading the document from the disk
+  load_report: Arc<RwLock<Option<LoadReport>>>,
+  /// write the updates in the [AsyncCollabPlugin] hooks
+  async_write: bool,
+  /// the origin of the local transactions, which is set when the document is loaded
+  local_origin: Arc<RwLock<Option<CollabOrigin>>>,
+  /// whether the last update was written by the [CollabPlugin::receive_update]
+  did_push_update: Arc<AtomicBool>,
+  /// the persisted metadata of the transactions, which is loaded with the document
+  loaded_txn_metas: Arc<RwLock<Vec<TransactionMeta>>>,
+}
+
+impl<DB> Clone for DiskPlugin<DB> {
+  fn clone(&self) -> Self {
+    Self {
+      uid: self.uid,
+      db: self.db.clone(),
+      did_load: self.did_load.clone(),
+      initial_update_count: self.initial_update_count.clone(),
+      update_count: self.update_count.clone(),
+      config: self.config.clone(),
+      compactor: self.compactor.clone(),
+      load_report: self.load_report.clone(),
+      async_write: self.async_write,
+      local_origin: self.local_origin.clone(),
+      did_push_update: self.did_push_update.clone(),
+      loaded_txn_metas: self.loaded_txn_metas.clone(),
+    }
+  }
+}
+
+impl<DB> Deref for DiskPlugin<DB> {
+  type Target = Weak<DB>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.db
+  }
+}
+
+impl<DB: KVTransactionDB> DiskPlugin<DB> {
+  pub fn new(uid: i64, db: Weak<DB>) -> Self {
+    Self::new_with_config(uid, db, CollabPersistenceConfig::default())
+  }
+
+  pub fn new_with_config(uid: i64, db: Weak<DB>, config: CollabPersistenceConfig) -> Self {
+    let initial_update_count = Arc::new(AtomicU32::new(0));
+    let update_count = Arc::new(AtomicU32::new(0));
+    let did_load = Arc::new(AtomicBool::new(false));
+    Self {
+      db,
+      uid,
+      did_load,
+      initial_update_count,
+      update_count,
+      config,
+      compactor: None,
+      load_report: Arc::new(RwLock::new(None)),
+      async_write: false,
+      local_origin: Arc::new(RwLock::new(None)),
+      did_push_update: Arc::new(AtomicBool::new(false)),
+      loaded_txn_metas: Arc::new(RwLock::new(vec![])),
+    }
+  }
+
+  /// Compact the update log of the document in the background with the given compactor.
+  /// The compactor is usually shared by all the documents of the same database.
+  pub fn with_compactor(mut self, compactor: Arc<UpdateLogCompactor>) -> Self {
+    self.compactor = Some(compactor);
+    self
+  }
+
+  /// Write the updates in the [AsyncCollabPlugin] hooks, so the errors are returned by
+  /// `Collab::subscribe_plugin_error`. The document is still loaded by the [CollabPlugin::init],
+  /// so the plugin must be added with both `CollabBuilder::with_plugin` and
+  /// `CollabBuilder::with_async_plugin`.
+  pub fn with_async_write(mut self) -> Self {
+    self.async_write = true;
+    self
+  }
+
+  /// Return the report of loading the document from the disk. It's None if the document was
+  /// created instead of loaded.
+  pub fn load_report(&self) -> Option<LoadReport> {
+    self.load_report.read().clone()
+  }
+
+  fn increase_count(&self) -> u32 {
+    self.update_count.fetch_add(1, SeqCst)
+  }
+
+  fn db(&self) -> Result<Arc<DB>, anyhow::Error> {
+    self
+      .db
+      .upgrade()
+      .ok_or_else(|| anyhow!("collab_db is dropped"))
+  }
+
+  fn is_local(&self, origin: &CollabOrigin) -> bool {
+    self.local_origin.read().as_ref() == Some(origin)
+  }
+
+  /// Write the update and the metadata of its transaction in the same write transaction, so the
+  /// metadata is never lost or written without its update.
+  fn push_update(
+    &self,
+    object_id: &str,
+    update: &[u8],
+    meta: Option<&TransactionMeta>,
+  ) -> Result<(), anyhow::Error> {
+    // Only push update if the doc is loaded
+    if !self.did_load.load(Ordering::SeqCst) {
+      return Ok(());
+    }
+    let db = self.db()?;
+    let _ = self.increase_count();
+    // /Acquire a write transaction to ensure consistency
+    db.with_write_txn(|w_db_txn| {
+      tracing::trace!("Receive {} update", object_id);
+      let _ = w_db_txn.push_update(self.uid, object_id, update)?;
+      if let Some(meta) = meta.filter(|_| self.config.enable_txn_meta) {
+        w_db_txn.push_txn_meta(self.uid, object_id, meta)?;
+      }
+      Ok(())
+    })?;
+    if let Some(compactor) = &self.compactor {
+      compactor.did_push_update(self.uid, object_id, update.len());
+    }
+    Ok(())
+  }
+
+  fn push_txn_meta(&self, object_id: &str, meta: &TransactionMeta) -> Result<(), anyhow::Error> {
+    if !self.config.enable_txn_meta || !self.did_load.load(Ordering::SeqCst) {
+      return Ok(());
+    }
+    self
+      .db()?
+      .with_write_txn(|w_db_txn| w_db_txn.push_txn_meta(self.uid, object_id, meta))?;
+    Ok(())
+  }
+
+  fn delete_all_updates(&self, object_id: &str) -> Result<(), anyhow::Error> {
+    self.db()?.with_write_txn(|w_db_txn| {
+      w_db_txn.delete_all_updates(self.uid, object_id)?;
+      Ok(())
+    })?;
+    Ok(())
+  }
+}
+
+impl<DB: KVTransactionDB> CollabPlugin for DiskPlugin<DB> {
+  fn init(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    *self.local_origin.write() = Some(origin.clone());
+    if let Some(db) = self.db.upgrade() {
+      let rocksdb_read = db.read_txn();
+      let mut txn = doc.transact_mut_with(origin.clone());
+      // Check the document is exist or not
+      if rocksdb_read.is_exist(self.uid, object_id) {
+        // Safety: The document is exist, so it must be loaded successfully.
+        let report = match rocksdb_read.load_doc_with_txn(self.uid, object_id, &mut txn) {
+          Ok(report) => {
+            self
+              .initial_update_count
+              .store(report.update_count, Ordering::SeqCst);
+            Some(report)
+          },
+          Err(e) => {
+            tracing::error!("🔴 load doc:{} failed: {}", object_id, e);
+            None
+          },
+        };
+        if self.config.enable_txn_meta {
+          match rocksdb_read.get_txn_metas(self.uid, object_id) {
+            Ok(metas) => *self.loaded_txn_metas.write() = metas,
+            Err(e) => tracing::error!("🔴 load transaction metas of {} failed: {}", object_id, e),
+          }
+        }
+        drop(rocksdb_read);
+
+        match report.as_ref() {
+          Some(report) if !report.quarantined.is_empty() => {
+            // Move the bad updates out of the update log, so they don't break the next load.
+            // The updates that were applied after them are kept in the flushed doc state.
+            let result = db.with_write_txn(|w_db_txn| {
+              w_db_txn.quarantine_updates(self.uid, object_id, report)?;
+              w_db_txn.flush_doc_with_txn(self.uid, object_id, &txn)?;
+              self.initial_update_count.store(0, Ordering::SeqCst);
+              Ok(())
+            });
+            if let Err(e) = result {
+              tracing::error!("🔴 quarantine updates of {} failed: {}", object_id, e);
+            }
+          },
+          _ => {
+            if self.config.flush_doc {
+              let _ = db.with_write_txn(|w_db_txn| {
+                w_db_txn.flush_doc_with_txn(self.uid, object_id, &txn)?;
+                self.initial_update_count.store(0, Ordering::SeqCst);
+                Ok(())
+              });
+            }
+          },
+        }
+        *self.load_report.write() = report;
+      } else {
+        // Drop the read txn before write txn
+        let result = db.with_write_txn(|w_db_txn| {
+          w_db_txn.create_new_doc(self.uid, object_id, &txn)?;
+          Ok(())
+        });
+
+        if let Err(e) = result {
+          tracing::error!("🔴 create doc for {:?} failed: {}", object_id, e)
+        }
+      }
+    } else {
+      tracing::warn!("collab_db is dropped");
+    };
+  }
+
+  fn take_txn_metas(&self, _object_id: &str) -> Vec<TransactionMeta> {
+    std::mem::take(&mut *self.loaded_txn_metas.write())
+  }
+
+  fn did_init(&self, _awareness: &Awareness, _object_id: &str) {
+    self.did_load.store(true, Ordering::SeqCst);
+  }
+
+  fn receive_update(&self, object_id: &str, txn: &TransactionMut, update: &[u8]) {
+    if self.async_write {
+      return;
+    }
+    // The local updates are written together with their metadata in
+    // receive_local_update_with_meta.
+    let is_local = self.is_local(&CollabOrigin::from(txn));
+    self.did_push_update.store(!is_local, Ordering::SeqCst);
+    if is_local {
+      return;
+    }
+    if let Err(e) = self.push_update(object_id, update, None) {
+      tracing::error!("🔴Save update failed: {:?}", e);
+    }
+  }
+
+  fn receive_local_update_with_meta(
+    &self,
+    _origin: &CollabOrigin,
+    object_id: &str,
+    update: &[u8],
+    meta: &TransactionMeta,
+  ) {
+    if self.async_write {
+      return;
+    }
+    // The revert of refused edits carries the origin of the undo manager, so its update was
+    // already written by receive_update.
+    let result = if self.did_push_update.swap(false, Ordering::SeqCst) {
+      self.push_txn_meta(object_id, meta)
+    } else {
+      self.push_update(object_id, update, Some(meta))
+    };
+    if let Err(e) = result {
+      tracing::error!("🔴Save update failed: {:?}", e);
+    }
+  }
+
+  fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {}
+
+  fn reset(&self, object_id: &str) {
+    if self.async_write {
+      return;
+    }
+    if let Err(e) = self.delete_all_updates(object_id) {
+      tracing::error!("🔴Reset failed: {:?}", e);
+    }
+  }
+}
+
+#[async_trait]
+impl<DB: KVTransactionDB> AsyncCollabPlugin for DiskPlugin<DB> {
+  fn name(&self) -> &str {
+    "disk"
+  }
+
+  async fn init(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    if !self.async_write {
+      return Err(anyhow!(
+        "the updates are written by the sync hooks, call DiskPlugin::with_async_write"
+      ));
+    }
+    Ok(())
+  }
+
+  /// The local updates are written together with their metadata in
+  /// [AsyncCollabPlugin::receive_local_update_with_meta].
+  async fn receive_update(
+    &self,
+    object_id: &str,
+    origin: &CollabOrigin,
+    update: &[u8],
+  ) -> Result<(), anyhow::Error> {
+    if !self.async_write || self.is_local(origin) {
+      return Ok(());
+    }
+    self.push_update(object_id, update, None)
+  }
+
+  async fn receive_local_update_with_meta(
+    &self,
+    object_id: &str,
+    update: &[u8],
+    meta: &TransactionMeta,
+  ) -> Result<(), anyhow::Error> {
+    if !self.async_write {
+      return Ok(());
+    }
+    self.push_update(object_id, update, Some(meta))
+  }
+
+  async fn reset(&self, object_id: &str) -> Result<(), anyhow::Error> {
+    if !self.async_write {
+      return Ok(());
+    }
+    self.delete_all_updates(object_id)
+  }
+}
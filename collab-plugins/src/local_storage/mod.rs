@@ -1,5 +1,20 @@
-#[cfg(feature = "rocksdb_plugin")]
-pub mod rocksdb;
+#[cfg(any(
+  feature = "rocksdb_plugin",
+  feature = "memory_plugin",
+  feature = "sqlite_plugin"
+))]
+pub mod disk;
+
+/// The disk plugin used to be RocksDB only. It's kept under its old name and module, so the
+/// existing code keeps compiling.
+#[cfg(any(
+  feature = "rocksdb_plugin",
+  feature = "memory_plugin",
+  feature = "sqlite_plugin"
+))]
+pub mod rocksdb {
+  pub type RocksdbDiskPlugin<DB> = super::disk::DiskPlugin<DB>;
+}
 
 #[derive(Clone)]
 pub struct CollabPersistenceConfig {
@@ -13,6 +28,10 @@ pub struct CollabPersistenceConfig {
   /// After flush the document, all updates will be removed and the document state vector that
   /// contains all the updates will be reset.
   pub(crate) flush_doc: bool,
+
+  /// Persist the metadata of the local transactions, the author, the time and the action of each
+  /// local update. Default is [true].
+  pub enable_txn_meta: bool,
 }
 
 impl CollabPersistenceConfig {
@@ -35,6 +54,11 @@ impl CollabPersistenceConfig {
     self.flush_doc = flush_doc;
     self
   }
+
+  pub fn enable_txn_meta(mut self, enable_txn_meta: bool) -> Self {
+    self.enable_txn_meta = enable_txn_meta;
+    self
+  }
 }
 
 impl Default for CollabPersistenceConfig {
@@ -43,6 +67,7 @@ impl Default for CollabPersistenceConfig {
       enable_snapshot: true,
       snapshot_per_update: 100,
       flush_doc: false,
+      enable_txn_meta: true,
     }
   }
 }
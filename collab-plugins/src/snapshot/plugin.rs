@@ -3,16 +3,17 @@ use std::panic::AssertUnwindSafe;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Weak};
 
+use collab::core::diff::diff_collab;
+use collab::core::encoding::EncoderVersion;
+use collab::core::path_observer::PathChange;
 use collab::preclude::{Collab, CollabPlugin};
 use collab_entity::CollabObject;
 use collab_persistence::doc::YrsDocAction;
-use collab_persistence::kv::rocks_kv::RocksCollabDB;
+use collab_persistence::kv::KVTransactionDB;
 use collab_persistence::snapshot::{CollabSnapshot, SnapshotAction};
 use collab_persistence::PersistenceError;
 use parking_lot::RwLock;
-use similar::{ChangeTag, TextDiff};
-use yrs::updates::decoder::Decode;
-use yrs::{ReadTxn, StateVector, TransactionMut, Update};
+use yrs::{ReadTxn, StateVector, TransactionMut};
 
 #[derive(Clone, Debug)]
 enum GenSnapshotState {
@@ -43,10 +44,12 @@ pub trait SnapshotPersistence: Send + Sync {
   ) -> Result<(), PersistenceError>;
 }
 
-pub struct CollabSnapshotPlugin {
+/// Generate a snapshot of the document every `snapshot_per_update` transactions. The snapshot is
+/// generated from the document stored in the given database, which can be any [KVTransactionDB].
+pub struct CollabSnapshotPlugin<DB> {
   uid: i64,
   object: CollabObject,
-  collab_db: Weak<RocksCollabDB>,
+  collab_db: Weak<DB>,
   /// the number of updates on disk when opening the document
   update_count: Arc<AtomicU32>,
   snapshot_per_update: u32,
@@ -54,12 +57,12 @@ pub struct CollabSnapshotPlugin {
   snapshot_persistence: Arc<dyn SnapshotPersistence>,
 }
 
-impl CollabSnapshotPlugin {
+impl<DB: KVTransactionDB> CollabSnapshotPlugin<DB> {
   pub fn new(
     uid: i64,
     object: CollabObject,
     snapshot_persistence: Arc<dyn SnapshotPersistence>,
-    collab_db: Weak<RocksCollabDB>,
+    collab_db: Weak<DB>,
     snapshot_per_update: u32,
   ) -> Self {
     let state = Arc::new(RwLock::new(GenSnapshotState::Idle));
@@ -81,7 +84,7 @@ impl CollabSnapshotPlugin {
   }
 }
 
-impl CollabPlugin for CollabSnapshotPlugin {
+impl<DB: KVTransactionDB> CollabPlugin for CollabSnapshotPlugin<DB> {
   fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {
     // After each transaction, we increment the update count
     let old_value = self.update_count.fetch_add(1, Ordering::SeqCst);
@@ -147,7 +150,7 @@ impl CollabPlugin for CollabSnapshotPlugin {
   }
 }
 
-impl SnapshotPersistence for Arc<RocksCollabDB> {
+impl<DB: KVTransactionDB> SnapshotPersistence for Arc<DB> {
   fn get_snapshots(&self, uid: i64, object_id: &str) -> Vec<CollabSnapshot> {
     self.read_txn().get_snapshots(uid, object_id)
   }
@@ -166,58 +169,58 @@ impl SnapshotPersistence for Arc<RocksCollabDB> {
   }
 }
 
+/// Returns the structural changes between the two snapshots of the object. See [diff_collab].
+/// Each snapshot is decoded with the encoder version that it was stored with.
 pub fn calculate_snapshot_diff(
   uid: i64,
   object_id: &str,
-  old_snapshot: &[u8],
-  new_snapshot: &[u8],
-) -> Result<String, anyhow::Error> {
-  if old_snapshot.is_empty() {
-    return Ok("".to_string());
+  old_snapshot: &CollabSnapshot,
+  new_snapshot: &CollabSnapshot,
+) -> Result<Vec<PathChange>, anyhow::Error> {
+  if old_snapshot.data.is_empty() {
+    return Ok(vec![]);
   }
 
-  if new_snapshot.is_empty() {
+  if new_snapshot.data.is_empty() {
     return Err(anyhow::anyhow!(
       "The new {} snapshot data is empty",
       object_id
     ));
   }
 
-  let old = try_decode_snapshot(uid, object_id, old_snapshot)?;
-  let new = try_decode_snapshot(uid, object_id, new_snapshot)?;
-
-  let mut display_str = String::new();
-  let diff = TextDiff::from_lines(&old, &new);
-  for change in diff.iter_all_changes() {
-    let sign = match change.tag() {
-      ChangeTag::Delete => "-",
-      ChangeTag::Insert => "+",
-      ChangeTag::Equal => " ",
-    };
-    display_str.push_str(&format!("{}{}", sign, change));
-  }
-  Ok(display_str)
+  let old = try_decode_snapshot(
+    uid,
+    object_id,
+    &old_snapshot.data,
+    old_snapshot.encoder_version,
+  )?;
+  let new = try_decode_snapshot(
+    uid,
+    object_id,
+    &new_snapshot.data,
+    new_snapshot.encoder_version,
+  )?;
+  Ok(diff_collab(&old, &new))
 }
 
+/// Returns a [Collab] with the content of the snapshot. The `encoder_version` is the version
+/// that the snapshot data was encoded with, which is the [CollabSnapshot::encoder_version] of the
+/// stored snapshots.
 pub fn try_decode_snapshot(
   uid: i64,
   object_id: &str,
   data: &[u8],
-) -> Result<String, PersistenceError> {
-  let mut decoded_str = String::new();
-  match {
-    let mut wrapper = AssertUnwindSafe(&mut decoded_str);
-    panic::catch_unwind(move || {
-      let collab = Collab::new(uid, object_id, "1", vec![]);
-      if let Ok(update) = Update::decode_v1(data) {
-        let mut txn = collab.origin_transact_mut();
-        txn.apply_update(update);
-        drop(txn);
-      }
-      **wrapper = collab.to_plain_text();
-    })
-  } {
-    Ok(_) => Ok(decoded_str),
+  encoder_version: EncoderVersion,
+) -> Result<Collab, PersistenceError> {
+  let update = encoder_version.decode_update(data)?;
+  match panic::catch_unwind(AssertUnwindSafe(|| {
+    let collab = Collab::new(uid, object_id, "1", vec![]);
+    let mut txn = collab.origin_transact_mut();
+    txn.apply_update(update);
+    drop(txn);
+    collab
+  })) {
+    Ok(collab) => Ok(collab),
     Err(e) => Err(PersistenceError::InvalidData(format!("{:?}", e))),
   }
 }
@@ -0,0 +1,579 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Weak};
+
+use collab::core::collab::MutexCollab;
+use collab::core::collab_plugin::CollabPluginType;
+use collab::core::collab_state::SyncState;
+use collab::core::origin::CollabOrigin;
+use collab::preclude::CollabPlugin;
+use collab::sync_protocol::awareness::Awareness;
+use collab::sync_protocol::envelope::{
+  CollabEnvelope, CollabFrame, CollabFrameReader, CollabSubscription, MsgId, MsgIdGenerator,
+};
+use collab::sync_protocol::message::{Message, MessageReader, SyncMessage};
+use collab::sync_protocol::{handle_msg, ClientSyncProtocol};
+use collab_entity::CollabObject;
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio::sync::{watch, Notify};
+use yrs::merge_updates_v1;
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{ReadTxn, Transact};
+
+use crate::sync_plugin::connector::connect_with_retry;
+use crate::sync_plugin::SyncConnector;
+
+#[derive(Clone, Debug)]
+pub struct ConnectionManagerConfig {
+  /// The max number of messages that are queued for one object. When the number is exceeded,
+  /// each run of consecutive pending updates of the object is merged into one update.
+  /// Default is 100. The value must be greater than 0.
+  pub max_pending_per_object: usize,
+  /// The max number of messages of one object that are sent but not acknowledged by the remote.
+  /// The object is not scheduled again until the remote acknowledges its messages, so a remote
+  /// that falls behind on an object holds back that object instead of the whole connection.
+  /// Default is 10. The value must be greater than 0.
+  pub max_in_flight_per_object: usize,
+  /// The max number of envelopes that are sent in one frame. The objects take turns to put their
+  /// messages into the frame, so an object with many pending messages can't starve the others.
+  /// Default is 20. The value must be greater than 0.
+  pub max_envelopes_per_frame: usize,
+  /// The token that is presented to the remote before the handshake of each object. The remote
+  /// resolves the token to the user and checks the permission of the user to the object.
+  /// Default is [None].
+  pub auth_token: Option<String>,
+}
+
+impl ConnectionManagerConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn max_pending_per_object(mut self, max_pending_per_object: usize) -> Self {
+    debug_assert!(max_pending_per_object > 0);
+    self.max_pending_per_object = max_pending_per_object;
+    self
+  }
+
+  pub fn max_in_flight_per_object(mut self, max_in_flight_per_object: usize) -> Self {
+    debug_assert!(max_in_flight_per_object > 0);
+    self.max_in_flight_per_object = max_in_flight_per_object;
+    self
+  }
+
+  pub fn max_envelopes_per_frame(mut self, max_envelopes_per_frame: usize) -> Self {
+    debug_assert!(max_envelopes_per_frame > 0);
+    self.max_envelopes_per_frame = max_envelopes_per_frame;
+    self
+  }
+
+  pub fn auth_token(mut self, auth_token: &str) -> Self {
+    self.auth_token = Some(auth_token.to_string());
+    self
+  }
+}
+
+impl Default for ConnectionManagerConfig {
+  fn default() -> Self {
+    Self {
+      max_pending_per_object: 100,
+      max_in_flight_per_object: 10,
+      max_envelopes_per_frame: 20,
+      auth_token: None,
+    }
+  }
+}
+
+/// The [CollabConnectionManager] multiplexes the sync of many collab objects over one connection
+/// that is opened by the [SyncConnector].
+///
+/// Each object is registered with [CollabConnectionManager::register] which returns the
+/// [MultiplexSyncPlugin] of the object. The object is subscribed on the connection when the
+/// plugin is initialized, and unsubscribed when the plugin is dropped. All the messages of the
+/// object are wrapped in its [CollabEnvelope]. When the connection is reopened, all the objects
+/// are subscribed again and run the handshake of the [ClientSyncProtocol].
+///
+/// Every object can have at most [ConnectionManagerConfig::max_in_flight_per_object] messages
+/// that are not acknowledged by the remote. The rest of its messages wait in its queue.
+pub struct CollabConnectionManager {
+  inner: Arc<ConnectionInner>,
+  /// The connection task stops when the manager is dropped.
+  #[allow(dead_code)]
+  stop_tx: watch::Sender<bool>,
+}
+
+impl CollabConnectionManager {
+  pub fn new<C: SyncConnector>(
+    origin: CollabOrigin,
+    connector: C,
+    config: ConnectionManagerConfig,
+  ) -> Self {
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let inner = Arc::new(ConnectionInner {
+      origin,
+      scheduler: Mutex::new(Scheduler::new(config.max_in_flight_per_object)),
+      config,
+      notify: Default::default(),
+    });
+    tokio::spawn(run_connection(inner.clone(), Arc::new(connector), stop_rx));
+    Self { inner, stop_tx }
+  }
+
+  /// Register the object to the connection. The returned plugin must be added to the collab of
+  /// the object before it is initialized.
+  pub fn register(&self, object: &CollabObject, collab: Weak<MutexCollab>) -> MultiplexSyncPlugin {
+    self.inner.scheduler.lock().objects.insert(
+      object.object_id.clone(),
+      ObjectQueue {
+        collab,
+        is_initialized: false,
+        pending: VecDeque::new(),
+        in_flight: HashSet::new(),
+        is_scheduled: false,
+      },
+    );
+    MultiplexSyncPlugin {
+      object_id: object.object_id.clone(),
+      inner: self.inner.clone(),
+    }
+  }
+
+  pub fn number_of_objects(&self) -> usize {
+    self.inner.scheduler.lock().objects.len()
+  }
+
+  /// Returns the number of messages of the object that are waiting to be sent.
+  pub fn number_of_pending_messages(&self, object_id: &str) -> usize {
+    self
+      .inner
+      .scheduler
+      .lock()
+      .objects
+      .get(object_id)
+      .map(|object| object.pending.len())
+      .unwrap_or(0)
+  }
+
+  /// Returns the number of messages of the object that are sent but not acknowledged yet.
+  pub fn number_of_in_flight_messages(&self, object_id: &str) -> usize {
+    self
+      .inner
+      .scheduler
+      .lock()
+      .objects
+      .get(object_id)
+      .map(|object| object.in_flight.len())
+      .unwrap_or(0)
+  }
+}
+
+/// The plugin of an object that is registered to the [CollabConnectionManager].
+pub struct MultiplexSyncPlugin {
+  object_id: String,
+  inner: Arc<ConnectionInner>,
+}
+
+impl CollabPlugin for MultiplexSyncPlugin {
+  fn did_init(&self, awareness: &Awareness, _object_id: &str) {
+    let messages = start_messages(awareness, self.inner.config.auth_token.as_deref());
+    let mut scheduler = self.inner.scheduler.lock();
+    if let Some(object) = scheduler.objects.get_mut(&self.object_id) {
+      object.is_initialized = true;
+    }
+    scheduler.subscribe(&self.object_id, messages);
+    drop(scheduler);
+    self.inner.notify.notify_one();
+  }
+
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    self.inner.push(
+      &self.object_id,
+      vec![Message::Sync(SyncMessage::Update(update.to_vec()))],
+    );
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::CloudStorage
+  }
+}
+
+impl Drop for MultiplexSyncPlugin {
+  fn drop(&mut self) {
+    self.inner.scheduler.lock().unsubscribe(&self.object_id);
+    self.inner.notify.notify_one();
+  }
+}
+
+struct ConnectionInner {
+  origin: CollabOrigin,
+  config: ConnectionManagerConfig,
+  scheduler: Mutex<Scheduler>,
+  /// Notifies the connection task that there are frames to send.
+  notify: Notify,
+}
+
+impl ConnectionInner {
+  fn push(&self, object_id: &str, messages: Vec<Message>) {
+    let is_pushed =
+      self
+        .scheduler
+        .lock()
+        .push(object_id, messages, self.config.max_pending_per_object);
+    if is_pushed {
+      self.notify.notify_one();
+    }
+  }
+
+  /// Subscribe all the initialized objects again after the connection was opened. The pending
+  /// messages are dropped because the handshake brings the remote up to date.
+  fn resubscribe_all(&self) {
+    // The collab must not be locked while holding the lock of the scheduler, because the collab
+    // calls the plugin with the lock of the collab held.
+    let objects = self
+      .scheduler
+      .lock()
+      .reset()
+      .into_iter()
+      .filter_map(|(object_id, collab)| collab.upgrade().map(|collab| (object_id, collab)))
+      .collect::<Vec<_>>();
+
+    for (object_id, collab) in objects {
+      let messages = {
+        let collab = collab.lock();
+        collab.set_sync_state(SyncState::SyncInitStart);
+        start_messages(collab.get_awareness(), self.config.auth_token.as_deref())
+      };
+      self.scheduler.lock().subscribe(&object_id, messages);
+    }
+    self.notify.notify_one();
+  }
+
+  /// Handle the frames received from the remote.
+  async fn handle_remote_data(&self, data: Vec<u8>) {
+    let mut decoder = DecoderV1::from(data.as_slice());
+    let frames = CollabFrameReader::new(&mut decoder).collect::<Vec<_>>();
+    for frame in frames {
+      match frame {
+        Ok(CollabFrame::Envelope(envelope)) => self.handle_envelope(envelope).await,
+        Ok(CollabFrame::Ack(ack)) => {
+          if !ack.is_ok() {
+            tracing::warn!("🟡remote reject message: {}", ack);
+          }
+          // The rejected messages are not resent, so they free the window as well.
+          if self.scheduler.lock().ack(&ack.object_id, ack.msg_id) {
+            self.notify.notify_one();
+          }
+        },
+        Ok(frame) => tracing::warn!("🟡unexpected frame: {}", frame),
+        Err(e) => {
+          tracing::error!("🔴decode frame failed: {}", e);
+          break;
+        },
+      }
+    }
+  }
+
+  async fn handle_envelope(&self, envelope: CollabEnvelope) {
+    let collab = self
+      .scheduler
+      .lock()
+      .objects
+      .get(&envelope.object_id)
+      .and_then(|object| object.collab.upgrade());
+    let collab = match collab {
+      None => return,
+      Some(collab) => collab,
+    };
+
+    let object_id = envelope.object_id;
+    let is_sync_step2 =
+      matches!(&envelope.message, Message::Sync(sync_msg) if sync_msg.is_sync_step2());
+    let origin = CollabOrigin::Server;
+    match handle_msg(
+      &Some(&origin),
+      &ClientSyncProtocol,
+      &collab,
+      envelope.message,
+    )
+    .await
+    {
+      Ok(Some(reply)) => {
+        let mut decoder = DecoderV1::from(reply.as_slice());
+        let messages = MessageReader::new(&mut decoder).flatten().collect();
+        self.push(&object_id, messages);
+      },
+      Ok(None) => {},
+      Err(e) => tracing::error!("🔴{} handle remote message failed: {}", object_id, e),
+    }
+
+    if is_sync_step2 {
+      let collab = collab.lock();
+      collab.set_sync_state(SyncState::SyncInitEnd);
+      collab.set_sync_state(SyncState::SyncFinished);
+    }
+  }
+}
+
+/// Schedules the messages of the objects. The subscriptions are sent first, and then the objects
+/// that have pending messages take turns to send one message. An object that has
+/// `max_in_flight` messages waiting for the acknowledgement of the remote is skipped.
+struct Scheduler {
+  objects: HashMap<String, ObjectQueue>,
+  /// The ids of the objects that can send a message, in the order they will be served.
+  ready: VecDeque<String>,
+  subscriptions: VecDeque<CollabSubscription>,
+  msg_id_generator: MsgIdGenerator,
+  max_in_flight: usize,
+}
+
+struct ObjectQueue {
+  collab: Weak<MutexCollab>,
+  is_initialized: bool,
+  pending: VecDeque<Message>,
+  /// The ids of the messages that are sent but not acknowledged.
+  in_flight: HashSet<MsgId>,
+  /// True if the object is in the ready queue of the [Scheduler].
+  is_scheduled: bool,
+}
+
+impl Scheduler {
+  fn new(max_in_flight: usize) -> Self {
+    Self {
+      objects: HashMap::new(),
+      ready: VecDeque::new(),
+      subscriptions: VecDeque::new(),
+      msg_id_generator: MsgIdGenerator::new(),
+      max_in_flight,
+    }
+  }
+
+  /// Returns false if the object is not registered.
+  fn push(&mut self, object_id: &str, messages: Vec<Message>, max_pending: usize) -> bool {
+    let object = match self.objects.get_mut(object_id) {
+      None => return false,
+      Some(object) => object,
+    };
+    if messages.is_empty() {
+      return false;
+    }
+
+    object.pending.extend(messages);
+    if object.pending.len() > max_pending {
+      merge_pending_updates(object_id, &mut object.pending);
+    }
+    self.schedule(object_id)
+  }
+
+  /// Release the acknowledged message from the window of the object. The acknowledgements of
+  /// the messages that are not in flight, for example, the duplicated ones or the ones sent
+  /// before the connection was reopened, are ignored. Returns true if the object is put into the
+  /// ready queue.
+  fn ack(&mut self, object_id: &str, msg_id: MsgId) -> bool {
+    let is_in_flight = self
+      .objects
+      .get_mut(object_id)
+      .map(|object| object.in_flight.remove(&msg_id))
+      .unwrap_or(false);
+    is_in_flight && self.schedule(object_id)
+  }
+
+  /// Put the object into the ready queue if it has pending messages and its window is not full.
+  /// Returns true if the object is in the ready queue.
+  fn schedule(&mut self, object_id: &str) -> bool {
+    let object = match self.objects.get_mut(object_id) {
+      None => return false,
+      Some(object) => object,
+    };
+    if !object.is_scheduled
+      && !object.pending.is_empty()
+      && object.in_flight.len() < self.max_in_flight
+    {
+      object.is_scheduled = true;
+      self.ready.push_back(object_id.to_string());
+    }
+    object.is_scheduled
+  }
+
+  fn subscribe(&mut self, object_id: &str, start_messages: Vec<Message>) {
+    if self.objects.contains_key(object_id) {
+      self
+        .subscriptions
+        .push_back(CollabSubscription::subscribe(object_id));
+      self.push(object_id, start_messages, usize::MAX);
+    }
+  }
+
+  fn unsubscribe(&mut self, object_id: &str) {
+    if self.objects.remove(object_id).is_some() {
+      self
+        .subscriptions
+        .push_back(CollabSubscription::unsubscribe(object_id));
+    }
+  }
+
+  /// Drop all the pending messages. Returns the initialized objects.
+  fn reset(&mut self) -> Vec<(String, Weak<MutexCollab>)> {
+    self.ready.clear();
+    self.subscriptions.clear();
+    self
+      .objects
+      .iter_mut()
+      .filter(|(_, object)| object.is_initialized)
+      .map(|(object_id, object)| {
+        object.pending.clear();
+        object.in_flight.clear();
+        object.is_scheduled = false;
+        (object_id.clone(), object.collab.clone())
+      })
+      .collect()
+  }
+
+  /// Encode the next frame. Returns [None] if there is nothing to send.
+  fn next_frame(&mut self, origin: &CollabOrigin, max_envelopes: usize) -> Option<Vec<u8>> {
+    let mut encoder = EncoderV1::new();
+    let mut is_empty = true;
+    while let Some(subscription) = self.subscriptions.pop_front() {
+      subscription.encode(&mut encoder);
+      is_empty = false;
+    }
+
+    let mut num_of_envelopes = 0;
+    while num_of_envelopes < max_envelopes {
+      let object_id = match self.ready.pop_front() {
+        None => break,
+        Some(object_id) => object_id,
+      };
+      // The object might be unsubscribed after it was put into the ready queue.
+      let object = match self.objects.get_mut(&object_id) {
+        None => continue,
+        Some(object) => object,
+      };
+      object.is_scheduled = false;
+      if let Some(msg) = object.pending.pop_front() {
+        let msg_id = self.msg_id_generator.next_id();
+        CollabEnvelope::new(&object_id, origin.clone(), msg_id, msg).encode(&mut encoder);
+        object.in_flight.insert(msg_id);
+        num_of_envelopes += 1;
+        is_empty = false;
+      }
+      self.schedule(&object_id);
+    }
+
+    if is_empty {
+      None
+    } else {
+      Some(encoder.to_vec())
+    }
+  }
+}
+
+/// Merge each run of consecutive pending updates of the object into one update, so the memory
+/// that is used by an object that produces updates faster than they can be sent stays bounded.
+/// The order of the updates and the other messages is kept.
+fn merge_pending_updates(object_id: &str, pending: &mut VecDeque<Message>) {
+  let mut merged = VecDeque::with_capacity(pending.len());
+  let mut updates = vec![];
+  for msg in pending.drain(..) {
+    match msg {
+      Message::Sync(SyncMessage::Update(update)) => updates.push(update),
+      msg => {
+        merge_updates(object_id, std::mem::take(&mut updates), &mut merged);
+        merged.push_back(msg);
+      },
+    }
+  }
+  merge_updates(object_id, updates, &mut merged);
+  *pending = merged;
+}
+
+fn merge_updates(object_id: &str, updates: Vec<Vec<u8>>, pending: &mut VecDeque<Message>) {
+  if updates.len() <= 1 {
+    pending.extend(
+      updates
+        .into_iter()
+        .map(|update| Message::Sync(SyncMessage::Update(update))),
+    );
+    return;
+  }
+
+  let update_refs = updates
+    .iter()
+    .map(|update| update.as_slice())
+    .collect::<Vec<_>>();
+  match merge_updates_v1(&update_refs) {
+    Ok(merged) => pending.push_back(Message::Sync(SyncMessage::Update(merged))),
+    Err(e) => {
+      tracing::error!("🔴{} merge pending updates failed: {}", object_id, e);
+      pending.extend(
+        updates
+          .into_iter()
+          .map(|update| Message::Sync(SyncMessage::Update(update))),
+      );
+    },
+  }
+}
+
+/// The messages that start the handshake of the [ClientSyncProtocol]. The token is presented
+/// first, so the remote can authenticate the client before handling the handshake.
+fn start_messages(awareness: &Awareness, auth_token: Option<&str>) -> Vec<Message> {
+  let sv = awareness.doc().transact().state_vector();
+  let mut messages = vec![];
+  if let Some(auth_token) = auth_token {
+    messages.push(Message::AuthRequest(auth_token.to_string()));
+  }
+  messages.push(Message::Sync(SyncMessage::SyncStep1(sv)));
+  match awareness.update() {
+    Ok(update) => messages.push(Message::Awareness(update)),
+    Err(e) => tracing::error!("🔴encode awareness update failed: {}", e),
+  }
+  messages
+}
+
+async fn run_connection<C: SyncConnector>(
+  inner: Arc<ConnectionInner>,
+  connector: Arc<C>,
+  mut stop_rx: watch::Receiver<bool>,
+) {
+  let name = inner.origin.to_string();
+  loop {
+    let (mut sink, mut stream) = match connect_with_retry(&name, &connector, &mut stop_rx).await {
+      None => return,
+      Some(connection) => connection,
+    };
+    inner.resubscribe_all();
+
+    loop {
+      let frame = inner
+        .scheduler
+        .lock()
+        .next_frame(&inner.origin, inner.config.max_envelopes_per_frame);
+      let has_more = frame.is_some();
+      if let Some(frame) = frame {
+        if let Err(e) = sink.send(frame).await {
+          tracing::warn!("🟡{} send frame failed: {}", name, e);
+          break;
+        }
+      }
+
+      // Keep reading the remote while there are frames to send, so the remote is not blocked by
+      // a large number of local updates.
+      tokio::select! {
+        _ = stop_rx.changed() => return,
+        _ = inner.notify.notified(), if !has_more => {},
+        _ = std::future::ready(()), if has_more => {},
+        data = stream.next() => match data {
+          Some(Ok(data)) => inner.handle_remote_data(data).await,
+          Some(Err(e)) => {
+            tracing::warn!("🟡{} connection error: {}", name, e);
+            break;
+          },
+          None => {
+            tracing::trace!("{} connection closed", name);
+            break;
+          },
+        },
+      }
+    }
+  }
+}
@@ -0,0 +1,53 @@
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::{Sink, Stream};
+use tokio::sync::watch;
+use tokio_retry::strategy::FibonacciBackoff;
+use tokio_retry::Retry;
+
+/// The first delay of reconnecting. The delay grows with a Fibonacci backoff.
+const RECONNECT_INTERVAL_MILLIS: u64 = 1000;
+/// The max delay between two reconnecting attempts.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// The [SyncConnector] is used by the [SyncPlugin](crate::sync_plugin::SyncPlugin) to open a
+/// connection to the remote. It will be called again to reconnect when the previous connection
+/// returns an error or is closed.
+#[async_trait]
+pub trait SyncConnector: Send + Sync + 'static {
+  type Error: Display + Send + Sync + 'static;
+  type Sink: Sink<Vec<u8>, Error = Self::Error> + Send + Unpin + 'static;
+  type Stream: Stream<Item = Result<Vec<u8>, Self::Error>> + Send + Unpin + 'static;
+
+  /// Returns the sink that the messages are sent to and the stream that the messages are
+  /// received from.
+  async fn connect(&self) -> Result<(Self::Sink, Self::Stream), Self::Error>;
+}
+
+/// Keep calling the [SyncConnector] with a backoff until a connection is opened. Returns [None]
+/// if the `stop_rx` was notified before that.
+pub(crate) async fn connect_with_retry<C: SyncConnector>(
+  name: &str,
+  connector: &Arc<C>,
+  stop_rx: &mut watch::Receiver<bool>,
+) -> Option<(C::Sink, C::Stream)> {
+  let retry_strategy =
+    FibonacciBackoff::from_millis(RECONNECT_INTERVAL_MILLIS).max_delay(MAX_RECONNECT_DELAY);
+  let connect = Retry::spawn(retry_strategy, || {
+    let connector = connector.clone();
+    async move {
+      connector.connect().await.map_err(|e| {
+        tracing::warn!("🟡{} connect failed: {}", name, e);
+        e
+      })
+    }
+  });
+
+  tokio::select! {
+    _ = stop_rx.changed() => None,
+    result = connect => result.ok(),
+  }
+}
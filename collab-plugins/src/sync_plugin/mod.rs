@@ -0,0 +1,7 @@
+pub use connection_manager::*;
+pub use connector::*;
+pub use plugin::*;
+
+mod connection_manager;
+mod connector;
+mod plugin;
@@ -0,0 +1,277 @@
+use std::sync::{Arc, Weak};
+
+use collab::core::collab::MutexCollab;
+use collab::core::collab_plugin::CollabPluginType;
+use collab::core::collab_state::SyncState;
+use collab::core::encoding::{convert_update, EncoderVersion};
+use collab::core::origin::CollabOrigin;
+use collab::preclude::CollabPlugin;
+use collab::sync_protocol::awareness::Awareness;
+use collab::sync_protocol::message::{Error, Message, MessageReader, SyncMessage};
+use collab::sync_protocol::{handle_msg, CollabSyncProtocol, VersionedClientSyncProtocol};
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, watch};
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+use crate::sync_plugin::connector::connect_with_retry;
+use crate::sync_plugin::SyncConnector;
+
+/// The [SyncPlugin] keeps the [MutexCollab] in sync with the remote over the connection that is
+/// opened by the [SyncConnector].
+///
+/// After connecting, the plugin runs the handshake of the [VersionedClientSyncProtocol] and then
+/// streams the local updates to the remote. The updates received from the remote are applied with the
+/// [CollabOrigin::Server] origin, so they will not be sent back. When the connection returns an
+/// error or is closed, the plugin reconnects and runs the handshake again.
+///
+/// The updates are exchanged with the v1 encoding unless the remote agrees to use the encoder
+/// version that is set with [SyncPlugin::with_encoder_version].
+pub struct SyncPlugin<C> {
+  object_id: String,
+  collab: Weak<MutexCollab>,
+  connector: Arc<C>,
+  auth_token: Option<String>,
+  encoder_version: EncoderVersion,
+  local_update_tx: mpsc::UnboundedSender<Vec<u8>>,
+  local_update_rx: Mutex<Option<mpsc::UnboundedReceiver<Vec<u8>>>>,
+  /// The sync task stops when the plugin is dropped.
+  #[allow(dead_code)]
+  stop_tx: watch::Sender<bool>,
+}
+
+impl<C> SyncPlugin<C>
+where
+  C: SyncConnector,
+{
+  pub fn new(object_id: &str, collab: Weak<MutexCollab>, connector: C) -> Self {
+    let (local_update_tx, local_update_rx) = mpsc::unbounded_channel();
+    let (stop_tx, _) = watch::channel(false);
+    Self {
+      object_id: object_id.to_string(),
+      collab,
+      connector: Arc::new(connector),
+      auth_token: None,
+      encoder_version: EncoderVersion::V1,
+      local_update_tx,
+      local_update_rx: Mutex::new(Some(local_update_rx)),
+      stop_tx,
+    }
+  }
+
+  /// Present the token to the remote before the handshake. The remote resolves the token to the
+  /// user and checks the permission of the user to the object.
+  pub fn with_auth_token(mut self, auth_token: &str) -> Self {
+    self.auth_token = Some(auth_token.to_string());
+    self
+  }
+
+  /// Ask the remote to exchange the updates with the given encoder version. The remote replies
+  /// with the version it supports, which is used until the connection is closed. The remotes
+  /// that don't know the request keep using the v1 encoding.
+  pub fn with_encoder_version(mut self, encoder_version: EncoderVersion) -> Self {
+    self.encoder_version = encoder_version;
+    self
+  }
+}
+
+impl<C> CollabPlugin for SyncPlugin<C>
+where
+  C: SyncConnector,
+{
+  fn did_init(&self, _awareness: &Awareness, _object_id: &str) {
+    // The sync task can only be started once.
+    if let Some(local_update_rx) = self.local_update_rx.lock().take() {
+      tokio::spawn(run_sync(
+        self.object_id.clone(),
+        self.collab.clone(),
+        self.connector.clone(),
+        self.auth_token.clone(),
+        self.encoder_version,
+        local_update_rx,
+        self.stop_tx.subscribe(),
+      ));
+    }
+  }
+
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    let _ = self.local_update_tx.send(update.to_vec());
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::CloudStorage
+  }
+}
+
+async fn run_sync<C: SyncConnector>(
+  object_id: String,
+  collab: Weak<MutexCollab>,
+  connector: Arc<C>,
+  auth_token: Option<String>,
+  encoder_version: EncoderVersion,
+  mut local_update_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+  mut stop_rx: watch::Receiver<bool>,
+) {
+  loop {
+    let (mut sink, mut stream) =
+      match connect_with_retry(&object_id, &connector, &mut stop_rx).await {
+        None => return,
+        Some(connection) => connection,
+      };
+
+    // The updates that were queued while disconnected are included in the sync step 2 that
+    // replies to the sync step 1 of the remote, so they can be dropped.
+    while local_update_rx.try_recv().is_ok() {}
+
+    // The v1 encoding is used until the remote replies to the encoding request.
+    let mut agreed_version = EncoderVersion::V1;
+
+    let start_msg = match collab.upgrade() {
+      None => return,
+      Some(collab) => {
+        let collab = collab.lock();
+        collab.set_sync_state(SyncState::SyncInitStart);
+        let mut encoder = EncoderV1::new();
+        if let Some(auth_token) = &auth_token {
+          Message::AuthRequest(auth_token.clone()).encode(&mut encoder);
+        }
+        if encoder_version != EncoderVersion::V1 {
+          Message::Encoding(encoder_version).encode(&mut encoder);
+        }
+        match VersionedClientSyncProtocol::new(agreed_version)
+          .start(collab.get_awareness(), &mut encoder)
+        {
+          Ok(_) => encoder.to_vec(),
+          Err(e) => {
+            tracing::error!("🔴{} start sync failed: {}", object_id, e);
+            return;
+          },
+        }
+      },
+    };
+
+    if let Err(e) = sink.send(start_msg).await {
+      tracing::warn!("🟡{} send sync step 1 failed: {}", object_id, e);
+      continue;
+    }
+
+    loop {
+      tokio::select! {
+        _ = stop_rx.changed() => return,
+        update = local_update_rx.recv() => {
+          let mut updates = match update {
+            None => return,
+            Some(update) => vec![update],
+          };
+          while let Ok(update) = local_update_rx.try_recv() {
+            updates.push(update);
+          }
+
+          set_sync_state(&collab, SyncState::SyncUpdate);
+          if let Err(e) = send_updates(&mut sink, updates, agreed_version).await {
+            tracing::warn!("🟡{} send updates failed: {}", object_id, e);
+            break;
+          }
+          set_sync_state(&collab, SyncState::SyncFinished);
+        },
+        data = stream.next() => match data {
+          Some(Ok(data)) => {
+            let collab = match collab.upgrade() {
+              None => return,
+              Some(collab) => collab,
+            };
+            match handle_remote_data(&collab, data, &mut agreed_version).await {
+              Ok(replies) => {
+                if let Err(e) = send_all(&mut sink, replies).await {
+                  tracing::warn!("🟡{} send reply failed: {}", object_id, e);
+                  break;
+                }
+              },
+              Err(e) => tracing::error!("🔴{} handle remote message failed: {}", object_id, e),
+            }
+          },
+          Some(Err(e)) => {
+            tracing::warn!("🟡{} connection error: {}", object_id, e);
+            break;
+          },
+          None => {
+            tracing::trace!("{} connection closed", object_id);
+            break;
+          },
+        },
+      }
+    }
+  }
+}
+
+/// Apply the messages received from the remote. Returns the replies that should be sent back.
+/// The `encoder_version` is updated when the remote replies to the encoding request.
+async fn handle_remote_data(
+  collab: &MutexCollab,
+  data: Vec<u8>,
+  encoder_version: &mut EncoderVersion,
+) -> Result<Vec<Vec<u8>>, Error> {
+  let origin = CollabOrigin::Server;
+  let mut decoder = DecoderV1::from(data.as_slice());
+  let messages = MessageReader::new(&mut decoder).collect::<Result<Vec<_>, _>>()?;
+
+  let mut replies = vec![];
+  for msg in messages {
+    if let Message::Encoding(version) = &msg {
+      *encoder_version = (*version).min(EncoderVersion::LATEST);
+    }
+    let is_sync_step2 = matches!(&msg, Message::Sync(sync_msg) if sync_msg.is_sync_step2());
+    let protocol = VersionedClientSyncProtocol::new(*encoder_version);
+    if let Some(reply) = handle_msg(&Some(&origin), &protocol, collab, msg).await? {
+      replies.push(reply);
+    }
+
+    if is_sync_step2 {
+      let collab = collab.lock();
+      collab.set_sync_state(SyncState::SyncInitEnd);
+      collab.set_sync_state(SyncState::SyncFinished);
+    }
+  }
+  Ok(replies)
+}
+
+/// Send the local updates, which are encoded with the v1 encoding, with the given encoder version.
+async fn send_updates<S>(
+  sink: &mut S,
+  updates: Vec<Vec<u8>>,
+  encoder_version: EncoderVersion,
+) -> Result<(), S::Error>
+where
+  S: futures_util::Sink<Vec<u8>> + Unpin,
+{
+  let messages = updates
+    .into_iter()
+    .filter_map(
+      |update| match convert_update(&update, EncoderVersion::V1, encoder_version) {
+        Ok(update) => Some(Message::Sync(SyncMessage::update(encoder_version, update)).encode_v1()),
+        Err(e) => {
+          tracing::error!("🔴convert update to {} failed: {}", encoder_version, e);
+          None
+        },
+      },
+    )
+    .collect();
+  send_all(sink, messages).await
+}
+
+async fn send_all<S>(sink: &mut S, messages: Vec<Vec<u8>>) -> Result<(), S::Error>
+where
+  S: futures_util::Sink<Vec<u8>> + Unpin,
+{
+  for msg in messages {
+    sink.send(msg).await?;
+  }
+  Ok(())
+}
+
+fn set_sync_state(collab: &Weak<MutexCollab>, state: SyncState) {
+  if let Some(collab) = collab.upgrade() {
+    collab.lock().set_sync_state(state);
+  }
+}
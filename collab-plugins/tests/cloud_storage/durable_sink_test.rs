@@ -0,0 +1,110 @@
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use collab::core::collab::MutexCollab;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab_entity::{CollabObject, CollabType};
+use collab_persistence::kv::rocks_kv::RocksCollabDB;
+use collab_persistence::sink_queue::SinkQueueAction;
+use collab_plugins::cloud_storage::postgres::SupabaseDBPlugin;
+use collab_plugins::cloud_storage::RemoteCollabStorage;
+use serde_json::json;
+use tempfile::TempDir;
+use yrs::updates::decoder::Decode;
+use yrs::{Transact, Update};
+
+use crate::cloud_storage::util::MemoryRemoteStorage;
+
+#[tokio::test]
+async fn resend_pending_changes_after_restart_test() {
+  let uid = 1;
+  let tempdir = TempDir::new().unwrap();
+  let db = Arc::new(RocksCollabDB::open(tempdir.path()).unwrap());
+  let remote = Arc::new(MemoryRemoteStorage::default());
+  let object = CollabObject::new(
+    uid,
+    "1".to_string(),
+    CollabType::Document,
+    "w1".to_string(),
+    "1".to_string(),
+  );
+
+  // The changes are queued but not sent while the remote storage is disabled.
+  remote.set_enable(false);
+  let (collab, plugin) = open_collab(&object, &remote, Arc::downgrade(&db));
+  wait_until(|| sink_msg_count(&db, &object) == 1).await;
+  {
+    let guard = collab.lock();
+    guard.insert("name", "appflowy");
+    guard.with_action_transact_mut("edit desc", |txn| {
+      guard.insert_with_txn(txn, "desc", "hello");
+    });
+  }
+  wait_until(|| sink_msg_count(&db, &object) == 3).await;
+  assert_eq!(plugin.pending_changes_count(), 3);
+  drop(plugin);
+  drop(collab);
+
+  // The pending changes are reloaded and sent after restarting.
+  remote.set_enable(true);
+  let (_collab, plugin) = open_collab(&object, &remote, Arc::downgrade(&db));
+  assert!(plugin.pending_changes_count() >= 3);
+  wait_until(|| plugin.pending_changes_count() == 0).await;
+  wait_until(|| sink_msg_count(&db, &object) == 0).await;
+
+  let restored = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  for update in remote.get_all_updates(&object).await.unwrap() {
+    restored
+      .lock()
+      .get_doc()
+      .transact_mut()
+      .apply_update(Update::decode_v1(&update).unwrap());
+  }
+  assert_eq!(
+    restored.to_json_value(),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+
+  // The metadata of the local transactions is kept in the queue and sent with the updates.
+  let txn_metas = remote.txn_metas.lock().get("1").cloned().unwrap();
+  assert_eq!(txn_metas.len(), 2);
+  assert!(txn_metas.iter().all(|meta| meta.uid == Some(uid)));
+  assert_eq!(txn_metas[1].action.as_deref(), Some("edit desc"));
+}
+
+fn open_collab(
+  object: &CollabObject,
+  remote: &Arc<MemoryRemoteStorage>,
+  db: Weak<RocksCollabDB>,
+) -> (Arc<MutexCollab>, Arc<SupabaseDBPlugin<RocksCollabDB>>) {
+  let origin = CollabOrigin::Client(CollabClient::new(object.uid, "1"));
+  let collab = Arc::new(MutexCollab::new(origin, &object.object_id, vec![]));
+  let plugin = Arc::new(SupabaseDBPlugin::new_with_durable_sink(
+    object.uid,
+    object.clone(),
+    Arc::downgrade(&collab),
+    1,
+    remote.clone(),
+    db,
+  ));
+  collab.lock().add_plugin(plugin.clone());
+  collab.lock().initialize();
+  (collab, plugin)
+}
+
+fn sink_msg_count(db: &RocksCollabDB, object: &CollabObject) -> usize {
+  db.read_txn()
+    .get_sink_msgs(object.uid, &object.object_id)
+    .unwrap()
+    .len()
+}
+
+async fn wait_until<F: Fn() -> bool>(f: F) {
+  for _ in 0..200 {
+    if f() {
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  panic!("timeout");
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use collab::core::collab::MutexCollab;
+use collab::core::commit::TransactionMeta;
+use collab::core::origin::CollabOrigin;
+use collab_entity::{CollabObject, CollabType};
+use collab_persistence::kv::encrypted_kv::{EncryptionKey, MemoryKeyProvider};
+use collab_plugins::cloud_storage::{EncryptedRemoteCollabStorage, RemoteCollabStorage};
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+use yrs::{Transact, Update};
+
+use crate::cloud_storage::util::MemoryRemoteStorage;
+
+#[tokio::test]
+async fn encrypted_remote_storage_test() {
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  key_provider.add_key("w1", EncryptionKey::generate());
+  let remote = Arc::new(MemoryRemoteStorage::default());
+  let storage = EncryptedRemoteCollabStorage::new(remote.clone(), "w1", key_provider.clone());
+  let object = CollabObject::new(
+    1,
+    "1".to_string(),
+    CollabType::Document,
+    "w1".to_string(),
+    "1".to_string(),
+  );
+  let mut remote_updates = storage.subscribe_remote_updates(&object).unwrap();
+
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().insert("name", "appflowy");
+  let (update, _) = collab.encode_as_update_v1();
+  storage
+    .send_init_sync(&object, 1, update.clone())
+    .await
+    .unwrap();
+  storage
+    .create_snapshot(&object, update.clone())
+    .await
+    .unwrap();
+
+  // The remote only stores the ciphertext.
+  let plaintext = b"appflowy";
+  let stored_update = remote.updates.lock().get("1").unwrap()[0].clone();
+  let stored_snapshot = remote.snapshots.lock().get("1").unwrap()[0].clone();
+  for stored in [&stored_update, &stored_snapshot] {
+    assert!(!stored.windows(plaintext.len()).any(|w| w == plaintext));
+  }
+
+  // The payloads are decrypted when they are received.
+  assert_eq!(
+    storage.get_all_updates(&object).await.unwrap(),
+    vec![update.clone()]
+  );
+  assert_eq!(remote_updates.recv().await.unwrap(), update);
+  let snapshots = storage.get_snapshots("1", 1).await;
+  assert_eq!(snapshots[0].blob, update);
+
+  // The updates that were encrypted with the previous key are still decrypted after rotating
+  // the key of the workspace.
+  key_provider.add_key("w1", EncryptionKey::generate());
+  collab.lock().insert("desc", "hello");
+  let (update_2, _) = collab.encode_as_update_v1();
+  let meta = TransactionMeta {
+    uid: Some(1),
+    action: Some("edit desc".to_string()),
+    ..Default::default()
+  };
+  storage
+    .send_update_with_meta(&object, 2, update_2, vec![meta])
+    .await
+    .unwrap();
+  // The metadata of the transactions is not sent in plaintext.
+  assert!(remote.txn_metas.lock().get("1").is_none());
+  let restored = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  for update in storage.get_all_updates(&object).await.unwrap() {
+    restored
+      .lock()
+      .get_doc()
+      .transact_mut()
+      .apply_update(Update::decode_v1(&update).unwrap());
+  }
+  assert_eq!(
+    restored.to_json_value(),
+    json!({"name": "appflowy", "desc": "hello"})
+  );
+}
+
+#[tokio::test]
+async fn reject_payload_of_other_object_test() {
+  let key_provider = Arc::new(MemoryKeyProvider::new());
+  key_provider.add_key("w1", EncryptionKey::generate());
+  let remote = Arc::new(MemoryRemoteStorage::default());
+  let storage = EncryptedRemoteCollabStorage::new(remote.clone(), "w1", key_provider);
+  let object_1 = CollabObject::new(
+    1,
+    "1".to_string(),
+    CollabType::Document,
+    "w1".to_string(),
+    "1".to_string(),
+  );
+  let object_2 = CollabObject::new(
+    1,
+    "2".to_string(),
+    CollabType::Document,
+    "w1".to_string(),
+    "1".to_string(),
+  );
+  storage
+    .send_update(&object_1, 1, vec![1, 2, 3])
+    .await
+    .unwrap();
+
+  // The payload of an object can't be decrypted as the payload of another object.
+  let payload = remote.updates.lock().get("1").unwrap()[0].clone();
+  remote.updates.lock().insert("2".to_string(), vec![payload]);
+  assert!(storage.get_all_updates(&object_2).await.is_err());
+}
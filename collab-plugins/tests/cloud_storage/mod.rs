@@ -1,3 +1,5 @@
 // mod aws;
 // mod postgres;
+mod durable_sink_test;
+mod encrypted_storage_test;
 mod util;
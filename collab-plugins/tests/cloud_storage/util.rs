@@ -1 +1,153 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use anyhow::Error;
+use async_trait::async_trait;
+use collab::core::commit::TransactionMeta;
+use collab_entity::CollabObject;
+use collab_plugins::cloud_storage::{
+  RemoteCollabSnapshot, RemoteCollabState, RemoteCollabStorage, RemoteUpdateReceiver,
+  RemoteUpdateSender,
+};
+use parking_lot::Mutex;
+use tokio::sync::mpsc::unbounded_channel;
+
+/// A [RemoteCollabStorage] that keeps the updates and the snapshots in memory. The updates that
+/// are sent are broadcast to the subscribers.
+pub struct MemoryRemoteStorage {
+  enable: AtomicBool,
+  pub updates: Mutex<HashMap<String, Vec<Vec<u8>>>>,
+  pub snapshots: Mutex<HashMap<String, Vec<Vec<u8>>>>,
+  pub txn_metas: Mutex<HashMap<String, Vec<TransactionMeta>>>,
+  subscribers: Mutex<HashMap<String, Vec<RemoteUpdateSender>>>,
+}
+
+impl Default for MemoryRemoteStorage {
+  fn default() -> Self {
+    Self {
+      enable: AtomicBool::new(true),
+      updates: Default::default(),
+      snapshots: Default::default(),
+      txn_metas: Default::default(),
+      subscribers: Default::default(),
+    }
+  }
+}
+
+impl MemoryRemoteStorage {
+  /// The messages are not sent to a disabled storage, so they stay in the queue.
+  pub fn set_enable(&self, enable: bool) {
+    self.enable.store(enable, Ordering::SeqCst);
+  }
+
+  fn push_update(&self, object_id: &str, update: Vec<u8>) {
+    if let Some(subscribers) = self.subscribers.lock().get(object_id) {
+      for subscriber in subscribers {
+        let _ = subscriber.send(update.clone());
+      }
+    }
+    self
+      .updates
+      .lock()
+      .entry(object_id.to_string())
+      .or_default()
+      .push(update);
+  }
+}
+
+#[async_trait]
+impl RemoteCollabStorage for MemoryRemoteStorage {
+  fn is_enable(&self) -> bool {
+    self.enable.load(Ordering::SeqCst)
+  }
+
+  async fn get_all_updates(&self, object: &CollabObject) -> Result<Vec<Vec<u8>>, Error> {
+    Ok(
+      self
+        .updates
+        .lock()
+        .get(&object.object_id)
+        .cloned()
+        .unwrap_or_default(),
+    )
+  }
+
+  async fn get_snapshots(&self, object_id: &str, limit: usize) -> Vec<RemoteCollabSnapshot> {
+    self
+      .snapshots
+      .lock()
+      .get(object_id)
+      .cloned()
+      .unwrap_or_default()
+      .into_iter()
+      .enumerate()
+      .rev()
+      .take(limit)
+      .map(|(sid, blob)| RemoteCollabSnapshot {
+        sid: sid as i64,
+        oid: object_id.to_string(),
+        blob,
+        created_at: 0,
+      })
+      .collect()
+  }
+
+  async fn get_collab_state(&self, _object_id: &str) -> Result<Option<RemoteCollabState>, Error> {
+    Ok(None)
+  }
+
+  async fn create_snapshot(&self, object: &CollabObject, snapshot: Vec<u8>) -> Result<i64, Error> {
+    let mut snapshots = self.snapshots.lock();
+    let snapshots = snapshots.entry(object.object_id.clone()).or_default();
+    snapshots.push(snapshot);
+    Ok(snapshots.len() as i64 - 1)
+  }
+
+  async fn send_update(
+    &self,
+    object: &CollabObject,
+    _id: u64,
+    update: Vec<u8>,
+  ) -> Result<(), Error> {
+    self.push_update(&object.object_id, update);
+    Ok(())
+  }
+
+  async fn send_update_with_meta(
+    &self,
+    object: &CollabObject,
+    _id: u64,
+    update: Vec<u8>,
+    txn_metas: Vec<TransactionMeta>,
+  ) -> Result<(), Error> {
+    self
+      .txn_metas
+      .lock()
+      .entry(object.object_id.clone())
+      .or_default()
+      .extend(txn_metas);
+    self.push_update(&object.object_id, update);
+    Ok(())
+  }
+
+  async fn send_init_sync(
+    &self,
+    object: &CollabObject,
+    _id: u64,
+    init_update: Vec<u8>,
+  ) -> Result<(), Error> {
+    self.push_update(&object.object_id, init_update);
+    Ok(())
+  }
+
+  fn subscribe_remote_updates(&self, object: &CollabObject) -> Option<RemoteUpdateReceiver> {
+    let (tx, rx) = unbounded_channel();
+    self
+      .subscribers
+      .lock()
+      .entry(object.object_id.clone())
+      .or_default()
+      .push(tx);
+    Some(rx)
+  }
+}
@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use collab::core::collab_plugin::PluginHook;
+use collab::preclude::{Collab, CollabBuilder};
+use collab_persistence::doc::YrsDocAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
+#[cfg(feature = "rocksdb_plugin")]
+use collab_persistence::kv::rocks_kv::RocksCollabDB;
+use collab_persistence::kv::sqlite_kv::SqliteCollabDB;
+use collab_persistence::kv::KVTransactionDB;
+use collab_persistence::quarantine::QuarantineAction;
+use collab_persistence::txn_meta::TransactionMetaAction;
+use collab_plugins::local_storage::disk::DiskPlugin;
+use serde_json::json;
+use tempfile::TempDir;
+use test_case::test_case;
+use yrs::updates::decoder::Decode;
+use yrs::{Transact, Update};
+
+#[cfg_attr(feature = "rocksdb_plugin", test_case(rocks_db() ; "rocksdb"))]
+#[test_case(MemoryCollabDB::new() ; "memory")]
+#[test_case(sqlite_db() ; "sqlite")]
+#[tokio::test]
+async fn restore_test<DB: KVTransactionDB>(db: DB) {
+  let db = &Arc::new(db);
+  {
+    let collab = CollabBuilder::new(1, "1")
+      .with_device_id("1")
+      .with_plugin(DiskPlugin::new(1, Arc::downgrade(db)))
+      .build()
+      .unwrap();
+    collab.lock().initialize();
+    collab.lock().insert("1", "a");
+    collab.lock().insert("2", "b");
+  }
+
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(DiskPlugin::new(1, Arc::downgrade(db)))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert_eq!(collab.to_json_value(), json!({"1": "a", "2": "b"}));
+}
+
+#[tokio::test]
+async fn quarantine_corrupted_update_on_load_test() {
+  let db = Arc::new(MemoryCollabDB::new());
+  {
+    let collab = CollabBuilder::new(1, "1")
+      .with_device_id("1")
+      .with_plugin(DiskPlugin::new(1, Arc::downgrade(&db)))
+      .build()
+      .unwrap();
+    collab.lock().initialize();
+    collab.lock().insert("1", "a");
+  }
+  db.with_write_txn(|w| w.push_update(1, "1", &[255, 255, 255]))
+    .unwrap();
+
+  let plugin = DiskPlugin::new(1, Arc::downgrade(&db));
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(plugin.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert_eq!(collab.to_json_value(), json!({"1": "a"}));
+  let report = plugin.load_report().unwrap();
+  assert_eq!(report.quarantined.len(), 1);
+  assert_eq!(
+    db.read_txn().get_quarantined_updates(1, "1").unwrap(),
+    vec![vec![255, 255, 255]]
+  );
+  collab.lock().insert("2", "b");
+  drop(collab);
+
+  // The corrupted update doesn't break the next load.
+  let plugin = DiskPlugin::new(1, Arc::downgrade(&db));
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(plugin.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert!(plugin.load_report().unwrap().is_clean());
+  assert_eq!(collab.to_json_value(), json!({"1": "a", "2": "b"}));
+}
+
+#[tokio::test]
+async fn async_write_test() {
+  let db = Arc::new(MemoryCollabDB::new());
+  {
+    let plugin = DiskPlugin::new(1, Arc::downgrade(&db)).with_async_write();
+    let collab = CollabBuilder::new(1, "1")
+      .with_device_id("1")
+      .with_plugin(plugin.clone())
+      .with_async_plugin(plugin)
+      .build()
+      .unwrap();
+    collab.lock().initialize();
+    collab.lock().insert("1", "a");
+    collab.lock().insert("2", "b");
+    let closed = collab.lock().close();
+    closed.await;
+  }
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(DiskPlugin::new(1, Arc::downgrade(&db)))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert_eq!(collab.to_json_value(), json!({"1": "a", "2": "b"}));
+  drop(collab);
+
+  // The errors of the writes are returned instead of being logged.
+  let plugin = DiskPlugin::new(1, Arc::downgrade(&db)).with_async_write();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(plugin.clone())
+    .with_async_plugin(plugin)
+    .build()
+    .unwrap();
+  let mut error_rx = collab.lock().subscribe_plugin_error();
+  collab.lock().initialize();
+  drop(db);
+  collab.lock().insert("3", "c");
+  let error = error_rx.recv().await.unwrap();
+  assert_eq!(error.plugin, "disk");
+  assert_eq!(error.hook, PluginHook::ReceiveLocalUpdate);
+}
+
+#[tokio::test]
+async fn persist_txn_meta_with_update_test() {
+  for async_write in [false, true] {
+    let db = Arc::new(MemoryCollabDB::new());
+    let plugin = DiskPlugin::new(1, Arc::downgrade(&db));
+    let builder = CollabBuilder::new(1, "1").with_device_id("1");
+    let collab = if async_write {
+      let plugin = plugin.with_async_write();
+      builder
+        .with_plugin(plugin.clone())
+        .with_async_plugin(plugin)
+    } else {
+      builder.with_plugin(plugin)
+    }
+    .build()
+    .unwrap();
+    collab.lock().initialize();
+    {
+      let guard = collab.lock();
+      guard.insert("1", "a");
+      guard.with_action_transact_mut("paste", |txn| guard.insert_with_txn(txn, "2", "b"));
+    }
+
+    // The remote updates are written without metadata.
+    let remote = Collab::new(2, "1", "2", vec![]);
+    remote.insert("3", "c");
+    let (doc_state, _) = remote.encode_as_update_v1();
+    collab
+      .lock()
+      .get_doc()
+      .transact_mut()
+      .apply_update(Update::decode_v1(&doc_state).unwrap());
+    let closed = collab.lock().close();
+    closed.await;
+
+    let read_txn = db.read_txn();
+    assert_eq!(read_txn.number_of_updates(1, "1"), 3);
+    let metas = read_txn.get_txn_metas(1, "1").unwrap();
+    assert_eq!(metas.len(), 2);
+    assert_eq!(metas[1].action.as_deref(), Some("paste"));
+  }
+}
+
+#[tokio::test]
+async fn import_persisted_txn_metas_test() {
+  let db = Arc::new(MemoryCollabDB::new());
+  {
+    let collab = CollabBuilder::new(1, "1")
+      .with_device_id("1")
+      .with_plugin(DiskPlugin::new(1, Arc::downgrade(&db)))
+      .build()
+      .unwrap();
+    collab.lock().initialize();
+    collab.lock().insert("title", "hello");
+  }
+
+  // The document is edited by another client id after reopening it. The author and the time of
+  // the previous edits are known from the persisted metadata.
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(DiskPlugin::new(1, Arc::downgrade(&db)))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  let title = collab.lock().blame(vec!["title"]).unwrap();
+  assert_ne!(title.client_id, collab.lock().get_doc().client_id());
+  assert_eq!(title.uid, Some(1));
+  assert_eq!(title.device_id.as_deref(), Some("1"));
+  assert!(title.timestamp.is_some());
+}
+
+#[cfg(feature = "rocksdb_plugin")]
+fn rocks_db() -> RocksCollabDB {
+  RocksCollabDB::open(TempDir::new().unwrap().into_path()).unwrap()
+}
+
+fn sqlite_db() -> SqliteCollabDB {
+  let path = TempDir::new().unwrap().into_path().join("collab.db");
+  SqliteCollabDB::open(path).unwrap()
+}
@@ -1,5 +1,6 @@
 mod delete_test;
 mod insert_test;
+mod kv_store_test;
 mod script;
 mod snapshot_test;
 mod undo_test;
@@ -8,19 +8,18 @@ use collab::preclude::*;
 use collab_entity::{CollabObject, CollabType};
 use collab_persistence::doc::YrsDocAction;
 use collab_persistence::kv::rocks_kv::RocksCollabDB;
-use collab_plugins::local_storage::rocksdb::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::DiskPlugin;
 use collab_plugins::local_storage::CollabPersistenceConfig;
 use collab_plugins::snapshot::CollabSnapshotPlugin;
 use lib0::any::Any;
 use tempfile::TempDir;
-use yrs::updates::decoder::Decode;
 
 use crate::setup_log;
 
 pub enum Script {
   CreateDocumentWithDiskPlugin {
     id: String,
-    plugin: RocksdbDiskPlugin,
+    plugin: DiskPlugin<RocksCollabDB>,
   },
   OpenDocumentWithDiskPlugin {
     id: String,
@@ -73,7 +72,7 @@ pub struct CollabPersistenceTest {
   #[allow(dead_code)]
   cleaner: Cleaner,
   db: Arc<RocksCollabDB>,
-  disk_plugin: Arc<RocksdbDiskPlugin>,
+  disk_plugin: Arc<DiskPlugin<RocksCollabDB>>,
   config: CollabPersistenceConfig,
 }
 
@@ -84,7 +83,7 @@ impl CollabPersistenceTest {
     let db_path = tempdir.into_path();
     let uid = 1;
     let db = Arc::new(RocksCollabDB::open(db_path.clone()).unwrap());
-    let disk_plugin = Arc::new(RocksdbDiskPlugin::new_with_config(
+    let disk_plugin = Arc::new(DiskPlugin::new_with_config(
       uid,
       Arc::downgrade(&db),
       config.clone(),
@@ -112,7 +111,7 @@ impl CollabPersistenceTest {
     object_id: String,
     object_ty: CollabType,
     _collab: Arc<MutexCollab>,
-  ) -> Arc<CollabSnapshotPlugin> {
+  ) -> Arc<CollabSnapshotPlugin<RocksCollabDB>> {
     let object = CollabObject::new(
       uid,
       object_id,
@@ -308,7 +307,7 @@ impl CollabPersistenceTest {
           .build()
           .unwrap();
         collab.lock().with_origin_transact_mut(|txn| {
-          txn.apply_update(Update::decode_v1(&snapshots[index as usize].data).unwrap());
+          txn.apply_update(snapshots[index as usize].decode_update().unwrap());
         });
 
         let json = collab.lock().to_json_value();
@@ -340,11 +339,11 @@ impl CollabPersistenceTest {
   }
 }
 
-pub fn disk_plugin(uid: i64) -> (Arc<RocksCollabDB>, RocksdbDiskPlugin) {
+pub fn disk_plugin(uid: i64) -> (Arc<RocksCollabDB>, DiskPlugin<RocksCollabDB>) {
   let tempdir = TempDir::new().unwrap();
   let path = tempdir.into_path();
   let db = Arc::new(RocksCollabDB::open(path).unwrap());
-  let plugin = RocksdbDiskPlugin::new(uid, Arc::downgrade(&db));
+  let plugin = DiskPlugin::new(uid, Arc::downgrade(&db));
   (db, plugin)
 }
 
@@ -1,4 +1,12 @@
+use std::sync::Arc;
+
+use collab::core::encoding::EncoderVersion;
+use collab::core::path_observer::PathChangeKind;
+use collab::preclude::Collab;
+use collab_persistence::encoding::EncodingAction;
+use collab_persistence::kv::memory_kv::MemoryCollabDB;
 use collab_plugins::local_storage::CollabPersistenceConfig;
+use collab_plugins::snapshot::{calculate_snapshot_diff, SnapshotPersistence};
 use rand::Rng;
 use serde_json::json;
 
@@ -300,3 +308,37 @@ fn generate_random_string(length: usize) -> String {
 
   random_string
 }
+
+#[tokio::test]
+async fn v2_encoded_snapshot_diff_test() {
+  let db = Arc::new(MemoryCollabDB::new());
+  db.with_write_txn(|txn| txn.set_encoder_version(EncoderVersion::V2))
+    .unwrap();
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.insert("title", "hello");
+  let (snapshot_data, _) = collab.encode_as_update_v1();
+  db.create_snapshot(1, "1", "".to_string(), snapshot_data)
+    .unwrap();
+  collab.insert("title", "world");
+  let (snapshot_data, _) = collab.encode_as_update_v1();
+  db.create_snapshot(1, "1", "".to_string(), snapshot_data)
+    .unwrap();
+
+  // The snapshots are stored with the encoder version of the store.
+  let snapshots = db.get_snapshots(1, "1");
+  assert_eq!(snapshots.len(), 2);
+  assert!(snapshots
+    .iter()
+    .all(|snapshot| snapshot.encoder_version == EncoderVersion::V2));
+
+  let changes = calculate_snapshot_diff(1, "1", &snapshots[0], &snapshots[1]).unwrap();
+  assert_eq!(changes.len(), 1);
+  assert_eq!(
+    changes[0].kind,
+    PathChangeKind::Updated {
+      key: "title".to_string(),
+      old_value: json!("hello"),
+      value: json!("world"),
+    }
+  );
+}
@@ -6,6 +6,7 @@ use tracing_subscriber::EnvFilter;
 
 mod cloud_storage;
 mod disk;
+mod sync;
 
 pub fn setup_log() {
   static START: Once = Once::new();
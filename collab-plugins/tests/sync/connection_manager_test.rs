@@ -0,0 +1,188 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use collab::core::collab::MutexCollab;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::sync_protocol::envelope::{AckStatus, CollabAck, CollabFrame, CollabFrameReader};
+use collab::sync_protocol::server::{CollabServer, CollabServerConfig};
+use collab_entity::{CollabObject, CollabType};
+use collab_plugins::sync_plugin::{CollabConnectionManager, ConnectionManagerConfig};
+use serde_json::json;
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+use crate::sync::util::{
+  wait_until, SilentConnector, TestMultiplexConnector, UnreachableConnector,
+};
+
+struct MultiplexClient {
+  uid: i64,
+  origin: CollabOrigin,
+  manager: CollabConnectionManager,
+  num_of_connects: Arc<AtomicUsize>,
+}
+
+impl MultiplexClient {
+  fn new(server: Arc<CollabServer>, uid: i64) -> Self {
+    let origin = CollabOrigin::Client(CollabClient::new(uid, uid.to_string()));
+    let num_of_connects = Arc::new(AtomicUsize::new(0));
+    let connector = TestMultiplexConnector {
+      server,
+      origin: origin.clone(),
+      num_of_connects: num_of_connects.clone(),
+    };
+    let manager =
+      CollabConnectionManager::new(origin.clone(), connector, ConnectionManagerConfig::new());
+    Self {
+      uid,
+      origin,
+      manager,
+      num_of_connects,
+    }
+  }
+
+  fn open(&self, object_id: &str) -> Arc<MutexCollab> {
+    open_collab(&self.manager, self.uid, &self.origin, object_id)
+  }
+}
+
+fn open_collab(
+  manager: &CollabConnectionManager,
+  uid: i64,
+  origin: &CollabOrigin,
+  object_id: &str,
+) -> Arc<MutexCollab> {
+  let object = CollabObject::new(
+    uid,
+    object_id.to_string(),
+    CollabType::Document,
+    "w1".to_string(),
+    uid.to_string(),
+  );
+  let collab = Arc::new(MutexCollab::new(origin.clone(), object_id, vec![]));
+  let plugin = manager.register(&object, Arc::downgrade(&collab));
+  collab.lock().add_plugin(Arc::new(plugin));
+  collab.lock().initialize();
+  collab
+}
+
+#[tokio::test]
+async fn multiplex_sync_many_objects_test() {
+  let server = Arc::new(CollabServer::new(CollabServerConfig::default()));
+  let client_1 = MultiplexClient::new(server.clone(), 1);
+  let client_2 = MultiplexClient::new(server.clone(), 2);
+
+  let object_ids = (0..10).map(|i| format!("object_{}", i)).collect::<Vec<_>>();
+  let collabs_1 = object_ids
+    .iter()
+    .map(|object_id| client_1.open(object_id))
+    .collect::<Vec<_>>();
+  let collabs_2 = object_ids
+    .iter()
+    .map(|object_id| client_2.open(object_id))
+    .collect::<Vec<_>>();
+  assert_eq!(client_1.manager.number_of_objects(), 10);
+
+  for (i, collab) in collabs_1.iter().enumerate() {
+    collab.lock().insert("index", i as i64);
+  }
+
+  for (i, collab) in collabs_2.iter().enumerate() {
+    wait_until(|| collab.to_json_value() == json!({ "index": i })).await;
+  }
+  // All the objects share one connection.
+  assert_eq!(client_1.num_of_connects.load(Ordering::SeqCst), 1);
+  assert_eq!(client_2.num_of_connects.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn multiplex_unsubscribe_when_collab_dropped_test() {
+  let server = Arc::new(CollabServer::new(CollabServerConfig::default()));
+  let client = MultiplexClient::new(server.clone(), 1);
+  let collab = client.open("object_1");
+  collab.lock().insert("1", "a");
+
+  wait_until(|| server.get_group("object_1").is_some()).await;
+  let group = server.get_group("object_1").unwrap();
+  wait_until(|| group.collab().to_json_value() == json!({"1": "a"})).await;
+  assert!(group.contains_subscriber(&client.origin));
+
+  drop(collab);
+  wait_until(|| !group.contains_subscriber(&client.origin)).await;
+  assert_eq!(client.manager.number_of_objects(), 0);
+}
+
+#[tokio::test]
+async fn multiplex_merge_pending_updates_test() {
+  let origin = CollabOrigin::Client(CollabClient::new(1, "1"));
+  let config = ConnectionManagerConfig::new().max_pending_per_object(10);
+  let manager = CollabConnectionManager::new(origin.clone(), UnreachableConnector, config);
+  let collab = open_collab(&manager, 1, &origin, "object_1");
+
+  for i in 0..100 {
+    collab.lock().insert(&i.to_string(), i);
+  }
+  // The updates that exceed the limit are merged into one update.
+  assert!(manager.number_of_pending_messages("object_1") <= 10);
+
+  // Only the runs of consecutive updates are merged. The handshake messages stay in front.
+  let config = ConnectionManagerConfig::new().max_pending_per_object(1);
+  let manager = CollabConnectionManager::new(origin.clone(), UnreachableConnector, config);
+  let collab = open_collab(&manager, 1, &origin, "object_1");
+  for i in 0..3 {
+    collab.lock().insert(&i.to_string(), i);
+  }
+  assert_eq!(manager.number_of_pending_messages("object_1"), 3);
+}
+
+#[tokio::test]
+async fn multiplex_in_flight_window_test() {
+  let origin = CollabOrigin::Client(CollabClient::new(1, "1"));
+  let config = ConnectionManagerConfig::new().max_in_flight_per_object(5);
+  let manager = CollabConnectionManager::new(origin.clone(), SilentConnector::default(), config);
+  let collab = open_collab(&manager, 1, &origin, "object_1");
+  // The handshake is sent when the connection is opened.
+  wait_until(|| manager.number_of_in_flight_messages("object_1") == 2).await;
+  for i in 0..20 {
+    collab.lock().insert(&i.to_string(), i);
+  }
+
+  // The messages that are not acknowledged by the remote hold back the object.
+  wait_until(|| manager.number_of_in_flight_messages("object_1") == 5).await;
+  tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+  assert_eq!(manager.number_of_in_flight_messages("object_1"), 5);
+  assert_eq!(manager.number_of_pending_messages("object_1"), 17);
+}
+
+#[tokio::test]
+async fn multiplex_ignore_unknown_ack_test() {
+  let origin = CollabOrigin::Client(CollabClient::new(1, "1"));
+  let config = ConnectionManagerConfig::new().max_in_flight_per_object(5);
+  let connector = SilentConnector::default();
+  let (frames, replies) = (connector.frames.clone(), connector.replies.clone());
+  let manager = CollabConnectionManager::new(origin.clone(), connector, config);
+  let _collab = open_collab(&manager, 1, &origin, "object_1");
+  wait_until(|| manager.number_of_in_flight_messages("object_1") == 2).await;
+  let msg_ids = {
+    let data = frames.lock()[0].try_next().unwrap().unwrap();
+    let mut decoder = DecoderV1::from(data.as_slice());
+    CollabFrameReader::new(&mut decoder)
+      .filter_map(|frame| match frame.unwrap() {
+        CollabFrame::Envelope(envelope) => Some(envelope.msg_id),
+        _ => None,
+      })
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(msg_ids.len(), 2);
+
+  // The unknown and the duplicated acknowledgements don't free the window.
+  let mut encoder = EncoderV1::new();
+  CollabAck::new("object_1", msg_ids[1] + 100, AckStatus::Ok).encode(&mut encoder);
+  CollabAck::new("object_2", msg_ids[0], AckStatus::Ok).encode(&mut encoder);
+  CollabAck::new("object_1", msg_ids[0], AckStatus::Ok).encode(&mut encoder);
+  CollabAck::new("object_1", msg_ids[0], AckStatus::Ok).encode(&mut encoder);
+  replies.lock()[0].unbounded_send(encoder.to_vec()).unwrap();
+  wait_until(|| manager.number_of_in_flight_messages("object_1") == 1).await;
+  tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+  assert_eq!(manager.number_of_in_flight_messages("object_1"), 1);
+}
@@ -0,0 +1,3 @@
+mod connection_manager_test;
+mod sync_plugin_test;
+mod util;
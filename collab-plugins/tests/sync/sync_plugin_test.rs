@@ -0,0 +1,101 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use collab::core::collab_state::SyncState;
+use collab::core::encoding::EncoderVersion;
+use collab::sync_protocol::server::{CollabServer, CollabServerConfig};
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::sync::util::{wait_until, TestAuthenticator, TestClient};
+
+#[tokio::test]
+async fn sync_plugin_sync_with_server_test() {
+  let server = Arc::new(CollabServer::new(CollabServerConfig::default()));
+  let client_1 = TestClient::new(server.clone(), "1", 1);
+  let client_2 = TestClient::new(server.clone(), "1", 2);
+
+  client_1.collab.lock().insert("1", "a");
+  wait_until(|| client_2.collab.to_json_value() == json!({"1": "a"})).await;
+
+  client_2.collab.lock().insert("2", "b");
+  wait_until(|| client_1.collab.to_json_value() == json!({"1": "a", "2": "b"})).await;
+
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.collab().to_json_value(), json!({"1": "a", "2": "b"}));
+}
+
+#[tokio::test]
+async fn sync_plugin_sync_state_test() {
+  let server = Arc::new(CollabServer::new(CollabServerConfig::default()));
+  let client = TestClient::new(server.clone(), "1", 1);
+  let mut sync_state = client.collab.lock().subscribe_sync_state();
+  while let Some(state) = sync_state.next().await {
+    if state == SyncState::SyncFinished {
+      break;
+    }
+  }
+
+  client.collab.lock().insert("1", "a");
+  let group = server.get_group("1").unwrap();
+  wait_until(|| group.collab().to_json_value() == json!({"1": "a"})).await;
+}
+
+#[tokio::test]
+async fn sync_plugin_reconnect_test() {
+  let server = Arc::new(CollabServer::new(CollabServerConfig::default()));
+  let client = TestClient::new(server.clone(), "1", 1);
+  wait_until(|| client.num_of_connects.load(Ordering::SeqCst) == 1).await;
+  let group = server.get_group("1").unwrap();
+
+  // Closing the connection on the server side makes the plugin reconnect.
+  server.unsubscribe("1", &client.origin);
+  client.collab.lock().insert("1", "a");
+  wait_until(|| client.num_of_connects.load(Ordering::SeqCst) == 2).await;
+  wait_until(|| group.collab().to_json_value() == json!({"1": "a"})).await;
+}
+
+#[tokio::test]
+async fn sync_plugin_with_auth_token_test() {
+  let server = Arc::new(
+    CollabServer::new(CollabServerConfig::default())
+      .with_authenticator(Arc::new(TestAuthenticator)),
+  );
+  let client_1 = TestClient::new_with_token(server.clone(), "1", 1, Some("token_1"));
+  // The client without the token can't sync with the server.
+  let client_2 = TestClient::new(server.clone(), "1", 2);
+
+  client_1.collab.lock().insert("1", "a");
+  wait_until(|| {
+    server
+      .get_group("1")
+      .map(|group| group.collab().to_json_value() == json!({"1": "a"}))
+      .unwrap_or(false)
+  })
+  .await;
+
+  let group = server.get_group("1").unwrap();
+  client_2.collab.lock().insert("2", "b");
+  tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+  assert_eq!(group.collab().to_json_value(), json!({"1": "a"}));
+  assert_eq!(client_2.collab.to_json_value(), json!({"2": "b"}));
+}
+
+#[tokio::test]
+async fn sync_plugin_with_encoder_version_test() {
+  let server = Arc::new(CollabServer::new(CollabServerConfig::default()));
+  let client_1 = TestClient::new_with_encoder_version(server.clone(), "1", 1, EncoderVersion::V2);
+  let client_2 = TestClient::new(server.clone(), "1", 2);
+
+  client_1.collab.lock().insert("1", "a");
+  wait_until(|| client_2.collab.to_json_value() == json!({"1": "a"})).await;
+
+  client_2.collab.lock().insert("2", "b");
+  wait_until(|| client_1.collab.to_json_value() == json!({"1": "a", "2": "b"})).await;
+
+  // The v2 client keeps syncing after reconnecting.
+  server.unsubscribe("1", &client_1.origin);
+  client_1.collab.lock().insert("3", "c");
+  wait_until(|| client_1.num_of_connects.load(Ordering::SeqCst) == 2).await;
+  wait_until(|| client_2.collab.to_json_value() == json!({"1": "a", "2": "b", "3": "c"})).await;
+}
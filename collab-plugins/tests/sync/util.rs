@@ -0,0 +1,197 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use collab::core::collab::MutexCollab;
+use collab::core::encoding::EncoderVersion;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::sync_protocol::auth::{CollabAuthenticator, CollabPermission};
+use collab::sync_protocol::server::CollabServer;
+use collab_plugins::sync_plugin::{SyncConnector, SyncPlugin};
+use futures::channel::mpsc::{unbounded, SendError, UnboundedReceiver, UnboundedSender};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+/// Connects the [SyncPlugin] to the [CollabServer] through in-memory channels.
+pub struct TestConnector {
+  server: Arc<CollabServer>,
+  object_id: String,
+  origin: CollabOrigin,
+  num_of_connects: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl SyncConnector for TestConnector {
+  type Error = SendError;
+  type Sink = UnboundedSender<Vec<u8>>;
+  type Stream = BoxStream<'static, Result<Vec<u8>, SendError>>;
+
+  async fn connect(&self) -> Result<(Self::Sink, Self::Stream), Self::Error> {
+    let (client_sink, server_stream) = unbounded();
+    let (server_sink, client_stream) = unbounded();
+    self
+      .server
+      .subscribe(
+        &self.object_id,
+        self.origin.clone(),
+        server_sink,
+        server_stream.map(Ok::<_, SendError>),
+      )
+      .await
+      .unwrap();
+    self.num_of_connects.fetch_add(1, Ordering::SeqCst);
+    Ok((client_sink, client_stream.map(Ok).boxed()))
+  }
+}
+
+/// Connects the [CollabConnectionManager] to the [CollabServer] through in-memory channels.
+pub struct TestMultiplexConnector {
+  pub server: Arc<CollabServer>,
+  pub origin: CollabOrigin,
+  pub num_of_connects: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl SyncConnector for TestMultiplexConnector {
+  type Error = SendError;
+  type Sink = UnboundedSender<Vec<u8>>;
+  type Stream = BoxStream<'static, Result<Vec<u8>, SendError>>;
+
+  async fn connect(&self) -> Result<(Self::Sink, Self::Stream), Self::Error> {
+    let (client_sink, server_stream) = unbounded();
+    let (server_sink, client_stream) = unbounded();
+    self.server.subscribe_multiplexed(
+      self.origin.clone(),
+      server_sink,
+      server_stream.map(Ok::<_, SendError>),
+    );
+    self.num_of_connects.fetch_add(1, Ordering::SeqCst);
+    Ok((client_sink, client_stream.map(Ok).boxed()))
+  }
+}
+
+/// A connector that never connects.
+pub struct UnreachableConnector;
+
+#[async_trait]
+impl SyncConnector for UnreachableConnector {
+  type Error = SendError;
+  type Sink = UnboundedSender<Vec<u8>>;
+  type Stream = BoxStream<'static, Result<Vec<u8>, SendError>>;
+
+  async fn connect(&self) -> Result<(Self::Sink, Self::Stream), Self::Error> {
+    futures::future::pending().await
+  }
+}
+
+/// A connector whose remote receives the frames but never replies on its own, so no message is
+/// acknowledged unless the test sends the acknowledgement through the `replies`.
+#[derive(Default)]
+pub struct SilentConnector {
+  pub frames: Arc<parking_lot::Mutex<Vec<UnboundedReceiver<Vec<u8>>>>>,
+  pub replies: Arc<parking_lot::Mutex<Vec<UnboundedSender<Vec<u8>>>>>,
+}
+
+#[async_trait]
+impl SyncConnector for SilentConnector {
+  type Error = SendError;
+  type Sink = UnboundedSender<Vec<u8>>;
+  type Stream = BoxStream<'static, Result<Vec<u8>, SendError>>;
+
+  async fn connect(&self) -> Result<(Self::Sink, Self::Stream), Self::Error> {
+    let (sink, frames) = unbounded();
+    let (replies, stream) = unbounded();
+    self.frames.lock().push(frames);
+    self.replies.lock().push(replies);
+    Ok((sink, stream.map(Ok).boxed()))
+  }
+}
+
+pub struct TestClient {
+  pub origin: CollabOrigin,
+  pub collab: Arc<MutexCollab>,
+  pub num_of_connects: Arc<AtomicUsize>,
+}
+
+impl TestClient {
+  pub fn new(server: Arc<CollabServer>, object_id: &str, uid: i64) -> Self {
+    Self::new_with_token(server, object_id, uid, None)
+  }
+
+  pub fn new_with_token(
+    server: Arc<CollabServer>,
+    object_id: &str,
+    uid: i64,
+    auth_token: Option<&str>,
+  ) -> Self {
+    Self::new_with_options(server, object_id, uid, auth_token, EncoderVersion::V1)
+  }
+
+  pub fn new_with_encoder_version(
+    server: Arc<CollabServer>,
+    object_id: &str,
+    uid: i64,
+    encoder_version: EncoderVersion,
+  ) -> Self {
+    Self::new_with_options(server, object_id, uid, None, encoder_version)
+  }
+
+  fn new_with_options(
+    server: Arc<CollabServer>,
+    object_id: &str,
+    uid: i64,
+    auth_token: Option<&str>,
+    encoder_version: EncoderVersion,
+  ) -> Self {
+    let origin = CollabOrigin::Client(CollabClient::new(uid, uid.to_string()));
+    let num_of_connects = Arc::new(AtomicUsize::new(0));
+    let collab = Arc::new(MutexCollab::new(origin.clone(), object_id, vec![]));
+    let connector = TestConnector {
+      server,
+      object_id: object_id.to_string(),
+      origin: origin.clone(),
+      num_of_connects: num_of_connects.clone(),
+    };
+    let mut plugin = SyncPlugin::new(object_id, Arc::downgrade(&collab), connector)
+      .with_encoder_version(encoder_version);
+    if let Some(auth_token) = auth_token {
+      plugin = plugin.with_auth_token(auth_token);
+    }
+    collab.lock().add_plugin(Arc::new(plugin));
+    collab.lock().initialize();
+    Self {
+      origin,
+      collab,
+      num_of_connects,
+    }
+  }
+}
+
+/// Accepts the token in the form of `token_{uid}` and grants the edit permission of all the
+/// objects.
+pub struct TestAuthenticator;
+
+#[async_trait]
+impl CollabAuthenticator for TestAuthenticator {
+  async fn authenticate(&self, token: &str) -> Result<i64, anyhow::Error> {
+    let uid = token
+      .strip_prefix("token_")
+      .ok_or_else(|| anyhow::anyhow!("invalid token"))?;
+    Ok(uid.parse()?)
+  }
+
+  async fn permission(&self, _uid: i64, _object_id: &str) -> Option<CollabPermission> {
+    Some(CollabPermission::Edit)
+  }
+}
+
+pub async fn wait_until<F: Fn() -> bool>(f: F) {
+  for _ in 0..50 {
+    if f() {
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  panic!("timeout");
+}
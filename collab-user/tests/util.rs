@@ -6,7 +6,7 @@ use std::time::Duration;
 use anyhow::Result;
 use collab::preclude::CollabBuilder;
 use collab_plugins::kv::rocks_kv::RocksCollabDB;
-use collab_plugins::local_storage::rocksdb::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::DiskPlugin;
 use collab_user::core::{
   MutexUserAwareness, RemindersChangeSender, UserAwareness, UserAwarenessNotifier,
 };
@@ -37,7 +37,7 @@ impl UserAwarenessTest {
 
     let path = tempdir.into_path();
     let db = Arc::new(RocksCollabDB::open(path.clone()).unwrap());
-    let disk_plugin = RocksdbDiskPlugin::new(uid, Arc::downgrade(&db));
+    let disk_plugin = DiskPlugin::new(uid, Arc::downgrade(&db));
     let cleaner: Cleaner = Cleaner::new(path);
 
     let collab = CollabBuilder::new(1, uid.to_string())
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use lib0::decoding::Read;
+use lib0::error::Error;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use yrs::block::{
+  ClientID, ItemContent, BLOCK_GC_REF_NUMBER, BLOCK_ITEM_DELETED_REF_NUMBER, BLOCK_SKIP_REF_NUMBER,
+  HAS_ORIGIN, HAS_PARENT_SUB, HAS_RIGHT_ORIGIN,
+};
+use yrs::updates::decoder::{Decode, Decoder, DecoderV1};
+use yrs::{
+  Assoc, DeleteSet, IndexedSequence, ReadTxn, Snapshot, StateVector, Text, TextRef, TransactionMut,
+  ID,
+};
+
+use crate::core::commit::TransactionMeta;
+use crate::core::origin::CollabOrigin;
+
+/// The author of an item of the document. The items are attributed to the client that inserted
+/// them. The user and the device of a client are known if:
+///
+/// * the client is the local [Collab](crate::core::collab::Collab).
+/// * the client sent its updates with the [CollabOrigin] of its user, for example, through the
+///   sync protocol. The client ids are bound to the origin of the first remote transaction that
+///   only contains the changes of one client.
+/// * the client was registered by
+///   [Collab::register_author](crate::core::collab::Collab::register_author), or its
+///   [TransactionMeta]s were imported by
+///   [Collab::import_txn_metas](crate::core::collab::Collab::import_txn_metas).
+///
+/// The time is only known if the [TransactionMeta] of the transaction that inserted the item is
+/// recorded, for example, the local transactions.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Authorship {
+  /// The client id of the [Doc](yrs::Doc) that inserted the item.
+  pub client_id: ClientID,
+  pub uid: Option<i64>,
+  pub device_id: Option<String>,
+  /// The wall-clock time in milliseconds when the item was inserted.
+  pub timestamp: Option<i64>,
+}
+
+/// A range of a text that was inserted by the same author.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TextBlame {
+  pub text: String,
+  pub authorship: Authorship,
+}
+
+#[derive(Default)]
+struct ClientAuthor {
+  uid: Option<i64>,
+  device_id: Option<String>,
+  /// The clocks of the recorded transactions of the client and their timestamps, ordered by the
+  /// start of the clocks.
+  txns: Vec<(Range<u32>, i64)>,
+}
+
+/// Maps the client ids of the document to their authors and the clocks of their transactions to
+/// the time of the transactions.
+#[derive(Default)]
+pub(crate) struct AuthorshipIndex {
+  clients: RwLock<HashMap<ClientID, ClientAuthor>>,
+}
+
+impl AuthorshipIndex {
+  /// Record the author and the time of a committed transaction.
+  pub(crate) fn insert_meta(&self, meta: &TransactionMeta) {
+    let client_id = match meta.client_id {
+      None => return,
+      Some(client_id) => client_id,
+    };
+
+    let mut clients = self.clients.write();
+    let client = clients.entry(client_id).or_default();
+    if meta.uid.is_some() {
+      client.uid = meta.uid;
+      client.device_id = meta.device_id.clone();
+    }
+    if let (Some(clock), Some(timestamp)) = (&meta.clock, meta.timestamp) {
+      let index = client
+        .txns
+        .partition_point(|(range, _)| range.start < clock.start);
+      match client.txns.get(index) {
+        Some((range, _)) if range.start == clock.start => {},
+        _ => client.txns.insert(index, (clock.clone(), timestamp)),
+      }
+    }
+  }
+
+  /// Bind the client to the user and the device of the origin. The previous binding of the client
+  /// is only replaced if `overwrite` is true.
+  pub(crate) fn bind(&self, client_id: ClientID, origin: &CollabOrigin, overwrite: bool) {
+    if let CollabOrigin::Client(origin) = origin {
+      let mut clients = self.clients.write();
+      let client = clients.entry(client_id).or_default();
+      if overwrite || client.uid.is_none() {
+        client.uid = Some(origin.uid);
+        client.device_id = Some(origin.device_id().to_string());
+      }
+    }
+  }
+
+  /// Bind the client that inserted the items of a remote transaction to the origin of the
+  /// transaction. It's skipped if the transaction contains the changes of more than one client,
+  /// for example, the initial sync, because the origin can't tell them apart.
+  pub(crate) fn bind_remote(&self, txn: &TransactionMut, origin: &CollabOrigin) {
+    let before_state = txn.before_state();
+    let mut clients = txn
+      .after_state()
+      .iter()
+      .filter(|(client_id, clock)| before_state.get(client_id) < **clock)
+      .map(|(client_id, _)| *client_id);
+    if let (Some(client_id), None) = (clients.next(), clients.next()) {
+      self.bind(client_id, origin, false);
+    }
+  }
+
+  pub(crate) fn authorship(&self, id: &ID) -> Authorship {
+    self.authorship_ranges(id, 1).remove(0).1
+  }
+
+  /// Split the clocks `id.clock..id.clock + len` of a client by the recorded transactions.
+  /// Returns the length of each part with its [Authorship].
+  fn authorship_ranges(&self, id: &ID, len: u32) -> Vec<(u32, Authorship)> {
+    let clients = self.clients.read();
+    let client = clients.get(&id.client);
+    let authorship = |timestamp| Authorship {
+      client_id: id.client,
+      uid: client.and_then(|client| client.uid),
+      device_id: client.and_then(|client| client.device_id.clone()),
+      timestamp,
+    };
+
+    let txns = client.map(|client| client.txns.as_slice()).unwrap_or(&[]);
+    let end = id.clock + len;
+    let mut parts: Vec<(u32, Authorship)> = vec![];
+    let mut clock = id.clock;
+    let mut index = txns.partition_point(|(range, _)| range.end <= clock);
+    while clock < end {
+      let (next, timestamp) = match txns.get(index) {
+        Some((range, timestamp)) if range.start <= clock => {
+          index += 1;
+          (range.end.min(end), Some(*timestamp))
+        },
+        Some((range, _)) => (range.start.min(end), None),
+        None => (end, None),
+      };
+      let authorship = authorship(timestamp);
+      match parts.last_mut() {
+        Some((part_len, last)) if *last == authorship => *part_len += next - clock,
+        _ => parts.push((next - clock, authorship)),
+      }
+      clock = next;
+    }
+    parts
+  }
+
+  /// Returns the authors of the visible ranges of the text. The adjacent ranges of the same
+  /// author are merged. Each item of the text is looked up from the start of the text, so it
+  /// takes quadratic time in the number of items.
+  pub(crate) fn blame_text(&self, txn: &mut TransactionMut, text: &TextRef) -> Vec<TextBlame> {
+    let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+    let items = match StateItems::decode_v1(&doc_state) {
+      Ok(items) => items,
+      Err(e) => {
+        tracing::error!("🔴decode doc state failed: {}", e);
+        return vec![];
+      },
+    };
+
+    let len = text.len(txn);
+    let mut blames: Vec<TextBlame> = vec![];
+    let mut index = 0;
+    while index < len {
+      // The sticky index points to the item at the index. A visible item is never split by
+      // other items, so the rest of the item follows it in the text.
+      let item = text
+        .sticky_index(txn, index, Assoc::After)
+        .and_then(|sticky_index| sticky_index.id().cloned())
+        .and_then(|id| Some((id, items.get(&id)?)));
+      let (id, item) = match item {
+        None => break,
+        Some(item) => item,
+      };
+      let offset = id.clock - item.id.clock;
+      let item_len = (item.len - offset).min(len - index);
+      index += item_len;
+
+      // The embedded values are not part of the text.
+      let content = match &item.text {
+        None => continue,
+        Some(content) => content,
+      };
+      // The clocks of the text are counted in UTF-16 code units.
+      let mut chars = content.chars().peekable();
+      let mut skipped = 0;
+      while let Some(c) = chars.next_if(|_| skipped < offset) {
+        skipped += c.len_utf16() as u32;
+      }
+      for (part_len, authorship) in self.authorship_ranges(&id, item_len) {
+        let mut text = String::new();
+        let mut text_len = 0;
+        while let Some(c) = chars.next_if(|_| text_len < part_len) {
+          text_len += c.len_utf16() as u32;
+          text.push(c);
+        }
+        match blames.last_mut() {
+          Some(last) if last.authorship == authorship => last.text.push_str(&text),
+          _ => blames.push(TextBlame { text, authorship }),
+        }
+      }
+    }
+    blames
+  }
+}
+
+/// The parent of an item, either a root type of the document or the item that holds a nested type.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum ItemParent {
+  Root(String),
+  Item(ID),
+}
+
+struct StateItem {
+  id: ID,
+  len: u32,
+  origin: Option<ID>,
+  right_origin: Option<ID>,
+  /// The parent and the map key of the item. [None] if they are copied from the origins.
+  parent: Option<(ItemParent, Option<String>)>,
+  /// The content of the text items.
+  text: Option<String>,
+  is_deleted: bool,
+}
+
+/// The items of an encoded document state. yrs doesn't expose the ids of the items that hold the
+/// map values, so they are read from the doc state instead.
+pub(crate) struct StateItems {
+  clients: HashMap<ClientID, Vec<StateItem>>,
+  delete_set: DeleteSet,
+}
+
+impl StateItems {
+  /// Decode the doc state that is encoded by [ReadTxn::encode_state_as_update_v1](yrs::ReadTxn).
+  pub(crate) fn decode_v1(doc_state: &[u8]) -> Result<Self, Error> {
+    let mut decoder = DecoderV1::from(doc_state);
+    let clients_len: u32 = decoder.read_var()?;
+    let mut clients = HashMap::with_capacity(clients_len as usize);
+    for _ in 0..clients_len {
+      let blocks_len: u32 = decoder.read_var()?;
+      let client = decoder.read_client()?;
+      let mut clock: u32 = decoder.read_var()?;
+      let items: &mut Vec<StateItem> = clients.entry(client).or_default();
+      for _ in 0..blocks_len {
+        let id = ID::new(client, clock);
+        let info = decoder.read_info()?;
+        match info {
+          BLOCK_SKIP_REF_NUMBER => clock += decoder.read_var::<u32>()?,
+          BLOCK_GC_REF_NUMBER => clock += decoder.read_len()?,
+          info => {
+            let origin = if info & HAS_ORIGIN != 0 {
+              Some(decoder.read_left_id()?)
+            } else {
+              None
+            };
+            let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
+              Some(decoder.read_right_id()?)
+            } else {
+              None
+            };
+            let parent = if origin.is_none() && right_origin.is_none() {
+              let parent = if decoder.read_parent_info()? {
+                ItemParent::Root(decoder.read_string()?.to_string())
+              } else {
+                ItemParent::Item(decoder.read_left_id()?)
+              };
+              let key = if info & HAS_PARENT_SUB != 0 {
+                Some(decoder.read_string()?.to_string())
+              } else {
+                None
+              };
+              Some((parent, key))
+            } else {
+              None
+            };
+            let content = ItemContent::decode(&mut decoder, info)?;
+            let len = content.len(yrs::OffsetKind::Utf16);
+            items.push(StateItem {
+              id,
+              len,
+              origin,
+              right_origin,
+              parent,
+              text: match content {
+                ItemContent::String(content) => Some(content.to_string()),
+                _ => None,
+              },
+              is_deleted: info & 0b1111 == BLOCK_ITEM_DELETED_REF_NUMBER,
+            });
+            clock += len;
+          },
+        }
+      }
+    }
+    let delete_set = DeleteSet::decode(&mut decoder)?;
+    Ok(Self {
+      clients,
+      delete_set,
+    })
+  }
+
+  fn get(&self, id: &ID) -> Option<&StateItem> {
+    let items = self.clients.get(&id.client)?;
+    let index = items.partition_point(|item| item.id.clock + item.len <= id.clock);
+    items.get(index).filter(|item| item.id.clock <= id.clock)
+  }
+
+  /// Returns the parent and the map key of the item. The items that have origins share the
+  /// parent of their origins, so the resolved parents are cached to walk each chain once.
+  fn parent_of<'a>(
+    &'a self,
+    item: &'a StateItem,
+    resolved: &mut HashMap<ID, Option<&'a (ItemParent, Option<String>)>>,
+  ) -> Option<&'a (ItemParent, Option<String>)> {
+    let mut chain = vec![];
+    let mut next = Some(item);
+    // The origins are always inserted before the item, so the chain ends.
+    let parent = loop {
+      let item = match next {
+        None => break None,
+        Some(item) => item,
+      };
+      if let Some(parent) = resolved.get(&item.id) {
+        break *parent;
+      }
+      if let Some(parent) = &item.parent {
+        break Some(parent);
+      }
+      chain.push(item.id);
+      next = item
+        .origin
+        .as_ref()
+        .or(item.right_origin.as_ref())
+        .and_then(|origin| self.get(origin));
+    };
+    for id in chain {
+      resolved.insert(id, parent);
+    }
+    parent
+  }
+
+  /// Returns the ids of the items that hold the current values of the maps, grouped by the
+  /// parent of the maps.
+  pub(crate) fn map_entries(&self) -> MapEntries {
+    let mut entries: MapEntries = HashMap::new();
+    let mut resolved = HashMap::new();
+    for item in self.clients.values().flatten() {
+      if item.is_deleted || self.delete_set.is_deleted(&item.id) {
+        continue;
+      }
+      if let Some((parent, Some(key))) = self.parent_of(item, &mut resolved) {
+        entries
+          .entry(parent.clone())
+          .or_default()
+          .insert(key.clone(), item.id);
+      }
+    }
+    entries
+  }
+}
+
+/// The ids of the items that hold the current values of the maps, grouped by the parent of the
+/// maps. Check out [StateItems::map_entries].
+pub(crate) type MapEntries = HashMap<ItemParent, HashMap<String, ID>>;
+
+/// Caches the [MapEntries] of the document, so the doc state is only encoded and decoded again
+/// after the document changed. The entries are cached with the [Snapshot] of the document, whose
+/// delete set also changes when a value is removed from a map.
+#[derive(Default)]
+pub(crate) struct MapEntriesCache {
+  cached: Mutex<Option<(Snapshot, Arc<MapEntries>)>>,
+}
+
+impl MapEntriesCache {
+  pub(crate) fn get<T: ReadTxn>(&self, txn: &T) -> Result<Arc<MapEntries>, Error> {
+    let snapshot = txn.snapshot();
+    let mut cached = self.cached.lock();
+    if let Some((cached_snapshot, entries)) = cached.as_ref() {
+      if cached_snapshot == &snapshot {
+        return Ok(entries.clone());
+      }
+    }
+
+    let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+    let entries = Arc::new(StateItems::decode_v1(&doc_state)?.map_entries());
+    *cached = Some((snapshot, entries.clone()));
+    Ok(entries)
+  }
+}
@@ -1,4 +1,7 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::future::Future;
 use std::ops::{Deref, DerefMut};
 use std::panic;
 use std::panic::AssertUnwindSafe;
@@ -8,24 +11,37 @@ use std::vec::IntoIter;
 use bytes::Bytes;
 use parking_lot::{Mutex, RwLock};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use tokio_stream::wrappers::WatchStream;
-use yrs::block::Prelim;
+use yrs::block::{ClientID, Prelim};
 use yrs::types::map::MapEvent;
-use yrs::types::{ToJson, Value};
+use yrs::types::{BranchPtr, DeepEventsSubscription, ToJson, Value};
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
 use yrs::{
-  ArrayPrelim, ArrayRef, Doc, Map, MapPrelim, MapRef, Observable, OffsetKind, Options, ReadTxn,
-  StateVector, Subscription, Transact, Transaction, TransactionMut, UndoManager, Update,
-  UpdateSubscription,
+  ArrayPrelim, ArrayRef, DeepObservable, Doc, Map, MapPrelim, MapRef, Observable, OffsetKind,
+  Options, ReadTxn, StateVector, Subscription, Transact, Transaction, TransactionMut, UndoManager,
+  Update, UpdateSubscription, ID,
 };
 
-use crate::core::collab_plugin::{CollabPlugin, CollabPluginType};
+use crate::core::blame::{Authorship, AuthorshipIndex, ItemParent, MapEntriesCache};
+use crate::core::collab_plugin::{
+  AsyncCollabPlugin, CollabPlugin, CollabPluginError, CollabPluginType,
+};
 use crate::core::collab_state::{InitState, SnapshotState, State, SyncState};
+use crate::core::commit::TransactionMeta;
+use crate::core::encoding::EncoderVersion;
 use crate::core::map_wrapper::{CustomMapRef, MapRefWrapper};
 use crate::core::origin::{CollabClient, CollabOrigin};
+use crate::core::path_observer::{path_changes, PathChangeEvent, PathSubscription};
+use crate::core::permission::{CollabEditPermission, EditGuard, GuardedTransactionMut, Rejection};
+use crate::core::plugin_executor::{PluginEvent, PluginExecutor};
 use crate::core::transaction::TransactionRetry;
+use crate::core::undo::{
+  branch_of, CollabUndoManager, UndoItemMeta, UndoOptions, UndoStackEvent, DEFAULT_UNDO_STACK,
+};
+use crate::core::version::{encode_restore_update, encode_state_from_snapshot, CollabVersion};
 use crate::error::CollabError;
 use crate::preclude::{ArrayRefWrapper, JsonValue, MapRefExtension};
 use crate::sync_protocol::awareness::Awareness;
@@ -60,14 +76,29 @@ pub struct Collab {
 
   /// A list of plugins that are used to extend the functionality of the [Collab].
   plugins: Plugins,
+  /// Runs the hooks of the [AsyncCollabPlugin]s.
+  plugin_executor: PluginExecutor,
 
   state: Arc<State>,
 
-  /// The [UndoManager] is used to undo and redo changes. By default, the [UndoManager]
-  /// is disabled. To enable it, call [Collab::enable_undo_manager].
-  undo_manager: Mutex<Option<UndoManager>>,
+  /// The undo stacks that are used to undo and redo changes, keyed by their names. By default,
+  /// there is no undo stack. To add one, call [Collab::enable_undo_redo] or
+  /// [Collab::add_undo_stack].
+  undo_managers: Mutex<HashMap<String, CollabUndoManager>>,
+  undo_event_tx: broadcast::Sender<UndoStackEvent>,
   update_subscription: RwLock<Option<UpdateSubscription>>,
   after_txn_subscription: RwLock<Option<AfterTransactionSubscription>>,
+
+  /// The [EditGuard] checks the local edits against the [CollabEditPermission]. By default, all
+  /// the local edits are allowed. Call [Collab::set_edit_permission] to restrict them.
+  edit_guard: Arc<EditGuard>,
+  #[allow(dead_code)]
+  edit_guard_subscriptions: (DeepEventsSubscription, AfterTransactionSubscription),
+
+  /// Maps the clients of the [Doc] to their authors. Check out [Collab::blame].
+  authorship: Arc<AuthorshipIndex>,
+  /// The items that hold the map values, which are read by [Collab::blame].
+  map_entries: MapEntriesCache,
 }
 
 impl Collab {
@@ -86,12 +117,30 @@ impl Collab {
     object_id: &str,
     collab_raw_data: CollabRawData,
     plugins: Vec<Arc<dyn CollabPlugin>>,
+  ) -> Result<Self, CollabError> {
+    Self::new_with_encoded_data(
+      origin,
+      object_id,
+      collab_raw_data,
+      EncoderVersion::V1,
+      plugins,
+    )
+  }
+
+  /// Same as [Collab::new_with_raw_data], but the updates are decoded with the given
+  /// [EncoderVersion].
+  pub fn new_with_encoded_data(
+    origin: CollabOrigin,
+    object_id: &str,
+    collab_raw_data: CollabRawData,
+    encoder_version: EncoderVersion,
+    plugins: Vec<Arc<dyn CollabPlugin>>,
   ) -> Result<Self, CollabError> {
     let collab = Self::new_with_origin(origin, object_id, plugins);
     if !collab_raw_data.is_empty() {
       let mut txn = collab.origin_transact_mut();
       for update in collab_raw_data {
-        let decoded_update = Update::decode_v1(&update)?;
+        let decoded_update = encoder_version.decode_update(&update)?;
         txn.try_apply_update(decoded_update)?;
       }
     }
@@ -110,34 +159,100 @@ impl Collab {
       ..Options::default()
     });
     let data = doc.get_or_insert_map(DATA_SECTION);
-    let undo_manager = Mutex::new(None);
+    let undo_managers = Mutex::new(HashMap::new());
+    let (undo_event_tx, _) = broadcast::channel(100);
     let plugins = Plugins::new(plugins);
+    let plugin_executor = PluginExecutor::new(&object_id);
     let state = Arc::new(State::new(&object_id));
     let awareness = Awareness::new(doc.clone());
+    let edit_guard = Arc::new(EditGuard::default());
+    let edit_guard_subscriptions =
+      observe_edit_guard(&doc, &data, &object_id, &plugins, &edit_guard, &origin);
+    let authorship = Arc::new(AuthorshipIndex::default());
 
     Self {
       origin,
       object_id,
       doc,
-      undo_manager,
+      undo_managers,
+      undo_event_tx,
       awareness,
       data,
       plugins,
+      plugin_executor,
       state,
       update_subscription: Default::default(),
       after_txn_subscription: Default::default(),
+      edit_guard,
+      edit_guard_subscriptions,
+      authorship,
+      map_entries: Default::default(),
     }
   }
 
+  /// Returns the [CollabEditPermission] that restricts the local edits.
+  pub fn edit_permission(&self) -> CollabEditPermission {
+    self.edit_guard.permission()
+  }
+
+  /// Restrict the local edits of the [Collab]. The refused edits are reverted. The plugins
+  /// receive the refused edits merged with their revert, which changes nothing, so the refused
+  /// edits are not persisted or sent to the remote.
+  pub fn set_edit_permission(&self, permission: CollabEditPermission) {
+    self.edit_guard.set_permission(permission);
+  }
+
   /// Returns the doc state and the state vector.
   pub fn encode_as_update_v1(&self) -> (Vec<u8>, Vec<u8>) {
+    self.encode_as_update(EncoderVersion::V1)
+  }
+
+  /// Returns the doc state encoded with the v2 encoding and the state vector.
+  pub fn encode_as_update_v2(&self) -> (Vec<u8>, Vec<u8>) {
+    self.encode_as_update(EncoderVersion::V2)
+  }
+
+  /// Returns the doc state encoded with the given [EncoderVersion] and the state vector. The
+  /// state vector is always encoded with the v1 encoding.
+  pub fn encode_as_update(&self, encoder_version: EncoderVersion) -> (Vec<u8>, Vec<u8>) {
     let txn = self.transact();
     (
-      txn.encode_state_as_update_v1(&StateVector::default()),
+      encoder_version.encode_state_as_update(&txn, &StateVector::default()),
       txn.state_vector().encode_v1(),
     )
   }
 
+  /// Record the current state of the document as a [CollabVersion]. The version is cheap to
+  /// store because it only contains the state vector and the delete set of the document.
+  pub fn create_version(&self, author: i64, label: Option<String>) -> CollabVersion {
+    let txn = self.transact();
+    CollabVersion::new(txn.snapshot(), author, label)
+  }
+
+  /// Returns a read-only [Collab] with the content of the document as it was at the time of the
+  /// given version. The returned [Collab] has no plugins, so it's never persisted or synced.
+  pub fn materialize_version(&self, version: &CollabVersion) -> Result<Collab, CollabError> {
+    let past_state =
+      encode_state_from_snapshot(&self.transact(), self.doc.options(), &version.snapshot)?;
+    let collab = Collab::new_with_origin(self.origin.clone(), &self.object_id, vec![]);
+    collab
+      .get_doc()
+      .transact_mut()
+      .try_apply_update(Update::decode_v1(&past_state)?)?;
+    collab.set_edit_permission(CollabEditPermission::ReadOnly);
+    Ok(collab)
+  }
+
+  /// Restore the content of the document to the given version. The restore is a new edit on
+  /// top of the current state, so the history after the version is kept and the restore is
+  /// persisted and synced like any other local edit.
+  pub fn restore_version(&self, version: &CollabVersion) -> Result<(), CollabError> {
+    self.try_with_origin_transact_mut(|txn| {
+      let update = encode_restore_update(txn, self.doc.options(), &version.snapshot)?;
+      txn.try_apply_update(Update::decode_v1(&update)?)
+    })?
+  }
+
   pub fn subscribe_sync_state(&self) -> WatchStream<SyncState> {
     WatchStream::new(self.state.sync_state_notifier.subscribe())
   }
@@ -182,6 +297,28 @@ impl Collab {
     }
   }
 
+  /// Add an [AsyncCollabPlugin] to the [Collab]. The hooks of the async plugins are called in
+  /// the order they are added, after the ones of the [CollabPlugin]s. It must be called in the
+  /// context of a tokio runtime.
+  ///
+  /// The plugins must be added before calling [Collab::initialize], otherwise their `init` hook
+  /// would never be called. It returns [CollabError::AsyncPluginAfterInit] in that case.
+  pub fn add_async_plugin(
+    &mut self,
+    plugin: Arc<dyn AsyncCollabPlugin>,
+  ) -> Result<(), CollabError> {
+    if !self.state.is_uninitialized() {
+      return Err(CollabError::AsyncPluginAfterInit);
+    }
+    self.plugin_executor.add_plugin(plugin);
+    Ok(())
+  }
+
+  /// Subscribe the errors that are returned by the hooks of the [AsyncCollabPlugin]s.
+  pub fn subscribe_plugin_error(&self) -> broadcast::Receiver<CollabPluginError> {
+    self.plugin_executor.subscribe_error()
+  }
+
   /// Upon calling this method, the [Collab]'s document will be initialized with the plugins. The callbacks from the plugins
   /// will be triggered in the order they were added. The input parameter, [init_sync], indicates whether the
   /// [Collab] is initialized with local data or remote updates. If true, it suggests that the data doesn't need
@@ -199,14 +336,19 @@ impl Collab {
       let plugins = self.plugins.read().clone();
       for plugin in plugins {
         plugin.init(&self.object_id, &self.origin, &self.doc);
+        self.import_txn_metas(&plugin.take_txn_metas(&self.object_id));
       }
     }
+    self.plugin_executor.send(PluginEvent::Init);
 
     let (update_subscription, after_txn_subscription) = observe_doc(
       &self.doc,
       self.object_id.clone(),
       self.plugins.clone(),
       self.origin.clone(),
+      self.edit_guard.clone(),
+      self.authorship.clone(),
+      self.plugin_executor.clone(),
     );
 
     *self.update_subscription.write() = Some(update_subscription);
@@ -233,14 +375,19 @@ impl Collab {
       let plugins = self.plugins.read().clone();
       for plugin in plugins {
         plugin.init(&self.object_id, &self.origin, &self.doc).await;
+        self.import_txn_metas(&plugin.take_txn_metas(&self.object_id));
       }
     }
+    self.plugin_executor.send(PluginEvent::Init);
 
     let (update_subscription, after_txn_subscription) = observe_doc(
       &self.doc,
       self.object_id.clone(),
       self.plugins.clone(),
       self.origin.clone(),
+      self.edit_guard.clone(),
+      self.authorship.clone(),
+      self.plugin_executor.clone(),
     );
 
     *self.update_subscription.write() = Some(update_subscription);
@@ -270,6 +417,7 @@ impl Collab {
       .read()
       .iter()
       .for_each(|plugin| plugin.reset(&self.object_id));
+    self.plugin_executor.send(PluginEvent::Reset);
   }
 
   /// Make a full update with the current state of the [Collab].
@@ -281,6 +429,31 @@ impl Collab {
       .read()
       .iter()
       .for_each(|plugin| plugin.flush(&self.object_id, &update));
+    self.plugin_executor.send(PluginEvent::Flush(update));
+  }
+
+  /// Close the [Collab]. The plugins stop receiving the updates of the document after their
+  /// `will_close` hooks are called, then their `did_close` hooks are called.
+  ///
+  /// The returned future completes when the hooks of all the [AsyncCollabPlugin]s are done, so
+  /// the pending updates are written before the app quits, for example.
+  pub fn close(&self) -> impl Future<Output = ()> + Send + 'static {
+    let plugins = self.plugins.read().clone();
+    plugins
+      .iter()
+      .for_each(|plugin| plugin.will_close(&self.object_id));
+    *self.update_subscription.write() = None;
+    *self.after_txn_subscription.write() = None;
+    plugins
+      .iter()
+      .for_each(|plugin| plugin.did_close(&self.object_id));
+
+    let closed_rx = self.plugin_executor.close();
+    async move {
+      if let Some(closed_rx) = closed_rx {
+        let _ = closed_rx.await;
+      }
+    }
   }
 
   pub fn observer_data<F>(&mut self, f: F) -> MapSubscription
@@ -290,6 +463,29 @@ impl Collab {
     self.data.observe(f)
   }
 
+  /// Observe the changes under the given path, which is relative to the data section. The
+  /// changes that replace or remove one of the parents of the path are observed too, and the
+  /// empty path observes the whole data section.
+  ///
+  /// The callback is called while the transaction is committed, so it must not lock the
+  /// [Collab]. The changes are dropped when the returned subscription is dropped.
+  pub fn observe_path<P, F>(&self, path: P, f: F) -> PathSubscription
+  where
+    P: Into<Path>,
+    F: Fn(&PathChangeEvent) + 'static,
+  {
+    let path = path.into();
+    self.data.clone().observe_deep(move |txn, events| {
+      let changes = path_changes(txn, events, &path);
+      if !changes.is_empty() {
+        f(&PathChangeEvent {
+          origin: CollabOrigin::from(txn),
+          changes,
+        });
+      }
+    })
+  }
+
   pub fn get(&self, key: &str) -> Option<Value> {
     let txn = self.doc.transact();
     self.data.get(&txn, key)
@@ -303,6 +499,13 @@ impl Collab {
     self.with_origin_transact_mut(|txn| self.insert_with_txn(txn, key, value))
   }
 
+  /// Same as [Collab::insert], but returns [CollabError::PermissionDenied] if the key can't be
+  /// edited with the current [CollabEditPermission].
+  pub fn try_insert<V: Prelim>(&self, key: &str, value: V) -> Result<V::Return, CollabError> {
+    self.check_edit_path(&[key.to_string()])?;
+    self.try_with_origin_transact_mut(|txn| self.insert_with_txn(txn, key, value))
+  }
+
   pub fn insert_with_txn<V: Prelim>(
     &self,
     txn: &mut TransactionMut,
@@ -385,6 +588,69 @@ impl Collab {
     map_ref.map(|map_ref| self.map_wrapper_with(map_ref))
   }
 
+  /// Returns the [Authorship] of the value at the given path of the data section, for example,
+  /// to show who last edited a cell of a database. A value is attributed to the transaction that
+  /// inserted it into its map, so the edits inside a nested map or text don't change the
+  /// [Authorship] of the key that holds it. Use [Collab::blame_map] and [TextRefWrapper::blame]
+  /// for the nested values.
+  ///
+  /// yrs doesn't expose the items that hold the map values, so the whole doc state is decoded to
+  /// find them. The decoded items are cached until the document changes.
+  ///
+  /// [TextRefWrapper::blame]: crate::core::text_wrapper::TextRefWrapper::blame
+  pub fn blame(&self, path: impl Into<Path>) -> Option<Authorship> {
+    let path = path.into();
+    let (key, parent) = path.split_last()?;
+    let id = self.get_map_entries(parent).remove(key)?;
+    Some(self.authorship.authorship(&id))
+  }
+
+  /// Returns the [Authorship] of each value of the map at the given path. The empty path is the
+  /// data section. Check out [Collab::blame].
+  pub fn blame_map(&self, path: impl Into<Path>) -> HashMap<String, Authorship> {
+    self
+      .get_map_entries(&path.into())
+      .into_iter()
+      .map(|(key, id)| (key, self.authorship.authorship(&id)))
+      .collect()
+  }
+
+  /// Bind the client id of a remote [Doc] to its user and device, for example, from the
+  /// awareness states of the peers. It replaces the binding that was guessed from the origin of
+  /// the remote updates.
+  pub fn register_author(&self, client_id: ClientID, origin: &CollabOrigin) {
+    self.authorship.bind(client_id, origin, true);
+  }
+
+  /// Import the [TransactionMeta]s of the transactions that were committed before this [Collab]
+  /// was opened, for example, the ones that are persisted in the local storage. Their authors and
+  /// times are reported by [Collab::blame].
+  pub fn import_txn_metas(&self, metas: &[TransactionMeta]) {
+    for meta in metas {
+      self.authorship.insert_meta(meta);
+    }
+  }
+
+  /// Returns the ids of the items that hold the values of the map at the given path.
+  fn get_map_entries(&self, path: &[String]) -> HashMap<String, ID> {
+    let entries = match self.map_entries.get(&self.transact()) {
+      Ok(entries) => entries,
+      Err(e) => {
+        tracing::error!("🔴decode doc state of {} failed: {}", self.object_id, e);
+        return HashMap::new();
+      },
+    };
+
+    let mut parent = ItemParent::Root(DATA_SECTION.to_string());
+    for key in path {
+      match entries.get(&parent).and_then(|entries| entries.get(key)) {
+        None => return HashMap::new(),
+        Some(id) => parent = ItemParent::Item(*id),
+      }
+    }
+    entries.get(&parent).cloned().unwrap_or_default()
+  }
+
   pub fn get_array_with_txn<P: Into<Path>, T: ReadTxn>(
     &self,
     txn: &T,
@@ -461,6 +727,28 @@ impl Collab {
     }
   }
 
+  /// Same as [Collab::remove_with_path], but returns [CollabError::PermissionDenied] if the path
+  /// can't be edited with the current [CollabEditPermission].
+  pub fn try_remove_with_path<P: Into<Path>>(
+    &mut self,
+    path: P,
+  ) -> Result<Option<Value>, CollabError> {
+    let path = path.into();
+    self.check_edit_path(&path)?;
+    Ok(self.remove_with_path(path))
+  }
+
+  fn check_edit_path(&self, path: &[String]) -> Result<(), CollabError> {
+    if self.edit_guard.permission().can_edit_path(path) {
+      Ok(())
+    } else {
+      Err(CollabError::PermissionDenied(format!(
+        "the path {:?} is not editable",
+        path
+      )))
+    }
+  }
+
   pub fn to_json(&self) -> lib0::any::Any {
     let txn = self.transact();
     self.data.to_json(&txn)
@@ -475,57 +763,164 @@ impl Collab {
     serde_json::to_value(&self.data.to_json(&txn)).unwrap()
   }
 
+  /// Add the [DEFAULT_UNDO_STACK] that tracks the local edits of the whole data section.
   pub fn enable_undo_redo(&mut self) {
-    if self.undo_manager.lock().is_some() {
-      tracing::warn!("Undo manager already enabled");
-      return;
-    }
     // a frequent case includes establishing a new transaction for every user key stroke. Meanwhile
     // we may decide to use different granularity of undo/redo actions. These are grouped together
-    // on time-based ranges (configurable in undo::Options, which is 500ms by default).
-    let mut undo_manager =
-      UndoManager::with_options(&self.doc, &self.data, yrs::undo::Options::default());
-    undo_manager.include_origin(self.origin.clone());
-    *self.undo_manager.lock() = Some(undo_manager);
+    // on time-based ranges (configurable in [UndoOptions], which is 500ms by default).
+    if let Err(e) = self.add_undo_stack(DEFAULT_UNDO_STACK, UndoOptions::default()) {
+      tracing::error!("{} enable undo redo failed: {}", self.object_id, e);
+    }
   }
 
-  /// Undo the previous change.
-  /// Returns true if the undo was successful, false if there was nothing to undo. If the
-  /// UndoManager is not enabled, returns false.
+  /// Add an undo stack with the given name. Each undo stack only reverts the edits within its
+  /// own scope, for example, one undo stack per block of a document or per view of a database.
+  /// Returns [CollabError::UndoScopeNotFound] if a path of the scope doesn't exist.
+  pub fn add_undo_stack(&self, name: &str, options: UndoOptions) -> Result<(), CollabError> {
+    if self.undo_managers.lock().contains_key(name) {
+      tracing::warn!("Undo stack {} already enabled", name);
+      return Ok(());
+    }
+
+    let scope = {
+      let txn = self.transact();
+      let mut scope = vec![];
+      for path in options.scope() {
+        let branch = self
+          .get_ref_from_path_with_txn(&txn, path.clone())
+          .as_ref()
+          .and_then(branch_of)
+          .ok_or_else(|| CollabError::UndoScopeNotFound(path.join("/")))?;
+        scope.push(branch);
+      }
+      if scope.is_empty() {
+        scope.push(BranchPtr::from(self.data.as_ref()));
+      }
+      scope
+    };
+    let undo_manager = CollabUndoManager::new(
+      name,
+      &self.doc,
+      scope,
+      &options,
+      &self.origin,
+      self.edit_guard.clone(),
+      self.undo_event_tx.clone(),
+    );
+    self
+      .undo_managers
+      .lock()
+      .insert(name.to_string(), undo_manager);
+    Ok(())
+  }
+
+  /// Remove the undo stack with the given name. Its items are dropped.
+  pub fn remove_undo_stack(&self, name: &str) {
+    self.undo_managers.lock().remove(name);
+  }
+
+  /// Subscribe the changes of all the undo stacks, for example, to update the state of the undo
+  /// button or to restore the cursor after undo.
+  pub fn subscribe_undo_stack(&self) -> broadcast::Receiver<UndoStackEvent> {
+    self.undo_event_tx.subscribe()
+  }
+
+  /// Returns true if there is something to undo in the [DEFAULT_UNDO_STACK]. If the undo stack is
+  /// not enabled, returns false.
   pub fn can_undo(&self) -> bool {
-    match &*self.undo_manager.lock() {
+    self.can_undo_with_stack(DEFAULT_UNDO_STACK)
+  }
+
+  /// Returns true if there is something to redo in the [DEFAULT_UNDO_STACK]. If the undo stack is
+  /// not enabled, returns false.
+  pub fn can_redo(&self) -> bool {
+    self.can_redo_with_stack(DEFAULT_UNDO_STACK)
+  }
+
+  /// Undo the previous change of the [DEFAULT_UNDO_STACK].
+  /// Returns true if the undo was successful, false if there was nothing to undo.
+  pub fn undo(&mut self) -> Result<bool, CollabError> {
+    self.undo_with_stack(DEFAULT_UNDO_STACK)
+  }
+
+  /// Redo the previous change of the [DEFAULT_UNDO_STACK].
+  /// Returns true if the redo was successful, false if there was nothing to redo.
+  pub fn redo(&mut self) -> Result<bool, CollabError> {
+    self.redo_with_stack(DEFAULT_UNDO_STACK)
+  }
+
+  pub fn can_undo_with_stack(&self, name: &str) -> bool {
+    match self.undo_managers.lock().get(name) {
       None => {
-        tracing::warn!("Undo manager not enabled, should enable_undo_redo first");
+        tracing::warn!(
+          "Undo stack {} not enabled, should add_undo_stack first",
+          name
+        );
         false
       },
       Some(undo_mgr) => undo_mgr.can_undo(),
     }
   }
 
-  /// Redo the previous change.
-  /// Returns true if the redo was successful, false if there was nothing to redo. If the
-  /// UndoManager is not enabled, returns false.
-  pub fn can_redo(&self) -> bool {
-    match &*self.undo_manager.lock() {
+  pub fn can_redo_with_stack(&self, name: &str) -> bool {
+    match self.undo_managers.lock().get(name) {
       None => {
-        tracing::warn!("Undo manager not enabled, should enable_undo_redo first");
+        tracing::warn!(
+          "Undo stack {} not enabled, should add_undo_stack first",
+          name
+        );
         false
       },
       Some(undo_mgr) => undo_mgr.can_redo(),
     }
   }
 
-  pub fn undo(&mut self) -> Result<bool, CollabError> {
-    match &mut *self.undo_manager.lock() {
+  pub fn undo_with_stack(&self, name: &str) -> Result<bool, CollabError> {
+    match self.undo_managers.lock().get_mut(name) {
       None => Err(CollabError::UndoManagerNotEnabled),
-      Some(mgr) => mgr.undo().map_err(|e| CollabError::Internal(Box::new(e))),
+      Some(undo_mgr) => undo_mgr.undo(),
     }
   }
 
-  pub fn redo(&mut self) -> Result<bool, CollabError> {
-    match &mut *self.undo_manager.lock() {
+  pub fn redo_with_stack(&self, name: &str) -> Result<bool, CollabError> {
+    match self.undo_managers.lock().get_mut(name) {
+      None => Err(CollabError::UndoManagerNotEnabled),
+      Some(undo_mgr) => undo_mgr.redo(),
+    }
+  }
+
+  /// Drop all the items of the undo stack and the redo stack.
+  pub fn clear_undo_stack(&self, name: &str) -> Result<(), CollabError> {
+    match self.undo_managers.lock().get_mut(name) {
       None => Err(CollabError::UndoManagerNotEnabled),
-      Some(mgr) => mgr.redo().map_err(|e| CollabError::Internal(Box::new(e))),
+      Some(undo_mgr) => undo_mgr.clear(),
+    }
+  }
+
+  /// Stop merging the next edits into the last item of the undo stack, even if they are made
+  /// within the capture timeout.
+  pub fn reset_undo_capture(&self, name: &str) {
+    if let Some(undo_mgr) = self.undo_managers.lock().get_mut(name) {
+      undo_mgr.reset();
+    }
+  }
+
+  /// Returns the [UndoItemMeta] of the item that will be undone next.
+  pub fn undo_meta(&self, name: &str) -> Option<UndoItemMeta> {
+    self.undo_managers.lock().get(name)?.undo_meta()
+  }
+
+  /// Returns the [UndoItemMeta] of the item that will be redone next.
+  pub fn redo_meta(&self, name: &str) -> Option<UndoItemMeta> {
+    self.undo_managers.lock().get(name)?.redo_meta()
+  }
+
+  /// Update the [UndoItemMeta] of the last item of the undo stack, for example, to record the
+  /// cursor position before the edits. Returns false if the undo stack is empty or not enabled.
+  pub fn update_undo_meta<F: FnOnce(&mut UndoItemMeta)>(&self, name: &str, f: F) -> bool {
+    match self.undo_managers.lock().get(name) {
+      None => false,
+      Some(undo_mgr) => undo_mgr.update_undo_meta(f),
     }
   }
 
@@ -544,14 +939,30 @@ impl Collab {
     TransactionRetry::new(&self.doc).try_get_write_txn()
   }
 
-  pub fn try_origin_transaction_mut(&self) -> Result<TransactionMut, CollabError> {
-    TransactionRetry::new(&self.doc).try_get_write_txn_with(self.origin.clone())
+  /// Returns a transaction that carries the origin of the current user. Returns
+  /// [CollabError::PermissionDenied] if the [Collab] is read-only.
+  ///
+  /// The edits made with the returned transaction are reverted when it's dropped if they are
+  /// refused by the [CollabEditPermission]. Use [Collab::try_with_origin_transact_mut] to get the
+  /// error.
+  pub fn try_origin_transaction_mut(&self) -> Result<GuardedTransactionMut, CollabError> {
+    if !self.edit_guard.permission().can_edit() {
+      return Err(CollabError::PermissionDenied(
+        "the collab is read-only".to_string(),
+      ));
+    }
+    self.context().guarded_txn(&self.doc, |doc| {
+      TransactionRetry::new(doc).try_get_write_txn_with(self.origin.clone())
+    })
   }
 
   /// Returns a transaction that can mutate the document. This transaction will carry the
   /// origin of the current user.
-  pub fn origin_transact_mut(&self) -> TransactionMut {
-    TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone())
+  ///
+  /// The edits that are refused by the [CollabEditPermission] are reverted when the transaction
+  /// is dropped.
+  pub fn origin_transact_mut(&self) -> GuardedTransactionMut {
+    self.context().guarded_write_txn(&self.doc)
   }
 
   /// Returns a transaction that can mutate the document. This transaction will carry the
@@ -559,27 +970,74 @@ impl Collab {
   ///
   /// If applying the remote update, please use the `transact_mut` of `doc`. Ot
   /// update will send to remote that the remote already has.
+  ///
+  /// The edits that are refused by the [CollabEditPermission] are reverted. Use
+  /// [Collab::try_with_origin_transact_mut] to get the error.
   pub fn with_origin_transact_mut<F, T>(&self, f: F) -> T
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
-    let mut txn = TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone());
+    self.guarded_transact_mut(f).0
+  }
+
+  /// Same as [Collab::with_origin_transact_mut], but the transaction is labeled with the given
+  /// action, for example, "rename view" or "paste". The action is passed to the plugins in the
+  /// [TransactionMeta] of the update.
+  pub fn with_action_transact_mut<F, T>(&self, action: &str, f: F) -> T
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    self.edit_guard.set_pending_action(action.to_string());
+    self.guarded_transact_mut(f).0
+  }
+
+  /// Same as [Collab::with_origin_transact_mut], but returns [CollabError::PermissionDenied] if
+  /// the edits are refused by the [CollabEditPermission], or [CollabError::TransactionRejected]
+  /// if they are rejected by a [CollabPlugin::before_commit]. The refused edits are reverted.
+  pub fn try_with_origin_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    if !self.edit_guard.permission().can_edit() {
+      return Err(CollabError::PermissionDenied(
+        "the collab is read-only".to_string(),
+      ));
+    }
+
+    match self.guarded_transact_mut(f) {
+      (_, Some(rejection)) => Err(rejection.into()),
+      (ret, None) => Ok(ret),
+    }
+  }
+
+  /// Run the transaction with the origin of the current user. If the edits are refused by the
+  /// [CollabEditPermission] or rejected by a plugin, they are reverted and the reason is returned.
+  fn guarded_transact_mut<F, T>(&self, f: F) -> (T, Option<Rejection>)
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    let mut txn = self.origin_transact_mut();
     let ret = f(&mut txn);
-    drop(txn);
-    ret
+    (ret, txn.finish())
   }
 
-  fn map_wrapper_with(&self, map_ref: MapRef) -> MapRefWrapper {
-    MapRefWrapper::new(
-      map_ref,
-      CollabContext::new(self.origin.clone(), self.plugins.clone(), self.doc.clone()),
+  fn context(&self) -> CollabContext {
+    CollabContext::new(
+      &self.object_id,
+      self.origin.clone(),
+      self.plugins.clone(),
+      self.doc.clone(),
+      self.data.clone(),
+      self.edit_guard.clone(),
+      self.authorship.clone(),
     )
   }
+
+  fn map_wrapper_with(&self, map_ref: MapRef) -> MapRefWrapper {
+    MapRefWrapper::new(map_ref, self.context())
+  }
   fn array_wrapper_with(&self, array_ref: ArrayRef) -> ArrayRefWrapper {
-    ArrayRefWrapper::new(
-      array_ref,
-      CollabContext::new(self.origin.clone(), self.plugins.clone(), self.doc.clone()),
-    )
+    ArrayRefWrapper::new(array_ref, self.context())
   }
 }
 
@@ -591,26 +1049,64 @@ fn observe_doc(
   oid: String,
   plugins: Plugins,
   local_origin: CollabOrigin,
+  edit_guard: Arc<EditGuard>,
+  authorship: Arc<AuthorshipIndex>,
+  plugin_executor: PluginExecutor,
 ) -> (UpdateSubscription, AfterTransactionSubscription) {
   let cloned_oid = oid.clone();
   let cloned_plugins = plugins.clone();
+  let cloned_plugin_executor = plugin_executor.clone();
   let update_sub = doc
     .observe_update_v1(move |txn, event| {
       // If the origin of the txn is none, it means that the update is coming from a remote source.
-      cloned_plugins.read().iter().for_each(|plugin| {
-        plugin.receive_update(&cloned_oid, txn, &event.update);
+      let remote_origin = CollabOrigin::from(txn);
+      // The revert of a refused transaction is made by the local client, but it carries the
+      // origin of the undo manager.
+      let is_local = remote_origin == local_origin || edit_guard.is_reverting();
+      let updates = if is_local {
+        edit_guard.local_updates(&event.update)
+      } else {
+        vec![Cow::Borrowed(event.update.as_slice())]
+      };
+      if updates.is_empty() {
+        return;
+      }
 
-        let remote_origin = CollabOrigin::from(txn);
-        if remote_origin == local_origin {
-          plugin.receive_local_update(&local_origin, &cloned_oid, &event.update);
-        } else {
-          tracing::trace!(
-            "[🙂Client]: {} did apply remote {} update",
-            local_origin,
-            remote_origin,
-          );
+      let meta = edit_guard.last_meta();
+      if is_local {
+        authorship.insert_meta(&meta);
+      } else {
+        authorship.bind_remote(txn, &remote_origin);
+      }
+      for update in updates {
+        cloned_plugins.read().iter().for_each(|plugin| {
+          plugin.receive_update(&cloned_oid, txn, &update);
+
+          if is_local {
+            plugin.receive_local_update_with_meta(&local_origin, &cloned_oid, &update, &meta);
+          } else {
+            tracing::trace!(
+              "[🙂Client]: {} did apply remote {} update",
+              local_origin,
+              remote_origin,
+            );
+          }
+        });
+
+        // The update is copied into the event, so skip it if there is no async plugin.
+        if cloned_plugin_executor.is_empty() {
+          continue;
         }
-      });
+        cloned_plugin_executor.send(PluginEvent::Update {
+          local_meta: is_local.then(|| Box::new(meta.clone())),
+          origin: if is_local {
+            local_origin.clone()
+          } else {
+            remote_origin.clone()
+          },
+          update: update.into_owned(),
+        });
+      }
     })
     .unwrap();
 
@@ -620,12 +1116,43 @@ fn observe_doc(
         .read()
         .iter()
         .for_each(|plugin| plugin.after_transaction(&oid, txn));
+      plugin_executor.send(PluginEvent::AfterTransaction);
     })
     .unwrap();
 
   (update_sub, after_txn_sub)
 }
 
+/// Observe the data section to check the local edits against the [CollabEditPermission] and
+/// the [CollabPlugin::before_commit] hooks. The deep observer is called with the changes of the
+/// transaction before the transaction is committed, and the after transaction callback is called
+/// before the update callbacks.
+fn observe_edit_guard(
+  doc: &Doc,
+  data: &MapRef,
+  object_id: &str,
+  plugins: &Plugins,
+  edit_guard: &Arc<EditGuard>,
+  local_origin: &CollabOrigin,
+) -> (DeepEventsSubscription, AfterTransactionSubscription) {
+  let cloned_edit_guard = edit_guard.clone();
+  let cloned_origin = local_origin.clone();
+  let object_id = object_id.to_string();
+  let plugins = plugins.clone();
+  let deep_sub = data.clone().observe_deep(move |txn, events| {
+    let plugins = plugins.read().clone();
+    cloned_edit_guard.check_events(&cloned_origin, txn, events, &object_id, &plugins);
+  });
+
+  let edit_guard = edit_guard.clone();
+  let local_origin = local_origin.clone();
+  let client_id = doc.client_id();
+  let after_txn_sub = doc
+    .observe_after_transaction(move |txn| edit_guard.did_commit(&local_origin, client_id, txn))
+    .unwrap();
+  (deep_sub, after_txn_sub)
+}
+
 impl Display for Collab {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     f.write_str(&serde_json::to_string(self).unwrap())?;
@@ -638,12 +1165,15 @@ pub struct CollabBuilder {
   uid: i64,
   device_id: String,
   plugins: Vec<Arc<dyn CollabPlugin>>,
+  async_plugins: Vec<Arc<dyn AsyncCollabPlugin>>,
   object_id: String,
   updates: CollabRawData,
+  encoder_version: EncoderVersion,
+  edit_permission: CollabEditPermission,
 }
 
 /// The raw data of a collab document. It is a list of updates. Each of them can be parsed by
-/// [Update::decode_v1].
+/// [Update::decode_v1], unless another [EncoderVersion] is specified.
 pub type CollabRawData = Vec<Vec<u8>>;
 
 impl CollabBuilder {
@@ -652,9 +1182,12 @@ impl CollabBuilder {
     Self {
       uid,
       plugins: vec![],
+      async_plugins: vec![],
       object_id: object_id.to_string(),
       device_id: "".to_string(),
       updates: vec![],
+      encoder_version: EncoderVersion::V1,
+      edit_permission: CollabEditPermission::Full,
     }
   }
 
@@ -674,50 +1207,153 @@ impl CollabBuilder {
     self
   }
 
+  /// Add an [AsyncCollabPlugin]. The [CollabBuilder::build] must be called in the context of a
+  /// tokio runtime if there is any async plugin.
+  pub fn with_async_plugin<T>(mut self, plugin: T) -> Self
+  where
+    T: AsyncCollabPlugin + 'static,
+  {
+    self.async_plugins.push(Arc::new(plugin));
+    self
+  }
+
   pub fn with_raw_data(mut self, updates: Vec<Vec<u8>>) -> Self {
     self.updates = updates;
     self
   }
 
+  /// The [EncoderVersion] of the raw data. Defaults to [EncoderVersion::V1].
+  pub fn with_encoder_version(mut self, encoder_version: EncoderVersion) -> Self {
+    self.encoder_version = encoder_version;
+    self
+  }
+
+  /// Restrict the local edits of the [Collab]. The raw data is always loaded.
+  pub fn with_edit_permission(mut self, permission: CollabEditPermission) -> Self {
+    self.edit_permission = permission;
+    self
+  }
+
   pub fn build(self) -> Result<MutexCollab, CollabError> {
     let origin = CollabOrigin::Client(CollabClient::new(self.uid, self.device_id));
-    MutexCollab::new_with_raw_data(origin, &self.object_id, self.updates, self.plugins)
+    let mut collab = Collab::new_with_encoded_data(
+      origin,
+      &self.object_id,
+      self.updates,
+      self.encoder_version,
+      self.plugins,
+    )?;
+    collab.set_edit_permission(self.edit_permission);
+    for plugin in self.async_plugins {
+      collab.add_async_plugin(plugin)?;
+    }
+    Ok(MutexCollab::from_collab(collab))
   }
 }
 
 #[derive(Clone)]
 pub struct CollabContext {
+  object_id: String,
   origin: CollabOrigin,
   doc: Doc,
-  #[allow(dead_code)]
   plugins: Plugins,
+  data: MapRef,
+  edit_guard: Arc<EditGuard>,
+  authorship: Arc<AuthorshipIndex>,
 }
 
 impl CollabContext {
-  fn new(origin: CollabOrigin, plugins: Plugins, doc: Doc) -> Self {
+  fn new(
+    object_id: &str,
+    origin: CollabOrigin,
+    plugins: Plugins,
+    doc: Doc,
+    data: MapRef,
+    edit_guard: Arc<EditGuard>,
+    authorship: Arc<AuthorshipIndex>,
+  ) -> Self {
     Self {
+      object_id: object_id.to_string(),
       origin,
       plugins,
       doc,
+      data,
+      edit_guard,
+      authorship,
     }
   }
 
+  pub(crate) fn authorship(&self) -> &AuthorshipIndex {
+    &self.authorship
+  }
+
   pub fn transact(&self) -> Transaction {
     TransactionRetry::new(&self.doc).get_read_txn()
   }
 
+  /// Run the transaction with the origin of the current user. The edits that are refused by the
+  /// [CollabEditPermission] or rejected by a plugin are reverted. Use
+  /// [CollabContext::try_with_transact_mut] to get the error.
   pub fn with_transact_mut<F, T>(&self, f: F) -> T
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
-    let mut txn = TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone());
+    let mut txn = self.guarded_write_txn(&self.doc);
+    f(&mut txn)
+  }
+
+  /// Same as [CollabContext::with_transact_mut], but returns [CollabError::PermissionDenied] if
+  /// the edits are refused by the [CollabEditPermission], or [CollabError::TransactionRejected]
+  /// if they are rejected by a [CollabPlugin::before_commit].
+  pub fn try_with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    let mut txn = self.guarded_write_txn(&self.doc);
     let ret = f(&mut txn);
-    drop(txn);
-    ret
+    match txn.finish() {
+      None => Ok(ret),
+      Some(rejection) => Err(rejection.into()),
+    }
+  }
+
+  fn guarded_write_txn<'doc>(&self, doc: &'doc Doc) -> GuardedTransactionMut<'doc> {
+    let txn = self.guarded_txn(doc, |doc| {
+      Ok(TransactionRetry::new(doc).get_write_txn_with(self.origin.clone()))
+    });
+    txn.unwrap()
+  }
+
+  /// Returns the transaction that is acquired by the given function. Its edits are tracked, so
+  /// they can be reverted if they are refused. They are only tracked if the permission is
+  /// restricted or there is a plugin, which can reject them in [CollabPlugin::before_commit].
+  fn guarded_txn<'doc, F>(
+    &self,
+    doc: &'doc Doc,
+    acquire: F,
+  ) -> Result<GuardedTransactionMut<'doc>, CollabError>
+  where
+    F: FnOnce(&'doc Doc) -> Result<TransactionMut<'doc>, CollabError>,
+  {
+    let can_refuse = !self.edit_guard.permission().is_full() || !self.plugins.read().is_empty();
+    let undo_manager = can_refuse.then(|| {
+      let mut undo_manager =
+        UndoManager::with_options(doc, &self.data, yrs::undo::Options::default());
+      undo_manager.include_origin(self.origin.clone());
+      undo_manager
+    });
+    let txn = acquire(doc)?;
+    Ok(GuardedTransactionMut::new(
+      txn,
+      undo_manager,
+      self.edit_guard.clone(),
+      &self.object_id,
+    ))
   }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Path(Vec<String>);
 
 impl IntoIterator for Path {
@@ -804,6 +1440,11 @@ impl MutexCollab {
     collab.encode_as_update_v1()
   }
 
+  pub fn encode_as_update_v2(&self) -> (Vec<u8>, Vec<u8>) {
+    let collab = self.0.lock();
+    collab.encode_as_update_v2()
+  }
+
   pub fn to_json_value(&self) -> JsonValue {
     self.0.lock().to_json_value()
   }
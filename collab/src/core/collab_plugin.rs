@@ -3,6 +3,7 @@ use bytes::Bytes;
 use std::sync::Arc;
 use yrs::{Doc, TransactionMut};
 
+use crate::core::commit::{LocalCommit, TransactionMeta};
 use crate::core::origin::CollabOrigin;
 use crate::sync_protocol::awareness::Awareness;
 
@@ -26,6 +27,13 @@ pub trait CollabPlugin: Send + Sync + 'static {
   #[cfg(feature = "async-plugin")]
   async fn init(&self, _object_id: &str, _origin: &CollabOrigin, _doc: &Doc) {}
 
+  /// Called right after [CollabPlugin::init]. Returns the [TransactionMeta]s of the transactions
+  /// that were loaded by the plugin, for example, from the local storage. They are imported with
+  /// [Collab::import_txn_metas](crate::core::collab::Collab::import_txn_metas).
+  fn take_txn_metas(&self, _object_id: &str) -> Vec<TransactionMeta> {
+    vec![]
+  }
+
   /// Called when the plugin is initialized.
   fn did_init(&self, _awareness: &Awareness, _object_id: &str) {}
 
@@ -37,6 +45,29 @@ pub trait CollabPlugin: Send + Sync + 'static {
   /// We use the [CollabOrigin] to know if the update comes from the local user or from a remote
   fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, _update: &[u8]) {}
 
+  /// Same as [CollabPlugin::receive_local_update], with the [TransactionMeta] of the local
+  /// transaction, which is attached in [CollabPlugin::before_commit]. It's the one that is
+  /// called by the collab, it calls [CollabPlugin::receive_local_update] by default.
+  fn receive_local_update_with_meta(
+    &self,
+    origin: &CollabOrigin,
+    object_id: &str,
+    update: &[u8],
+    _meta: &TransactionMeta,
+  ) {
+    self.receive_local_update(origin, object_id, update)
+  }
+
+  /// Called before a local [TransactionMut] is committed, before any plugin receives its update.
+  /// The plugin can inspect the changes, attach metadata to the transaction or return the reason
+  /// of rejecting it.
+  ///
+  /// The changes of a rejected transaction are reverted. The plugins receive its update merged
+  /// with the revert, which doesn't change the document, and its metadata is dropped.
+  fn before_commit(&self, _commit: &mut LocalCommit) -> Result<(), String> {
+    Ok(())
+  }
+
   /// Called after each [TransactionMut]
   fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {}
 
@@ -50,6 +81,13 @@ pub trait CollabPlugin: Send + Sync + 'static {
   fn reset(&self, _object_id: &str) {}
 
   fn flush(&self, _object_id: &str, _update: &Bytes) {}
+
+  /// Called when the collab object is about to be closed. The plugin still receives the updates
+  /// of the collab object.
+  fn will_close(&self, _object_id: &str) {}
+
+  /// Called when the collab object was closed. The plugin doesn't receive any update anymore.
+  fn did_close(&self, _object_id: &str) {}
 }
 
 /// Implement the [CollabPlugin] trait for Box<T> and Arc<T> where T implements CollabPlugin.
@@ -68,6 +106,10 @@ where
     (**self).init(object_id, origin, doc).await;
   }
 
+  fn take_txn_metas(&self, object_id: &str) -> Vec<TransactionMeta> {
+    (**self).take_txn_metas(object_id)
+  }
+
   fn did_init(&self, _awareness: &Awareness, _object_id: &str) {
     (**self).did_init(_awareness, _object_id)
   }
@@ -75,6 +117,32 @@ where
   fn receive_update(&self, object_id: &str, txn: &TransactionMut, update: &[u8]) {
     (**self).receive_update(object_id, txn, update)
   }
+
+  fn receive_local_update(&self, origin: &CollabOrigin, object_id: &str, update: &[u8]) {
+    (**self).receive_local_update(origin, object_id, update)
+  }
+
+  fn receive_local_update_with_meta(
+    &self,
+    origin: &CollabOrigin,
+    object_id: &str,
+    update: &[u8],
+    meta: &TransactionMeta,
+  ) {
+    (**self).receive_local_update_with_meta(origin, object_id, update, meta)
+  }
+
+  fn before_commit(&self, commit: &mut LocalCommit) -> Result<(), String> {
+    (**self).before_commit(commit)
+  }
+
+  fn will_close(&self, object_id: &str) {
+    (**self).will_close(object_id)
+  }
+
+  fn did_close(&self, object_id: &str) {
+    (**self).did_close(object_id)
+  }
 }
 
 #[async_trait]
@@ -92,6 +160,10 @@ where
     (**self).init(object_id, origin, doc).await;
   }
 
+  fn take_txn_metas(&self, object_id: &str) -> Vec<TransactionMeta> {
+    (**self).take_txn_metas(object_id)
+  }
+
   fn did_init(&self, _awareness: &Awareness, _object_id: &str) {
     (**self).did_init(_awareness, _object_id)
   }
@@ -99,4 +171,133 @@ where
   fn receive_update(&self, object_id: &str, txn: &TransactionMut, update: &[u8]) {
     (**self).receive_update(object_id, txn, update)
   }
+
+  fn receive_local_update(&self, origin: &CollabOrigin, object_id: &str, update: &[u8]) {
+    (**self).receive_local_update(origin, object_id, update)
+  }
+
+  fn receive_local_update_with_meta(
+    &self,
+    origin: &CollabOrigin,
+    object_id: &str,
+    update: &[u8],
+    meta: &TransactionMeta,
+  ) {
+    (**self).receive_local_update_with_meta(origin, object_id, update, meta)
+  }
+
+  fn before_commit(&self, commit: &mut LocalCommit) -> Result<(), String> {
+    (**self).before_commit(commit)
+  }
+
+  fn will_close(&self, object_id: &str) {
+    (**self).will_close(object_id)
+  }
+
+  fn did_close(&self, object_id: &str) {
+    (**self).did_close(object_id)
+  }
+}
+
+/// The hooks of an [AsyncCollabPlugin].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PluginHook {
+  Init,
+  ReceiveUpdate,
+  ReceiveLocalUpdate,
+  AfterTransaction,
+  Reset,
+  Flush,
+  WillClose,
+  DidClose,
+}
+
+/// The error that is returned by a hook of an [AsyncCollabPlugin]. Use
+/// [Collab::subscribe_plugin_error](crate::core::collab::Collab::subscribe_plugin_error) to
+/// receive the errors.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("{plugin} {hook:?} of {object_id} failed: {error}")]
+pub struct CollabPluginError {
+  pub object_id: String,
+  /// The name of the plugin. Check out the [AsyncCollabPlugin::name].
+  pub plugin: String,
+  pub hook: PluginHook,
+  pub error: Arc<anyhow::Error>,
+}
+
+/// Same as the [CollabPlugin], but the hooks are async and fallible. Instead of spawning tasks
+/// and logging the errors, for example, when writing to the disk or sending the updates over the
+/// network, the plugin returns the error and the app is notified.
+///
+/// The hooks of all the async plugins of a collab object are called one after another on a task
+/// of the collab object, in the order the events happened and the plugins were added. A hook is
+/// only called after the previous one finished, so the updates are received in order.
+#[async_trait]
+pub trait AsyncCollabPlugin: Send + Sync + 'static {
+  /// The name of the plugin that is used in the [CollabPluginError].
+  fn name(&self) -> &str {
+    std::any::type_name::<Self>()
+  }
+
+  /// Called when the collab object is initialized.
+  async fn init(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Called when the plugin receives an update, which is either a local or a remote update.
+  async fn receive_update(
+    &self,
+    _object_id: &str,
+    _origin: &CollabOrigin,
+    _update: &[u8],
+  ) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Called after the [AsyncCollabPlugin::receive_update] if the update comes from the local user.
+  async fn receive_local_update(
+    &self,
+    _object_id: &str,
+    _update: &[u8],
+  ) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Same as [AsyncCollabPlugin::receive_local_update], with the [TransactionMeta] of the local
+  /// transaction. It's the one that is called by the collab, it calls
+  /// [AsyncCollabPlugin::receive_local_update] by default.
+  async fn receive_local_update_with_meta(
+    &self,
+    object_id: &str,
+    update: &[u8],
+    _meta: &TransactionMeta,
+  ) -> Result<(), anyhow::Error> {
+    self.receive_local_update(object_id, update).await
+  }
+
+  /// Called after each [TransactionMut].
+  async fn after_transaction(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Same as the [CollabPlugin::reset].
+  async fn reset(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Same as the [CollabPlugin::flush].
+  async fn flush(&self, _object_id: &str, _update: &Bytes) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Called when the collab object is about to be closed. The updates that happened before
+  /// closing were already received.
+  async fn will_close(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
+
+  /// Called when the collab object was closed. It's the last hook that is called.
+  async fn did_close(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    Ok(())
+  }
 }
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use yrs::block::ClientID;
+use yrs::types::Events;
+use yrs::TransactionMut;
+
+use crate::core::origin::CollabOrigin;
+use crate::core::permission::changed_paths;
+
+/// The metadata of a local transaction. It's passed to the plugins together with the update of
+/// the transaction, for example, to persist it for the activity feed or the audit log.
+///
+/// The author and the time are filled in when the transaction is committed. The action is set
+/// by [Collab::with_action_transact_mut](crate::core::collab::Collab::with_action_transact_mut),
+/// and the plugins can attach more in
+/// [CollabPlugin::before_commit](crate::core::collab_plugin::CollabPlugin::before_commit).
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransactionMeta {
+  /// The uid of the user who made the transaction.
+  pub uid: Option<i64>,
+  /// The device of the user who made the transaction.
+  pub device_id: Option<String>,
+  /// The wall-clock time in milliseconds when the transaction was committed.
+  pub timestamp: Option<i64>,
+  /// A free-form label of the intent of the transaction, for example, "rename view" or "paste".
+  pub action: Option<String>,
+  pub attributes: HashMap<String, String>,
+  /// The client id of the [Doc](yrs::Doc) that made the transaction.
+  pub client_id: Option<ClientID>,
+  /// The clocks of the items that were inserted by the transaction. [None] if the transaction
+  /// only deleted items. Together with the client id, it maps the items of the document back to
+  /// the transaction, check out [Authorship](crate::core::blame::Authorship).
+  pub clock: Option<Range<u32>>,
+}
+
+impl TransactionMeta {
+  pub fn is_empty(&self) -> bool {
+    self.uid.is_none()
+      && self.device_id.is_none()
+      && self.timestamp.is_none()
+      && self.action.is_none()
+      && self.attributes.is_empty()
+      && self.client_id.is_none()
+      && self.clock.is_none()
+  }
+
+  /// Fill in the author and the time of the transaction if they are not set yet.
+  pub(crate) fn stamp(&mut self, origin: &CollabOrigin) {
+    if self.uid.is_none() {
+      self.uid = origin.client_user_id();
+    }
+    if self.device_id.is_none() {
+      self.device_id = origin
+        .client_device_id()
+        .map(|device_id| device_id.to_string());
+    }
+    if self.timestamp.is_none() {
+      self.timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .ok();
+    }
+  }
+
+  /// Fill in the client id and the clocks of the items that were inserted by the committed
+  /// transaction.
+  pub(crate) fn stamp_clock(&mut self, client_id: ClientID, txn: &TransactionMut) {
+    let clock = txn.before_state().get(&client_id)..txn.after_state().get(&client_id);
+    self.client_id = Some(client_id);
+    self.clock = (!clock.is_empty()).then_some(clock);
+  }
+}
+
+/// A local transaction that is being committed. It's passed to
+/// [CollabPlugin::before_commit](crate::core::collab_plugin::CollabPlugin::before_commit), so the
+/// plugins can inspect the changes before any plugin receives the update of the transaction.
+pub struct LocalCommit<'a, 'doc> {
+  object_id: &'a str,
+  txn: &'a TransactionMut<'doc>,
+  events: &'a Events,
+  meta: &'a mut TransactionMeta,
+}
+
+impl<'a, 'doc> LocalCommit<'a, 'doc> {
+  pub(crate) fn new(
+    object_id: &'a str,
+    txn: &'a TransactionMut<'doc>,
+    events: &'a Events,
+    meta: &'a mut TransactionMeta,
+  ) -> Self {
+    Self {
+      object_id,
+      txn,
+      events,
+      meta,
+    }
+  }
+
+  pub fn object_id(&self) -> &str {
+    self.object_id
+  }
+
+  pub fn txn(&self) -> &TransactionMut<'doc> {
+    self.txn
+  }
+
+  /// The deep events of the data section of the collab.
+  pub fn events(&self) -> &Events {
+    self.events
+  }
+
+  /// Returns the paths of the values that were changed by the transaction. The paths are
+  /// relative to the data section, the same as the paths of the
+  /// [CollabEditPermission](crate::core::permission::CollabEditPermission).
+  pub fn changed_paths(&self) -> Vec<Vec<String>> {
+    changed_paths(self.txn, self.events)
+  }
+
+  /// Encode the changes of the transaction, for example, to check the size of the update.
+  pub fn encode_update_v1(&self) -> Vec<u8> {
+    self.txn.encode_update_v1()
+  }
+
+  /// Attach the metadata to the transaction. It's passed to the plugins together with the
+  /// update of the transaction.
+  pub fn insert_meta<K: ToString, V: ToString>(&mut self, key: K, value: V) {
+    self
+      .meta
+      .attributes
+      .insert(key.to_string(), value.to_string());
+  }
+
+  /// Set the action of the transaction. It replaces the action that was passed to
+  /// [Collab::with_action_transact_mut](crate::core::collab::Collab::with_action_transact_mut).
+  pub fn set_action<T: ToString>(&mut self, action: T) {
+    self.meta.action = Some(action.to_string());
+  }
+
+  pub fn meta(&self) -> &TransactionMeta {
+    self.meta
+  }
+}
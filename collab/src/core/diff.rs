@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::Value as JsonValue;
+use yrs::types::Value as YrsValue;
+use yrs::{Array, ArrayRef, GetString, Map, MapRef, ReadTxn, TextRef};
+
+use crate::core::collab::{Collab, Path, DATA_SECTION};
+use crate::core::path_observer::{value_to_json, PathChange, PathChangeKind, TextDeltaChange};
+
+/// Returns the structural changes that turn the data of the `old` [Collab] into the data of the
+/// `new` [Collab]. The changes use the same [PathChange]s as the path observers:
+///
+/// * the added, removed and replaced keys of the maps.
+/// * the inserted and deleted elements of the arrays. An element that is changed in place is
+///   reported as deleted and inserted again.
+/// * the changed range of the texts. The formatting attributes are not compared.
+///
+/// The changes of each array and text are ordered in the same way as a delta: the index of a
+/// change takes the previous changes of the same array into account.
+pub fn diff_collab(old: &Collab, new: &Collab) -> Vec<PathChange> {
+  let old_txn = old.transact();
+  let new_txn = new.transact();
+  let mut changes = vec![];
+  if let (Some(old_data), Some(new_data)) =
+    (old_txn.get_map(DATA_SECTION), new_txn.get_map(DATA_SECTION))
+  {
+    diff_map(
+      &old_txn,
+      &old_data,
+      &new_txn,
+      &new_data,
+      &Path::from(Vec::<String>::new()),
+      &mut changes,
+    );
+  }
+  changes
+}
+
+fn diff_map<O: ReadTxn, N: ReadTxn>(
+  old_txn: &O,
+  old_map: &MapRef,
+  new_txn: &N,
+  new_map: &MapRef,
+  path: &Path,
+  changes: &mut Vec<PathChange>,
+) {
+  let old_entries = old_map
+    .iter(old_txn)
+    .map(|(key, value)| (key.to_string(), value))
+    .collect::<BTreeMap<_, _>>();
+  let new_entries = new_map
+    .iter(new_txn)
+    .map(|(key, value)| (key.to_string(), value))
+    .collect::<BTreeMap<_, _>>();
+
+  let keys = old_entries
+    .keys()
+    .chain(new_entries.keys())
+    .cloned()
+    .collect::<BTreeSet<_>>();
+  for key in keys {
+    match (old_entries.get(&key), new_entries.get(&key)) {
+      (Some(old_value), None) => push_change(
+        changes,
+        path,
+        PathChangeKind::Removed {
+          key,
+          old_value: value_to_json(old_txn, old_value),
+        },
+      ),
+      (None, Some(value)) => push_change(
+        changes,
+        path,
+        PathChangeKind::Inserted {
+          key,
+          value: value_to_json(new_txn, value),
+        },
+      ),
+      (Some(old_value), Some(value)) => {
+        diff_value(old_txn, old_value, new_txn, value, path, key, changes)
+      },
+      (None, None) => {},
+    }
+  }
+}
+
+fn diff_value<O: ReadTxn, N: ReadTxn>(
+  old_txn: &O,
+  old_value: &YrsValue,
+  new_txn: &N,
+  new_value: &YrsValue,
+  path: &Path,
+  key: String,
+  changes: &mut Vec<PathChange>,
+) {
+  let child_path = || {
+    let mut child_path = path.clone();
+    child_path.push(key.clone());
+    child_path
+  };
+  match (old_value, new_value) {
+    (YrsValue::YMap(old_map), YrsValue::YMap(new_map)) => {
+      diff_map(old_txn, old_map, new_txn, new_map, &child_path(), changes)
+    },
+    (YrsValue::YArray(old_array), YrsValue::YArray(new_array)) => diff_array(
+      old_txn,
+      old_array,
+      new_txn,
+      new_array,
+      &child_path(),
+      changes,
+    ),
+    (YrsValue::YText(old_text), YrsValue::YText(new_text)) => {
+      diff_text(old_txn, old_text, new_txn, new_text, &child_path(), changes)
+    },
+    _ => {
+      let old_value = value_to_json(old_txn, old_value);
+      let value = value_to_json(new_txn, new_value);
+      if old_value != value {
+        push_change(
+          changes,
+          path,
+          PathChangeKind::Updated {
+            key,
+            old_value,
+            value,
+          },
+        );
+      }
+    },
+  }
+}
+
+fn diff_array<O: ReadTxn, N: ReadTxn>(
+  old_txn: &O,
+  old_array: &ArrayRef,
+  new_txn: &N,
+  new_array: &ArrayRef,
+  path: &Path,
+  changes: &mut Vec<PathChange>,
+) {
+  let old_values = old_array
+    .iter(old_txn)
+    .map(|value| value_to_json(old_txn, &value))
+    .collect::<Vec<_>>();
+  let new_values = new_array
+    .iter(new_txn)
+    .map(|value| value_to_json(new_txn, &value))
+    .collect::<Vec<_>>();
+
+  let mut index = 0;
+  let mut deleted = 0;
+  let mut inserted = vec![];
+  for op in diff_sequence(&old_values, &new_values) {
+    match op {
+      SequenceOp::Equal => {
+        flush_array_changes(changes, path, &mut index, &mut deleted, &mut inserted);
+        index += 1;
+      },
+      SequenceOp::Delete => deleted += 1,
+      SequenceOp::Insert(i) => inserted.push(new_values[i].clone()),
+    }
+  }
+  flush_array_changes(changes, path, &mut index, &mut deleted, &mut inserted);
+}
+
+fn flush_array_changes(
+  changes: &mut Vec<PathChange>,
+  path: &Path,
+  index: &mut u32,
+  deleted: &mut u32,
+  inserted: &mut Vec<JsonValue>,
+) {
+  if *deleted > 0 {
+    push_change(
+      changes,
+      path,
+      PathChangeKind::ArrayDeleted {
+        index: *index,
+        len: *deleted,
+      },
+    );
+    *deleted = 0;
+  }
+  if !inserted.is_empty() {
+    let values = std::mem::take(inserted);
+    let len = values.len() as u32;
+    push_change(
+      changes,
+      path,
+      PathChangeKind::ArrayInserted {
+        index: *index,
+        values,
+      },
+    );
+    *index += len;
+  }
+}
+
+/// Reports the changed range of the text, between the common prefix and the common suffix of
+/// the old and the new text. The lengths are counted in UTF-16 code units, the same as the
+/// offsets of the [Collab].
+fn diff_text<O: ReadTxn, N: ReadTxn>(
+  old_txn: &O,
+  old_text: &TextRef,
+  new_txn: &N,
+  new_text: &TextRef,
+  path: &Path,
+  changes: &mut Vec<PathChange>,
+) {
+  let old_chars = old_text.get_string(old_txn).chars().collect::<Vec<_>>();
+  let new_chars = new_text.get_string(new_txn).chars().collect::<Vec<_>>();
+  if old_chars == new_chars {
+    return;
+  }
+
+  let prefix = old_chars
+    .iter()
+    .zip(new_chars.iter())
+    .take_while(|(old, new)| old == new)
+    .count();
+  let suffix = old_chars[prefix..]
+    .iter()
+    .rev()
+    .zip(new_chars[prefix..].iter().rev())
+    .take_while(|(old, new)| old == new)
+    .count();
+
+  let mut delta = vec![];
+  if prefix > 0 {
+    delta.push(TextDeltaChange::Retain {
+      retain: utf16_len(&old_chars[..prefix]),
+      attributes: None,
+    });
+  }
+  let deleted = &old_chars[prefix..old_chars.len() - suffix];
+  if !deleted.is_empty() {
+    delta.push(TextDeltaChange::Delete {
+      delete: utf16_len(deleted),
+    });
+  }
+  let inserted = &new_chars[prefix..new_chars.len() - suffix];
+  if !inserted.is_empty() {
+    delta.push(TextDeltaChange::Insert {
+      insert: JsonValue::String(inserted.iter().collect()),
+      attributes: None,
+    });
+  }
+  push_change(changes, path, PathChangeKind::TextDelta { delta });
+}
+
+fn utf16_len(chars: &[char]) -> u32 {
+  chars.iter().map(|c| c.len_utf16() as u32).sum()
+}
+
+fn push_change(changes: &mut Vec<PathChange>, path: &Path, kind: PathChangeKind) {
+  changes.push(PathChange {
+    path: path.clone(),
+    kind,
+  });
+}
+
+enum SequenceOp {
+  Equal,
+  Delete,
+  /// The index of the inserted element in the new sequence.
+  Insert(usize),
+}
+
+/// The maximum number of cells of the table that is used to compute the longest common
+/// subsequence in [diff_sequence].
+const MAX_LCS_TABLE_SIZE: usize = 1 << 20;
+
+/// Returns the operations that turn the `old` sequence into the `new` sequence, based on their
+/// longest common subsequence. The deletions are placed before the insertions at the same
+/// position.
+///
+/// The part between the common prefix and the common suffix is replaced as a whole if the table of
+/// the longest common subsequence would have more than [MAX_LCS_TABLE_SIZE] cells.
+fn diff_sequence<T: PartialEq>(old: &[T], new: &[T]) -> Vec<SequenceOp> {
+  let prefix = old
+    .iter()
+    .zip(new.iter())
+    .take_while(|(old, new)| old == new)
+    .count();
+  let suffix = old[prefix..]
+    .iter()
+    .rev()
+    .zip(new[prefix..].iter().rev())
+    .take_while(|(old, new)| old == new)
+    .count();
+  let old_middle = &old[prefix..old.len() - suffix];
+  let new_middle = &new[prefix..new.len() - suffix];
+
+  let mut ops = (0..prefix).map(|_| SequenceOp::Equal).collect::<Vec<_>>();
+  let table_size = (old_middle.len() + 1).saturating_mul(new_middle.len() + 1);
+  if table_size > MAX_LCS_TABLE_SIZE {
+    ops.extend(old_middle.iter().map(|_| SequenceOp::Delete));
+    ops.extend((0..new_middle.len()).map(|j| SequenceOp::Insert(prefix + j)));
+    ops.extend((0..suffix).map(|_| SequenceOp::Equal));
+    return ops;
+  }
+
+  // lcs[i][j] is the length of the longest common subsequence of old_middle[i..] and
+  // new_middle[j..].
+  let mut lcs = vec![vec![0_usize; new_middle.len() + 1]; old_middle.len() + 1];
+  for i in (0..old_middle.len()).rev() {
+    for j in (0..new_middle.len()).rev() {
+      lcs[i][j] = if old_middle[i] == new_middle[j] {
+        lcs[i + 1][j + 1] + 1
+      } else {
+        lcs[i + 1][j].max(lcs[i][j + 1])
+      };
+    }
+  }
+
+  let (mut i, mut j) = (0, 0);
+  while i < old_middle.len() || j < new_middle.len() {
+    if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+      ops.push(SequenceOp::Equal);
+      i += 1;
+      j += 1;
+    } else if i < old_middle.len() && (j == new_middle.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+      ops.push(SequenceOp::Delete);
+      i += 1;
+    } else {
+      ops.push(SequenceOp::Insert(prefix + j));
+      j += 1;
+    }
+  }
+  ops.extend((0..suffix).map(|_| SequenceOp::Equal));
+  ops
+}
@@ -0,0 +1,100 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{merge_updates_v1, merge_updates_v2, ReadTxn, StateVector, Update};
+
+/// The lib0 encoding of the updates of a document. The v2 encoding is usually much smaller than
+/// the v1 encoding, especially for text, but the two encodings are not compatible: an update must
+/// be decoded with the version it was encoded with.
+///
+/// The state vectors are always encoded with the v1 encoding.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum EncoderVersion {
+  #[default]
+  V1,
+  V2,
+}
+
+impl EncoderVersion {
+  /// The latest version that can be encoded and decoded.
+  pub const LATEST: EncoderVersion = EncoderVersion::V2;
+
+  pub fn decode_update(&self, update: &[u8]) -> Result<Update, lib0::error::Error> {
+    match self {
+      EncoderVersion::V1 => Update::decode_v1(update),
+      EncoderVersion::V2 => Update::decode_v2(update),
+    }
+  }
+
+  pub fn encode_update(&self, update: &Update) -> Vec<u8> {
+    match self {
+      EncoderVersion::V1 => update.encode_v1(),
+      EncoderVersion::V2 => update.encode_v2(),
+    }
+  }
+
+  /// Encode the changes of the document that are not included in the given [StateVector]. Pass
+  /// [StateVector::default] to encode the whole document.
+  pub fn encode_state_as_update<T: ReadTxn>(&self, txn: &T, sv: &StateVector) -> Vec<u8> {
+    match self {
+      EncoderVersion::V1 => txn.encode_state_as_update_v1(sv),
+      EncoderVersion::V2 => txn.encode_state_as_update_v2(sv),
+    }
+  }
+
+  /// Merge the updates into a single update. The updates must be encoded with this version.
+  pub fn merge_updates(&self, updates: &[&[u8]]) -> Result<Vec<u8>, lib0::error::Error> {
+    match self {
+      EncoderVersion::V1 => merge_updates_v1(updates),
+      EncoderVersion::V2 => merge_updates_v2(updates),
+    }
+  }
+}
+
+impl Display for EncoderVersion {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      EncoderVersion::V1 => f.write_str("v1"),
+      EncoderVersion::V2 => f.write_str("v2"),
+    }
+  }
+}
+
+impl From<EncoderVersion> for u8 {
+  fn from(version: EncoderVersion) -> Self {
+    match version {
+      EncoderVersion::V1 => 1,
+      EncoderVersion::V2 => 2,
+    }
+  }
+}
+
+impl TryFrom<u8> for EncoderVersion {
+  type Error = lib0::error::Error;
+
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    match value {
+      1 => Ok(EncoderVersion::V1),
+      2 => Ok(EncoderVersion::V2),
+      _ => Err(lib0::error::Error::UnexpectedValue),
+    }
+  }
+}
+
+/// Convert an update that was encoded with the `from` version to the `to` version. The update is
+/// returned as is if the two versions are the same.
+pub fn convert_update(
+  update: &[u8],
+  from: EncoderVersion,
+  to: EncoderVersion,
+) -> Result<Vec<u8>, lib0::error::Error> {
+  if from == to {
+    return Ok(update.to_vec());
+  }
+  let update = from.decode_update(update)?;
+  Ok(to.encode_update(&update))
+}
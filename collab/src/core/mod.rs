@@ -1,11 +1,21 @@
 pub mod any_array;
 pub mod any_map;
 pub mod array_wrapper;
+pub mod blame;
 pub mod collab;
 pub mod collab_plugin;
 mod collab_serde;
 pub mod collab_state;
+pub mod commit;
+pub mod diff;
+pub mod encoding;
 pub mod map_wrapper;
 pub mod origin;
+pub mod path_observer;
+pub mod permission;
+mod plugin_executor;
+pub mod remap;
 pub mod text_wrapper;
 pub mod transaction;
+pub mod undo;
+pub mod version;
@@ -8,6 +8,9 @@ pub enum CollabOrigin {
   Client(CollabClient),
   Server,
   Empty,
+  /// The updates that are imported from an offline bundle. Check out the `CollabBundle` of the
+  /// `collab-entity` crate.
+  Bundle,
 }
 
 impl CollabOrigin {
@@ -16,6 +19,14 @@ impl CollabOrigin {
       CollabOrigin::Client(origin) => Some(origin.uid),
       CollabOrigin::Server => None,
       CollabOrigin::Empty => None,
+      CollabOrigin::Bundle => None,
+    }
+  }
+
+  pub fn client_device_id(&self) -> Option<&str> {
+    match self {
+      CollabOrigin::Client(origin) => Some(origin.device_id()),
+      _ => None,
     }
   }
 }
@@ -29,6 +40,7 @@ impl Display for CollabOrigin {
       )),
       CollabOrigin::Server => f.write_fmt(format_args!("server")),
       CollabOrigin::Empty => Ok(()),
+      CollabOrigin::Bundle => f.write_fmt(format_args!("bundle")),
     }
   }
 }
@@ -84,6 +96,10 @@ impl CollabClient {
     );
     Self { uid, device_id }
   }
+
+  pub fn device_id(&self) -> &str {
+    &self.device_id
+  }
 }
 
 impl From<CollabClient> for Origin {
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use lib0::any::Any;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use yrs::types::text::TextEvent;
+use yrs::types::{
+  Attrs, Change, DeepEventsSubscription, Delta, EntryChange, Event, Events, PathSegment, ToJson,
+  Value as YrsValue,
+};
+use yrs::{ReadTxn, TransactionMut};
+
+use crate::core::collab::Path;
+use crate::core::origin::CollabOrigin;
+use crate::util::lib0_any_to_json_value;
+
+pub type PathSubscription = DeepEventsSubscription;
+
+/// The changes of a transaction under the observed [Path]. See
+/// [Collab::observe_path](crate::core::collab::Collab::observe_path).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PathChangeEvent {
+  /// The origin of the transaction that made the changes.
+  pub origin: CollabOrigin,
+  pub changes: Vec<PathChange>,
+}
+
+/// A change of the map, array or text at the given [Path]. The path is relative to the data
+/// section of the [Collab](crate::core::collab::Collab).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PathChange {
+  pub path: Path,
+  #[serde(flatten)]
+  pub kind: PathChangeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PathChangeKind {
+  /// A new key was inserted into the map.
+  Inserted { key: String, value: JsonValue },
+  /// The value of the key was replaced.
+  Updated {
+    key: String,
+    old_value: JsonValue,
+    value: JsonValue,
+  },
+  /// The key was removed from the map. In a [PathChangeEvent], the old value of a removed map,
+  /// array or text is empty, because its content is deleted together with it.
+  Removed { key: String, old_value: JsonValue },
+  /// The values were inserted into the array at the given index.
+  ArrayInserted { index: u32, values: Vec<JsonValue> },
+  /// The number of `len` values were deleted from the array at the given index.
+  ArrayDeleted { index: u32, len: u32 },
+  /// The text was changed. The delta follows the format of the Quill delta.
+  TextDelta { delta: Vec<TextDeltaChange> },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextDeltaChange {
+  Insert {
+    insert: JsonValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attributes: Option<HashMap<String, JsonValue>>,
+  },
+  Delete {
+    delete: u32,
+  },
+  Retain {
+    retain: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attributes: Option<HashMap<String, JsonValue>>,
+  },
+}
+
+impl PathChange {
+  /// Returns true if the change touches the value at the given path. It's the case when the
+  /// change is made under the path or when the change replaces or removes one of its parents.
+  fn is_related_to(&self, path: &[String]) -> bool {
+    let mut changed_path = self.path.to_vec();
+    if let Some(key) = self.kind.key() {
+      changed_path.push(key.to_string());
+    }
+    changed_path.starts_with(path) || path.starts_with(&changed_path)
+  }
+}
+
+impl PathChangeKind {
+  /// Returns the key of the changed map entry, or None if an array or a text was changed.
+  pub fn key(&self) -> Option<&str> {
+    match self {
+      PathChangeKind::Inserted { key, .. }
+      | PathChangeKind::Updated { key, .. }
+      | PathChangeKind::Removed { key, .. } => Some(key),
+      _ => None,
+    }
+  }
+}
+
+/// Converts the deep events of the data section into the [PathChange]s that are related to
+/// the given path.
+pub(crate) fn path_changes(
+  txn: &TransactionMut,
+  events: &Events,
+  path: &[String],
+) -> Vec<PathChange> {
+  events
+    .iter()
+    .flat_map(|event| event_changes(txn, event))
+    .filter(|change| change.is_related_to(path))
+    .collect()
+}
+
+fn event_changes(txn: &TransactionMut, event: &Event) -> Vec<PathChange> {
+  let path = Path::from(
+    event
+      .path()
+      .into_iter()
+      .map(|segment| match segment {
+        PathSegment::Key(key) => key.to_string(),
+        PathSegment::Index(index) => index.to_string(),
+      })
+      .collect::<Vec<String>>(),
+  );
+
+  let kinds = match event {
+    Event::Map(event) => event
+      .keys(txn)
+      .iter()
+      .map(|(key, change)| {
+        let key = key.to_string();
+        match change {
+          EntryChange::Inserted(value) => PathChangeKind::Inserted {
+            key,
+            value: value_to_json(txn, value),
+          },
+          EntryChange::Updated(old_value, value) => PathChangeKind::Updated {
+            key,
+            old_value: value_to_json(txn, old_value),
+            value: value_to_json(txn, value),
+          },
+          EntryChange::Removed(old_value) => PathChangeKind::Removed {
+            key,
+            old_value: value_to_json(txn, old_value),
+          },
+        }
+      })
+      .collect::<Vec<_>>(),
+    Event::Array(event) => {
+      let mut index = 0;
+      let mut kinds = vec![];
+      for change in event.delta(txn) {
+        match change {
+          Change::Added(values) => {
+            kinds.push(PathChangeKind::ArrayInserted {
+              index,
+              values: values
+                .iter()
+                .map(|value| value_to_json(txn, value))
+                .collect(),
+            });
+            index += values.len() as u32;
+          },
+          Change::Removed(len) => kinds.push(PathChangeKind::ArrayDeleted { index, len: *len }),
+          Change::Retain(len) => index += len,
+        }
+      }
+      kinds
+    },
+    Event::Text(event) => vec![PathChangeKind::TextDelta {
+      delta: text_delta(txn, event),
+    }],
+    _ => vec![],
+  };
+
+  kinds
+    .into_iter()
+    .map(|kind| PathChange {
+      path: path.clone(),
+      kind,
+    })
+    .collect()
+}
+
+fn text_delta(txn: &TransactionMut, event: &TextEvent) -> Vec<TextDeltaChange> {
+  event
+    .delta(txn)
+    .iter()
+    .map(|delta| match delta {
+      Delta::Inserted(value, attrs) => TextDeltaChange::Insert {
+        insert: value_to_json(txn, value),
+        attributes: attrs.as_deref().map(attrs_to_json),
+      },
+      Delta::Deleted(len) => TextDeltaChange::Delete { delete: *len },
+      Delta::Retain(len, attrs) => TextDeltaChange::Retain {
+        retain: *len,
+        attributes: attrs.as_deref().map(attrs_to_json),
+      },
+    })
+    .collect()
+}
+
+pub(crate) fn value_to_json<T: ReadTxn>(txn: &T, value: &YrsValue) -> JsonValue {
+  any_to_json(value.to_json(txn))
+}
+
+fn attrs_to_json(attrs: &Attrs) -> HashMap<String, JsonValue> {
+  attrs
+    .iter()
+    .map(|(key, value)| (key.to_string(), any_to_json(value.clone())))
+    .collect()
+}
+
+fn any_to_json(any: Any) -> JsonValue {
+  lib0_any_to_json_value(any).unwrap_or(JsonValue::Null)
+}
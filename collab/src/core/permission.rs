@@ -0,0 +1,345 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use yrs::block::ClientID;
+use yrs::types::{Event, Events, PathSegment};
+use yrs::{merge_updates_v1, TransactionMut, UndoManager};
+
+use crate::core::collab::Path;
+use crate::core::collab_plugin::CollabPlugin;
+use crate::core::commit::{LocalCommit, TransactionMeta};
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+
+/// The [CollabEditPermission] controls which local edits are allowed on a
+/// [Collab](crate::core::collab::Collab). It only applies to the transactions that carry the
+/// origin of the [Collab]. The remote updates are always applied.
+///
+/// The paths are relative to the data section of the [Collab], the same as the paths that are
+/// used by [Collab::get_map_with_path](crate::core::collab::Collab::get_map_with_path).
+///
+/// The data section is the only section of the [Collab], so it's the only one that is guarded.
+/// The awareness state is not a part of the document, and it's not restricted on purpose, so the
+/// read-only clients can still share their presence.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum CollabEditPermission {
+  /// All the local edits are allowed.
+  #[default]
+  Full,
+  /// All the local edits are refused.
+  ReadOnly,
+  /// Only the local edits under the given paths are allowed. For example, the path
+  /// `["comments"]` allows editing the `comments` key and everything nested in it.
+  Paths(Vec<Path>),
+}
+
+impl CollabEditPermission {
+  pub fn is_full(&self) -> bool {
+    matches!(self, CollabEditPermission::Full)
+  }
+
+  /// Returns true if some local edits are allowed.
+  pub fn can_edit(&self) -> bool {
+    !matches!(self, CollabEditPermission::ReadOnly)
+  }
+
+  /// Returns true if the value at the given path can be edited.
+  pub fn can_edit_path(&self, path: &[String]) -> bool {
+    match self {
+      CollabEditPermission::Full => true,
+      CollabEditPermission::ReadOnly => false,
+      CollabEditPermission::Paths(paths) => paths.iter().any(|allowed| path.starts_with(allowed)),
+    }
+  }
+}
+
+/// The reason of refusing a local transaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Rejection {
+  /// Refused by the [CollabEditPermission].
+  PermissionDenied(String),
+  /// Refused by a plugin in [CollabPlugin::before_commit].
+  Rejected(String),
+}
+
+impl Display for Rejection {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Rejection::PermissionDenied(reason) | Rejection::Rejected(reason) => f.write_str(reason),
+    }
+  }
+}
+
+impl From<Rejection> for CollabError {
+  fn from(rejection: Rejection) -> Self {
+    match rejection {
+      Rejection::PermissionDenied(reason) => CollabError::PermissionDenied(reason),
+      Rejection::Rejected(reason) => CollabError::TransactionRejected(reason),
+    }
+  }
+}
+
+/// The [EditGuard] checks the local transactions against the [CollabEditPermission] and the
+/// [CollabPlugin::before_commit] hooks. The updates of the refused transactions are held back,
+/// and passed to the plugins merged with the update of their revert. The merged update changes
+/// nothing, but it keeps the clocks of the local client continuous for the plugins, so the
+/// later updates can be applied by the storage and the remote peers.
+#[derive(Default)]
+pub(crate) struct EditGuard {
+  permission: RwLock<CollabEditPermission>,
+  /// The reason of refusing the transaction that is being committed.
+  pending_rejection: Mutex<Option<Rejection>>,
+  /// The reason of refusing the last committed transaction. [None] if it was allowed.
+  last_rejection: Mutex<Option<Rejection>>,
+  /// The metadata that the plugins attached to the transaction that is being committed.
+  pending_meta: Mutex<TransactionMeta>,
+  /// The metadata of the last committed transaction.
+  last_meta: Mutex<TransactionMeta>,
+  /// Set while reverting a refused transaction.
+  is_reverting: AtomicBool,
+  /// The updates of the refused transactions that are not passed to the plugins yet.
+  held_updates: Mutex<Vec<Vec<u8>>>,
+}
+
+impl EditGuard {
+  pub(crate) fn permission(&self) -> CollabEditPermission {
+    self.permission.read().clone()
+  }
+
+  pub(crate) fn set_permission(&self, permission: CollabEditPermission) {
+    *self.permission.write() = permission;
+  }
+
+  /// Called with the deep events of the data section before the transaction is committed. The
+  /// [CollabPlugin::before_commit] hooks are only called if the edits are permitted.
+  pub(crate) fn check_events(
+    &self,
+    local_origin: &CollabOrigin,
+    txn: &TransactionMut,
+    events: &Events,
+    object_id: &str,
+    plugins: &[Arc<dyn CollabPlugin>],
+  ) {
+    if &CollabOrigin::from(txn) != local_origin {
+      return;
+    }
+
+    let permission = self.permission.read();
+    if let CollabEditPermission::Paths(_) = &*permission {
+      let refused_path = changed_paths(txn, events)
+        .into_iter()
+        .find(|path| !permission.can_edit_path(path));
+      if let Some(path) = refused_path {
+        self.pending_rejection.lock().get_or_insert_with(|| {
+          Rejection::PermissionDenied(format!("the path {:?} is not editable", path))
+        });
+      }
+    }
+
+    if self.pending_rejection.lock().is_some() {
+      return;
+    }
+    let mut meta = self.pending_meta.lock();
+    meta.stamp(local_origin);
+    let mut commit = LocalCommit::new(object_id, txn, events, &mut meta);
+    for plugin in plugins {
+      if let Err(reason) = plugin.before_commit(&mut commit) {
+        *self.pending_rejection.lock() = Some(Rejection::Rejected(reason));
+        break;
+      }
+    }
+  }
+
+  /// Called after the transaction is committed. It's called before the plugins receive the
+  /// update of the transaction.
+  pub(crate) fn did_commit(
+    &self,
+    local_origin: &CollabOrigin,
+    client_id: ClientID,
+    txn: &TransactionMut,
+  ) {
+    let pending_rejection = self.pending_rejection.lock().take();
+    let mut pending_meta = std::mem::take(&mut *self.pending_meta.lock());
+    let rejection = if self.is_reverting() {
+      // The revert is made by the local client, so it's passed to the plugins as a local update.
+      pending_meta.stamp(local_origin);
+      pending_meta.stamp_clock(client_id, txn);
+      *self.last_meta.lock() = pending_meta;
+      None
+    } else if &CollabOrigin::from(txn) == local_origin {
+      pending_meta.stamp(local_origin);
+      pending_meta.stamp_clock(client_id, txn);
+      *self.last_meta.lock() = pending_meta;
+      match &*self.permission.read() {
+        CollabEditPermission::ReadOnly => Some(Rejection::PermissionDenied(
+          "the collab is read-only".to_string(),
+        )),
+        _ => pending_rejection,
+      }
+    } else {
+      *self.last_meta.lock() = TransactionMeta::default();
+      None
+    };
+    *self.last_rejection.lock() = rejection;
+  }
+
+  /// Set the action of the next local transaction.
+  pub(crate) fn set_pending_action(&self, action: String) {
+    self.pending_meta.lock().action = Some(action);
+  }
+
+  /// Returns the metadata of the last committed transaction.
+  pub(crate) fn last_meta(&self) -> TransactionMeta {
+    self.last_meta.lock().clone()
+  }
+
+  /// Returns true while reverting a refused transaction.
+  pub(crate) fn is_reverting(&self) -> bool {
+    self.is_reverting.load(Ordering::SeqCst)
+  }
+
+  /// Returns the updates that are passed to the plugins for the update of the last committed
+  /// local transaction. The update of a refused transaction is held back and nothing is
+  /// returned. Otherwise, the held updates are merged with the given update, which is the
+  /// revert of the refused transactions in most cases.
+  pub(crate) fn local_updates<'a>(&self, update: &'a [u8]) -> Vec<Cow<'a, [u8]>> {
+    let mut held_updates = self.held_updates.lock();
+    if self.last_rejection.lock().is_some() {
+      held_updates.push(update.to_vec());
+      return vec![];
+    }
+    if held_updates.is_empty() {
+      return vec![Cow::Borrowed(update)];
+    }
+
+    let mut updates = std::mem::take(&mut *held_updates);
+    updates.push(update.to_vec());
+    let update_refs = updates
+      .iter()
+      .map(|update| update.as_slice())
+      .collect::<Vec<_>>();
+    match merge_updates_v1(&update_refs) {
+      Ok(merged) => vec![Cow::Owned(merged)],
+      Err(e) => {
+        tracing::error!(
+          "merge the updates of the refused transactions failed: {}",
+          e
+        );
+        updates.into_iter().map(Cow::Owned).collect()
+      },
+    }
+  }
+
+  pub(crate) fn last_rejection(&self) -> Option<Rejection> {
+    self.last_rejection.lock().clone()
+  }
+
+  pub(crate) fn revert<F: FnOnce()>(&self, f: F) {
+    self.is_reverting.store(true, Ordering::SeqCst);
+    f();
+    self.is_reverting.store(false, Ordering::SeqCst);
+  }
+}
+
+/// A [TransactionMut] that carries the origin of the local user. If its edits are refused by the
+/// [CollabEditPermission] or rejected by a [CollabPlugin::before_commit], they are reverted when
+/// it's dropped.
+pub struct GuardedTransactionMut<'doc> {
+  txn: Option<TransactionMut<'doc>>,
+  /// Tracks the edits of the transaction, so they can be reverted. [None] if nothing can refuse
+  /// them.
+  undo_manager: Option<UndoManager>,
+  edit_guard: Arc<EditGuard>,
+  object_id: String,
+}
+
+impl<'doc> GuardedTransactionMut<'doc> {
+  pub(crate) fn new(
+    txn: TransactionMut<'doc>,
+    undo_manager: Option<UndoManager>,
+    edit_guard: Arc<EditGuard>,
+    object_id: &str,
+  ) -> Self {
+    Self {
+      txn: Some(txn),
+      undo_manager,
+      edit_guard,
+      object_id: object_id.to_string(),
+    }
+  }
+
+  /// Commit the transaction. Returns the reason if its edits were refused and reverted.
+  pub(crate) fn finish(mut self) -> Option<Rejection> {
+    self.commit_or_revert()
+  }
+
+  fn commit_or_revert(&mut self) -> Option<Rejection> {
+    drop(self.txn.take()?);
+    let rejection = self.edit_guard.last_rejection()?;
+    tracing::warn!("{} refuse local edits: {}", self.object_id, rejection);
+    match self.undo_manager.as_mut() {
+      None => tracing::error!(
+        "{} can't revert refused edits, the permission changed during the transaction",
+        self.object_id
+      ),
+      Some(undo_manager) => self.edit_guard.revert(|| {
+        if let Err(e) = undo_manager.undo() {
+          tracing::error!("{} revert refused edits failed: {}", self.object_id, e);
+        }
+      }),
+    }
+    Some(rejection)
+  }
+}
+
+impl<'doc> Deref for GuardedTransactionMut<'doc> {
+  type Target = TransactionMut<'doc>;
+
+  fn deref(&self) -> &Self::Target {
+    self.txn.as_ref().unwrap()
+  }
+}
+
+impl<'doc> DerefMut for GuardedTransactionMut<'doc> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    self.txn.as_mut().unwrap()
+  }
+}
+
+impl<'doc> Drop for GuardedTransactionMut<'doc> {
+  fn drop(&mut self) {
+    self.commit_or_revert();
+  }
+}
+
+/// Returns the paths of the values that were changed by the events. The paths are relative to
+/// the type that the events were observed on.
+pub(crate) fn changed_paths(txn: &TransactionMut, events: &Events) -> Vec<Vec<String>> {
+  let mut paths = vec![];
+  for event in events.iter() {
+    let path = event
+      .path()
+      .into_iter()
+      .map(|segment| match segment {
+        PathSegment::Key(key) => key.to_string(),
+        PathSegment::Index(index) => index.to_string(),
+      })
+      .collect::<Vec<String>>();
+
+    match event {
+      Event::Map(event) => {
+        for key in event.keys(txn).keys() {
+          let mut path = path.clone();
+          path.push(key.to_string());
+          paths.push(path);
+        }
+      },
+      _ => paths.push(path),
+    }
+  }
+  paths
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{broadcast, oneshot};
+
+use crate::core::collab_plugin::{AsyncCollabPlugin, CollabPluginError, PluginHook};
+use crate::core::commit::TransactionMeta;
+use crate::core::origin::CollabOrigin;
+
+/// The events that are passed to the hooks of the [AsyncCollabPlugin]s.
+pub(crate) enum PluginEvent {
+  Init,
+  Update {
+    origin: CollabOrigin,
+    update: Vec<u8>,
+    /// The metadata of the transaction if the update comes from the local user.
+    local_meta: Option<Box<TransactionMeta>>,
+  },
+  AfterTransaction,
+  Reset,
+  Flush(Bytes),
+  WillClose,
+  /// The last event. The sender is notified after all the plugins were closed.
+  DidClose(oneshot::Sender<()>),
+}
+
+/// Runs the hooks of the [AsyncCollabPlugin]s of a collab object. The events are queued and
+/// processed one after another on a task that is spawned when the first plugin is added, so the
+/// plugins receive the events in the order they happened.
+#[derive(Clone)]
+pub(crate) struct PluginExecutor {
+  object_id: String,
+  plugins: Arc<RwLock<Vec<Arc<dyn AsyncCollabPlugin>>>>,
+  event_tx: Arc<Mutex<Option<UnboundedSender<PluginEvent>>>>,
+  error_tx: broadcast::Sender<CollabPluginError>,
+}
+
+impl PluginExecutor {
+  pub(crate) fn new(object_id: &str) -> Self {
+    let (error_tx, _) = broadcast::channel(100);
+    Self {
+      object_id: object_id.to_string(),
+      plugins: Default::default(),
+      event_tx: Default::default(),
+      error_tx,
+    }
+  }
+
+  /// Add the plugin and spawn the task that runs the hooks if it's not spawned yet. It must be
+  /// called in the context of a tokio runtime.
+  pub(crate) fn add_plugin(&self, plugin: Arc<dyn AsyncCollabPlugin>) {
+    self.plugins.write().push(plugin);
+    let mut event_tx = self.event_tx.lock();
+    if event_tx.is_none() {
+      let (tx, rx) = unbounded_channel();
+      tokio::spawn(run_plugins(
+        self.object_id.clone(),
+        self.plugins.clone(),
+        rx,
+        self.error_tx.clone(),
+      ));
+      *event_tx = Some(tx);
+    }
+  }
+
+  pub(crate) fn subscribe_error(&self) -> broadcast::Receiver<CollabPluginError> {
+    self.error_tx.subscribe()
+  }
+
+  /// Returns true if there is no plugin or the plugins were closed.
+  pub(crate) fn is_empty(&self) -> bool {
+    self.event_tx.lock().is_none()
+  }
+
+  /// Queue the event. It does nothing if there is no plugin.
+  pub(crate) fn send(&self, event: PluginEvent) {
+    if let Some(event_tx) = self.event_tx.lock().as_ref() {
+      if event_tx.send(event).is_err() {
+        tracing::warn!("{} plugins were closed", self.object_id);
+      }
+    }
+  }
+
+  /// Queue the close events. Returns a receiver that is notified after all the plugins were
+  /// closed, or [None] if there is no plugin.
+  pub(crate) fn close(&self) -> Option<oneshot::Receiver<()>> {
+    let event_tx = self.event_tx.lock().take()?;
+    let (tx, rx) = oneshot::channel();
+    let _ = event_tx.send(PluginEvent::WillClose);
+    let _ = event_tx.send(PluginEvent::DidClose(tx));
+    Some(rx)
+  }
+}
+
+async fn run_plugins(
+  object_id: String,
+  plugins: Arc<RwLock<Vec<Arc<dyn AsyncCollabPlugin>>>>,
+  mut event_rx: UnboundedReceiver<PluginEvent>,
+  error_tx: broadcast::Sender<CollabPluginError>,
+) {
+  while let Some(event) = event_rx.recv().await {
+    let plugins = plugins.read().clone();
+    for plugin in plugins {
+      let object_id = object_id.as_str();
+      let results = match &event {
+        PluginEvent::Init => vec![(PluginHook::Init, plugin.init(object_id).await)],
+        PluginEvent::Update {
+          origin,
+          update,
+          local_meta,
+        } => {
+          let mut results = vec![(
+            PluginHook::ReceiveUpdate,
+            plugin.receive_update(object_id, origin, update).await,
+          )];
+          if let Some(meta) = local_meta {
+            results.push((
+              PluginHook::ReceiveLocalUpdate,
+              plugin
+                .receive_local_update_with_meta(object_id, update, meta)
+                .await,
+            ));
+          }
+          results
+        },
+        PluginEvent::AfterTransaction => vec![(
+          PluginHook::AfterTransaction,
+          plugin.after_transaction(object_id).await,
+        )],
+        PluginEvent::Reset => vec![(PluginHook::Reset, plugin.reset(object_id).await)],
+        PluginEvent::Flush(update) => {
+          vec![(PluginHook::Flush, plugin.flush(object_id, update).await)]
+        },
+        PluginEvent::WillClose => vec![(PluginHook::WillClose, plugin.will_close(object_id).await)],
+        PluginEvent::DidClose(_) => vec![(PluginHook::DidClose, plugin.did_close(object_id).await)],
+      };
+
+      for (hook, result) in results {
+        if let Err(error) = result {
+          let error = CollabPluginError {
+            object_id: object_id.to_string(),
+            plugin: plugin.name().to_string(),
+            hook,
+            error: Arc::new(error),
+          };
+          tracing::error!("🔴{}", error);
+          // It fails if there is no subscriber, the error is logged anyway.
+          let _ = error_tx.send(error);
+        }
+      }
+    }
+
+    if let PluginEvent::DidClose(closed_tx) = event {
+      let _ = closed_tx.send(());
+      break;
+    }
+  }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use lib0::any::Any;
+use yrs::types::text::YChange;
+use yrs::types::{Attrs, Value as YrsValue};
+use yrs::{
+  Array, ArrayPrelim, ArrayRef, Map, MapPrelim, MapRef, ReadTxn, Text, TextPrelim, TextRef,
+  TransactionMut,
+};
+
+use crate::core::collab::{Collab, DATA_SECTION};
+
+/// Copies the data of the `source` [Collab] into the `target` [Collab], replacing each id of
+/// `ids` with its new id. It's used to clone the objects of a workspace under new ids.
+///
+/// An id is replaced when it's the key of a map, a string value or a string in the formatting
+/// attributes of a text. The content of the texts is copied as is. The `target` doesn't share
+/// the history of the `source`, so the two collabs can't be synced with each other.
+pub fn remap_collab(source: &Collab, target: &Collab, ids: &HashMap<String, String>) {
+  let source_txn = source.transact();
+  let source_data = match source_txn.get_map(DATA_SECTION) {
+    None => return,
+    Some(data) => data,
+  };
+  let target_data = target.get_doc().get_or_insert_map(DATA_SECTION);
+  let remapper = IdRemapper { ids };
+  target.with_origin_transact_mut(|txn| {
+    remapper.copy_map(&source_txn, &source_data, txn, &target_data);
+  });
+}
+
+struct IdRemapper<'a> {
+  ids: &'a HashMap<String, String>,
+}
+
+impl<'a> IdRemapper<'a> {
+  fn remap_str(&self, value: &str) -> String {
+    match self.ids.get(value) {
+      None => value.to_string(),
+      Some(new_id) => new_id.clone(),
+    }
+  }
+
+  fn remap_any(&self, value: Any) -> Any {
+    match value {
+      Any::String(s) => Any::String(self.remap_str(&s).into_boxed_str()),
+      Any::Array(values) => Any::Array(
+        values
+          .into_vec()
+          .into_iter()
+          .map(|value| self.remap_any(value))
+          .collect(),
+      ),
+      Any::Map(map) => Any::Map(Box::new(
+        map
+          .into_iter()
+          .map(|(key, value)| (self.remap_str(&key), self.remap_any(value)))
+          .collect(),
+      )),
+      value => value,
+    }
+  }
+
+  fn copy_map<T: ReadTxn>(
+    &self,
+    source_txn: &T,
+    source: &MapRef,
+    txn: &mut TransactionMut,
+    target: &MapRef,
+  ) {
+    for (key, value) in source.iter(source_txn) {
+      let key = self.remap_str(key);
+      match value {
+        YrsValue::YMap(map) => {
+          let new_map = target.insert(txn, key, MapPrelim::<Any>::new());
+          self.copy_map(source_txn, &map, txn, &new_map);
+        },
+        YrsValue::YArray(array) => {
+          let new_array = target.insert(txn, key, ArrayPrelim::<Vec<Any>, Any>::from(vec![]));
+          self.copy_array(source_txn, &array, txn, &new_array);
+        },
+        YrsValue::YText(text) => {
+          let new_text = target.insert(txn, key, TextPrelim::new(""));
+          self.copy_text(source_txn, &text, txn, &new_text);
+        },
+        YrsValue::Any(any) => {
+          target.insert(txn, key, self.remap_any(any));
+        },
+        value => tracing::warn!("🟡unsupported value when remapping collab: {:?}", value),
+      }
+    }
+  }
+
+  fn copy_array<T: ReadTxn>(
+    &self,
+    source_txn: &T,
+    source: &ArrayRef,
+    txn: &mut TransactionMut,
+    target: &ArrayRef,
+  ) {
+    for value in source.iter(source_txn) {
+      match value {
+        YrsValue::YMap(map) => {
+          let new_map = target.push_back(txn, MapPrelim::<Any>::new());
+          self.copy_map(source_txn, &map, txn, &new_map);
+        },
+        YrsValue::YArray(array) => {
+          let new_array = target.push_back(txn, ArrayPrelim::<Vec<Any>, Any>::from(vec![]));
+          self.copy_array(source_txn, &array, txn, &new_array);
+        },
+        YrsValue::YText(text) => {
+          let new_text = target.push_back(txn, TextPrelim::new(""));
+          self.copy_text(source_txn, &text, txn, &new_text);
+        },
+        YrsValue::Any(any) => {
+          target.push_back(txn, self.remap_any(any));
+        },
+        value => tracing::warn!("🟡unsupported value when remapping collab: {:?}", value),
+      }
+    }
+  }
+
+  fn copy_text<T: ReadTxn>(
+    &self,
+    source_txn: &T,
+    source: &TextRef,
+    txn: &mut TransactionMut,
+    target: &TextRef,
+  ) {
+    let mut index = 0;
+    for chunk in source.diff(source_txn, YChange::identity) {
+      // Inserting with empty attributes stops the chunk from inheriting the formatting of the
+      // previous chunk.
+      let attrs = chunk
+        .attributes
+        .map(|attrs| {
+          attrs
+            .into_iter()
+            .map(|(key, value)| (key, self.remap_any(value)))
+            .collect::<Attrs>()
+        })
+        .unwrap_or_default();
+      match chunk.insert {
+        YrsValue::Any(Any::String(s)) => {
+          target.insert_with_attributes(txn, index, &s, attrs);
+          index += s.encode_utf16().count() as u32;
+        },
+        YrsValue::Any(any) => {
+          target.insert_embed_with_attributes(txn, index, self.remap_any(any), attrs);
+          index += 1;
+        },
+        value => tracing::warn!(
+          "🟡unsupported text embed when remapping collab: {:?}",
+          value
+        ),
+      }
+    }
+  }
+}
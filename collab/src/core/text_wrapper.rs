@@ -1,3 +1,4 @@
+use crate::core::blame::TextBlame;
 use crate::preclude::{CollabContext, YrsDelta};
 use lib0::any::Any;
 use std::ops::{Deref, DerefMut};
@@ -43,6 +44,17 @@ impl TextRefWrapper {
     deltas
   }
 
+  /// Returns the authors of the ranges of the text, for example, to show the changes by author.
+  /// The ranges are ordered, so their texts add up to the whole text. The adjacent ranges of the
+  /// same author are merged, and the formatting of the text is ignored.
+  pub fn blame(&self) -> Vec<TextBlame> {
+    self.with_transact_mut(|txn| self.blame_with_txn(txn))
+  }
+
+  pub fn blame_with_txn(&self, txn: &mut TransactionMut) -> Vec<TextBlame> {
+    self.collab_ctx.authorship().blame_text(txn, &self.text_ref)
+  }
+
   pub fn apply_delta_with_txn(&self, txn: &mut TransactionMut, delta: Vec<Delta>) {
     let mut index = 0;
     for d in delta {
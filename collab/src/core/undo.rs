@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use yrs::types::{Branch, BranchPtr, Value};
+use yrs::undo::{EventKind, StackItem, UndoEventSubscription};
+use yrs::{Doc, Origin, UndoManager};
+
+use crate::core::collab::Path;
+use crate::core::origin::CollabOrigin;
+use crate::core::permission::EditGuard;
+use crate::error::CollabError;
+
+/// The name of the undo stack that is created by
+/// [Collab::enable_undo_redo](crate::core::collab::Collab::enable_undo_redo).
+pub const DEFAULT_UNDO_STACK: &str = "default";
+
+/// The options of an undo stack. Check out
+/// [Collab::add_undo_stack](crate::core::collab::Collab::add_undo_stack).
+#[derive(Clone, Debug)]
+pub struct UndoOptions {
+  capture_timeout_millis: u64,
+  tracked_origins: Vec<CollabOrigin>,
+  scope: Vec<Path>,
+}
+
+impl Default for UndoOptions {
+  fn default() -> Self {
+    Self {
+      capture_timeout_millis: 500,
+      tracked_origins: vec![],
+      scope: vec![],
+    }
+  }
+}
+
+impl UndoOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The edits that are made within the timeout are merged into one stack item. It's 500ms by
+  /// default. Set it to 0 to create a stack item for every transaction.
+  pub fn with_capture_timeout(mut self, millis: u64) -> Self {
+    self.capture_timeout_millis = millis;
+    self
+  }
+
+  /// Track the transactions of the given origin. Only the local transactions are tracked if no
+  /// origin is given.
+  pub fn with_tracked_origin(mut self, origin: CollabOrigin) -> Self {
+    self.tracked_origins.push(origin);
+    self
+  }
+
+  /// Only track the edits under the given path of the data section, for example, a block of a
+  /// document or a view of a database. It can be called multiple times to track several
+  /// sub-trees. The whole data section is tracked if no path is given.
+  ///
+  /// The value at the path must exist when the undo stack is added. If it's replaced later, the
+  /// edits of the new value are not tracked.
+  pub fn with_scope(mut self, path: impl Into<Path>) -> Self {
+    self.scope.push(path.into());
+    self
+  }
+
+  pub(crate) fn scope(&self) -> &[Path] {
+    &self.scope
+  }
+}
+
+/// The metadata of an item of an undo stack.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UndoItemMeta {
+  /// The action of the first transaction of the item, for example, "paste". Check out
+  /// [Collab::with_action_transact_mut](crate::core::collab::Collab::with_action_transact_mut).
+  pub action: Option<String>,
+  /// The cursor position that is encoded by the editor. It's passed back with the
+  /// [UndoStackEvent] when the item is undone or redone, so the editor can restore the cursor.
+  pub cursor: Option<String>,
+  pub attributes: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UndoStackEventKind {
+  /// A new item is pushed onto the undo stack.
+  Added,
+  /// The edits are merged into the last item of the undo stack, because they are made within
+  /// the capture timeout.
+  Updated,
+  /// The item is undone and moved to the redo stack.
+  Undone,
+  /// The item is redone and moved back to the undo stack.
+  Redone,
+  /// The undo stack and the redo stack are cleared.
+  Cleared,
+  /// The items of the undo or the redo stack are dropped, because there was nothing left to
+  /// revert, for example, the edits were deleted by a remote client.
+  Dropped,
+}
+
+/// Emitted when an undo stack is changed. Check out
+/// [Collab::subscribe_undo_stack](crate::core::collab::Collab::subscribe_undo_stack).
+#[derive(Clone, Debug)]
+pub struct UndoStackEvent {
+  /// The name of the undo stack.
+  pub stack: String,
+  pub kind: UndoStackEventKind,
+  /// The metadata of the item that is changed.
+  pub meta: UndoItemMeta,
+  pub undo_len: usize,
+  pub redo_len: usize,
+}
+
+/// Mirrors the stacks of the [UndoManager] to keep the metadata of their items.
+#[derive(Default)]
+struct UndoStacks {
+  undo: Vec<(StackItem, UndoItemMeta)>,
+  redo: Vec<(StackItem, UndoItemMeta)>,
+  /// Set when the undo or the redo pushed the item that reverts the popped item.
+  reverted: bool,
+}
+
+/// An [UndoManager] over a sub-tree of the data section. The metadata of the stack items are kept
+/// next to the stacks of the [UndoManager], because yrs' stack items can't carry them.
+pub(crate) struct CollabUndoManager {
+  name: String,
+  undo_manager: UndoManager,
+  stacks: Arc<Mutex<UndoStacks>>,
+  event_tx: broadcast::Sender<UndoStackEvent>,
+  #[allow(dead_code)]
+  subscriptions: Vec<UndoEventSubscription>,
+}
+
+impl CollabUndoManager {
+  pub(crate) fn new(
+    name: &str,
+    doc: &Doc,
+    scope: Vec<BranchPtr>,
+    options: &UndoOptions,
+    local_origin: &CollabOrigin,
+    edit_guard: Arc<EditGuard>,
+    event_tx: broadcast::Sender<UndoStackEvent>,
+  ) -> Self {
+    let yrs_options = yrs::undo::Options {
+      capture_timeout_millis: options.capture_timeout_millis,
+      ..Default::default()
+    };
+    let mut scope = scope.into_iter();
+    let mut undo_manager = UndoManager::with_options(doc, &scope.next().unwrap(), yrs_options);
+    for branch in scope {
+      undo_manager.expand_scope(&branch);
+    }
+    if options.tracked_origins.is_empty() {
+      undo_manager.include_origin(local_origin.clone());
+    }
+    for origin in &options.tracked_origins {
+      undo_manager.include_origin(origin.clone());
+    }
+
+    let stacks = Arc::new(Mutex::new(UndoStacks::default()));
+    let manager_origin = undo_manager.as_origin();
+    let subscriptions = vec![
+      undo_manager.observe_item_added({
+        let stacks = stacks.clone();
+        let edit_guard = edit_guard.clone();
+        let event_tx = event_tx.clone();
+        let name = name.to_string();
+        move |_, event| {
+          let mut stacks = stacks.lock();
+          let item = event.item.clone();
+          match event.kind {
+            // The item that reverts an undone item is pushed onto the redo stack. It takes the
+            // metadata of the undone item when the undo finishes.
+            EventKind::Undo => {
+              stacks.redo.push((item, UndoItemMeta::default()));
+              stacks.reverted = true;
+            },
+            EventKind::Redo if is_undo_or_redo(event.origin.as_ref(), &manager_origin) => {
+              stacks.undo.push((item, UndoItemMeta::default()));
+              stacks.reverted = true;
+            },
+            EventKind::Redo => {
+              // The new edits drop the redo stack.
+              stacks.redo.clear();
+              let meta = UndoItemMeta {
+                action: edit_guard.last_meta().action,
+                ..Default::default()
+              };
+              stacks.undo.push((item, meta.clone()));
+              send_event(&event_tx, &name, UndoStackEventKind::Added, meta, &stacks);
+            },
+          }
+        }
+      }),
+      undo_manager.observe_item_updated({
+        let stacks = stacks.clone();
+        let event_tx = event_tx.clone();
+        let name = name.to_string();
+        move |_, event| {
+          let mut stacks = stacks.lock();
+          stacks.redo.clear();
+          let action = edit_guard.last_meta().action;
+          if let Some((item, meta)) = stacks.undo.last_mut() {
+            *item = event.item.clone();
+            if meta.action.is_none() {
+              meta.action = action;
+            }
+            let meta = meta.clone();
+            send_event(&event_tx, &name, UndoStackEventKind::Updated, meta, &stacks);
+          }
+        }
+      }),
+      undo_manager.observe_item_popped({
+        let stacks = stacks.clone();
+        let event_tx = event_tx.clone();
+        let name = name.to_string();
+        move |_, event| {
+          let mut stacks = stacks.lock();
+          let reverted = std::mem::take(&mut stacks.reverted);
+          let UndoStacks { undo, redo, .. } = &mut *stacks;
+          let (from, to, kind) = match event.kind {
+            EventKind::Undo => (undo, redo, UndoStackEventKind::Undone),
+            EventKind::Redo => (redo, undo, UndoStackEventKind::Redone),
+          };
+          // The items above the popped item are dropped by the [UndoManager], because there was
+          // nothing left to revert.
+          let meta = match from.iter().rposition(|(item, _)| item == &event.item) {
+            None => UndoItemMeta::default(),
+            Some(index) => from.drain(index..).next().unwrap().1,
+          };
+          if reverted {
+            if let Some((_, reverted_meta)) = to.last_mut() {
+              *reverted_meta = meta.clone();
+            }
+          }
+          send_event(&event_tx, &name, kind, meta, &stacks);
+        }
+      }),
+    ];
+
+    Self {
+      name: name.to_string(),
+      undo_manager,
+      stacks,
+      event_tx,
+      subscriptions,
+    }
+  }
+
+  pub(crate) fn can_undo(&self) -> bool {
+    self.undo_manager.can_undo()
+  }
+
+  pub(crate) fn can_redo(&self) -> bool {
+    self.undo_manager.can_redo()
+  }
+
+  pub(crate) fn undo(&mut self) -> Result<bool, CollabError> {
+    let changed = self
+      .undo_manager
+      .undo()
+      .map_err(|e| CollabError::Internal(Box::new(e)))?;
+    if !changed {
+      self.drop_items(|stacks| &mut stacks.undo);
+    }
+    Ok(changed)
+  }
+
+  pub(crate) fn redo(&mut self) -> Result<bool, CollabError> {
+    let changed = self
+      .undo_manager
+      .redo()
+      .map_err(|e| CollabError::Internal(Box::new(e)))?;
+    if !changed {
+      self.drop_items(|stacks| &mut stacks.redo);
+    }
+    Ok(changed)
+  }
+
+  /// The [UndoManager] pops the items that have nothing left to revert without emitting an event,
+  /// and the undo or the redo returns false only when the whole stack is popped. The mirrored
+  /// stack is dropped to match it.
+  fn drop_items<F>(&self, stack: F)
+  where
+    F: FnOnce(&mut UndoStacks) -> &mut Vec<(StackItem, UndoItemMeta)>,
+  {
+    let mut stacks = self.stacks.lock();
+    let items = stack(&mut stacks);
+    if items.is_empty() {
+      return;
+    }
+    items.clear();
+    send_event(
+      &self.event_tx,
+      &self.name,
+      UndoStackEventKind::Dropped,
+      UndoItemMeta::default(),
+      &stacks,
+    );
+  }
+
+  pub(crate) fn clear(&mut self) -> Result<(), CollabError> {
+    self
+      .undo_manager
+      .clear()
+      .map_err(|e| CollabError::Internal(Box::new(e)))?;
+    let mut stacks = self.stacks.lock();
+    stacks.undo.clear();
+    stacks.redo.clear();
+    send_event(
+      &self.event_tx,
+      &self.name,
+      UndoStackEventKind::Cleared,
+      UndoItemMeta::default(),
+      &stacks,
+    );
+    Ok(())
+  }
+
+  /// Stop merging the next edits into the last item of the undo stack.
+  pub(crate) fn reset(&mut self) {
+    self.undo_manager.reset();
+  }
+
+  pub(crate) fn undo_meta(&self) -> Option<UndoItemMeta> {
+    self.stacks.lock().undo.last().map(|(_, meta)| meta.clone())
+  }
+
+  pub(crate) fn redo_meta(&self) -> Option<UndoItemMeta> {
+    self.stacks.lock().redo.last().map(|(_, meta)| meta.clone())
+  }
+
+  /// Update the metadata of the last item of the undo stack. Returns false if the undo stack is
+  /// empty.
+  pub(crate) fn update_undo_meta<F: FnOnce(&mut UndoItemMeta)>(&self, f: F) -> bool {
+    match self.stacks.lock().undo.last_mut() {
+      None => false,
+      Some((_, meta)) => {
+        f(meta);
+        true
+      },
+    }
+  }
+}
+
+/// Returns true if the transaction is made by the [UndoManager] itself, that is, an undo or a
+/// redo.
+fn is_undo_or_redo(origin: Option<&Origin>, manager_origin: &Origin) -> bool {
+  origin == Some(manager_origin)
+}
+
+fn send_event(
+  event_tx: &broadcast::Sender<UndoStackEvent>,
+  name: &str,
+  kind: UndoStackEventKind,
+  meta: UndoItemMeta,
+  stacks: &UndoStacks,
+) {
+  let _ = event_tx.send(UndoStackEvent {
+    stack: name.to_string(),
+    kind,
+    meta,
+    undo_len: stacks.undo.len(),
+    redo_len: stacks.redo.len(),
+  });
+}
+
+/// Returns the branch of the shared type, so it can be tracked by the [UndoManager].
+pub(crate) fn branch_of(value: &Value) -> Option<BranchPtr> {
+  let branch: &Branch = match value {
+    Value::YText(text) => text.as_ref(),
+    Value::YArray(array) => array.as_ref(),
+    Value::YMap(map) => map.as_ref(),
+    Value::YXmlElement(element) => element.as_ref(),
+    Value::YXmlFragment(fragment) => fragment.as_ref(),
+    Value::YXmlText(text) => text.as_ref(),
+    Value::Any(_) | Value::YDoc(_) => return None,
+  };
+  Some(BranchPtr::from(branch))
+}
@@ -0,0 +1,164 @@
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lib0::encoding::Write;
+use serde::{Deserialize, Serialize};
+use yrs::block::BLOCK_GC_REF_NUMBER;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{DeleteSet, Doc, Options, ReadTxn, Snapshot, StateVector, Transact, UndoManager, Update};
+
+use crate::core::collab::{TransactionMutExt, DATA_SECTION};
+use crate::error::CollabError;
+
+/// The origin of the transactions that are used to compute the edits of restoring a version.
+const RESTORE_VERSION_ORIGIN: &str = "restore_version";
+
+/// A point-in-time version of a [Collab](crate::core::collab::Collab). It only records the
+/// [Snapshot] of the document, which is the state vector and the delete set at the time the
+/// version was created, instead of a copy of the document. The content of the version is
+/// recovered from the document itself, which keeps the deleted content because it's created
+/// with the `skip_gc` option.
+///
+/// Use [Collab::create_version](crate::core::collab::Collab::create_version) to record a version.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollabVersion {
+  #[serde(with = "snapshot_serde")]
+  pub snapshot: Snapshot,
+  /// The uid of the user who created the version.
+  pub author: i64,
+  /// An optional name of the version, for example, "Before the review".
+  pub label: Option<String>,
+  /// The timestamp in seconds when the version was created.
+  pub created_at: i64,
+}
+
+impl CollabVersion {
+  pub fn new(snapshot: Snapshot, author: i64, label: Option<String>) -> Self {
+    let created_at = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|duration| duration.as_secs() as i64)
+      .unwrap_or_default();
+    Self {
+      snapshot,
+      author,
+      label,
+      created_at,
+    }
+  }
+}
+
+/// Encode the state of the document as it was at the time of the [Snapshot]. The `options` are
+/// the options of the document. Returns an error if the document was garbage collected or the
+/// snapshot doesn't belong to the document.
+pub(crate) fn encode_state_from_snapshot<T: ReadTxn>(
+  txn: &T,
+  options: &Options,
+  snapshot: &Snapshot,
+) -> Result<Vec<u8>, CollabError> {
+  if !options.skip_gc {
+    return Err(CollabError::YrsTransactionError(
+      "can't encode the past state of a garbage collected document".to_string(),
+    ));
+  }
+
+  // yrs caps the clock of each client at the clock of the client's last item, which leaves out
+  // the last item when the snapshot covers all the items of the client. The state is encoded
+  // from a copy of the document that has a placeholder after the last item of each client, so
+  // the cap never cuts the items of the snapshot. The placeholders are never encoded.
+  let doc = Doc::with_options(Options {
+    skip_gc: true,
+    ..Options::default()
+  });
+  {
+    let mut copy = doc.transact_mut();
+    let state = txn.encode_state_as_update_v1(&StateVector::default());
+    copy.try_apply_update(Update::decode_v1(&state)?)?;
+    let placeholders = encode_placeholders(&txn.state_vector());
+    copy.try_apply_update(Update::decode_v1(&placeholders)?)?;
+  }
+
+  let mut encoder = EncoderV1::new();
+  match panic::catch_unwind(AssertUnwindSafe(|| {
+    doc
+      .transact()
+      .encode_state_from_snapshot(snapshot, &mut encoder)
+  })) {
+    Ok(Ok(_)) => Ok(encoder.to_vec()),
+    Ok(Err(e)) => Err(CollabError::Internal(Box::new(e))),
+    Err(e) => Err(CollabError::YrsTransactionError(format!("{:?}", e))),
+  }
+}
+
+/// Returns the update that adds a garbage collected item of length 1 after the last item of each
+/// client of the state vector.
+fn encode_placeholders(state_vector: &StateVector) -> Vec<u8> {
+  let mut encoder = EncoderV1::new();
+  encoder.write_var(state_vector.len());
+  for (client, clock) in state_vector.iter() {
+    encoder.write_var(1_u32);
+    encoder.write_client(*client);
+    encoder.write_var(*clock);
+    encoder.write_info(BLOCK_GC_REF_NUMBER);
+    encoder.write_len(1);
+  }
+  DeleteSet::new().encode(&mut encoder);
+  encoder.to_vec()
+}
+
+/// Returns the update that turns the document of the given `txn` back into the state of the
+/// [Snapshot]. The update only adds new operations: the content inserted after the snapshot is
+/// deleted, and the content deleted after the snapshot is inserted again.
+///
+/// It replays the changes made after the snapshot on a document that is restored from the
+/// snapshot, and then undoes them. The document uses the client id of the `options`, so the
+/// operations of the update continue the clock of the local client. The update must be applied
+/// within the same transaction, otherwise a concurrent local edit could take the same clock.
+pub(crate) fn encode_restore_update<T: ReadTxn>(
+  txn: &T,
+  options: &Options,
+  snapshot: &Snapshot,
+) -> Result<Vec<u8>, CollabError> {
+  let past_state = encode_state_from_snapshot(txn, options, snapshot)?;
+  let doc = Doc::with_options(Options {
+    client_id: options.client_id,
+    skip_gc: true,
+    ..Options::default()
+  });
+  let data = doc.get_or_insert_map(DATA_SECTION);
+  doc
+    .transact_mut()
+    .try_apply_update(Update::decode_v1(&past_state)?)?;
+
+  let mut undo_manager = UndoManager::new(&doc, &data);
+  undo_manager.include_origin(RESTORE_VERSION_ORIGIN);
+  let past_state_vector = doc.transact().state_vector();
+  doc
+    .transact_mut_with(RESTORE_VERSION_ORIGIN)
+    .try_apply_update(Update::decode_v1(&txn.encode_diff_v1(&past_state_vector))?)?;
+  undo_manager
+    .undo()
+    .map_err(|e| CollabError::Internal(Box::new(e)))?;
+
+  let update = doc.transact().encode_diff_v1(&txn.state_vector());
+  Ok(update)
+}
+
+/// Serialize the [Snapshot] as the bytes that are encoded with the lib0 v1 encoding.
+mod snapshot_serde {
+  use serde::de::Error;
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+  use yrs::updates::decoder::Decode;
+  use yrs::updates::encoder::Encode;
+  use yrs::Snapshot;
+
+  pub fn serialize<S: Serializer>(snapshot: &Snapshot, serializer: S) -> Result<S::Ok, S::Error> {
+    snapshot.encode_v1().serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Snapshot, D::Error> {
+    let bytes = Vec::<u8>::deserialize(deserializer)?;
+    Snapshot::decode_v1(&bytes).map_err(D::Error::custom)
+  }
+}
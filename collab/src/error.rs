@@ -12,12 +12,30 @@ pub enum CollabError {
   #[error("Try apply update failed: {0}")]
   YrsTransactionError(String),
 
+  #[error("Permission denied: {0}")]
+  PermissionDenied(String),
+
+  #[error("Transaction rejected: {0}")]
+  TransactionRejected(String),
+
   #[error("UndoManager is not enabled")]
   UndoManagerNotEnabled,
 
+  #[error("The scope of the undo stack is not found: {0}")]
+  UndoScopeNotFound(String),
+
   #[error(transparent)]
   DecodeUpdate(#[from] lib0::error::Error),
 
+  #[error("The async plugins must be added before the collab is initialized")]
+  AsyncPluginAfterInit,
+
+  #[error("Invalid bundle: {0}")]
+  InvalidBundle(String),
+
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+
   #[error("Internal failure: {0}")]
   Internal(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
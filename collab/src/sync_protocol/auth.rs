@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use yrs::updates::encoder::Encode;
+use yrs::Update;
+
+use crate::core::encoding::EncoderVersion;
+use crate::core::origin::CollabOrigin;
+use crate::sync_protocol::awareness::{Awareness, AwarenessUpdate};
+use crate::sync_protocol::message::{Error, Message};
+use crate::sync_protocol::protocol::encode_server_sync_step1;
+use crate::sync_protocol::{CollabSyncProtocol, ServerSyncProtocol};
+
+/// The permission of a user to a collab object.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum CollabPermission {
+  /// The user can only receive the updates of the object.
+  Read,
+  /// The user can receive the updates and share its awareness state, for example the cursor
+  /// position, but it can't edit the object.
+  Comment,
+  /// The user can edit the object.
+  Edit,
+}
+
+impl CollabPermission {
+  pub fn can_edit(&self) -> bool {
+    matches!(self, CollabPermission::Edit)
+  }
+
+  pub fn can_update_awareness(&self) -> bool {
+    matches!(self, CollabPermission::Comment | CollabPermission::Edit)
+  }
+}
+
+/// The [CollabAuthenticator] is used by the server to authenticate the clients during the
+/// handshake. The client presents its token with a
+/// [Message::AuthRequest](crate::sync_protocol::message::Message::AuthRequest) and the server
+/// replies with a [Message::Auth](crate::sync_protocol::message::Message::Auth).
+#[async_trait]
+pub trait CollabAuthenticator: Send + Sync + 'static {
+  /// Resolve the token to the uid of the user. Returns an error if the token is invalid.
+  async fn authenticate(&self, token: &str) -> Result<i64, anyhow::Error>;
+
+  /// Returns the permission of the user to the object. Returns [None] if the user can't access
+  /// the object.
+  async fn permission(&self, uid: i64, object_id: &str) -> Option<CollabPermission>;
+}
+
+/// A [ServerSyncProtocol] that only applies the updates that are allowed by the
+/// [CollabPermission] of the client. The denied updates return [Error::PermissionDenied]
+/// instead of being applied.
+#[derive(Clone)]
+pub struct AuthorizedServerSyncProtocol {
+  permission: CollabPermission,
+  encoder_version: EncoderVersion,
+}
+
+impl AuthorizedServerSyncProtocol {
+  pub fn new(permission: CollabPermission) -> Self {
+    Self {
+      permission,
+      encoder_version: EncoderVersion::V1,
+    }
+  }
+
+  /// Encode the updates sent to the client with the [EncoderVersion] agreed with it.
+  pub fn with_encoder_version(mut self, encoder_version: EncoderVersion) -> Self {
+    self.encoder_version = encoder_version;
+    self
+  }
+}
+
+impl CollabSyncProtocol for AuthorizedServerSyncProtocol {
+  fn encoder_version(&self) -> EncoderVersion {
+    self.encoder_version
+  }
+
+  fn handle_sync_step1(
+    &self,
+    awareness: &Awareness,
+    sv: yrs::StateVector,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    Ok(Some(encode_server_sync_step1(
+      awareness,
+      sv,
+      self.encoder_version,
+    )))
+  }
+
+  fn handle_sync_step2(
+    &self,
+    origin: &Option<&CollabOrigin>,
+    awareness: &mut Awareness,
+    update: Update,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    // The client always replies the sync step 1 with a sync step 2, which is empty if the client
+    // has no changes.
+    if !self.permission.can_edit() && !is_empty_update(&update) {
+      return Err(Error::PermissionDenied {
+        reason: format!("{:?} permission can't edit the object", self.permission),
+      });
+    }
+    ServerSyncProtocol.handle_sync_step2(origin, awareness, update)
+  }
+
+  fn handle_awareness_update(
+    &self,
+    awareness: &mut Awareness,
+    update: AwarenessUpdate,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    if !self.permission.can_update_awareness() {
+      return Err(Error::PermissionDenied {
+        reason: format!(
+          "{:?} permission can't update the awareness",
+          self.permission
+        ),
+      });
+    }
+    ServerSyncProtocol.handle_awareness_update(awareness, update)
+  }
+
+  /// Only agree on the version that the updates sent to the client are encoded with. Check out
+  /// [AuthorizedServerSyncProtocol::with_encoder_version].
+  fn handle_encoding(
+    &self,
+    _awareness: &Awareness,
+    version: EncoderVersion,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    let version = version.min(self.encoder_version);
+    Ok(Some(Message::Encoding(version).encode_v1()))
+  }
+}
+
+fn is_empty_update(update: &Update) -> bool {
+  update.encode_v1() == Update::new().encode_v1()
+}
@@ -2,7 +2,7 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::Formatter;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use thiserror::Error;
 use yrs::block::ClientID;
@@ -135,6 +135,48 @@ impl Awareness {
     self.remove_state(client_id);
   }
 
+  /// Returns the time when the state of the given client was updated last time.
+  pub fn last_updated(&self, client_id: ClientID) -> Option<Instant> {
+    self.meta.get(&client_id).map(|meta| meta.last_updated)
+  }
+
+  /// Removes the states of the remote clients that were not updated within the `timeout`,
+  /// effectively marking them as disconnected. The state of the current client is never removed.
+  /// Returns the removed clients.
+  ///
+  /// Unlike [Awareness::remove_state], the clocks of the removed clients are kept, so the later
+  /// updates of these clients will be applied as usual.
+  pub fn remove_outdated_states(&mut self, timeout: Duration) -> Vec<ClientID> {
+    let local_client_id = self.doc.client_id();
+    let removed = self
+      .states
+      .keys()
+      .filter(|client_id| **client_id != local_client_id)
+      .filter(|client_id| {
+        self
+          .meta
+          .get(client_id)
+          .map(|meta| meta.last_updated.elapsed() >= timeout)
+          .unwrap_or(true)
+      })
+      .cloned()
+      .collect::<Vec<_>>();
+
+    for client_id in removed.iter() {
+      self.states.remove(client_id);
+    }
+
+    if let Some(eh) = self.on_update.as_ref() {
+      if !removed.is_empty() {
+        let e = Event::new(vec![], vec![], removed.clone());
+        for cb in eh.callbacks() {
+          cb(self, &e);
+        }
+      }
+    }
+    removed
+  }
+
   fn update_meta(&mut self, client_id: ClientID) {
     match self.meta.entry(client_id) {
       Entry::Occupied(mut e) => {
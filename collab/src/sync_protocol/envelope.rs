@@ -0,0 +1,378 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use lib0::decoding::Read;
+use lib0::encoding::Write;
+use yrs::updates::decoder::{Decode, Decoder, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+use crate::core::origin::CollabOrigin;
+use crate::sync_protocol::message::{Message, MSG_ACK, MSG_ENVELOPE, MSG_SUBSCRIPTION};
+
+/// The version of the [CollabEnvelope] and [CollabAck] layout written by this crate. The newer
+/// versions are only allowed to append fields, so the older peers can still read the fields
+/// that they know about.
+pub const ENVELOPE_VERSION: u8 = 1;
+
+pub const ACK_STATUS_OK: u8 = 0;
+pub const ACK_STATUS_REJECTED: u8 = 1;
+
+pub const SUBSCRIPTION_SUBSCRIBE: u8 = 0;
+pub const SUBSCRIPTION_UNSUBSCRIBE: u8 = 1;
+
+pub type MsgId = u64;
+
+/// The [CollabEnvelope] wraps a [Message] with the id of the object that the message belongs to,
+/// the origin of the sender, and a message id that is used to acknowledge the message.
+///
+/// The envelope is encoded as a [MSG_ENVELOPE] tag followed by a length-prefixed buffer, so a peer
+/// that doesn't know about the envelope will decode it as a [Message::Custom] and the rest of the
+/// messages in the same frame can still be read.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CollabEnvelope {
+  pub version: u8,
+  pub object_id: String,
+  pub origin: CollabOrigin,
+  pub msg_id: MsgId,
+  pub message: Message,
+}
+
+impl CollabEnvelope {
+  pub fn new(object_id: &str, origin: CollabOrigin, msg_id: MsgId, message: Message) -> Self {
+    Self {
+      version: ENVELOPE_VERSION,
+      object_id: object_id.to_string(),
+      origin,
+      msg_id,
+      message,
+    }
+  }
+
+  /// Returns the [CollabAck] that acknowledges this envelope.
+  pub fn ack(&self) -> CollabAck {
+    CollabAck::new(&self.object_id, self.msg_id, AckStatus::Ok)
+  }
+
+  /// Returns the [CollabAck] that rejects this envelope with the given reason.
+  pub fn nack(&self, reason: impl ToString) -> CollabAck {
+    CollabAck::new(
+      &self.object_id,
+      self.msg_id,
+      AckStatus::Rejected(reason.to_string()),
+    )
+  }
+
+  fn decode_body(data: &[u8]) -> Result<Self, lib0::error::Error> {
+    let mut decoder = DecoderV1::from(data);
+    let version: u8 = decoder.read_var()?;
+    let object_id = decoder.read_string()?.to_string();
+    let origin = serde_json::from_slice::<CollabOrigin>(decoder.read_buf()?)
+      .map_err(|_| lib0::error::Error::UnexpectedValue)?;
+    let msg_id: MsgId = decoder.read_var()?;
+    let message = Message::decode_v1(decoder.read_buf()?)?;
+    Ok(Self {
+      version,
+      object_id,
+      origin,
+      msg_id,
+      message,
+    })
+  }
+}
+
+impl Encode for CollabEnvelope {
+  fn encode<E: Encoder>(&self, encoder: &mut E) {
+    let mut body = EncoderV1::new();
+    body.write_var(self.version);
+    body.write_string(&self.object_id);
+    body.write_buf(serde_json::to_vec(&self.origin).unwrap_or_default());
+    body.write_var(self.msg_id);
+    body.write_buf(self.message.encode_v1());
+
+    encoder.write_var(MSG_ENVELOPE);
+    encoder.write_buf(body.to_vec());
+  }
+}
+
+impl Display for CollabEnvelope {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Envelope({}|{}|msg_id:{}|{})",
+      self.object_id, self.origin, self.msg_id, self.message
+    )
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AckStatus {
+  /// The message was received and handled.
+  Ok,
+  /// The message was received but failed to be handled. The sender should not resend it.
+  Rejected(String),
+}
+
+/// The [CollabAck] is sent back to the sender of a [CollabEnvelope] after the envelope was
+/// handled. A message that is not acknowledged within a reasonable time can be considered as lost.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CollabAck {
+  pub version: u8,
+  pub object_id: String,
+  pub msg_id: MsgId,
+  pub status: AckStatus,
+}
+
+impl CollabAck {
+  pub fn new(object_id: &str, msg_id: MsgId, status: AckStatus) -> Self {
+    Self {
+      version: ENVELOPE_VERSION,
+      object_id: object_id.to_string(),
+      msg_id,
+      status,
+    }
+  }
+
+  pub fn is_ok(&self) -> bool {
+    matches!(self.status, AckStatus::Ok)
+  }
+
+  fn decode_body(data: &[u8]) -> Result<Self, lib0::error::Error> {
+    let mut decoder = DecoderV1::from(data);
+    let version: u8 = decoder.read_var()?;
+    let object_id = decoder.read_string()?.to_string();
+    let msg_id: MsgId = decoder.read_var()?;
+    let status = match decoder.read_var::<u8>()? {
+      ACK_STATUS_OK => AckStatus::Ok,
+      ACK_STATUS_REJECTED => AckStatus::Rejected(decoder.read_string()?.to_string()),
+      _ => return Err(lib0::error::Error::UnexpectedValue),
+    };
+    Ok(Self {
+      version,
+      object_id,
+      msg_id,
+      status,
+    })
+  }
+}
+
+impl Encode for CollabAck {
+  fn encode<E: Encoder>(&self, encoder: &mut E) {
+    let mut body = EncoderV1::new();
+    body.write_var(self.version);
+    body.write_string(&self.object_id);
+    body.write_var(self.msg_id);
+    match &self.status {
+      AckStatus::Ok => body.write_var(ACK_STATUS_OK),
+      AckStatus::Rejected(reason) => {
+        body.write_var(ACK_STATUS_REJECTED);
+        body.write_string(reason);
+      },
+    }
+
+    encoder.write_var(MSG_ACK);
+    encoder.write_buf(body.to_vec());
+  }
+}
+
+impl Display for CollabAck {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match &self.status {
+      AckStatus::Ok => write!(f, "Ack({}|msg_id:{})", self.object_id, self.msg_id),
+      AckStatus::Rejected(reason) => write!(
+        f,
+        "Nack({}|msg_id:{}|{})",
+        self.object_id, self.msg_id, reason
+      ),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SubscriptionAction {
+  /// Start receiving the messages of the object.
+  Subscribe,
+  /// Stop receiving the messages of the object.
+  Unsubscribe,
+}
+
+/// The [CollabSubscription] is sent when a collab object is opened or closed on a connection
+/// that is shared by multiple objects. The [CollabEnvelope]s of an object are only handled after
+/// the object was subscribed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CollabSubscription {
+  pub version: u8,
+  pub object_id: String,
+  pub action: SubscriptionAction,
+}
+
+impl CollabSubscription {
+  pub fn subscribe(object_id: &str) -> Self {
+    Self::new(object_id, SubscriptionAction::Subscribe)
+  }
+
+  pub fn unsubscribe(object_id: &str) -> Self {
+    Self::new(object_id, SubscriptionAction::Unsubscribe)
+  }
+
+  fn new(object_id: &str, action: SubscriptionAction) -> Self {
+    Self {
+      version: ENVELOPE_VERSION,
+      object_id: object_id.to_string(),
+      action,
+    }
+  }
+
+  fn decode_body(data: &[u8]) -> Result<Self, lib0::error::Error> {
+    let mut decoder = DecoderV1::from(data);
+    let version: u8 = decoder.read_var()?;
+    let object_id = decoder.read_string()?.to_string();
+    let action = match decoder.read_var::<u8>()? {
+      SUBSCRIPTION_SUBSCRIBE => SubscriptionAction::Subscribe,
+      SUBSCRIPTION_UNSUBSCRIBE => SubscriptionAction::Unsubscribe,
+      _ => return Err(lib0::error::Error::UnexpectedValue),
+    };
+    Ok(Self {
+      version,
+      object_id,
+      action,
+    })
+  }
+}
+
+impl Encode for CollabSubscription {
+  fn encode<E: Encoder>(&self, encoder: &mut E) {
+    let mut body = EncoderV1::new();
+    body.write_var(self.version);
+    body.write_string(&self.object_id);
+    match self.action {
+      SubscriptionAction::Subscribe => body.write_var(SUBSCRIPTION_SUBSCRIBE),
+      SubscriptionAction::Unsubscribe => body.write_var(SUBSCRIPTION_UNSUBSCRIBE),
+    }
+
+    encoder.write_var(MSG_SUBSCRIPTION);
+    encoder.write_buf(body.to_vec());
+  }
+}
+
+impl Display for CollabSubscription {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:?}({})", self.action, self.object_id)
+  }
+}
+
+/// A [CollabFrame] is one of the values that can be read from a connection. The [Message]s that
+/// are not wrapped in an envelope are still supported, so the peers that don't use the envelope
+/// can interoperate.
+#[derive(Debug, Eq, PartialEq)]
+pub enum CollabFrame {
+  Message(Message),
+  Envelope(CollabEnvelope),
+  Ack(CollabAck),
+  Subscription(CollabSubscription),
+}
+
+impl Encode for CollabFrame {
+  fn encode<E: Encoder>(&self, encoder: &mut E) {
+    match self {
+      CollabFrame::Message(msg) => msg.encode(encoder),
+      CollabFrame::Envelope(envelope) => envelope.encode(encoder),
+      CollabFrame::Ack(ack) => ack.encode(encoder),
+      CollabFrame::Subscription(subscription) => subscription.encode(encoder),
+    }
+  }
+}
+
+impl Decode for CollabFrame {
+  fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, lib0::error::Error> {
+    let tag: u8 = decoder.read_var()?;
+    match tag {
+      MSG_ENVELOPE => Ok(CollabFrame::Envelope(CollabEnvelope::decode_body(
+        decoder.read_buf()?,
+      )?)),
+      MSG_ACK => Ok(CollabFrame::Ack(CollabAck::decode_body(
+        decoder.read_buf()?,
+      )?)),
+      MSG_SUBSCRIPTION => Ok(CollabFrame::Subscription(CollabSubscription::decode_body(
+        decoder.read_buf()?,
+      )?)),
+      tag => Ok(CollabFrame::Message(Message::decode_with_tag(
+        tag, decoder,
+      )?)),
+    }
+  }
+}
+
+impl Display for CollabFrame {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      CollabFrame::Message(msg) => Display::fmt(msg, f),
+      CollabFrame::Envelope(envelope) => Display::fmt(envelope, f),
+      CollabFrame::Ack(ack) => Display::fmt(ack, f),
+      CollabFrame::Subscription(subscription) => Display::fmt(subscription, f),
+    }
+  }
+}
+
+impl From<Message> for CollabFrame {
+  fn from(msg: Message) -> Self {
+    CollabFrame::Message(msg)
+  }
+}
+
+impl From<CollabEnvelope> for CollabFrame {
+  fn from(envelope: CollabEnvelope) -> Self {
+    CollabFrame::Envelope(envelope)
+  }
+}
+
+impl From<CollabAck> for CollabFrame {
+  fn from(ack: CollabAck) -> Self {
+    CollabFrame::Ack(ack)
+  }
+}
+
+impl From<CollabSubscription> for CollabFrame {
+  fn from(subscription: CollabSubscription) -> Self {
+    CollabFrame::Subscription(subscription)
+  }
+}
+
+/// [CollabFrameReader] reads the [CollabFrame]s from the decoder one by one.
+pub struct CollabFrameReader<'a, D: Decoder>(&'a mut D);
+
+impl<'a, D: Decoder> CollabFrameReader<'a, D> {
+  pub fn new(decoder: &'a mut D) -> Self {
+    CollabFrameReader(decoder)
+  }
+}
+
+impl<'a, D: Decoder> Iterator for CollabFrameReader<'a, D> {
+  type Item = Result<CollabFrame, lib0::error::Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    match CollabFrame::decode(self.0) {
+      Ok(frame) => Some(Ok(frame)),
+      Err(lib0::error::Error::EndOfBuffer(_)) => None,
+      Err(error) => Some(Err(error)),
+    }
+  }
+}
+
+/// Generates monotonically increasing [MsgId]s. The first id is 1.
+#[derive(Debug, Default)]
+pub struct MsgIdGenerator(AtomicU64);
+
+impl MsgIdGenerator {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Start the ids after the given id. It's used to continue the ids of a previous connection.
+  pub fn with_start(last_msg_id: MsgId) -> Self {
+    Self(AtomicU64::new(last_msg_id))
+  }
+
+  pub fn next_id(&self) -> MsgId {
+    self.0.fetch_add(1, Ordering::SeqCst) + 1
+  }
+}
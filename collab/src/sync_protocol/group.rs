@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::{Mutex, RwLock};
+use tokio::spawn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, watch};
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{StateVector, TransactionMut};
+
+use crate::core::collab::{Collab, CollabRawData, MutexCollab};
+use crate::core::collab_plugin::CollabPlugin;
+use crate::core::encoding::{convert_update, EncoderVersion};
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+use crate::sync_protocol::auth::{
+  AuthorizedServerSyncProtocol, CollabAuthenticator, CollabPermission,
+};
+use crate::sync_protocol::message::{Error, Message, MessageReader, SyncMessage};
+use crate::sync_protocol::{handle_msg, CollabSyncProtocol, ServerSyncProtocol};
+
+/// The capacity of the broadcast channel of a [CollabGroup]. A subscriber that falls behind more
+/// than this number of messages will receive the full document state instead.
+const BROADCAST_CAPACITY: usize = 1000;
+
+/// A [CollabGroup] keeps the authoritative [MutexCollab] of one object and the clients that are
+/// subscribed to it. Every update applied by a subscriber is broadcast to the other subscribers.
+pub struct CollabGroup {
+  object_id: String,
+  collab: MutexCollab,
+  broadcast: broadcast::Sender<CollabBroadcastMessage>,
+  subscribers: RwLock<HashMap<CollabOrigin, Subscription>>,
+  subscription_id_counter: AtomicU64,
+  modified_at: Arc<Mutex<Instant>>,
+  authenticator: Option<Arc<dyn CollabAuthenticator>>,
+}
+
+impl CollabGroup {
+  /// Create a new group for the given object. The [CollabRawData] is used to restore the state
+  /// of the authoritative collab. The raw data will not be broadcast to the subscribers.
+  pub async fn new(object_id: &str, collab_raw_data: CollabRawData) -> Result<Self, CollabError> {
+    let (broadcast, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let modified_at = Arc::new(Mutex::new(Instant::now()));
+    let plugin = CollabBroadcastPlugin {
+      sender: broadcast.clone(),
+      modified_at: modified_at.clone(),
+    };
+    let collab = Collab::new_with_raw_data(
+      CollabOrigin::Server,
+      object_id,
+      collab_raw_data,
+      vec![Arc::new(plugin)],
+    )?;
+
+    #[cfg(not(feature = "async-plugin"))]
+    collab.initialize();
+
+    #[cfg(feature = "async-plugin")]
+    collab.initialize().await;
+
+    Ok(Self {
+      object_id: object_id.to_string(),
+      collab: MutexCollab::from_collab(collab),
+      broadcast,
+      subscribers: Default::default(),
+      subscription_id_counter: AtomicU64::new(0),
+      modified_at,
+      authenticator: None,
+    })
+  }
+
+  /// Require the subscribers to present a token before they can sync with the group. The
+  /// messages of a subscriber are handled according to its [CollabPermission].
+  pub fn with_authenticator(mut self, authenticator: Arc<dyn CollabAuthenticator>) -> Self {
+    self.authenticator = Some(authenticator);
+    self
+  }
+
+  pub fn object_id(&self) -> &str {
+    &self.object_id
+  }
+
+  /// Returns the authoritative collab of the group. Any changes made to it will be broadcast to
+  /// all the subscribers.
+  pub fn collab(&self) -> &MutexCollab {
+    &self.collab
+  }
+
+  /// Subscribe a client to the group. The [Sink] is used to send messages to the client and the
+  /// [Stream] is used to receive messages from it. If the client was already subscribed, the
+  /// previous connection will be closed.
+  ///
+  /// The group sends its Sync Step1 to the client right after subscribing, so the client will
+  /// reply with the updates that the group is missing. If the group has a [CollabAuthenticator],
+  /// the Sync Step1 is sent after the client is authenticated, and the client doesn't receive
+  /// any updates before that.
+  pub fn subscribe<Sink, Stream, E>(
+    self: &Arc<Self>,
+    origin: CollabOrigin,
+    sink: Sink,
+    stream: Stream,
+  ) where
+    Sink: futures_util::Sink<Vec<u8>> + Send + Unpin + 'static,
+    <Sink as futures_util::Sink<Vec<u8>>>::Error: Display,
+    Stream: futures_util::Stream<Item = Result<Vec<u8>, E>> + Send + Unpin + 'static,
+    E: Display + Send + 'static,
+  {
+    let stream = stream.map(|data| data.map(InboundData::from));
+    self.subscribe_inbound(origin, sink, stream);
+  }
+
+  /// Same as [CollabGroup::subscribe], but the [InboundData] of the client is notified after it
+  /// was handled.
+  pub(crate) fn subscribe_inbound<Sink, Stream, E>(
+    self: &Arc<Self>,
+    origin: CollabOrigin,
+    sink: Sink,
+    stream: Stream,
+  ) where
+    Sink: futures_util::Sink<Vec<u8>> + Send + Unpin + 'static,
+    <Sink as futures_util::Sink<Vec<u8>>>::Error: Display,
+    Stream: futures_util::Stream<Item = Result<InboundData, E>> + Send + Unpin + 'static,
+    E: Display + Send + 'static,
+  {
+    tracing::trace!("{} subscribe client: {}", self.object_id, origin);
+    let subscription_id = self.subscription_id_counter.fetch_add(1, Ordering::SeqCst);
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let (reply_tx, reply_rx) = mpsc::unbounded_channel();
+    // Without the authenticator, all the clients can edit the object.
+    let permission = match self.authenticator {
+      None => Some(CollabPermission::Edit),
+      Some(_) => None,
+    };
+    // The updates are encoded with the v1 encoding until the client agrees on another version.
+    let (state_tx, state_rx) = watch::channel(SubscriberState {
+      permission,
+      encoder_version: EncoderVersion::V1,
+    });
+    if permission.is_some() {
+      self.send_start_message(&reply_tx);
+    }
+
+    let subscription = Subscription {
+      id: subscription_id,
+      stop_tx,
+    };
+    self.touch();
+    if let Some(old) = self
+      .subscribers
+      .write()
+      .insert(origin.clone(), subscription)
+    {
+      tracing::trace!("{} replace subscription: {}", self.object_id, old.id);
+    }
+
+    spawn(run_outbound(
+      origin.clone(),
+      Arc::downgrade(self),
+      sink,
+      self.broadcast.subscribe(),
+      reply_rx,
+      state_rx,
+      stop_rx.clone(),
+    ));
+    spawn(run_inbound(
+      origin,
+      subscription_id,
+      Arc::downgrade(self),
+      stream,
+      reply_tx,
+      state_tx,
+      stop_rx,
+    ));
+  }
+
+  /// Remove the client from the group. The connection of the client will be closed.
+  pub fn unsubscribe(&self, origin: &CollabOrigin) {
+    if self.subscribers.write().remove(origin).is_some() {
+      tracing::trace!("{} unsubscribe client: {}", self.object_id, origin);
+      self.touch();
+    }
+  }
+
+  pub fn contains_subscriber(&self, origin: &CollabOrigin) -> bool {
+    self.subscribers.read().contains_key(origin)
+  }
+
+  pub fn number_of_subscribers(&self) -> usize {
+    self.subscribers.read().len()
+  }
+
+  /// Returns true if the group has no subscribers and was not modified within the given
+  /// timeout.
+  pub fn is_inactive(&self, timeout: Duration) -> bool {
+    self.subscribers.read().is_empty() && self.modified_at.lock().elapsed() >= timeout
+  }
+
+  fn remove_subscriber(&self, origin: &CollabOrigin, subscription_id: u64) {
+    let mut subscribers = self.subscribers.write();
+    let is_current = subscribers
+      .get(origin)
+      .map(|subscription| subscription.id == subscription_id)
+      .unwrap_or(false);
+    if is_current {
+      subscribers.remove(origin);
+      drop(subscribers);
+      tracing::trace!("{} client disconnected: {}", self.object_id, origin);
+      self.touch();
+    }
+  }
+
+  fn touch(&self) {
+    *self.modified_at.lock() = Instant::now();
+  }
+
+  /// Send the Sync Step1 and the awareness state of the group to the client.
+  fn send_start_message(&self, reply_tx: &mpsc::UnboundedSender<Vec<u8>>) {
+    let mut encoder = EncoderV1::new();
+    let start_result = {
+      let collab = self.collab.lock();
+      ServerSyncProtocol.start(collab.get_awareness(), &mut encoder)
+    };
+    match start_result {
+      Ok(_) => {
+        let _ = reply_tx.send(encoder.to_vec());
+      },
+      Err(e) => tracing::error!("🔴{} start sync failed: {}", self.object_id, e),
+    }
+  }
+
+  /// Resolve the permission of the client with the token. Returns the deny reason if the client
+  /// can't access the object.
+  async fn authorize(
+    &self,
+    origin: &CollabOrigin,
+    token: &str,
+  ) -> Result<CollabPermission, String> {
+    let authenticator = match &self.authenticator {
+      None => return Ok(CollabPermission::Edit),
+      Some(authenticator) => authenticator,
+    };
+
+    let uid = authenticator
+      .authenticate(token)
+      .await
+      .map_err(|e| format!("invalid token: {}", e))?;
+    if origin.client_user_id() != Some(uid) {
+      return Err(format!("the token doesn't belong to {}", origin));
+    }
+
+    authenticator
+      .permission(uid, &self.object_id)
+      .await
+      .ok_or_else(|| format!("user:{} can't access {}", uid, self.object_id))
+  }
+
+  /// Handle the messages received from the client. The replies are sent back through the
+  /// `reply_tx` and the awareness updates are broadcast to the other subscribers.
+  ///
+  /// The messages are handled according to the permission of the client. The messages that are
+  /// not allowed are replied with a [Message::Auth] that contains the deny reason. The other
+  /// messages are still handled, and [Error::PermissionDenied] is returned with the first reason.
+  ///
+  /// A [Message::Encoding] is replied with the [EncoderVersion] that both sides can decode, and
+  /// the updates sent to the client are encoded with it from then on.
+  async fn handle_client_data(
+    &self,
+    origin: &CollabOrigin,
+    data: Vec<u8>,
+    reply_tx: &mpsc::UnboundedSender<Vec<u8>>,
+    state_tx: &watch::Sender<SubscriberState>,
+  ) -> Result<(), Error> {
+    let msg_origin = Some(origin);
+    let mut decoder = DecoderV1::from(data.as_slice());
+    let messages = MessageReader::new(&mut decoder).collect::<Vec<_>>();
+    let mut denied_reason = None;
+    for msg in messages {
+      let msg = msg?;
+      tracing::trace!(
+        "[🌐Server {}]: receive {} from {}",
+        self.object_id,
+        msg,
+        origin
+      );
+
+      if let Message::AuthRequest(token) = msg {
+        match self.authorize(origin, &token).await {
+          Ok(permission) => {
+            let was_authorized = state_tx.borrow().permission.is_some();
+            state_tx.send_modify(|state| state.permission = Some(permission));
+            let _ = reply_tx.send(Message::Auth(None).encode_v1());
+            if !was_authorized {
+              self.send_start_message(reply_tx);
+            }
+          },
+          Err(reason) => {
+            tracing::warn!("🟡{} deny {}: {}", self.object_id, origin, reason);
+            state_tx.send_modify(|state| state.permission = None);
+            let _ = reply_tx.send(Message::Auth(Some(reason.clone())).encode_v1());
+            denied_reason.get_or_insert(reason);
+          },
+        }
+        continue;
+      }
+
+      if let Message::Encoding(version) = msg {
+        let version = version.min(EncoderVersion::LATEST);
+        state_tx.send_modify(|state| state.encoder_version = version);
+        let _ = reply_tx.send(Message::Encoding(version).encode_v1());
+        continue;
+      }
+
+      let state = *state_tx.borrow();
+      let protocol = match state.permission {
+        None => {
+          let reason = "unauthenticated".to_string();
+          let _ = reply_tx.send(Message::Auth(Some(reason.clone())).encode_v1());
+          denied_reason.get_or_insert(reason);
+          continue;
+        },
+        Some(permission) => {
+          AuthorizedServerSyncProtocol::new(permission).with_encoder_version(state.encoder_version)
+        },
+      };
+
+      let awareness_payload = match &msg {
+        Message::Awareness(_) => Some(msg.encode_v1()),
+        _ => None,
+      };
+
+      match handle_msg(&msg_origin, &protocol, &self.collab, msg).await {
+        Ok(reply) => {
+          if let Some(reply) = reply {
+            let _ = reply_tx.send(reply);
+          }
+          if let Some(payload) = awareness_payload {
+            let _ = self.broadcast.send(CollabBroadcastMessage {
+              origin: origin.clone(),
+              payload,
+              update: None,
+            });
+          }
+        },
+        Err(Error::PermissionDenied { reason }) => {
+          tracing::warn!(
+            "🟡{} reject message from {}: {}",
+            self.object_id,
+            origin,
+            reason
+          );
+          let _ = reply_tx.send(Message::Auth(Some(reason.clone())).encode_v1());
+          denied_reason.get_or_insert(reason);
+        },
+        Err(e) => return Err(e),
+      }
+    }
+    match denied_reason {
+      None => Ok(()),
+      Some(reason) => Err(Error::PermissionDenied { reason }),
+    }
+  }
+
+  fn encode_full_update(&self, encoder_version: EncoderVersion) -> Vec<u8> {
+    let update = encoder_version
+      .encode_state_as_update(&self.collab.lock().transact(), &StateVector::default());
+    Message::Sync(SyncMessage::update(encoder_version, update)).encode_v1()
+  }
+}
+
+/// Forward the replies and the broadcast messages to the client. The broadcast messages that were
+/// sent by the client itself are skipped.
+async fn run_outbound<Sink>(
+  origin: CollabOrigin,
+  group: Weak<CollabGroup>,
+  mut sink: Sink,
+  mut broadcast_rx: broadcast::Receiver<CollabBroadcastMessage>,
+  mut reply_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+  state_rx: watch::Receiver<SubscriberState>,
+  mut stop_rx: watch::Receiver<bool>,
+) where
+  Sink: futures_util::Sink<Vec<u8>> + Send + Unpin + 'static,
+  <Sink as futures_util::Sink<Vec<u8>>>::Error: Display,
+{
+  loop {
+    let payload = tokio::select! {
+      _ = stop_rx.changed() => break,
+      reply = reply_rx.recv() => match reply {
+        Some(payload) => payload,
+        None => break,
+      },
+      msg = broadcast_rx.recv() => match msg {
+        Ok(msg) => {
+          // The client doesn't receive any updates before it is authenticated.
+          let state = *state_rx.borrow();
+          if msg.origin == origin || state.permission.is_none() {
+            continue;
+          }
+          match msg.encode_for(state.encoder_version) {
+            Some(payload) => payload,
+            None => continue,
+          }
+        },
+        Err(RecvError::Lagged(count)) => {
+          // The client missed some updates. Send the full state to bring it up to date.
+          tracing::warn!("{} lagged behind {} messages", origin, count);
+          match group.upgrade() {
+            Some(group) => group.encode_full_update(state_rx.borrow().encoder_version),
+            None => break,
+          }
+        },
+        Err(RecvError::Closed) => break,
+      },
+    };
+
+    if let Err(e) = sink.send(payload).await {
+      tracing::error!("🔴send message to {} failed: {}", origin, e);
+      break;
+    }
+  }
+}
+
+/// Receive the messages from the client until the stream is closed or returns an error. The
+/// client will be removed from the group when this function returns.
+async fn run_inbound<Stream, E>(
+  origin: CollabOrigin,
+  subscription_id: u64,
+  group: Weak<CollabGroup>,
+  mut stream: Stream,
+  reply_tx: mpsc::UnboundedSender<Vec<u8>>,
+  state_tx: watch::Sender<SubscriberState>,
+  mut stop_rx: watch::Receiver<bool>,
+) where
+  Stream: futures_util::Stream<Item = Result<InboundData, E>> + Send + Unpin + 'static,
+  E: Display + Send + 'static,
+{
+  loop {
+    let mut data = tokio::select! {
+      _ = stop_rx.changed() => return,
+      data = stream.next() => match data {
+        Some(Ok(data)) => data,
+        Some(Err(e)) => {
+          tracing::trace!("{} stream error: {}", origin, e);
+          break;
+        },
+        None => break,
+      },
+    };
+
+    match group.upgrade() {
+      None => return,
+      Some(group) => {
+        group.touch();
+        let result = group
+          .handle_client_data(
+            &origin,
+            std::mem::take(&mut data.data),
+            &reply_tx,
+            &state_tx,
+          )
+          .await;
+        match &result {
+          // The denied messages were logged and replied already.
+          Ok(_) | Err(Error::PermissionDenied { .. }) => {},
+          Err(e) => tracing::error!(
+            "🔴{} handle message from {} failed: {}",
+            group.object_id,
+            origin,
+            e
+          ),
+        }
+        data.handled(result.map_err(|e| e.to_string()));
+      },
+    }
+  }
+
+  if let Some(group) = group.upgrade() {
+    group.remove_subscriber(&origin, subscription_id);
+  }
+}
+
+/// The data that a subscriber sends to the group.
+pub(crate) struct InboundData {
+  data: Vec<u8>,
+  /// Called with the result of handling the data. It's called with an error if the data is
+  /// dropped before it's handled.
+  on_handled: Option<OnHandled>,
+}
+
+type OnHandled = Box<dyn FnOnce(Result<(), String>) + Send>;
+
+impl InboundData {
+  pub(crate) fn with_callback<F>(data: Vec<u8>, on_handled: F) -> Self
+  where
+    F: FnOnce(Result<(), String>) + Send + 'static,
+  {
+    Self {
+      data,
+      on_handled: Some(Box::new(on_handled)),
+    }
+  }
+
+  fn handled(mut self, result: Result<(), String>) {
+    if let Some(on_handled) = self.on_handled.take() {
+      on_handled(result);
+    }
+  }
+}
+
+impl From<Vec<u8>> for InboundData {
+  fn from(data: Vec<u8>) -> Self {
+    Self {
+      data,
+      on_handled: None,
+    }
+  }
+}
+
+impl Drop for InboundData {
+  fn drop(&mut self) {
+    if let Some(on_handled) = self.on_handled.take() {
+      on_handled(Err("the subscription was closed".to_string()));
+    }
+  }
+}
+
+/// The state of a subscriber that is shared by its inbound and outbound tasks.
+#[derive(Clone, Copy, Debug)]
+struct SubscriberState {
+  /// The permission of the subscriber, or [None] if it's not authenticated yet.
+  permission: Option<CollabPermission>,
+  /// The [EncoderVersion] of the updates sent to the subscriber.
+  encoder_version: EncoderVersion,
+}
+
+/// Closes the connection of the subscriber when it is dropped.
+struct Subscription {
+  id: u64,
+  #[allow(dead_code)]
+  stop_tx: watch::Sender<bool>,
+}
+
+#[derive(Clone, Debug)]
+struct CollabBroadcastMessage {
+  /// The origin of the transaction that generated the message.
+  origin: CollabOrigin,
+  /// The encoded message. The update of the message is encoded with [EncoderVersion::V1].
+  payload: Vec<u8>,
+  /// The update of the message, if any. It's used to encode the message for the subscribers
+  /// that agreed on another [EncoderVersion].
+  update: Option<Vec<u8>>,
+}
+
+impl CollabBroadcastMessage {
+  /// Returns the payload for a subscriber that uses the given [EncoderVersion]. Returns [None]
+  /// if the update can't be converted.
+  fn encode_for(&self, encoder_version: EncoderVersion) -> Option<Vec<u8>> {
+    match &self.update {
+      Some(update) if encoder_version != EncoderVersion::V1 => {
+        match convert_update(update, EncoderVersion::V1, encoder_version) {
+          Ok(update) => {
+            Some(Message::Sync(SyncMessage::update(encoder_version, update)).encode_v1())
+          },
+          Err(e) => {
+            tracing::error!("🔴convert update to {} failed: {}", encoder_version, e);
+            None
+          },
+        }
+      },
+      _ => Some(self.payload.clone()),
+    }
+  }
+}
+
+/// Broadcast the updates of the authoritative collab to the subscribers.
+struct CollabBroadcastPlugin {
+  sender: broadcast::Sender<CollabBroadcastMessage>,
+  modified_at: Arc<Mutex<Instant>>,
+}
+
+impl CollabPlugin for CollabBroadcastPlugin {
+  fn receive_update(&self, _object_id: &str, txn: &TransactionMut, update: &[u8]) {
+    *self.modified_at.lock() = Instant::now();
+    let origin = CollabOrigin::from(txn);
+    let payload = Message::Sync(SyncMessage::Update(update.to_vec())).encode_v1();
+    // Sending fails only when there is no subscriber.
+    let _ = self.sender.send(CollabBroadcastMessage {
+      origin,
+      payload,
+      update: Some(update.to_vec()),
+    });
+  }
+}
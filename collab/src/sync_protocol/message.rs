@@ -1,3 +1,4 @@
+use crate::core::encoding::EncoderVersion;
 use crate::sync_protocol::awareness::AwarenessUpdate;
 use std::fmt::{Debug, Display, Formatter};
 use thiserror::Error;
@@ -13,6 +14,16 @@ pub const MSG_AWARENESS: u8 = 1;
 pub const MSG_AUTH: u8 = 2;
 /// Tag id for [Message::AwarenessQuery].
 pub const MSG_QUERY_AWARENESS: u8 = 3;
+/// Tag id for [CollabEnvelope](crate::sync_protocol::envelope::CollabEnvelope).
+pub const MSG_ENVELOPE: u8 = 4;
+/// Tag id for [CollabAck](crate::sync_protocol::envelope::CollabAck).
+pub const MSG_ACK: u8 = 5;
+/// Tag id for [CollabSubscription](crate::sync_protocol::envelope::CollabSubscription).
+pub const MSG_SUBSCRIPTION: u8 = 6;
+/// Tag id for [Message::AuthRequest].
+pub const MSG_AUTH_REQUEST: u8 = 7;
+/// Tag id for [Message::Encoding].
+pub const MSG_ENCODING: u8 = 8;
 
 pub const PERMISSION_DENIED: u8 = 0;
 pub const PERMISSION_GRANTED: u8 = 1;
@@ -21,8 +32,14 @@ pub const PERMISSION_GRANTED: u8 = 1;
 pub enum Message {
   Sync(SyncMessage),
   Auth(Option<String>),
+  /// Sent by the client to present its token. The server replies with a [Message::Auth].
+  AuthRequest(String),
   AwarenessQuery,
   Awareness(AwarenessUpdate),
+  /// The latest [EncoderVersion] that the sender can decode. The receiver replies with the
+  /// version both sides agree on, and the updates are encoded with it from then on. Without this
+  /// message, the updates are encoded with [EncoderVersion::V1].
+  Encoding(EncoderVersion),
   Custom(u8, Vec<u8>),
 }
 
@@ -42,9 +59,19 @@ impl Encode for Message {
           encoder.write_var(PERMISSION_GRANTED);
         }
       },
+      Message::AuthRequest(token) => {
+        encoder.write_var(MSG_AUTH_REQUEST);
+        encoder.write_string(token);
+      },
       Message::AwarenessQuery => {
         encoder.write_var(MSG_QUERY_AWARENESS);
       },
+      Message::Encoding(version) => {
+        // The version is written as a buffer, so a remote that doesn't know this message can
+        // skip it as a [Message::Custom].
+        encoder.write_var(MSG_ENCODING);
+        encoder.write_buf([u8::from(*version)]);
+      },
       Message::Awareness(update) => {
         encoder.write_var(MSG_AWARENESS);
         encoder.write_buf(&update.encode_v1())
@@ -60,6 +87,16 @@ impl Encode for Message {
 impl Decode for Message {
   fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, lib0::error::Error> {
     let tag: u8 = decoder.read_var()?;
+    Self::decode_with_tag(tag, decoder)
+  }
+}
+
+impl Message {
+  /// Decode the message body after the tag was read from the decoder.
+  pub(crate) fn decode_with_tag<D: Decoder>(
+    tag: u8,
+    decoder: &mut D,
+  ) -> Result<Self, lib0::error::Error> {
     match tag {
       MSG_SYNC => {
         let msg = SyncMessage::decode(decoder)?;
@@ -78,7 +115,19 @@ impl Decode for Message {
         };
         Ok(Message::Auth(reason))
       },
+      MSG_AUTH_REQUEST => {
+        let token = decoder.read_string()?.to_string();
+        Ok(Message::AuthRequest(token))
+      },
       MSG_QUERY_AWARENESS => Ok(Message::AwarenessQuery),
+      MSG_ENCODING => {
+        let data = decoder.read_buf()?;
+        let version = data
+          .first()
+          .ok_or(lib0::error::Error::UnexpectedValue)
+          .and_then(|version| EncoderVersion::try_from(*version))?;
+        Ok(Message::Encoding(version))
+      },
       tag => {
         let data = decoder.read_buf()?;
         Ok(Message::Custom(tag, data.to_vec()))
@@ -92,8 +141,10 @@ impl Display for Message {
     match self {
       Message::Sync(sync_msg) => f.write_str(&sync_msg.to_string()),
       Message::Auth(_) => f.write_str("Auth"),
+      Message::AuthRequest(_) => f.write_str("AuthRequest"),
       Message::AwarenessQuery => f.write_str("AwarenessQuery"),
       Message::Awareness(_) => f.write_str("Awareness"),
+      Message::Encoding(version) => write!(f, "Encoding({})", version),
       Message::Custom(_, _) => f.write_str("Custom"),
     }
   }
@@ -105,12 +156,46 @@ pub const MSG_SYNC_STEP_1: u8 = 0;
 pub const MSG_SYNC_STEP_2: u8 = 1;
 /// Tag id for [SyncMessage::Update].
 pub const MSG_SYNC_UPDATE: u8 = 2;
+/// Tag id for [SyncMessage::SyncStep2V2].
+pub const MSG_SYNC_STEP_2_V2: u8 = 3;
+/// Tag id for [SyncMessage::UpdateV2].
+pub const MSG_SYNC_UPDATE_V2: u8 = 4;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SyncMessage {
   SyncStep1(StateVector),
   SyncStep2(Vec<u8>),
   Update(Vec<u8>),
+  /// Same as [SyncMessage::SyncStep2], but the update is encoded with [EncoderVersion::V2]. It's
+  /// only sent to the remotes that agreed on it with a [Message::Encoding].
+  SyncStep2V2(Vec<u8>),
+  /// Same as [SyncMessage::Update], but the update is encoded with [EncoderVersion::V2].
+  UpdateV2(Vec<u8>),
+}
+
+impl SyncMessage {
+  /// Create a sync step 2 message for the update that is encoded with the given version.
+  pub fn sync_step2(encoder_version: EncoderVersion, update: Vec<u8>) -> Self {
+    match encoder_version {
+      EncoderVersion::V1 => SyncMessage::SyncStep2(update),
+      EncoderVersion::V2 => SyncMessage::SyncStep2V2(update),
+    }
+  }
+
+  /// Create an update message for the update that is encoded with the given version.
+  pub fn update(encoder_version: EncoderVersion, update: Vec<u8>) -> Self {
+    match encoder_version {
+      EncoderVersion::V1 => SyncMessage::Update(update),
+      EncoderVersion::V2 => SyncMessage::UpdateV2(update),
+    }
+  }
+
+  pub fn is_sync_step2(&self) -> bool {
+    matches!(
+      self,
+      SyncMessage::SyncStep2(_) | SyncMessage::SyncStep2V2(_)
+    )
+  }
 }
 
 impl Display for SyncMessage {
@@ -125,6 +210,12 @@ impl Display for SyncMessage {
       SyncMessage::Update(data) => {
         write!(f, "Update({})", data.len())
       },
+      SyncMessage::SyncStep2V2(data) => {
+        write!(f, "SyncStep2V2({})", data.len())
+      },
+      SyncMessage::UpdateV2(data) => {
+        write!(f, "UpdateV2({})", data.len())
+      },
     }
   }
 }
@@ -144,6 +235,14 @@ impl Encode for SyncMessage {
         encoder.write_var(MSG_SYNC_UPDATE);
         encoder.write_buf(u);
       },
+      SyncMessage::SyncStep2V2(u) => {
+        encoder.write_var(MSG_SYNC_STEP_2_V2);
+        encoder.write_buf(u);
+      },
+      SyncMessage::UpdateV2(u) => {
+        encoder.write_var(MSG_SYNC_UPDATE_V2);
+        encoder.write_buf(u);
+      },
     }
   }
 }
@@ -165,6 +264,14 @@ impl Decode for SyncMessage {
         let buf = decoder.read_buf()?;
         Ok(SyncMessage::Update(buf.into()))
       },
+      MSG_SYNC_STEP_2_V2 => {
+        let buf = decoder.read_buf()?;
+        Ok(SyncMessage::SyncStep2V2(buf.into()))
+      },
+      MSG_SYNC_UPDATE_V2 => {
+        let buf = decoder.read_buf()?;
+        Ok(SyncMessage::UpdateV2(buf.into()))
+      },
       _ => Err(lib0::error::Error::UnexpectedValue),
     }
   }
@@ -1,5 +1,11 @@
+pub mod auth;
 pub mod awareness;
+pub mod envelope;
+pub mod group;
 pub mod message;
+mod multiplex;
+pub mod presence;
 mod protocol;
+pub mod server;
 
 pub use protocol::*;
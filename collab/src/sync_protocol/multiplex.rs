@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+use crate::core::origin::CollabOrigin;
+use crate::sync_protocol::envelope::{
+  CollabEnvelope, CollabFrame, CollabFrameReader, MsgIdGenerator, SubscriptionAction,
+};
+use crate::sync_protocol::group::InboundData;
+use crate::sync_protocol::message::{Error, MessageReader};
+use crate::sync_protocol::server::CollabServer;
+
+/// Receive the frames of a connection that is shared by multiple objects. The
+/// [CollabSubscription](crate::sync_protocol::envelope::CollabSubscription) frames subscribe the
+/// client to the group of the object, and the [CollabEnvelope]s are routed to the group of the
+/// object that they belong to. Every envelope is acknowledged after the group handled it, or
+/// rejected if the group failed to handle it or denied its messages.
+pub(crate) async fn run_multiplexed_inbound<Stream, E>(
+  server: Weak<CollabServer>,
+  origin: CollabOrigin,
+  mut stream: Stream,
+  outbound_tx: mpsc::UnboundedSender<Vec<u8>>,
+) where
+  Stream: futures_util::Stream<Item = Result<Vec<u8>, E>> + Send + Unpin + 'static,
+  E: Display + Send + 'static,
+{
+  let msg_id_generator = Arc::new(MsgIdGenerator::new());
+  let mut objects: HashMap<String, mpsc::UnboundedSender<InboundData>> = HashMap::new();
+
+  while let Some(data) = stream.next().await {
+    let data = match data {
+      Ok(data) => data,
+      Err(e) => {
+        tracing::trace!("{} stream error: {}", origin, e);
+        break;
+      },
+    };
+    let server = match server.upgrade() {
+      None => return,
+      Some(server) => server,
+    };
+
+    let mut decoder = DecoderV1::from(data.as_slice());
+    let mut replies = EncoderV1::new();
+    for frame in CollabFrameReader::new(&mut decoder) {
+      let frame = match frame {
+        Ok(frame) => frame,
+        Err(e) => {
+          tracing::error!("🔴decode frame from {} failed: {}", origin, e);
+          break;
+        },
+      };
+
+      match frame {
+        CollabFrame::Subscription(subscription) => match subscription.action {
+          SubscriptionAction::Subscribe => {
+            let object_id = subscription.object_id;
+            let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
+            let sink = EnvelopeSink {
+              object_id: object_id.clone(),
+              msg_id_generator: msg_id_generator.clone(),
+              tx: outbound_tx.clone(),
+            };
+            let stream = UnboundedReceiverStream::new(inbound_rx).map(Ok::<_, Infallible>);
+            match server.get_or_create_group(&object_id).await {
+              Ok(group) => {
+                group.subscribe_inbound(origin.clone(), sink, stream);
+                objects.insert(object_id, inbound_tx);
+              },
+              Err(e) => tracing::error!("🔴{} subscribe {} failed: {}", origin, object_id, e),
+            }
+          },
+          SubscriptionAction::Unsubscribe => {
+            objects.remove(&subscription.object_id);
+            server.unsubscribe(&subscription.object_id, &origin);
+          },
+        },
+        CollabFrame::Envelope(envelope) => match objects.get(&envelope.object_id) {
+          Some(inbound_tx) => {
+            let data = envelope.message.encode_v1();
+            let outbound_tx = outbound_tx.clone();
+            // A closed group drops the data, which rejects the envelope.
+            let _ = inbound_tx.send(InboundData::with_callback(data, move |result| {
+              let ack = match result {
+                Ok(_) => envelope.ack(),
+                Err(reason) => envelope.nack(reason),
+              };
+              let _ = outbound_tx.send(ack.encode_v1());
+            }));
+          },
+          None => envelope
+            .nack("object is not subscribed")
+            .encode(&mut replies),
+        },
+        CollabFrame::Ack(ack) => {
+          if !ack.is_ok() {
+            tracing::warn!("🟡{} reject message: {}", origin, ack);
+          }
+        },
+        CollabFrame::Message(msg) => {
+          tracing::warn!("🟡{} send message without envelope: {}", origin, msg);
+        },
+      }
+    }
+
+    let replies = replies.to_vec();
+    if !replies.is_empty() && outbound_tx.send(replies).is_err() {
+      break;
+    }
+  }
+
+  if let Some(server) = server.upgrade() {
+    for object_id in objects.keys() {
+      server.unsubscribe(object_id, &origin);
+    }
+  }
+}
+
+/// Wraps the messages that a [CollabGroup](crate::sync_protocol::group::CollabGroup) sends to a
+/// subscriber into [CollabEnvelope]s of the object, and sends them through the shared connection.
+struct EnvelopeSink {
+  object_id: String,
+  msg_id_generator: Arc<MsgIdGenerator>,
+  tx: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl futures_util::Sink<Vec<u8>> for EnvelopeSink {
+  type Error = Error;
+
+  fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn start_send(self: Pin<&mut Self>, item: Vec<u8>) -> Result<(), Self::Error> {
+    let mut decoder = DecoderV1::from(item.as_slice());
+    let mut encoder = EncoderV1::new();
+    for msg in MessageReader::new(&mut decoder) {
+      CollabEnvelope::new(
+        &self.object_id,
+        CollabOrigin::Server,
+        self.msg_id_generator.next_id(),
+        msg?,
+      )
+      .encode(&mut encoder);
+    }
+    // The connection is closed when the receiver was dropped.
+    self
+      .tx
+      .send(encoder.to_vec())
+      .map_err(|_| Error::Other("connection closed".into()))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+/// Forward the data of the shared connection to the sink until the sink returns an error.
+pub(crate) async fn run_multiplexed_outbound<Sink>(
+  origin: CollabOrigin,
+  mut sink: Sink,
+  mut outbound_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+) where
+  Sink: futures_util::Sink<Vec<u8>> + Send + Unpin + 'static,
+  <Sink as futures_util::Sink<Vec<u8>>>::Error: Display,
+{
+  while let Some(data) = outbound_rx.recv().await {
+    if let Err(e) = sink.send(data).await {
+      tracing::error!("🔴send message to {} failed: {}", origin, e);
+      break;
+    }
+  }
+}
@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use tokio::time::interval;
+use yrs::block::ClientID;
+use yrs::{Assoc, IndexedSequence, ReadTxn, StickyIndex, TransactionMut};
+
+use crate::core::collab::MutexCollab;
+use crate::sync_protocol::awareness::{Awareness, Event, UpdateSubscription};
+
+/// The remote clients that are not updated within this duration are removed.
+pub const DEFAULT_PRESENCE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The interval of checking the outdated clients.
+pub const DEFAULT_PRESENCE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The user that is shared with the other clients.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PresenceUser {
+  pub uid: i64,
+  pub name: String,
+  /// The color that is used to draw the cursor of the user. For example, `#FF0000`.
+  pub color: String,
+}
+
+/// The selection of a client.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceSelection {
+  /// The selection in a text. The `anchor` and the `head` are [StickyIndex]s, so they stay
+  /// at the same place of the text when the text is edited concurrently. It's a cursor if the
+  /// `anchor` is equal to the `head`.
+  Text {
+    #[serde(with = "sticky_index_serde")]
+    anchor: StickyIndex,
+    #[serde(with = "sticky_index_serde")]
+    head: StickyIndex,
+  },
+  /// The selected value that is identified by the path. For example, the path of a database
+  /// cell is `[row_id, field_id]`.
+  Path { path: Vec<String> },
+}
+
+impl PresenceSelection {
+  /// Create a selection in the text from the `anchor` index to the `head` index. Returns [None]
+  /// if any of the indexes is beyond the length of the text.
+  pub fn text<T: IndexedSequence>(
+    txn: &mut TransactionMut,
+    text: &T,
+    anchor: u32,
+    head: u32,
+  ) -> Option<Self> {
+    let anchor = text.sticky_index(txn, anchor, Assoc::After)?;
+    let head = text.sticky_index(txn, head, Assoc::After)?;
+    Some(Self::Text { anchor, head })
+  }
+
+  /// Create a cursor at the index of the text.
+  pub fn cursor<T: IndexedSequence>(
+    txn: &mut TransactionMut,
+    text: &T,
+    index: u32,
+  ) -> Option<Self> {
+    Self::text(txn, text, index, index)
+  }
+
+  pub fn path(path: Vec<String>) -> Self {
+    Self::Path { path }
+  }
+
+  /// Returns the current indexes of the `anchor` and the `head` of the text selection. Returns
+  /// [None] if the selection is not a text selection or the text was removed.
+  pub fn text_range<T: ReadTxn>(&self, txn: &T) -> Option<(u32, u32)> {
+    match self {
+      PresenceSelection::Text { anchor, head } => {
+        let anchor = anchor.get_offset(txn)?;
+        let head = head.get_offset(txn)?;
+        Some((anchor.index, head.index))
+      },
+      PresenceSelection::Path { .. } => None,
+    }
+  }
+}
+
+/// The typed awareness state of a client.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PresenceState {
+  pub user: PresenceUser,
+  pub selection: Option<PresenceSelection>,
+  /// The timestamp in milliseconds when the client updated its state last time.
+  pub last_seen: i64,
+}
+
+impl PresenceState {
+  pub fn new(user: PresenceUser, selection: Option<PresenceSelection>) -> Self {
+    Self {
+      user,
+      selection,
+      last_seen: timestamp(),
+    }
+  }
+
+  fn from_awareness(awareness: &Awareness, client_id: ClientID) -> Option<Self> {
+    let json = awareness.clients().get(&client_id)?;
+    serde_json::from_str(json).ok()
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PresenceEvent {
+  /// A client appeared, or appeared again after being removed.
+  Added(ClientID, PresenceState),
+  Updated(ClientID, PresenceState),
+  /// A client was disconnected or expired.
+  Removed(ClientID),
+}
+
+#[derive(Clone, Debug)]
+pub struct PresenceConfig {
+  /// The remote clients are removed if their states are not updated within the timeout. The
+  /// local state is renewed every half of the timeout to keep the current client alive.
+  /// Default is [DEFAULT_PRESENCE_TIMEOUT].
+  pub timeout: Duration,
+  /// Default is [DEFAULT_PRESENCE_CHECK_INTERVAL].
+  pub check_interval: Duration,
+}
+
+impl PresenceConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  pub fn with_check_interval(mut self, check_interval: Duration) -> Self {
+    self.check_interval = check_interval;
+    self
+  }
+}
+
+impl Default for PresenceConfig {
+  fn default() -> Self {
+    Self {
+      timeout: DEFAULT_PRESENCE_TIMEOUT,
+      check_interval: DEFAULT_PRESENCE_CHECK_INTERVAL,
+    }
+  }
+}
+
+/// The [CollabPresence] is a typed layer on top of the [Awareness] of a
+/// [Collab](crate::core::collab::Collab). Each client
+/// shares a [PresenceState] that contains the user, the selection and the last seen time.
+///
+/// The states of the remote clients are removed when they are outdated, and the local state is
+/// renewed periodically. The changes are emitted as [PresenceEvent]s, including the changes of
+/// the local state. The event of the local client can be used to send the awareness update to
+/// the remote.
+///
+/// The methods lock the collab, so they must not be called while the collab is locked. The
+/// [CollabPresence] spawns a background task, so it must be created within a tokio runtime.
+pub struct CollabPresence {
+  collab: Weak<MutexCollab>,
+  event_tx: broadcast::Sender<PresenceEvent>,
+  #[allow(dead_code)]
+  subscription: UpdateSubscription,
+  /// The background task stops when the [CollabPresence] is dropped.
+  #[allow(dead_code)]
+  stop_tx: watch::Sender<bool>,
+}
+
+unsafe impl Send for CollabPresence {}
+unsafe impl Sync for CollabPresence {}
+
+impl CollabPresence {
+  pub fn new(collab: &Arc<MutexCollab>, config: PresenceConfig) -> Self {
+    let (event_tx, _) = broadcast::channel(1000);
+    let subscription = {
+      let mut collab = collab.lock();
+      let awareness = collab.get_mut_awareness();
+      let known_clients = Mutex::new(awareness.clients().keys().cloned().collect());
+      let cloned_event_tx = event_tx.clone();
+      awareness.on_update(move |awareness, event| {
+        for event in presence_events(awareness, event, &known_clients) {
+          let _ = cloned_event_tx.send(event);
+        }
+      })
+    };
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let collab = Arc::downgrade(collab);
+    tokio::spawn(run_presence_check(collab.clone(), config, stop_rx));
+    Self {
+      collab,
+      event_tx,
+      subscription,
+      stop_tx,
+    }
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<PresenceEvent> {
+    self.event_tx.subscribe()
+  }
+
+  /// Share the user and the selection of the current client.
+  pub fn set_local_state(&self, user: PresenceUser, selection: Option<PresenceSelection>) {
+    if let Some(collab) = self.collab.upgrade() {
+      set_local_state(
+        collab.lock().get_mut_awareness(),
+        &PresenceState::new(user, selection),
+      );
+    }
+  }
+
+  /// Update the selection of the current client. It does nothing if the local state was not set
+  /// by [CollabPresence::set_local_state].
+  pub fn set_local_selection(&self, selection: Option<PresenceSelection>) {
+    if let Some(collab) = self.collab.upgrade() {
+      let mut collab = collab.lock();
+      let awareness = collab.get_mut_awareness();
+      if let Some(state) = PresenceState::from_awareness(awareness, awareness.client_id()) {
+        set_local_state(awareness, &PresenceState::new(state.user, selection));
+      }
+    }
+  }
+
+  /// Clear the state of the current client, effectively marking it as disconnected.
+  pub fn clean_local_state(&self) {
+    if let Some(collab) = self.collab.upgrade() {
+      collab.lock().get_mut_awareness().clean_local_state();
+    }
+  }
+
+  pub fn local_state(&self) -> Option<PresenceState> {
+    let collab = self.collab.upgrade()?;
+    let collab = collab.lock();
+    let awareness = collab.get_awareness();
+    PresenceState::from_awareness(awareness, awareness.client_id())
+  }
+
+  /// Returns the states of the remote clients. The states that are not [PresenceState] are
+  /// ignored.
+  pub fn remote_states(&self) -> HashMap<ClientID, PresenceState> {
+    let collab = match self.collab.upgrade() {
+      None => return HashMap::new(),
+      Some(collab) => collab,
+    };
+    let collab = collab.lock();
+    let awareness = collab.get_awareness();
+    awareness
+      .clients()
+      .keys()
+      .filter(|client_id| **client_id != awareness.client_id())
+      .filter_map(|client_id| {
+        PresenceState::from_awareness(awareness, *client_id).map(|state| (*client_id, state))
+      })
+      .collect()
+  }
+}
+
+fn set_local_state(awareness: &mut Awareness, state: &PresenceState) {
+  match serde_json::to_string(state) {
+    Ok(json) => awareness.set_local_state(json),
+    Err(e) => tracing::error!("🔴serialize presence state failed: {}", e),
+  }
+}
+
+/// Convert the [Event] of the [Awareness] to the [PresenceEvent]s. A client that was updated
+/// after being removed is reported as added.
+fn presence_events(
+  awareness: &Awareness,
+  event: &Event,
+  known_clients: &Mutex<HashSet<ClientID>>,
+) -> Vec<PresenceEvent> {
+  let mut known_clients = known_clients.lock();
+  let mut events = vec![];
+  for client_id in event.added().iter().chain(event.updated()) {
+    if let Some(state) = PresenceState::from_awareness(awareness, *client_id) {
+      if known_clients.insert(*client_id) {
+        events.push(PresenceEvent::Added(*client_id, state));
+      } else {
+        events.push(PresenceEvent::Updated(*client_id, state));
+      }
+    }
+  }
+  for client_id in event.removed() {
+    if known_clients.remove(client_id) {
+      events.push(PresenceEvent::Removed(*client_id));
+    }
+  }
+  events
+}
+
+/// Remove the outdated remote clients and renew the local state periodically.
+async fn run_presence_check(
+  collab: Weak<MutexCollab>,
+  config: PresenceConfig,
+  mut stop_rx: watch::Receiver<bool>,
+) {
+  let mut interval = interval(config.check_interval);
+  loop {
+    tokio::select! {
+      _ = stop_rx.changed() => break,
+      _ = interval.tick() => {
+        let collab = match collab.upgrade() {
+          None => break,
+          Some(collab) => collab,
+        };
+        let mut collab = collab.lock();
+        let awareness = collab.get_mut_awareness();
+        let removed = awareness.remove_outdated_states(config.timeout);
+        if !removed.is_empty() {
+          tracing::trace!("remove outdated awareness clients: {:?}", removed);
+        }
+
+        let client_id = awareness.client_id();
+        let is_outdated = awareness
+          .last_updated(client_id)
+          .map(|last_updated| last_updated.elapsed() >= config.timeout / 2)
+          .unwrap_or(false);
+        if is_outdated {
+          if let Some(state) = PresenceState::from_awareness(awareness, client_id) {
+            set_local_state(awareness, &PresenceState::new(state.user, state.selection));
+          }
+        }
+      },
+    }
+  }
+}
+
+fn timestamp() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_millis() as i64)
+    .unwrap_or_default()
+}
+
+/// Serialize the [StickyIndex] as the bytes that are encoded with the lib0 v1 encoding.
+mod sticky_index_serde {
+  use serde::de::Error;
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+  use yrs::updates::decoder::Decode;
+  use yrs::updates::encoder::Encode;
+  use yrs::StickyIndex;
+
+  pub fn serialize<S: Serializer>(index: &StickyIndex, serializer: S) -> Result<S::Ok, S::Error> {
+    index.encode_v1().serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StickyIndex, D::Error> {
+    let bytes = Vec::<u8>::deserialize(deserializer)?;
+    StickyIndex::decode_v1(&bytes).map_err(D::Error::custom)
+  }
+}
@@ -1,7 +1,8 @@
 use crate::core::collab::MutexCollab;
+use crate::core::encoding::EncoderVersion;
 use crate::core::origin::CollabOrigin;
 use crate::sync_protocol::awareness::{Awareness, AwarenessUpdate};
-use crate::sync_protocol::message::{Error, Message, SyncMessage};
+use crate::sync_protocol::message::{Error, Message, SyncMessage, MSG_AUTH_REQUEST};
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
 use yrs::{ReadTxn, StateVector, Transact, Update};
@@ -29,6 +30,25 @@ use yrs::{ReadTxn, StateVector, Transact, Update};
 pub struct ClientSyncProtocol;
 impl CollabSyncProtocol for ClientSyncProtocol {}
 
+/// A [ClientSyncProtocol] that encodes the updates with the [EncoderVersion] agreed with the
+/// remote. The [ClientSyncProtocol] always uses [EncoderVersion::V1].
+#[derive(Clone)]
+pub struct VersionedClientSyncProtocol {
+  encoder_version: EncoderVersion,
+}
+
+impl VersionedClientSyncProtocol {
+  pub fn new(encoder_version: EncoderVersion) -> Self {
+    Self { encoder_version }
+  }
+}
+
+impl CollabSyncProtocol for VersionedClientSyncProtocol {
+  fn encoder_version(&self) -> EncoderVersion {
+    self.encoder_version
+  }
+}
+
 #[derive(Clone)]
 pub struct ServerSyncProtocol;
 impl CollabSyncProtocol for ServerSyncProtocol {
@@ -37,17 +57,51 @@ impl CollabSyncProtocol for ServerSyncProtocol {
     awareness: &Awareness,
     sv: StateVector,
   ) -> Result<Option<Vec<u8>>, Error> {
-    let txn = awareness.doc().transact();
-    let step2_update = txn.encode_state_as_update_v1(&sv);
-    let step1_update = txn.state_vector();
+    Ok(Some(encode_server_sync_step1(
+      awareness,
+      sv,
+      self.encoder_version(),
+    )))
+  }
 
-    let mut encoder = EncoderV1::new();
-    Message::Sync(SyncMessage::SyncStep2(step2_update)).encode(&mut encoder);
-    Message::Sync(SyncMessage::SyncStep1(step1_update)).encode(&mut encoder);
-    Ok(Some(encoder.to_vec()))
+  /// The [ServerSyncProtocol] keeps no state per connection and always encodes the updates with
+  /// [EncoderVersion::V1], so it never agrees on a later version. The
+  /// [CollabServer](crate::sync_protocol::server::CollabServer) keeps the version agreed with
+  /// each connection instead.
+  fn handle_encoding(
+    &self,
+    _awareness: &Awareness,
+    version: EncoderVersion,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    let version = version.min(self.encoder_version());
+    Ok(Some(Message::Encoding(version).encode_v1()))
   }
 }
+
+/// Reply the sync step 1 of the client with the missing updates, encoded with the given
+/// [EncoderVersion], followed by the sync step 1 of the server.
+pub(crate) fn encode_server_sync_step1(
+  awareness: &Awareness,
+  sv: StateVector,
+  encoder_version: EncoderVersion,
+) -> Vec<u8> {
+  let txn = awareness.doc().transact();
+  let step2_update = encoder_version.encode_state_as_update(&txn, &sv);
+  let step1_update = txn.state_vector();
+
+  let mut encoder = EncoderV1::new();
+  Message::Sync(SyncMessage::sync_step2(encoder_version, step2_update)).encode(&mut encoder);
+  Message::Sync(SyncMessage::SyncStep1(step1_update)).encode(&mut encoder);
+  encoder.to_vec()
+}
+
 pub trait CollabSyncProtocol {
+  /// The [EncoderVersion] of the updates sent by this protocol. The incoming updates are always
+  /// decoded with the version of their message.
+  fn encoder_version(&self) -> EncoderVersion {
+    EncoderVersion::V1
+  }
+
   fn start<E: Encoder>(&self, awareness: &Awareness, encoder: &mut E) -> Result<(), Error> {
     let (sv, update) = {
       let sv = awareness.doc().transact().state_vector();
@@ -66,9 +120,10 @@ pub trait CollabSyncProtocol {
     awareness: &Awareness,
     sv: StateVector,
   ) -> Result<Option<Vec<u8>>, Error> {
-    let update = awareness.doc().transact().encode_state_as_update_v1(&sv);
+    let encoder_version = self.encoder_version();
+    let update = encoder_version.encode_state_as_update(&awareness.doc().transact(), &sv);
     Ok(Some(
-      Message::Sync(SyncMessage::SyncStep2(update)).encode_v1(),
+      Message::Sync(SyncMessage::sync_step2(encoder_version, update)).encode_v1(),
     ))
   }
 
@@ -111,6 +166,15 @@ pub trait CollabSyncProtocol {
     }
   }
 
+  /// Handle the token presented by the remote. By default the auth request is not supported.
+  fn handle_auth_request(
+    &self,
+    _awareness: &Awareness,
+    _token: String,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    Err(Error::Unsupported(MSG_AUTH_REQUEST))
+  }
+
   /// Returns an [AwarenessUpdate] which is a serializable representation of a current `awareness`
   /// instance.
   fn handle_awareness_query(&self, awareness: &Awareness) -> Result<Option<Vec<u8>>, Error> {
@@ -129,6 +193,16 @@ pub trait CollabSyncProtocol {
     Ok(None)
   }
 
+  /// Handle the [EncoderVersion] announced by the remote. By default the message is only
+  /// acknowledged. The server replies with the version both sides can decode.
+  fn handle_encoding(
+    &self,
+    _awareness: &Awareness,
+    _version: EncoderVersion,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    Ok(None)
+  }
+
   fn missing_handle(
     &self,
     _awareness: &mut Awareness,
@@ -168,11 +242,31 @@ pub async fn handle_msg<P: CollabSyncProtocol>(
           Update::decode_v1(&update)?,
         )
       },
+      SyncMessage::SyncStep2V2(update) => {
+        let mut collab = collab.lock();
+        protocol.handle_sync_step2(
+          origin,
+          collab.get_mut_awareness(),
+          Update::decode_v2(&update)?,
+        )
+      },
+      SyncMessage::UpdateV2(update) => {
+        let mut collab = collab.lock();
+        protocol.handle_update(
+          origin,
+          collab.get_mut_awareness(),
+          Update::decode_v2(&update)?,
+        )
+      },
     },
     Message::Auth(reason) => {
       let collab = collab.lock();
       protocol.handle_auth(collab.get_awareness(), reason)
     },
+    Message::AuthRequest(token) => {
+      let collab = collab.lock();
+      protocol.handle_auth_request(collab.get_awareness(), token)
+    },
     Message::AwarenessQuery => {
       let collab = collab.lock();
       protocol.handle_awareness_query(collab.get_awareness())
@@ -181,6 +275,10 @@ pub async fn handle_msg<P: CollabSyncProtocol>(
       let mut collab = collab.lock();
       protocol.handle_awareness_update(collab.get_mut_awareness(), update)
     },
+    Message::Encoding(version) => {
+      let collab = collab.lock();
+      protocol.handle_encoding(collab.get_awareness(), version)
+    },
     Message::Custom(tag, data) => {
       let mut collab = collab.lock();
       protocol.missing_handle(collab.get_mut_awareness(), tag, data)
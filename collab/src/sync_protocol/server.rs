@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use tokio::spawn;
+use tokio::sync::mpsc;
+use tokio::time::interval;
+
+use crate::core::collab::CollabRawData;
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+use crate::sync_protocol::auth::CollabAuthenticator;
+use crate::sync_protocol::group::CollabGroup;
+use crate::sync_protocol::multiplex::{run_multiplexed_inbound, run_multiplexed_outbound};
+
+/// The default time a [CollabGroup] without subscribers is kept in memory.
+pub const DEFAULT_GROUP_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// The default interval to check the inactive [CollabGroup]s.
+pub const DEFAULT_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct CollabServerConfig {
+  /// `idle_timeout` is the time a group without subscribers is kept in memory. After that, the
+  /// group will be removed from the server.
+  pub idle_timeout: Duration,
+  /// `eviction_interval` is the interval to check the inactive groups.
+  pub eviction_interval: Duration,
+}
+
+impl CollabServerConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+    self.idle_timeout = idle_timeout;
+    self
+  }
+
+  pub fn with_eviction_interval(mut self, eviction_interval: Duration) -> Self {
+    self.eviction_interval = eviction_interval;
+    self
+  }
+}
+
+impl Default for CollabServerConfig {
+  fn default() -> Self {
+    Self {
+      idle_timeout: DEFAULT_GROUP_IDLE_TIMEOUT,
+      eviction_interval: DEFAULT_EVICTION_INTERVAL,
+    }
+  }
+}
+
+type CollabGroups = Arc<RwLock<HashMap<String, Arc<CollabGroup>>>>;
+
+/// The [CollabServer] hosts the authoritative [CollabGroup] of each object and runs the
+/// [ServerSyncProtocol](crate::sync_protocol::ServerSyncProtocol) against its subscribers. The
+/// groups that have no subscribers are removed after [CollabServerConfig::idle_timeout].
+///
+/// The server spawns a background task to evict the inactive groups, so it must be created
+/// within a tokio runtime.
+pub struct CollabServer {
+  groups: CollabGroups,
+  config: CollabServerConfig,
+  authenticator: Option<Arc<dyn CollabAuthenticator>>,
+}
+
+impl CollabServer {
+  pub fn new(config: CollabServerConfig) -> Self {
+    let groups: CollabGroups = Default::default();
+    spawn(run_eviction(
+      Arc::downgrade(&groups),
+      config.eviction_interval,
+      config.idle_timeout,
+    ));
+    Self {
+      groups,
+      config,
+      authenticator: None,
+    }
+  }
+
+  /// Require the clients to be authenticated by the [CollabAuthenticator] before syncing. Only
+  /// applies to the groups that are created after calling this method.
+  pub fn with_authenticator(mut self, authenticator: Arc<dyn CollabAuthenticator>) -> Self {
+    self.authenticator = Some(authenticator);
+    self
+  }
+
+  /// Returns the group of the given object if it exists.
+  pub fn get_group(&self, object_id: &str) -> Option<Arc<CollabGroup>> {
+    self.groups.read().get(object_id).cloned()
+  }
+
+  /// Returns the group of the given object. An empty group will be created if it doesn't exist.
+  pub async fn get_or_create_group(
+    &self,
+    object_id: &str,
+  ) -> Result<Arc<CollabGroup>, CollabError> {
+    self.create_group(object_id, vec![]).await
+  }
+
+  /// Create a group for the given object with the [CollabRawData]. If the group already exists,
+  /// the existing group will be returned and the raw data will be ignored.
+  pub async fn create_group(
+    &self,
+    object_id: &str,
+    collab_raw_data: CollabRawData,
+  ) -> Result<Arc<CollabGroup>, CollabError> {
+    if let Some(group) = self.get_group(object_id) {
+      return Ok(group);
+    }
+
+    let mut group = CollabGroup::new(object_id, collab_raw_data).await?;
+    if let Some(authenticator) = &self.authenticator {
+      group = group.with_authenticator(authenticator.clone());
+    }
+    let group = Arc::new(group);
+    // Another task might create the group while the lock was released.
+    let group = self
+      .groups
+      .write()
+      .entry(object_id.to_string())
+      .or_insert(group)
+      .clone();
+    Ok(group)
+  }
+
+  /// Subscribe a client to the object. See [CollabGroup::subscribe] for more details.
+  pub async fn subscribe<Sink, Stream, E>(
+    &self,
+    object_id: &str,
+    origin: CollabOrigin,
+    sink: Sink,
+    stream: Stream,
+  ) -> Result<(), CollabError>
+  where
+    Sink: futures_util::Sink<Vec<u8>> + Send + Unpin + 'static,
+    <Sink as futures_util::Sink<Vec<u8>>>::Error: Display,
+    Stream: futures_util::Stream<Item = Result<Vec<u8>, E>> + Send + Unpin + 'static,
+    E: Display + Send + 'static,
+  {
+    let group = self.get_or_create_group(object_id).await?;
+    group.subscribe(origin, sink, stream);
+    Ok(())
+  }
+
+  /// Subscribe a client to a connection that is shared by multiple objects. Unlike
+  /// [CollabServer::subscribe], the messages must be wrapped in the
+  /// [CollabEnvelope](crate::sync_protocol::envelope::CollabEnvelope) of the object they belong
+  /// to, and the client subscribes to each object by sending a
+  /// [CollabSubscription](crate::sync_protocol::envelope::CollabSubscription).
+  pub fn subscribe_multiplexed<Sink, Stream, E>(
+    self: &Arc<Self>,
+    origin: CollabOrigin,
+    sink: Sink,
+    stream: Stream,
+  ) where
+    Sink: futures_util::Sink<Vec<u8>> + Send + Unpin + 'static,
+    <Sink as futures_util::Sink<Vec<u8>>>::Error: Display,
+    Stream: futures_util::Stream<Item = Result<Vec<u8>, E>> + Send + Unpin + 'static,
+    E: Display + Send + 'static,
+  {
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+    spawn(run_multiplexed_outbound(origin.clone(), sink, outbound_rx));
+    spawn(run_multiplexed_inbound(
+      Arc::downgrade(self),
+      origin,
+      stream,
+      outbound_tx,
+    ));
+  }
+
+  pub fn unsubscribe(&self, object_id: &str, origin: &CollabOrigin) {
+    if let Some(group) = self.get_group(object_id) {
+      group.unsubscribe(origin);
+    }
+  }
+
+  pub fn remove_group(&self, object_id: &str) -> Option<Arc<CollabGroup>> {
+    self.groups.write().remove(object_id)
+  }
+
+  /// Remove the groups that are inactive for longer than [CollabServerConfig::idle_timeout].
+  /// Returns the object ids of the removed groups.
+  pub fn remove_inactive_groups(&self) -> Vec<String> {
+    remove_inactive_groups(&self.groups, self.config.idle_timeout)
+  }
+
+  pub fn number_of_groups(&self) -> usize {
+    self.groups.read().len()
+  }
+}
+
+fn remove_inactive_groups(groups: &CollabGroups, idle_timeout: Duration) -> Vec<String> {
+  let mut groups = groups.write();
+  let inactive_object_ids = groups
+    .iter()
+    .filter(|(_, group)| group.is_inactive(idle_timeout))
+    .map(|(object_id, _)| object_id.clone())
+    .collect::<Vec<_>>();
+
+  for object_id in &inactive_object_ids {
+    tracing::trace!("remove inactive group: {}", object_id);
+    groups.remove(object_id);
+  }
+  inactive_object_ids
+}
+
+/// The eviction task stops when the [CollabServer] is dropped.
+async fn run_eviction(
+  groups: Weak<RwLock<HashMap<String, Arc<CollabGroup>>>>,
+  period: Duration,
+  idle_timeout: Duration,
+) {
+  let mut interval = interval(period);
+  loop {
+    interval.tick().await;
+    match groups.upgrade() {
+      None => break,
+      Some(groups) => {
+        remove_inactive_groups(&groups, idle_timeout);
+      },
+    }
+  }
+}
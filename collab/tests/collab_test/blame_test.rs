@@ -0,0 +1,190 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::blame::Authorship;
+use collab::core::commit::TransactionMeta;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::preclude::*;
+use parking_lot::RwLock;
+use yrs::updates::decoder::Decode;
+
+#[tokio::test]
+async fn blame_text_test() {
+  let collab_1 = Collab::new(1, "1", "1", vec![]);
+  collab_1.initialize();
+  let collab_2 = Collab::new(2, "1", "2", vec![]);
+  collab_2.initialize();
+
+  let text = collab_1.with_origin_transact_mut(|txn| {
+    let document = collab_1.insert_map_with_txn(txn, "document");
+    document.insert_text_with_txn(txn, "text")
+  });
+  collab_1.with_origin_transact_mut(|txn| text.insert(txn, 0, "hello"));
+  // The two transactions are squashed into one item, but the blame still tells them apart.
+  std::thread::sleep(Duration::from_millis(5));
+  collab_1.with_origin_transact_mut(|txn| text.insert(txn, 5, " world"));
+
+  let blames = text.blame();
+  assert_eq!(blames.len(), 2);
+  assert_eq!(blames[0].text, "hello");
+  assert_eq!(blames[1].text, " world");
+  assert!(blames[0].authorship.timestamp < blames[1].authorship.timestamp);
+  for blame in &blames {
+    assert_eq!(blame.authorship.client_id, collab_1.get_doc().client_id());
+    assert_eq!(blame.authorship.uid, Some(1));
+    assert_eq!(blame.authorship.device_id.as_deref(), Some("1"));
+  }
+
+  sync(&collab_1, 1, &collab_2);
+  let text = {
+    let txn = collab_2.transact();
+    let document = collab_2.get_map_with_txn(&txn, vec!["document"]).unwrap();
+    document.get_text_ref_with_txn(&txn, "text").unwrap()
+  };
+  collab_2.with_origin_transact_mut(|txn| text.insert(txn, 5, ","));
+
+  // The remote client is bound to the origin of its update. The time of the remote edits is
+  // unknown.
+  let blames = text.blame();
+  let texts = blames
+    .iter()
+    .map(|blame| blame.text.as_str())
+    .collect::<Vec<_>>();
+  assert_eq!(texts, vec!["hello", ",", " world"]);
+  assert_eq!(blames[0].authorship, authorship(&collab_1, 1, None));
+  assert_eq!(blames[2].authorship, authorship(&collab_1, 1, None));
+  assert_eq!(blames[1].authorship.uid, Some(2));
+  assert!(blames[1].authorship.timestamp.is_some());
+
+  // The deleted ranges are skipped, and the ranges are counted in UTF-16 code units.
+  collab_2.with_origin_transact_mut(|txn| {
+    text.remove_range(txn, 0, 1);
+    text.insert(txn, 0, "😀");
+    text.insert(txn, 9, "😀");
+  });
+  let blames = text.blame();
+  let texts = blames
+    .iter()
+    .map(|blame| (blame.text.as_str(), blame.authorship.uid.unwrap()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    texts,
+    vec![
+      ("😀", 2),
+      ("ello", 1),
+      (",", 2),
+      (" w", 1),
+      ("😀", 2),
+      ("orld", 1)
+    ]
+  );
+}
+
+#[tokio::test]
+async fn blame_map_test() {
+  let local_metas = LocalMetaRecorder::default();
+  let collab_1 = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_metas.clone())
+    .build()
+    .unwrap();
+  collab_1.lock().initialize();
+  let mut collab_2 = Collab::new(2, "1", "2", vec![]);
+  collab_2.initialize();
+
+  {
+    let collab_1 = collab_1.lock();
+    collab_1.insert("title", "my database");
+    collab_1.with_origin_transact_mut(|txn| {
+      let cells = collab_1.insert_map_with_txn(txn, "cells");
+      cells.insert_map_with_txn(txn, "c1", MapPrelim::<lib0Any>::new());
+      cells.insert_map_with_txn(txn, "c2", MapPrelim::<lib0Any>::new());
+    });
+    collab_1.with_origin_transact_mut(|txn| {
+      let c1 = collab_1.get_map_with_txn(txn, vec!["cells", "c1"]).unwrap();
+      c1.insert_with_txn(txn, "data", "1");
+      let c2 = collab_1.get_map_with_txn(txn, vec!["cells", "c2"]).unwrap();
+      c2.insert_with_txn(txn, "data", "2");
+    });
+    sync(&collab_1, 1, &collab_2);
+  }
+
+  // Overwrite the value of a nested map.
+  collab_2.with_origin_transact_mut(|txn| {
+    let c2 = collab_2.get_map_with_txn(txn, vec!["cells", "c2"]).unwrap();
+    c2.insert_with_txn(txn, "data", "3");
+  });
+
+  let client_1 = collab_1.lock().get_doc().client_id();
+  let title = collab_2.blame(vec!["title"]).unwrap();
+  assert_eq!(title, authorship(&collab_1.lock(), 1, None));
+  let c1 = collab_2.blame(vec!["cells", "c1", "data"]).unwrap();
+  assert_eq!(c1.client_id, client_1);
+  let c2 = collab_2.blame(vec!["cells", "c2", "data"]).unwrap();
+  assert_eq!(c2.client_id, collab_2.get_doc().client_id());
+  assert_eq!(c2.uid, Some(2));
+  assert!(c2.timestamp.is_some());
+  assert!(collab_2.blame(vec!["cells", "c3", "data"]).is_none());
+
+  let cells = collab_2.blame_map(vec!["cells"]);
+  assert_eq!(cells.len(), 2);
+  assert!(cells.values().all(|cell| cell.client_id == client_1));
+
+  // The times of the remote edits are filled in by their transaction metadata.
+  collab_2.import_txn_metas(&local_metas.metas());
+  let title = collab_2.blame(vec!["title"]).unwrap();
+  assert_eq!(title.uid, Some(1));
+  assert!(title.timestamp.is_some());
+
+  // The registered author replaces the guessed one.
+  collab_2.register_author(client_1, &CollabOrigin::Client(CollabClient::new(3, "3")));
+  let c1 = collab_2.blame(vec!["cells", "c1", "data"]).unwrap();
+  assert_eq!(c1.uid, Some(3));
+  assert_eq!(c1.device_id.as_deref(), Some("3"));
+
+  // Removing a value only changes the delete set of the document.
+  let state_vector = collab_2.transact().state_vector();
+  collab_2.remove("title");
+  assert_eq!(collab_2.transact().state_vector(), state_vector);
+  assert!(collab_2.blame(vec!["title"]).is_none());
+}
+
+/// Apply the missing updates of the `from` collab to the `to` collab, with the origin of the
+/// `from` collab. The device id of the collabs is the same as the uid.
+fn sync(from: &Collab, uid: i64, to: &Collab) {
+  let state_vector = to.transact().state_vector();
+  let update = from.transact().encode_state_as_update_v1(&state_vector);
+  let origin = CollabOrigin::Client(CollabClient::new(uid, uid));
+  let mut txn = to.get_doc().transact_mut_with(origin);
+  txn.apply_update(Update::decode_v1(&update).unwrap());
+}
+
+fn authorship(collab: &Collab, uid: i64, timestamp: Option<i64>) -> Authorship {
+  Authorship {
+    client_id: collab.get_doc().client_id(),
+    uid: Some(uid),
+    device_id: Some(uid.to_string()),
+    timestamp,
+  }
+}
+
+#[derive(Default, Clone)]
+struct LocalMetaRecorder(Arc<RwLock<Vec<TransactionMeta>>>);
+
+impl LocalMetaRecorder {
+  fn metas(&self) -> Vec<TransactionMeta> {
+    self.0.read().clone()
+  }
+}
+
+impl CollabPlugin for LocalMetaRecorder {
+  fn receive_local_update_with_meta(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _update: &[u8],
+    meta: &TransactionMeta,
+  ) {
+    self.0.write().push(meta.clone());
+  }
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use collab::core::commit::{LocalCommit, TransactionMeta};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::*;
+use parking_lot::RwLock;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+
+use crate::helper::CollabStateCachePlugin;
+
+#[tokio::test]
+async fn plugin_reject_local_transaction_test() {
+  let local_updates = LocalMetaRecorder::default();
+  let update_cache = CollabStateCachePlugin::new();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(CommitValidator)
+    .with_plugin(local_updates.clone())
+    .with_plugin(update_cache.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let guard = collab.lock();
+  guard.try_insert("title", "hello").unwrap();
+
+  // The locked path can't be edited.
+  let result = guard.try_with_origin_transact_mut(|txn| {
+    guard.insert_with_txn(txn, "title", "world");
+    guard.insert_with_txn(txn, "locked", true);
+  });
+  assert!(matches!(result, Err(CollabError::TransactionRejected(_))));
+
+  // The update is too large.
+  let result = guard.try_insert("content", "a".repeat(1024));
+  assert!(matches!(result, Err(CollabError::TransactionRejected(_))));
+
+  // The infallible edits are reverted too.
+  guard.insert("locked", true);
+  drop(guard);
+
+  // The plugins only receive the rejected transactions merged with their revert, and the
+  // metadata of the rejected transactions is dropped.
+  assert_eq!(collab.to_json_value(), json!({"title": "hello"}));
+  let metas = local_updates.metas();
+  assert_eq!(metas.len(), 4);
+  assert_eq!(
+    metas
+      .iter()
+      .filter(|meta| meta.attributes.contains_key("paths"))
+      .count(),
+    1
+  );
+  let restored = Collab::new(1, "1", "1", vec![]);
+  {
+    let mut txn = restored.origin_transact_mut();
+    for update in update_cache.get_updates().unwrap() {
+      txn.apply_update(Update::decode_v1(&update).unwrap());
+    }
+  }
+  assert_eq!(restored.to_json_value(), json!({"title": "hello"}));
+}
+
+#[tokio::test]
+async fn plugin_attach_transaction_meta_test() {
+  let local_updates = LocalMetaRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(CommitValidator)
+    .with_plugin(local_updates.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  {
+    let guard = collab.lock();
+    guard.insert("title", "hello");
+    guard.with_origin_transact_mut(|txn| {
+      guard.insert_with_txn(txn, "title", "world");
+      guard.insert_with_txn(txn, "count", "1");
+    });
+  }
+
+  let metas = local_updates.metas();
+  assert_eq!(metas.len(), 2);
+  assert_eq!(metas[0].attributes.get("paths").unwrap(), "title");
+  assert_eq!(metas[1].attributes.get("paths").unwrap(), "count,title");
+
+  // The remote updates don't carry the metadata of the local transactions.
+  let remote = Collab::new(2, "1", "2", vec![]);
+  remote.insert("remote", "hello");
+  let (doc_state, _) = remote.encode_as_update_v1();
+  collab
+    .lock()
+    .get_doc()
+    .transact_mut()
+    .apply_update(Update::decode_v1(&doc_state).unwrap());
+  assert_eq!(local_updates.metas().len(), 2);
+}
+
+#[tokio::test]
+async fn local_transaction_meta_test() {
+  let local_updates = LocalMetaRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_updates.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  {
+    let guard = collab.lock();
+    guard.insert("title", "hello");
+    guard.with_action_transact_mut("rename view", |txn| {
+      guard.insert_with_txn(txn, "title", "world");
+    });
+    guard.insert("content", "paste");
+  }
+
+  let metas = local_updates.metas();
+  assert_eq!(metas.len(), 3);
+  // The author and the time are attached to every local transaction.
+  assert!(metas.iter().all(|meta| meta.uid == Some(1)));
+  assert!(metas.iter().all(|meta| meta.timestamp.is_some()));
+  assert!(metas[0].timestamp <= metas[2].timestamp);
+  // The action is only attached to the labeled transaction.
+  assert_eq!(metas[0].action, None);
+  assert_eq!(metas[1].action.as_deref(), Some("rename view"));
+  assert_eq!(metas[2].action, None);
+}
+
+/// Rejects the edits of the "locked" key and the updates that are larger than 512 bytes. The
+/// changed keys of the accepted transactions are attached as metadata.
+struct CommitValidator;
+
+impl CollabPlugin for CommitValidator {
+  fn before_commit(&self, commit: &mut LocalCommit) -> Result<(), String> {
+    let mut paths = commit
+      .changed_paths()
+      .into_iter()
+      .map(|path| path.join("/"))
+      .collect::<Vec<_>>();
+    if paths.iter().any(|path| path == "locked") {
+      return Err("the locked key can't be edited".to_string());
+    }
+    if commit.encode_update_v1().len() > 512 {
+      return Err("the update is too large".to_string());
+    }
+
+    paths.sort();
+    commit.insert_meta("paths", paths.join(","));
+    Ok(())
+  }
+}
+
+#[derive(Default, Clone)]
+struct LocalMetaRecorder(Arc<RwLock<Vec<TransactionMeta>>>);
+
+impl LocalMetaRecorder {
+  fn metas(&self) -> Vec<TransactionMeta> {
+    self.0.read().clone()
+  }
+}
+
+impl CollabPlugin for LocalMetaRecorder {
+  fn receive_local_update_with_meta(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _update: &[u8],
+    meta: &TransactionMeta,
+  ) {
+    self.0.write().push(meta.clone());
+  }
+}
@@ -0,0 +1,181 @@
+use collab::core::diff::diff_collab;
+use collab::core::path_observer::{PathChange, PathChangeKind, TextDeltaChange};
+use collab::preclude::*;
+use serde_json::json;
+
+#[tokio::test]
+async fn diff_map_test() {
+  let old = Collab::new(1, "1", "1", vec![]);
+  let new = Collab::new(1, "1", "1", vec![]);
+  for collab in [&old, &new] {
+    collab.with_origin_transact_mut(|txn| {
+      let views = collab.insert_map_with_txn(txn, "views");
+      let v1 = views.create_map_with_txn(txn, "v1");
+      v1.insert_with_txn(txn, "name", "my view");
+      v1.insert_with_txn(txn, "parent", "w1");
+      views.create_map_with_txn(txn, "v2");
+      collab.insert_with_txn(txn, "title", "hello");
+    });
+  }
+  assert!(diff_collab(&old, &new).is_empty());
+
+  new.with_origin_transact_mut(|txn| {
+    let views = new.get_map_with_txn(txn, vec!["views"]).unwrap();
+    views.delete_with_txn(txn, "v2");
+    let v3 = views.create_map_with_txn(txn, "v3");
+    v3.insert_with_txn(txn, "name", "new view");
+    let v1 = new.get_map_with_txn(txn, vec!["views", "v1"]).unwrap();
+    v1.insert_with_txn(txn, "name", "renamed view");
+    v1.delete_with_txn(txn, "parent");
+  });
+
+  assert_eq!(
+    diff_collab(&old, &new),
+    vec![
+      PathChange {
+        path: vec!["views", "v1"].into(),
+        kind: PathChangeKind::Updated {
+          key: "name".to_string(),
+          old_value: json!("my view"),
+          value: json!("renamed view"),
+        },
+      },
+      PathChange {
+        path: vec!["views", "v1"].into(),
+        kind: PathChangeKind::Removed {
+          key: "parent".to_string(),
+          old_value: json!("w1"),
+        },
+      },
+      PathChange {
+        path: vec!["views"].into(),
+        kind: PathChangeKind::Removed {
+          key: "v2".to_string(),
+          old_value: json!({}),
+        },
+      },
+      PathChange {
+        path: vec!["views"].into(),
+        kind: PathChangeKind::Inserted {
+          key: "v3".to_string(),
+          value: json!({"name": "new view"}),
+        },
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn diff_array_test() {
+  let old = Collab::new(1, "1", "1", vec![]);
+  old.with_origin_transact_mut(|txn| {
+    old.create_array_with_txn(txn, "children", vec!["a", "b", "c", "d"]);
+  });
+  let new = Collab::new(1, "1", "1", vec![]);
+  new.with_origin_transact_mut(|txn| {
+    new.create_array_with_txn(txn, "children", vec!["a", "c", "x", "y", "d", "b"]);
+  });
+
+  let kinds = diff_collab(&old, &new)
+    .into_iter()
+    .map(|change| {
+      assert_eq!(change.path, vec!["children"].into());
+      change.kind
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(
+    kinds,
+    vec![
+      PathChangeKind::ArrayDeleted { index: 1, len: 1 },
+      PathChangeKind::ArrayInserted {
+        index: 2,
+        values: vec![json!("x"), json!("y")],
+      },
+      PathChangeKind::ArrayInserted {
+        index: 5,
+        values: vec![json!("b")],
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn diff_large_array_test() {
+  let values = (0..1200).map(|i| i.to_string()).collect::<Vec<_>>();
+  let old = Collab::new(1, "1", "1", vec![]);
+  old.with_origin_transact_mut(|txn| {
+    let mut children = vec!["a".to_string(), "b".to_string()];
+    children.extend(values.clone());
+    children.push("z".to_string());
+    old.create_array_with_txn(txn, "children", children);
+  });
+  let new = Collab::new(1, "1", "1", vec![]);
+  new.with_origin_transact_mut(|txn| {
+    let mut children = vec!["a".to_string()];
+    children.extend(values.clone());
+    children.push("b".to_string());
+    children.push("z".to_string());
+    new.create_array_with_txn(txn, "children", children);
+  });
+
+  // The elements between the common prefix and suffix are replaced as a whole, instead of
+  // computing the longest common subsequence of the large arrays.
+  let mut inserted = values.iter().map(|value| json!(value)).collect::<Vec<_>>();
+  inserted.push(json!("b"));
+  let kinds = diff_collab(&old, &new)
+    .into_iter()
+    .map(|change| change.kind)
+    .collect::<Vec<_>>();
+  assert_eq!(
+    kinds,
+    vec![
+      PathChangeKind::ArrayDeleted {
+        index: 1,
+        len: 1201
+      },
+      PathChangeKind::ArrayInserted {
+        index: 1,
+        values: inserted,
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn diff_text_test() {
+  let old = Collab::new(1, "1", "1", vec![]);
+  let text = old.with_origin_transact_mut(|txn| {
+    let document = old.insert_map_with_txn(txn, "document");
+    let text = document.insert_text_with_txn(txn, "text");
+    text.insert(txn, 0, "hello 🙂 world");
+    text
+  });
+  let version = old.create_version(1, None);
+  old.with_origin_transact_mut(|txn| {
+    // The emoji takes two UTF-16 code units.
+    text.remove_range(txn, 9, 5);
+    text.insert(txn, 9, "collab");
+  });
+  let new = old;
+  let old = new.materialize_version(&version).unwrap();
+
+  assert_eq!(
+    diff_collab(&old, &new),
+    vec![PathChange {
+      path: vec!["document", "text"].into(),
+      kind: PathChangeKind::TextDelta {
+        delta: vec![
+          TextDeltaChange::Retain {
+            retain: 9,
+            attributes: None,
+          },
+          TextDeltaChange::Delete { delete: 5 },
+          TextDeltaChange::Insert {
+            insert: json!("collab"),
+            attributes: None,
+          },
+        ],
+      },
+    }]
+  );
+}
@@ -0,0 +1,62 @@
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::encoding::{convert_update, EncoderVersion};
+use collab::core::origin::CollabOrigin;
+use collab::preclude::*;
+use serde_json::json;
+
+#[tokio::test]
+async fn encode_collab_with_v2_test() {
+  // Type one character per transaction in the middle of the text, so the text is made of many
+  // small blocks.
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let text = collab.with_origin_transact_mut(|txn| {
+    let document = collab.insert_map_with_txn(txn, "document");
+    document.insert_text_with_txn(txn, "text")
+  });
+  for (i, c) in "hello world, ".repeat(20).chars().enumerate() {
+    collab.with_origin_transact_mut(|txn| text.insert(txn, (i / 2) as u32, &c.to_string()));
+  }
+  let (doc_state_v1, sv_v1) = collab.encode_as_update_v1();
+  let (doc_state_v2, sv_v2) = collab.encode_as_update_v2();
+  // The state vector is always encoded with the v1 encoding.
+  assert_eq!(sv_v1, sv_v2);
+  assert!(
+    doc_state_v2.len() < doc_state_v1.len(),
+    "v1: {}, v2: {}",
+    doc_state_v1.len(),
+    doc_state_v2.len()
+  );
+
+  let restored = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_raw_data(vec![doc_state_v2])
+    .with_encoder_version(EncoderVersion::V2)
+    .build()
+    .unwrap();
+  assert_eq!(restored.to_json_value(), collab.to_json_value());
+}
+
+#[tokio::test]
+async fn convert_update_test() {
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().insert("name", "appflowy");
+  let (doc_state, _) = collab.encode_as_update_v1();
+
+  let v2 = convert_update(&doc_state, EncoderVersion::V1, EncoderVersion::V2).unwrap();
+  let v1 = convert_update(&v2, EncoderVersion::V2, EncoderVersion::V1).unwrap();
+  assert_eq!(
+    convert_update(&v1, EncoderVersion::V1, EncoderVersion::V1).unwrap(),
+    v1
+  );
+  for (update, version) in [(v1, EncoderVersion::V1), (v2, EncoderVersion::V2)] {
+    let collab =
+      Collab::new_with_encoded_data(CollabOrigin::Empty, "1", vec![update], version, vec![])
+        .unwrap();
+    assert_eq!(collab.to_json_value(), json!({"name": "appflowy"}));
+  }
+
+  let merged = EncoderVersion::V2
+    .merge_updates(&[&EncoderVersion::V2.encode_update(&Update::new())])
+    .unwrap();
+  assert!(EncoderVersion::V2.decode_update(&merged).is_ok());
+}
@@ -1,5 +1,15 @@
+mod blame_test;
+mod commit_test;
+mod diff_test;
+mod encoding_test;
 mod helper;
 mod insert_test;
+mod observer_test;
+mod permission_test;
+mod plugin_test;
+mod remap_test;
 mod restore_test;
 mod struct_define;
+mod undo_test;
 mod update_test;
+mod version_test;
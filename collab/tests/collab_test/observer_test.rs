@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::core::path_observer::{PathChange, PathChangeEvent, PathChangeKind, TextDeltaChange};
+use collab::preclude::*;
+use parking_lot::RwLock;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+
+#[tokio::test]
+async fn observe_map_path_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.with_origin_transact_mut(|txn| {
+    let views = collab.insert_map_with_txn(txn, "views");
+    views.create_map_with_txn_if_not_exist(txn, "v1");
+    collab.insert_map_with_txn(txn, "workspace");
+  });
+
+  let events = EventRecorder::default();
+  let _subscription = collab.observe_path(vec!["views"], events.callback());
+  collab.with_origin_transact_mut(|txn| {
+    let v1 = collab.get_map_with_txn(txn, vec!["views", "v1"]).unwrap();
+    v1.insert_with_txn(txn, "name", "my view");
+    // The changes of other paths are not observed.
+    let workspace = collab.get_map_with_txn(txn, vec!["workspace"]).unwrap();
+    workspace.insert_with_txn(txn, "name", "my workspace");
+  });
+  collab.with_origin_transact_mut(|txn| {
+    let v1 = collab.get_map_with_txn(txn, vec!["views", "v1"]).unwrap();
+    v1.insert_with_txn(txn, "name", "new name");
+  });
+  collab.with_origin_transact_mut(|txn| {
+    let views = collab.get_map_with_txn(txn, vec!["views"]).unwrap();
+    views.delete_with_txn(txn, "v1");
+  });
+
+  let events = events.events();
+  assert_eq!(events.len(), 3);
+  assert_eq!(
+    events[0],
+    PathChangeEvent {
+      origin: CollabOrigin::Client(CollabClient::new(1, "1")),
+      changes: vec![PathChange {
+        path: vec!["views", "v1"].into(),
+        kind: PathChangeKind::Inserted {
+          key: "name".to_string(),
+          value: json!("my view"),
+        },
+      }],
+    }
+  );
+  assert_eq!(
+    events[1].changes[0].kind,
+    PathChangeKind::Updated {
+      key: "name".to_string(),
+      old_value: json!("my view"),
+      value: json!("new name"),
+    }
+  );
+  assert_eq!(
+    events[2].changes[0],
+    PathChange {
+      path: vec!["views"].into(),
+      kind: PathChangeKind::Removed {
+        key: "v1".to_string(),
+        // The content of the removed map is deleted with it.
+        old_value: json!({}),
+      },
+    }
+  );
+}
+
+#[tokio::test]
+async fn observe_array_and_text_path_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let text = collab.with_origin_transact_mut(|txn| {
+    let document = collab.insert_map_with_txn(txn, "document");
+    document.insert_array_with_txn::<String>(txn, "children", vec!["a".to_string()]);
+    document.insert_text_with_txn(txn, "text")
+  });
+
+  let events = EventRecorder::default();
+  let _subscription = collab.observe_path(vec!["document"], events.callback());
+  collab.with_origin_transact_mut(|txn| {
+    let children = collab
+      .get_array_with_txn(txn, vec!["document", "children"])
+      .unwrap();
+    children.push_back(txn, "b");
+    children.push_back(txn, "c");
+  });
+  collab.with_origin_transact_mut(|txn| {
+    let children = collab
+      .get_array_with_txn(txn, vec!["document", "children"])
+      .unwrap();
+    children.remove(txn, 0);
+  });
+  collab.with_origin_transact_mut(|txn| text.insert(txn, 0, "hello"));
+
+  let events = events.events();
+  assert_eq!(events.len(), 3);
+  assert_eq!(
+    events[0].changes[0],
+    PathChange {
+      path: vec!["document", "children"].into(),
+      kind: PathChangeKind::ArrayInserted {
+        index: 1,
+        values: vec![json!("b"), json!("c")],
+      },
+    }
+  );
+  assert_eq!(
+    events[1].changes[0].kind,
+    PathChangeKind::ArrayDeleted { index: 0, len: 1 }
+  );
+  assert_eq!(
+    events[2].changes[0],
+    PathChange {
+      path: vec!["document", "text"].into(),
+      kind: PathChangeKind::TextDelta {
+        delta: vec![TextDeltaChange::Insert {
+          insert: json!("hello"),
+          attributes: None,
+        }],
+      },
+    }
+  );
+
+  // The events are serde-serializable.
+  let value = serde_json::to_value(&events[1]).unwrap();
+  assert_eq!(
+    value["changes"][0],
+    json!({"path": ["document", "children"], "type": "array_deleted", "index": 0, "len": 1})
+  );
+  let event: PathChangeEvent = serde_json::from_value(value).unwrap();
+  assert_eq!(event, events[1]);
+}
+
+#[tokio::test]
+async fn observe_path_remote_origin_test() {
+  let remote = Collab::new(2, "1", "2", vec![]);
+  remote.insert("title", "hello");
+  let (doc_state, _) = remote.encode_as_update_v1();
+
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let events = EventRecorder::default();
+  let _subscription = collab.observe_path(vec!["title"], events.callback());
+  {
+    let mut txn = collab.get_doc().transact_mut_with(CollabOrigin::Server);
+    txn.apply_update(Update::decode_v1(&doc_state).unwrap());
+  }
+
+  let events = events.events();
+  assert_eq!(events.len(), 1);
+  assert_eq!(events[0].origin, CollabOrigin::Server);
+}
+
+#[derive(Default, Clone)]
+struct EventRecorder(Arc<RwLock<Vec<PathChangeEvent>>>);
+
+impl EventRecorder {
+  fn callback(&self) -> impl Fn(&PathChangeEvent) + 'static {
+    let events = self.0.clone();
+    move |event| events.write().push(event.clone())
+  }
+
+  fn events(&self) -> Vec<PathChangeEvent> {
+    self.0.read().clone()
+  }
+}
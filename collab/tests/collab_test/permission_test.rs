@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use collab::core::origin::CollabOrigin;
+use collab::core::permission::CollabEditPermission;
+use collab::error::CollabError;
+use collab::preclude::*;
+use parking_lot::RwLock;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+
+use crate::helper::CollabStateCachePlugin;
+
+#[tokio::test]
+async fn read_only_collab_refuse_edits_test() {
+  let local_updates = LocalUpdateRecorder::default();
+  let update_cache = CollabStateCachePlugin::new();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_updates.clone())
+    .with_plugin(update_cache.clone())
+    .with_edit_permission(CollabEditPermission::ReadOnly)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let guard = collab.lock();
+  let result = guard.try_insert("title", "hello");
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+
+  let result =
+    guard.try_with_origin_transact_mut(|txn| guard.insert_with_txn(txn, "title", "hello"));
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+
+  // The infallible edits are reverted.
+  guard.insert("title", "hello");
+  drop(guard);
+  assert_eq!(collab.to_json_value(), json!({}));
+
+  // The plugins only receive the refused edits merged with their revert.
+  assert_replay_eq(&collab.lock(), local_updates.updates());
+  assert_replay_eq(&collab.lock(), update_cache.get_updates().unwrap());
+}
+
+#[tokio::test]
+async fn read_only_collab_apply_remote_update_test() {
+  let remote = Collab::new(2, "1", "2", vec![]);
+  remote.insert("title", "hello");
+  let (doc_state, _) = remote.encode_as_update_v1();
+
+  let local_updates = LocalUpdateRecorder::default();
+  let update_cache = CollabStateCachePlugin::new();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_updates.clone())
+    .with_plugin(update_cache.clone())
+    .with_edit_permission(CollabEditPermission::ReadOnly)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  {
+    let collab = collab.lock();
+    let mut txn = collab.get_doc().transact_mut_with(CollabOrigin::Server);
+    txn.apply_update(Update::decode_v1(&doc_state).unwrap());
+  }
+
+  assert_eq!(collab.to_json_value(), json!({"title": "hello"}));
+  assert!(local_updates.updates().is_empty());
+  assert!(!update_cache.get_updates().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn path_scoped_collab_edit_test() {
+  let local_updates = LocalUpdateRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_updates.clone())
+    .with_edit_permission(CollabEditPermission::Paths(vec![vec!["comments"].into()]))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let guard = collab.lock();
+  // The comments can be edited.
+  guard
+    .try_with_origin_transact_mut(|txn| {
+      let comments = guard.insert_map_with_txn(txn, "comments");
+      comments.insert_with_txn(txn, "1", "great");
+    })
+    .unwrap();
+  guard
+    .try_with_origin_transact_mut(|txn| {
+      let comments = guard.get_map_with_txn(txn, vec!["comments"]).unwrap();
+      comments.insert_with_txn(txn, "2", "nice");
+    })
+    .unwrap();
+  assert_eq!(local_updates.updates().len(), 2);
+
+  // The other paths can't be edited.
+  let result = guard.try_insert("title", "hello");
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+
+  let result = guard.try_with_origin_transact_mut(|txn| {
+    guard.insert_with_txn(txn, "title", "hello");
+    let comments = guard.get_map_with_txn(txn, vec!["comments"]).unwrap();
+    comments.insert_with_txn(txn, "3", "refused");
+  });
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+  drop(guard);
+
+  // The refused edits are reverted, and the plugins receive them merged with their revert.
+  assert_eq!(
+    collab.to_json_value(),
+    json!({"comments": {"1": "great", "2": "nice"}})
+  );
+  assert_replay_eq(&collab.lock(), local_updates.updates());
+}
+
+#[tokio::test]
+async fn path_scoped_collab_continuous_updates_test() {
+  let local_updates = LocalUpdateRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_updates.clone())
+    .with_edit_permission(CollabEditPermission::Paths(vec![vec!["comments"].into()]))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let guard = collab.lock();
+  guard.insert("comments", "first");
+  guard.insert("title", "refused");
+  guard.insert("comments", "second");
+  drop(guard);
+  assert_eq!(collab.to_json_value(), json!({"comments": "second"}));
+
+  // The refused edit uses the clocks of the local client. The updates that the plugins receive
+  // must cover them, otherwise the last update can't be applied by the remote.
+  assert_replay_eq(&collab.lock(), local_updates.updates());
+}
+
+#[tokio::test]
+async fn path_scoped_collab_wrapper_edit_test() {
+  let local_updates = LocalUpdateRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(local_updates.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let guard = collab.lock();
+  let (meta, comments) = guard.with_origin_transact_mut(|txn| {
+    let meta = guard.insert_map_with_txn(txn, "meta");
+    let comments = guard.insert_map_with_txn(txn, "comments");
+    (meta, comments)
+  });
+  guard.set_edit_permission(CollabEditPermission::Paths(vec![vec!["comments"].into()]));
+
+  // The wrappers that the domain crates edit with are guarded the same as the collab.
+  comments.insert("1", "great");
+  meta.insert("name", "refused-via-wrapper");
+  let result = meta
+    .collab_ctx
+    .try_with_transact_mut(|txn| meta.insert_with_txn(txn, "name", "refused"));
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+
+  // The edits of the raw transactions are reverted when the transaction is dropped.
+  let mut txn = guard.origin_transact_mut();
+  meta.insert_with_txn(&mut txn, "name", "refused-via-txn");
+  drop(txn);
+  drop(guard);
+
+  assert_eq!(
+    collab.to_json_value(),
+    json!({"comments": {"1": "great"}, "meta": {}})
+  );
+  assert_replay_eq(&collab.lock(), local_updates.updates());
+}
+
+/// Apply the updates to a new collab, and check that it has the same state as the given collab.
+fn assert_replay_eq(collab: &Collab, updates: Vec<Vec<u8>>) {
+  let replay = Collab::new(2, "1", "2", vec![]);
+  {
+    let mut txn = replay.get_doc().transact_mut();
+    for update in updates {
+      txn.apply_update(Update::decode_v1(&update).unwrap());
+    }
+  }
+  assert_eq!(replay.to_json_value(), collab.to_json_value());
+  assert_eq!(
+    replay.transact().state_vector(),
+    collab.transact().state_vector()
+  );
+}
+
+#[derive(Default, Clone)]
+struct LocalUpdateRecorder(Arc<RwLock<Vec<Vec<u8>>>>);
+
+impl LocalUpdateRecorder {
+  fn updates(&self) -> Vec<Vec<u8>> {
+    self.0.read().clone()
+  }
+}
+
+impl CollabPlugin for LocalUpdateRecorder {
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    self.0.write().push(update.to_vec());
+  }
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use collab::core::collab_plugin::{AsyncCollabPlugin, PluginHook};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::*;
+use parking_lot::RwLock;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+
+use crate::helper::CollabStateCachePlugin;
+
+#[tokio::test]
+async fn async_plugin_receive_updates_in_order_test() {
+  let sync_updates = CollabStateCachePlugin::new();
+  let events = AsyncEventRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(sync_updates.clone())
+    .with_async_plugin(events.clone())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  for i in 0..5 {
+    collab.lock().insert(&i.to_string(), i.to_string());
+  }
+  let remote = Collab::new(2, "1", "2", vec![]);
+  remote.insert("remote", "hello");
+  let (doc_state, _) = remote.encode_as_update_v1();
+  collab
+    .lock()
+    .get_doc()
+    .transact_mut()
+    .apply_update(Update::decode_v1(&doc_state).unwrap());
+  let closed = collab.lock().close();
+  closed.await;
+
+  // The async plugin receives the same updates as the sync plugins, in the same order.
+  let updates = events.updates();
+  let local_updates = updates
+    .iter()
+    .filter(|(is_local, _)| *is_local)
+    .map(|(_, update)| update.clone())
+    .collect::<Vec<_>>();
+  assert_eq!(local_updates, sync_updates.get_updates().unwrap()[1..6]);
+  assert_eq!(updates.len(), 6);
+  assert!(!updates[5].0);
+
+  let restored = Collab::new(1, "1", "1", vec![]);
+  {
+    let mut txn = restored.origin_transact_mut();
+    for (_, update) in updates {
+      txn.apply_update(Update::decode_v1(&update).unwrap());
+    }
+  }
+  assert_eq!(
+    restored.to_json_value(),
+    json!({"0": "0", "1": "1", "2": "2", "3": "3", "4": "4", "remote": "hello"})
+  );
+
+  // The close hooks are the last ones.
+  let hooks = events.hooks();
+  assert_eq!(hooks.first(), Some(&PluginHook::Init));
+  assert_eq!(
+    &hooks[hooks.len() - 2..],
+    &[PluginHook::WillClose, PluginHook::DidClose]
+  );
+
+  // The plugins don't receive the updates after closing.
+  collab.lock().insert("closed", true);
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  assert_eq!(events.updates().len(), 6);
+}
+
+#[tokio::test]
+async fn async_plugin_report_errors_test() {
+  let events = AsyncEventRecorder::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_async_plugin(FailingPlugin)
+    .with_async_plugin(events.clone())
+    .build()
+    .unwrap();
+  let mut error_rx = collab.lock().subscribe_plugin_error();
+  collab.lock().initialize();
+
+  collab.lock().insert("title", "hello");
+  let error = error_rx.recv().await.unwrap();
+  assert_eq!(error.object_id, "1");
+  assert_eq!(error.plugin, "failing");
+  assert_eq!(error.hook, PluginHook::ReceiveLocalUpdate);
+  assert_eq!(error.error.to_string(), "disk is full");
+
+  // The error doesn't stop the other plugins.
+  let closed = collab.lock().close();
+  closed.await;
+  assert_eq!(events.updates().len(), 1);
+}
+
+#[tokio::test]
+async fn add_async_plugin_after_initialize_test() {
+  let mut collab = Collab::new(1, "1", "1", vec![]);
+  collab
+    .add_async_plugin(Arc::new(AsyncEventRecorder::default()))
+    .unwrap();
+  collab.initialize();
+
+  // The init hook of the plugin would never be called.
+  let events = AsyncEventRecorder::default();
+  assert!(matches!(
+    collab.add_async_plugin(Arc::new(events.clone())),
+    Err(CollabError::AsyncPluginAfterInit)
+  ));
+  collab.insert("title", "hello");
+  collab.close().await;
+  assert!(events.hooks().is_empty());
+}
+
+/// The received updates and whether they are local updates.
+type ReceivedUpdates = Vec<(bool, Vec<u8>)>;
+
+#[derive(Default, Clone)]
+struct AsyncEventRecorder {
+  hooks: Arc<RwLock<Vec<PluginHook>>>,
+  updates: Arc<RwLock<ReceivedUpdates>>,
+}
+
+impl AsyncEventRecorder {
+  fn hooks(&self) -> Vec<PluginHook> {
+    self.hooks.read().clone()
+  }
+
+  fn updates(&self) -> ReceivedUpdates {
+    self.updates.read().clone()
+  }
+}
+
+#[async_trait]
+impl AsyncCollabPlugin for AsyncEventRecorder {
+  async fn init(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    self.hooks.write().push(PluginHook::Init);
+    Ok(())
+  }
+
+  async fn receive_update(
+    &self,
+    _object_id: &str,
+    origin: &CollabOrigin,
+    update: &[u8],
+  ) -> Result<(), anyhow::Error> {
+    // Slow down the plugin, the next updates wait until the update is received.
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let is_local = origin != &CollabOrigin::Empty;
+    self.hooks.write().push(PluginHook::ReceiveUpdate);
+    self.updates.write().push((is_local, update.to_vec()));
+    Ok(())
+  }
+
+  async fn will_close(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    self.hooks.write().push(PluginHook::WillClose);
+    Ok(())
+  }
+
+  async fn did_close(&self, _object_id: &str) -> Result<(), anyhow::Error> {
+    self.hooks.write().push(PluginHook::DidClose);
+    Ok(())
+  }
+}
+
+struct FailingPlugin;
+
+#[async_trait]
+impl AsyncCollabPlugin for FailingPlugin {
+  fn name(&self) -> &str {
+    "failing"
+  }
+
+  async fn receive_local_update(
+    &self,
+    _object_id: &str,
+    _update: &[u8],
+  ) -> Result<(), anyhow::Error> {
+    Err(anyhow!("disk is full"))
+  }
+}
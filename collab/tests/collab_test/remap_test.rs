@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use collab::core::remap::remap_collab;
+use collab::preclude::text::YChange;
+use collab::preclude::*;
+use serde_json::json;
+
+#[tokio::test]
+async fn remap_collab_test() {
+  let source = Collab::new(1, "w1", "1", vec![]);
+  let text = source.with_origin_transact_mut(|txn| {
+    let views = source.insert_map_with_txn(txn, "views");
+    let v1 = views.create_map_with_txn(txn, "v1");
+    v1.insert_with_txn(txn, "id", "v1");
+    v1.insert_with_txn(txn, "parent_view_id", "w1");
+    v1.insert_with_txn(txn, "name", "v1");
+    source.create_array_with_txn(txn, "children", vec!["v1", "v2"]);
+    v1.insert_text_with_txn(txn, "text")
+  });
+  source.with_origin_transact_mut(|txn| {
+    text.insert(txn, 0, "hello 🙂 world");
+    let bold = Attrs::from([("bold".into(), true.into())]);
+    text.format(txn, 0, 5, bold);
+    let mention = Attrs::from([("mention".into(), "v2".into())]);
+    text.format(txn, 9, 5, mention);
+  });
+
+  let ids = HashMap::from([
+    ("w1".to_string(), "w2".to_string()),
+    ("v1".to_string(), "v3".to_string()),
+    ("v2".to_string(), "v4".to_string()),
+  ]);
+  let target = Collab::new(1, "w2", "1", vec![]);
+  remap_collab(&source, &target, &ids);
+
+  // The text content is not remapped, even if it's equal to an id.
+  assert_eq!(
+    target.to_json_value(),
+    json!({
+      "views": {
+        "v3": {
+          "id": "v3",
+          "parent_view_id": "w2",
+          "name": "v3",
+          "text": "hello 🙂 world",
+        }
+      },
+      "children": ["v3", "v4"],
+    })
+  );
+
+  let txn = target.transact();
+  let text = target
+    .get_map_with_txn(&txn, vec!["views", "v3"])
+    .unwrap()
+    .get_text_ref_with_txn(&txn, "text")
+    .unwrap();
+  let delta = text
+    .diff(&txn, YChange::identity)
+    .into_iter()
+    .map(|chunk| {
+      let insert = chunk.insert.to_string(&txn);
+      let attrs = chunk.attributes.map(|attrs| {
+        attrs
+          .iter()
+          .map(|(key, value)| (key.to_string(), value.to_string()))
+          .collect::<Vec<_>>()
+      });
+      (insert, attrs)
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(
+    delta,
+    vec![
+      (
+        "hello".to_string(),
+        Some(vec![("bold".to_string(), "true".to_string())])
+      ),
+      (" 🙂 ".to_string(), None),
+      (
+        "world".to_string(),
+        Some(vec![("mention".to_string(), "v4".to_string())])
+      ),
+    ]
+  );
+}
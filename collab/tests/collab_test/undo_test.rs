@@ -0,0 +1,219 @@
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::core::undo::{UndoOptions, UndoStackEvent, UndoStackEventKind, DEFAULT_UNDO_STACK};
+use collab::error::CollabError;
+use collab::preclude::*;
+use serde_json::json;
+use tokio::sync::broadcast::Receiver;
+
+#[tokio::test]
+async fn scoped_undo_stack_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.initialize();
+  collab.with_origin_transact_mut(|txn| {
+    let views = collab.insert_map_with_txn(txn, "views");
+    views.insert_map_with_txn(txn, "v1", MapPrelim::<lib0Any>::new());
+    views.insert_map_with_txn(txn, "v2", MapPrelim::<lib0Any>::new());
+  });
+  for view in ["v1", "v2"] {
+    let options = UndoOptions::new()
+      .with_capture_timeout(0)
+      .with_scope(vec!["views", view]);
+    collab.add_undo_stack(view, options).unwrap();
+  }
+  let result = collab.add_undo_stack("v3", UndoOptions::new().with_scope(vec!["views", "v3"]));
+  assert!(matches!(result, Err(CollabError::UndoScopeNotFound(_))));
+
+  collab.with_origin_transact_mut(|txn| {
+    let v1 = collab.get_map_with_txn(txn, vec!["views", "v1"]).unwrap();
+    v1.insert_with_txn(txn, "filter", "a");
+  });
+  collab.with_origin_transact_mut(|txn| {
+    let v2 = collab.get_map_with_txn(txn, vec!["views", "v2"]).unwrap();
+    v2.insert_with_txn(txn, "sort", "b");
+  });
+  // The edits out of the scopes are not tracked.
+  collab.insert("title", "my database");
+
+  // Undo in one view doesn't revert the edits of the other view.
+  assert!(collab.undo_with_stack("v1").unwrap());
+  assert!(!collab.can_undo_with_stack("v1"));
+  assert_eq!(
+    collab.to_json_value(),
+    json!({"title": "my database", "views": {"v1": {}, "v2": {"sort": "b"}}})
+  );
+
+  assert!(collab.redo_with_stack("v1").unwrap());
+  assert!(collab.undo_with_stack("v2").unwrap());
+  assert_eq!(
+    collab.to_json_value(),
+    json!({"title": "my database", "views": {"v1": {"filter": "a"}, "v2": {}}})
+  );
+
+  collab.remove_undo_stack("v1");
+  assert!(matches!(
+    collab.undo_with_stack("v1"),
+    Err(CollabError::UndoManagerNotEnabled)
+  ));
+}
+
+#[tokio::test]
+async fn undo_capture_timeout_test() {
+  let mut collab = Collab::new(1, "1", "1", vec![]);
+  collab.initialize();
+  collab.enable_undo_redo();
+  collab
+    .add_undo_stack("keystroke", UndoOptions::new().with_capture_timeout(0))
+    .unwrap();
+
+  collab.insert("1", "a");
+  collab.insert("2", "b");
+
+  // Every transaction is an item of the undo stack without the timeout.
+  assert!(collab.undo_with_stack("keystroke").unwrap());
+  assert_eq!(collab.to_json_value(), json!({"1": "a"}));
+
+  // The edits within the default timeout are merged into one item.
+  assert!(collab.undo().unwrap());
+  assert!(!collab.can_undo());
+  assert_eq!(collab.to_json_value(), json!({}));
+
+  collab.insert("3", "c");
+  collab.reset_undo_capture(DEFAULT_UNDO_STACK);
+  collab.insert("4", "d");
+  assert!(collab.undo().unwrap());
+  assert_eq!(collab.to_json_value(), json!({"3": "c"}));
+  assert!(collab.undo().unwrap());
+  assert_eq!(collab.to_json_value(), json!({}));
+}
+
+#[tokio::test]
+async fn undo_tracked_origin_test() {
+  let remote_origin = CollabOrigin::Client(CollabClient::new(2, "2"));
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.initialize();
+  collab
+    .add_undo_stack(
+      "remote",
+      UndoOptions::new()
+        .with_capture_timeout(0)
+        .with_tracked_origin(remote_origin.clone()),
+    )
+    .unwrap();
+
+  // Only the edits of the tracked origins are tracked.
+  collab.insert("local", "a");
+  assert!(!collab.can_undo_with_stack("remote"));
+  {
+    let mut txn = collab.get_doc().transact_mut_with(remote_origin);
+    collab.insert_with_txn(&mut txn, "remote", "b");
+  }
+  assert!(collab.undo_with_stack("remote").unwrap());
+  assert_eq!(collab.to_json_value(), json!({"local": "a"}));
+}
+
+#[tokio::test]
+async fn undo_meta_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.initialize();
+  collab
+    .add_undo_stack(
+      DEFAULT_UNDO_STACK,
+      UndoOptions::new().with_capture_timeout(0),
+    )
+    .unwrap();
+  let mut events = collab.subscribe_undo_stack();
+
+  collab.insert("title", "hello");
+  collab.with_action_transact_mut("paste", |txn| {
+    collab.insert_with_txn(txn, "content", "world");
+  });
+  assert!(collab.update_undo_meta(DEFAULT_UNDO_STACK, |meta| {
+    meta.cursor = Some("content:5".to_string());
+  }));
+
+  let event = next_event(&mut events);
+  assert_eq!(event.kind, UndoStackEventKind::Added);
+  assert_eq!(event.meta.action, None);
+  let event = next_event(&mut events);
+  assert_eq!(event.kind, UndoStackEventKind::Added);
+  assert_eq!(event.meta.action.as_deref(), Some("paste"));
+  assert_eq!((event.undo_len, event.redo_len), (2, 0));
+
+  // The metadata moves between the stacks together with the item.
+  assert!(collab.undo_with_stack(DEFAULT_UNDO_STACK).unwrap());
+  let event = next_event(&mut events);
+  assert_eq!(event.stack, DEFAULT_UNDO_STACK);
+  assert_eq!(event.kind, UndoStackEventKind::Undone);
+  assert_eq!(event.meta.action.as_deref(), Some("paste"));
+  assert_eq!(event.meta.cursor.as_deref(), Some("content:5"));
+  assert_eq!((event.undo_len, event.redo_len), (1, 1));
+  let redo_meta = collab.redo_meta(DEFAULT_UNDO_STACK).unwrap();
+  assert_eq!(redo_meta.cursor.as_deref(), Some("content:5"));
+  assert_eq!(collab.undo_meta(DEFAULT_UNDO_STACK).unwrap().action, None);
+
+  assert!(collab.redo_with_stack(DEFAULT_UNDO_STACK).unwrap());
+  let event = next_event(&mut events);
+  assert_eq!(event.kind, UndoStackEventKind::Redone);
+  assert_eq!(event.meta.cursor.as_deref(), Some("content:5"));
+  assert_eq!((event.undo_len, event.redo_len), (2, 0));
+  let undo_meta = collab.undo_meta(DEFAULT_UNDO_STACK).unwrap();
+  assert_eq!(undo_meta.action.as_deref(), Some("paste"));
+
+  // The new edits drop the redo stack.
+  assert!(collab.undo_with_stack(DEFAULT_UNDO_STACK).unwrap());
+  next_event(&mut events);
+  collab.insert("content", "new");
+  let event = next_event(&mut events);
+  assert_eq!(event.kind, UndoStackEventKind::Added);
+  assert_eq!((event.undo_len, event.redo_len), (2, 0));
+  assert!(collab.redo_meta(DEFAULT_UNDO_STACK).is_none());
+
+  collab.clear_undo_stack(DEFAULT_UNDO_STACK).unwrap();
+  let event = next_event(&mut events);
+  assert_eq!(event.kind, UndoStackEventKind::Cleared);
+  assert!(!collab.can_undo_with_stack(DEFAULT_UNDO_STACK));
+  assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn undo_remotely_deleted_item_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.initialize();
+  let document = collab.with_origin_transact_mut(|txn| collab.insert_map_with_txn(txn, "document"));
+  collab
+    .add_undo_stack(
+      DEFAULT_UNDO_STACK,
+      UndoOptions::new().with_capture_timeout(0),
+    )
+    .unwrap();
+  let mut events = collab.subscribe_undo_stack();
+
+  collab.with_action_transact_mut("paste", |txn| {
+    document.insert_with_txn(txn, "content", "world");
+  });
+  next_event(&mut events);
+  let undo_meta = collab.undo_meta(DEFAULT_UNDO_STACK).unwrap();
+  assert_eq!(undo_meta.action.as_deref(), Some("paste"));
+
+  // The edits of the untracked origins are not recorded, but they leave the item with nothing
+  // to revert.
+  {
+    let remote_origin = CollabOrigin::Client(CollabClient::new(2, "2"));
+    let mut txn = collab.get_doc().transact_mut_with(remote_origin);
+    document.remove(&mut txn, "content");
+  }
+  assert!(collab.can_undo_with_stack(DEFAULT_UNDO_STACK));
+
+  // The item is dropped without reverting anything, and its metadata goes with it.
+  assert!(!collab.undo_with_stack(DEFAULT_UNDO_STACK).unwrap());
+  assert!(!collab.can_undo_with_stack(DEFAULT_UNDO_STACK));
+  assert!(collab.undo_meta(DEFAULT_UNDO_STACK).is_none());
+  let event = next_event(&mut events);
+  assert_eq!(event.kind, UndoStackEventKind::Dropped);
+  assert_eq!((event.undo_len, event.redo_len), (0, 0));
+  assert!(events.try_recv().is_err());
+}
+
+fn next_event(events: &mut Receiver<UndoStackEvent>) -> UndoStackEvent {
+  events.try_recv().unwrap()
+}
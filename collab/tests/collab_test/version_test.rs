@@ -0,0 +1,173 @@
+use collab::core::collab::MutexCollab;
+use collab::core::origin::CollabOrigin;
+use collab::core::permission::CollabEditPermission;
+use collab::core::version::CollabVersion;
+use collab::error::CollabError;
+use collab::preclude::*;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+
+#[tokio::test]
+async fn materialize_version_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let text = collab.with_origin_transact_mut(|txn| {
+    let document = collab.insert_map_with_txn(txn, "document");
+    document.insert_with_txn(txn, "name", "v1");
+    document.insert_text_with_txn(txn, "text")
+  });
+  collab.with_origin_transact_mut(|txn| text.insert(txn, 0, "hello"));
+  let v1 = collab.create_version(1, Some("first".to_string()));
+
+  collab.with_origin_transact_mut(|txn| {
+    text.insert(txn, 5, " world");
+    text.remove_range(txn, 0, 1);
+    let document = collab.get_map_with_txn(txn, vec!["document"]).unwrap();
+    document.insert_with_txn(txn, "name", "v2");
+  });
+  let v2 = collab.create_version(2, None);
+  collab.with_origin_transact_mut(|txn| {
+    let document = collab.get_map_with_txn(txn, vec!["document"]).unwrap();
+    document.delete_with_txn(txn, "text");
+  });
+
+  let old_collab = collab.materialize_version(&v1).unwrap();
+  assert_eq!(
+    old_collab.to_json_value(),
+    json!({"document": {"name": "v1", "text": "hello"}})
+  );
+  let old_collab = collab.materialize_version(&v2).unwrap();
+  assert_eq!(
+    old_collab.to_json_value(),
+    json!({"document": {"name": "v2", "text": "ello world"}})
+  );
+  assert_eq!(collab.to_json_value(), json!({"document": {"name": "v2"}}));
+
+  // The materialized collab is read-only.
+  let result = old_collab.try_insert("name", "my document");
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+}
+
+#[tokio::test]
+async fn restore_version_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let remote = Collab::new(2, "1", "2", vec![]);
+  collab.with_origin_transact_mut(|txn| {
+    let document = collab.insert_map_with_txn(txn, "document");
+    document.insert_with_txn(txn, "name", "my document");
+    document.insert_array_with_txn::<String>(txn, "children", vec!["a".to_string()]);
+  });
+  let version = collab.create_version(1, Some("before".to_string()));
+
+  collab.with_origin_transact_mut(|txn| {
+    let document = collab.get_map_with_txn(txn, vec!["document"]).unwrap();
+    document.delete_with_txn(txn, "name");
+    let children = collab
+      .get_array_with_txn(txn, vec!["document", "children"])
+      .unwrap();
+    children.push_back(txn, "b");
+    collab.insert_with_txn(txn, "title", "hello");
+  });
+  sync(&collab, &remote);
+  let edited_version = collab.create_version(1, None);
+
+  let state_vector = collab.transact().state_vector();
+  collab.restore_version(&version).unwrap();
+  let expected = json!({"document": {"name": "my document", "children": ["a"]}});
+  assert_eq!(collab.to_json_value(), expected);
+
+  // The operations of the restore are made by the local client.
+  let client_id = collab.get_doc().client_id();
+  let restored_state_vector = collab.transact().state_vector();
+  assert_eq!(restored_state_vector.len(), state_vector.len());
+  for (client, clock) in restored_state_vector.iter() {
+    if *client == client_id {
+      assert!(*clock > state_vector.get(client));
+    } else {
+      assert_eq!(*clock, state_vector.get(client));
+    }
+  }
+
+  // The restore is a new edit, so the history after the version is kept.
+  let edited = collab.materialize_version(&edited_version).unwrap();
+  assert_eq!(
+    edited.to_json_value(),
+    json!({"document": {"children": ["a", "b"]}, "title": "hello"})
+  );
+
+  // The restore is synced like any other edit.
+  sync(&collab, &remote);
+  assert_eq!(remote.to_json_value(), expected);
+}
+
+#[tokio::test]
+async fn materialize_latest_version_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let remote = Collab::new(2, "1", "2", vec![]);
+  remote.insert("remote", "hello");
+  sync(&remote, &collab);
+  collab.with_origin_transact_mut(|txn| {
+    let document = collab.insert_map_with_txn(txn, "document");
+    let text = document.insert_text_with_txn(txn, "text");
+    text.insert(txn, 0, "abc");
+  });
+  // The version covers all the items of both clients.
+  let version = collab.create_version(1, None);
+  let expected = json!({"document": {"text": "abc"}, "remote": "hello"});
+  assert_eq!(
+    collab
+      .materialize_version(&version)
+      .unwrap()
+      .to_json_value(),
+    expected
+  );
+
+  collab.insert("remote", "world");
+  assert_eq!(
+    collab
+      .materialize_version(&version)
+      .unwrap()
+      .to_json_value(),
+    expected
+  );
+}
+
+#[tokio::test]
+async fn restore_version_of_read_only_collab_test() {
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().insert("title", "hello");
+  let version = collab.lock().create_version(1, None);
+  collab.lock().insert("title", "world");
+
+  collab
+    .lock()
+    .set_edit_permission(CollabEditPermission::ReadOnly);
+  let result = collab.lock().restore_version(&version);
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+  assert_eq!(collab.to_json_value(), json!({"title": "world"}));
+}
+
+#[tokio::test]
+async fn version_serde_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  collab.insert("title", "hello");
+  let version = collab.create_version(1, Some("first".to_string()));
+  collab.insert("title", "world");
+
+  let json = serde_json::to_string(&version).unwrap();
+  let decoded: CollabVersion = serde_json::from_str(&json).unwrap();
+  assert_eq!(decoded, version);
+  assert_eq!(
+    collab
+      .materialize_version(&decoded)
+      .unwrap()
+      .to_json_value(),
+    json!({"title": "hello"})
+  );
+}
+
+fn sync(from: &Collab, to: &Collab) {
+  let sv = to.transact().state_vector();
+  let update = from.transact().encode_state_as_update_v1(&sv);
+  let mut txn = to.get_doc().transact_mut_with(CollabOrigin::Server);
+  txn.apply_update(Update::decode_v1(&update).unwrap());
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use collab::core::collab::MutexCollab;
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::sync_protocol::auth::{CollabAuthenticator, CollabPermission};
+use collab::sync_protocol::envelope::{
+  CollabEnvelope, CollabFrame, CollabFrameReader, CollabSubscription,
+};
+use collab::sync_protocol::message::{Message, MessageReader, SyncMessage};
+use collab::sync_protocol::server::{CollabServer, CollabServerConfig};
+use collab::sync_protocol::{handle_msg, ClientSyncProtocol, CollabSyncProtocol};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde_json::json;
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+#[tokio::test]
+async fn editor_sync_with_server_test() {
+  let server = test_server();
+  let client = AuthTestClient::connect(&server, "1", 1, "token_1").await;
+  wait_until(|| client.auth_replies() == vec![None]).await;
+
+  client.collab.lock().insert("1", "a");
+  let group = server.get_group("1").unwrap();
+  wait_until(|| group.collab().to_json_value() == json!({"1": "a"})).await;
+}
+
+#[tokio::test]
+async fn read_only_client_update_rejected_test() {
+  let server = test_server();
+  let editor = AuthTestClient::connect(&server, "1", 1, "token_1").await;
+  let reader = AuthTestClient::connect(&server, "1", 2, "token_2").await;
+  wait_until(|| !reader.auth_replies().is_empty()).await;
+  assert!(reader.auth_replies()[0].is_none());
+
+  // The reader receives the updates of the editor.
+  editor.collab.lock().insert("1", "a");
+  wait_until(|| reader.collab.to_json_value() == json!({"1": "a"})).await;
+
+  // The update of the reader is rejected and never applied to the group.
+  reader.collab.lock().insert("2", "b");
+  wait_until(|| {
+    reader
+      .auth_replies()
+      .iter()
+      .flatten()
+      .any(|reason| reason.contains("can't edit"))
+  })
+  .await;
+
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.collab().to_json_value(), json!({"1": "a"}));
+  assert_eq!(editor.collab.to_json_value(), json!({"1": "a"}));
+}
+
+#[tokio::test]
+async fn invalid_token_test() {
+  let server = test_server();
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().insert("name", "appflowy");
+  let (doc_state, _) = collab.encode_as_update_v1();
+  server.create_group("1", vec![doc_state]).await.unwrap();
+
+  let client = AuthTestClient::new("1", 1);
+  client.collab.lock().insert("offline", "edit");
+  let client = client.connect_to(&server, "invalid_token").await;
+  wait_until(|| !client.auth_replies().is_empty()).await;
+  assert!(client.auth_replies()[0].is_some());
+
+  // The handshake is not started, so the client and the group don't exchange any data.
+  tokio::time::sleep(Duration::from_millis(300)).await;
+  assert_eq!(client.collab.to_json_value(), json!({"offline": "edit"}));
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.collab().to_json_value(), json!({"name": "appflowy"}));
+}
+
+#[tokio::test]
+async fn token_of_other_user_test() {
+  let server = test_server();
+  // The token belongs to the user 2.
+  let client = AuthTestClient::connect(&server, "1", 1, "token_2").await;
+  wait_until(|| !client.auth_replies().is_empty()).await;
+  assert!(client.auth_replies()[0].is_some());
+
+  client.collab.lock().insert("1", "a");
+  tokio::time::sleep(Duration::from_millis(300)).await;
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.collab().to_json_value(), json!({}));
+}
+
+#[tokio::test]
+async fn no_permission_to_object_test() {
+  let server = test_server();
+  // The user 1 doesn't have the permission to the object 2.
+  let client = AuthTestClient::connect(&server, "2", 1, "token_1").await;
+  wait_until(|| !client.auth_replies().is_empty()).await;
+  assert!(client.auth_replies()[0].is_some());
+}
+
+#[tokio::test]
+async fn multiplex_ack_after_handled_test() {
+  let server = Arc::new(test_server());
+  let origin = CollabOrigin::Client(CollabClient::new(2, "2"));
+  let (client_sink, server_stream) = unbounded::<Vec<u8>>();
+  let (server_sink, mut client_stream) = unbounded::<Vec<u8>>();
+  server.subscribe_multiplexed(
+    origin.clone(),
+    server_sink,
+    server_stream.map(Ok::<_, Infallible>),
+  );
+
+  let remote = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  remote.lock().insert("1", "a");
+  let (update, _) = remote.encode_as_update_v1();
+  let mut encoder = EncoderV1::new();
+  CollabSubscription::subscribe("1").encode(&mut encoder);
+  let messages = [
+    Message::AuthRequest("token_2".to_string()),
+    Message::Sync(SyncMessage::Update(update)),
+  ];
+  for (msg_id, msg) in messages.into_iter().enumerate() {
+    CollabEnvelope::new("1", origin.clone(), msg_id as u64, msg).encode(&mut encoder);
+  }
+  CollabEnvelope::new(
+    "2",
+    origin.clone(),
+    2,
+    Message::AuthRequest("token_2".to_string()),
+  )
+  .encode(&mut encoder);
+  client_sink.unbounded_send(encoder.to_vec()).unwrap();
+
+  // The envelopes are acknowledged after they are handled by the group. The update of the
+  // read-only client is denied, so it's rejected.
+  let mut acks = HashMap::new();
+  while acks.len() < 3 {
+    let data = tokio::time::timeout(Duration::from_secs(5), client_stream.next())
+      .await
+      .unwrap()
+      .unwrap();
+    let mut decoder = DecoderV1::from(data.as_slice());
+    for frame in CollabFrameReader::new(&mut decoder) {
+      if let CollabFrame::Ack(ack) = frame.unwrap() {
+        acks.insert(ack.msg_id, ack.is_ok());
+      }
+    }
+  }
+  assert_eq!(acks, HashMap::from([(0, true), (1, false), (2, false)]));
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.collab().to_json_value(), json!({}));
+}
+
+fn test_server() -> CollabServer {
+  let authenticator = TestAuthenticator {
+    tokens: HashMap::from([("token_1".to_string(), 1), ("token_2".to_string(), 2)]),
+    permissions: HashMap::from([
+      ((1, "1".to_string()), CollabPermission::Edit),
+      ((2, "1".to_string()), CollabPermission::Read),
+    ]),
+  };
+  CollabServer::new(CollabServerConfig::default()).with_authenticator(Arc::new(authenticator))
+}
+
+struct TestAuthenticator {
+  tokens: HashMap<String, i64>,
+  permissions: HashMap<(i64, String), CollabPermission>,
+}
+
+#[async_trait]
+impl CollabAuthenticator for TestAuthenticator {
+  async fn authenticate(&self, token: &str) -> Result<i64, anyhow::Error> {
+    self
+      .tokens
+      .get(token)
+      .cloned()
+      .ok_or_else(|| anyhow::anyhow!("unknown token"))
+  }
+
+  async fn permission(&self, uid: i64, object_id: &str) -> Option<CollabPermission> {
+    self.permissions.get(&(uid, object_id.to_string())).cloned()
+  }
+}
+
+struct AuthTestClient {
+  origin: CollabOrigin,
+  object_id: String,
+  collab: MutexCollab,
+  sink: UnboundedSender<Vec<u8>>,
+  server_stream: Option<futures::channel::mpsc::UnboundedReceiver<Vec<u8>>>,
+  /// The replies of the [Message::AuthRequest] and the rejected messages.
+  auth_replies: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl AuthTestClient {
+  fn new(object_id: &str, uid: i64) -> Self {
+    let origin = CollabOrigin::Client(CollabClient::new(uid, uid.to_string()));
+    let (sink, server_stream) = unbounded();
+    let plugin = ForwardLocalUpdatePlugin { sink: sink.clone() };
+    let collab = MutexCollab::new(origin.clone(), object_id, vec![Arc::new(plugin)]);
+    collab.lock().initialize();
+    Self {
+      origin,
+      object_id: object_id.to_string(),
+      collab,
+      sink,
+      server_stream: Some(server_stream),
+      auth_replies: Default::default(),
+    }
+  }
+
+  async fn connect(server: &CollabServer, object_id: &str, uid: i64, token: &str) -> Self {
+    Self::new(object_id, uid).connect_to(server, token).await
+  }
+
+  async fn connect_to(mut self, server: &CollabServer, token: &str) -> Self {
+    let (server_sink, mut client_stream) = unbounded::<Vec<u8>>();
+    server
+      .subscribe(
+        &self.object_id,
+        self.origin.clone(),
+        server_sink,
+        self.server_stream.take().unwrap().map(Ok::<_, Infallible>),
+      )
+      .await
+      .unwrap();
+
+    // Present the token before starting the sync.
+    let mut encoder = EncoderV1::new();
+    Message::AuthRequest(token.to_string()).encode(&mut encoder);
+    ClientSyncProtocol
+      .start(self.collab.lock().get_awareness(), &mut encoder)
+      .unwrap();
+    self.sink.unbounded_send(encoder.to_vec()).unwrap();
+
+    let collab = self.collab.clone();
+    let sink = self.sink.clone();
+    let auth_replies = self.auth_replies.clone();
+    tokio::spawn(async move {
+      while let Some(data) = client_stream.next().await {
+        let mut decoder = DecoderV1::from(data.as_slice());
+        let messages = MessageReader::new(&mut decoder)
+          .flatten()
+          .collect::<Vec<_>>();
+        for msg in messages {
+          if let Message::Auth(reason) = msg {
+            auth_replies.lock().push(reason);
+            continue;
+          }
+
+          let origin = CollabOrigin::Server;
+          if let Some(reply) = handle_msg(&Some(&origin), &ClientSyncProtocol, &collab, msg)
+            .await
+            .unwrap()
+          {
+            let _ = sink.unbounded_send(reply);
+          }
+        }
+      }
+    });
+    self
+  }
+
+  fn auth_replies(&self) -> Vec<Option<String>> {
+    self.auth_replies.lock().clone()
+  }
+}
+
+struct ForwardLocalUpdatePlugin {
+  sink: UnboundedSender<Vec<u8>>,
+}
+
+impl CollabPlugin for ForwardLocalUpdatePlugin {
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    let msg = Message::Sync(SyncMessage::Update(update.to_vec()));
+    let _ = self.sink.unbounded_send(msg.encode_v1());
+  }
+}
+
+async fn wait_until<F: Fn() -> bool>(f: F) {
+  for _ in 0..50 {
+    if f() {
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  panic!("timeout");
+}
@@ -0,0 +1,219 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::collab::MutexCollab;
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::encoding::EncoderVersion;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::sync_protocol::auth::{AuthorizedServerSyncProtocol, CollabPermission};
+use collab::sync_protocol::awareness::Awareness;
+use collab::sync_protocol::message::{Message, MessageReader, SyncMessage};
+use collab::sync_protocol::server::{CollabServer, CollabServerConfig};
+use collab::sync_protocol::{
+  handle_msg, CollabSyncProtocol, ServerSyncProtocol, VersionedClientSyncProtocol,
+};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde_json::json;
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::Doc;
+
+#[test]
+fn encode_v2_message_test() {
+  let messages = vec![
+    Message::Encoding(EncoderVersion::V2),
+    Message::Sync(SyncMessage::SyncStep2V2(vec![1, 2, 3])),
+    Message::Sync(SyncMessage::UpdateV2(vec![4, 5, 6])),
+  ];
+  for msg in messages {
+    assert_eq!(Message::decode_v1(&msg.encode_v1()).unwrap(), msg);
+  }
+
+  assert_eq!(
+    SyncMessage::update(EncoderVersion::V1, vec![1]),
+    SyncMessage::Update(vec![1])
+  );
+  assert!(SyncMessage::sync_step2(EncoderVersion::V2, vec![1]).is_sync_step2());
+}
+
+#[test]
+fn server_protocol_only_agrees_on_its_encoding_test() {
+  let awareness = Awareness::new(Doc::new());
+  let reply = ServerSyncProtocol
+    .handle_encoding(&awareness, EncoderVersion::V2)
+    .unwrap()
+    .unwrap();
+  assert_eq!(
+    Message::decode_v1(&reply).unwrap(),
+    Message::Encoding(EncoderVersion::V1)
+  );
+
+  let protocol = AuthorizedServerSyncProtocol::new(CollabPermission::Edit)
+    .with_encoder_version(EncoderVersion::V2);
+  let reply = protocol
+    .handle_encoding(&awareness, EncoderVersion::V2)
+    .unwrap()
+    .unwrap();
+  assert_eq!(
+    Message::decode_v1(&reply).unwrap(),
+    Message::Encoding(EncoderVersion::V2)
+  );
+}
+
+#[tokio::test]
+async fn v2_client_sync_with_server_test() {
+  let server = CollabServer::new(CollabServerConfig::default());
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().insert("name", "appflowy");
+  let (doc_state, _) = collab.encode_as_update_v1();
+  server.create_group("1", vec![doc_state]).await.unwrap();
+
+  let v1_client = EncodingTestClient::connect(&server, 1, EncoderVersion::V1).await;
+  let v2_client = EncodingTestClient::connect(&server, 2, EncoderVersion::V2).await;
+  wait_until(|| v2_client.collab.to_json_value() == json!({"name": "appflowy"})).await;
+  wait_until(|| v1_client.collab.to_json_value() == json!({"name": "appflowy"})).await;
+  assert_eq!(v2_client.encoder_version(), EncoderVersion::V2);
+  assert_eq!(v1_client.encoder_version(), EncoderVersion::V1);
+
+  // The updates of the v2 client are applied by the server and broadcast to the v1 client.
+  v2_client.collab.lock().insert("1", "a");
+  wait_until(|| v1_client.collab.to_json_value() == json!({"name": "appflowy", "1": "a"})).await;
+
+  v1_client.collab.lock().insert("2", "b");
+  wait_until(|| {
+    v2_client.collab.to_json_value() == json!({"name": "appflowy", "1": "a", "2": "b"})
+  })
+  .await;
+
+  // Each client only receives the updates encoded with its version.
+  let v1_tags = v1_client.received_tags();
+  let v2_tags = v2_client.received_tags();
+  assert!(v1_tags.contains(&"Update") && !v1_tags.contains(&"UpdateV2"));
+  assert!(v2_tags.contains(&"UpdateV2") && !v2_tags.contains(&"Update"));
+  assert!(v2_tags.contains(&"SyncStep2V2") && !v2_tags.contains(&"SyncStep2"));
+}
+
+/// A client that announces the latest [EncoderVersion] it can decode before the sync. It uses
+/// the version replied by the server for the updates it sends.
+struct EncodingTestClient {
+  collab: MutexCollab,
+  encoder_version: Arc<Mutex<EncoderVersion>>,
+  received_tags: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl EncodingTestClient {
+  async fn connect(server: &CollabServer, uid: i64, encoder_version: EncoderVersion) -> Self {
+    let origin = CollabOrigin::Client(CollabClient::new(uid, uid.to_string()));
+    let agreed_version = Arc::new(Mutex::new(EncoderVersion::V1));
+    let (sink, server_stream) = unbounded::<Vec<u8>>();
+    let plugin = ForwardLocalUpdatePlugin {
+      sink: sink.clone(),
+      encoder_version: agreed_version.clone(),
+    };
+    let collab = MutexCollab::new(origin.clone(), "1", vec![Arc::new(plugin)]);
+    collab.lock().initialize();
+
+    let (server_sink, mut client_stream) = unbounded::<Vec<u8>>();
+    server
+      .subscribe(
+        "1",
+        origin,
+        server_sink,
+        server_stream.map(Ok::<_, Infallible>),
+      )
+      .await
+      .unwrap();
+
+    let mut encoder = EncoderV1::new();
+    if encoder_version != EncoderVersion::V1 {
+      Message::Encoding(encoder_version).encode(&mut encoder);
+    }
+    VersionedClientSyncProtocol::new(EncoderVersion::V1)
+      .start(collab.lock().get_awareness(), &mut encoder)
+      .unwrap();
+    sink.unbounded_send(encoder.to_vec()).unwrap();
+
+    let received_tags = Arc::new(Mutex::new(vec![]));
+    let cloned_collab = collab.clone();
+    let cloned_version = agreed_version.clone();
+    let cloned_tags = received_tags.clone();
+    tokio::spawn(async move {
+      while let Some(data) = client_stream.next().await {
+        let mut decoder = DecoderV1::from(data.as_slice());
+        let messages = MessageReader::new(&mut decoder)
+          .flatten()
+          .collect::<Vec<_>>();
+        for msg in messages {
+          if let Message::Sync(sync_msg) = &msg {
+            cloned_tags.lock().push(sync_message_tag(sync_msg));
+          }
+          if let Message::Encoding(version) = &msg {
+            *cloned_version.lock() = *version;
+          }
+          let protocol = VersionedClientSyncProtocol::new(*cloned_version.lock());
+          let origin = CollabOrigin::Server;
+          if let Some(reply) = handle_msg(&Some(&origin), &protocol, &cloned_collab, msg)
+            .await
+            .unwrap()
+          {
+            let _ = sink.unbounded_send(reply);
+          }
+        }
+      }
+    });
+
+    Self {
+      collab,
+      encoder_version: agreed_version,
+      received_tags,
+    }
+  }
+
+  fn encoder_version(&self) -> EncoderVersion {
+    *self.encoder_version.lock()
+  }
+
+  fn received_tags(&self) -> Vec<&'static str> {
+    self.received_tags.lock().clone()
+  }
+}
+
+struct ForwardLocalUpdatePlugin {
+  sink: UnboundedSender<Vec<u8>>,
+  encoder_version: Arc<Mutex<EncoderVersion>>,
+}
+
+impl CollabPlugin for ForwardLocalUpdatePlugin {
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    let encoder_version = *self.encoder_version.lock();
+    let update = EncoderVersion::V1.decode_update(update).unwrap();
+    let msg = Message::Sync(SyncMessage::update(
+      encoder_version,
+      encoder_version.encode_update(&update),
+    ));
+    let _ = self.sink.unbounded_send(msg.encode_v1());
+  }
+}
+
+fn sync_message_tag(msg: &SyncMessage) -> &'static str {
+  match msg {
+    SyncMessage::SyncStep1(_) => "SyncStep1",
+    SyncMessage::SyncStep2(_) => "SyncStep2",
+    SyncMessage::Update(_) => "Update",
+    SyncMessage::SyncStep2V2(_) => "SyncStep2V2",
+    SyncMessage::UpdateV2(_) => "UpdateV2",
+  }
+}
+
+async fn wait_until<F: Fn() -> bool>(f: F) {
+  for _ in 0..50 {
+    if f() {
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  panic!("timeout");
+}
@@ -0,0 +1,128 @@
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::sync_protocol::envelope::{
+  AckStatus, CollabEnvelope, CollabFrame, CollabFrameReader, CollabSubscription, MsgIdGenerator,
+  SubscriptionAction, ENVELOPE_VERSION,
+};
+use collab::sync_protocol::message::{Message, MessageReader, SyncMessage, MSG_ENVELOPE};
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::StateVector;
+
+fn client_origin() -> CollabOrigin {
+  CollabOrigin::Client(CollabClient::new(1, "1"))
+}
+
+#[test]
+fn envelope_encode_decode_test() {
+  let envelope = CollabEnvelope::new(
+    "object_1",
+    client_origin(),
+    7,
+    Message::Sync(SyncMessage::Update(vec![1, 2, 3])),
+  );
+  let frame = CollabFrame::decode_v1(&envelope.encode_v1()).unwrap();
+  match frame {
+    CollabFrame::Envelope(decoded) => {
+      assert_eq!(decoded.version, ENVELOPE_VERSION);
+      assert_eq!(decoded.object_id, "object_1");
+      assert_eq!(decoded.origin, client_origin());
+      assert_eq!(decoded.msg_id, 7);
+      assert_eq!(decoded, envelope);
+    },
+    _ => panic!("expected envelope"),
+  }
+}
+
+#[test]
+fn ack_and_nack_encode_decode_test() {
+  let envelope = CollabEnvelope::new("object_1", CollabOrigin::Server, 3, Message::AwarenessQuery);
+
+  let ack = envelope.ack();
+  assert!(ack.is_ok());
+  assert_eq!(
+    CollabFrame::decode_v1(&ack.encode_v1()).unwrap(),
+    CollabFrame::Ack(ack)
+  );
+
+  let nack = envelope.nack("invalid update");
+  assert_eq!(nack.msg_id, 3);
+  assert_eq!(
+    nack.status,
+    AckStatus::Rejected("invalid update".to_string())
+  );
+  assert_eq!(
+    CollabFrame::decode_v1(&nack.encode_v1()).unwrap(),
+    CollabFrame::Ack(nack)
+  );
+}
+
+#[test]
+fn read_mixed_frames_test() {
+  let mut encoder = EncoderV1::new();
+  Message::Sync(SyncMessage::SyncStep1(StateVector::default())).encode(&mut encoder);
+  CollabEnvelope::new("object_1", client_origin(), 1, Message::AwarenessQuery).encode(&mut encoder);
+  Message::Auth(None).encode(&mut encoder);
+  let data = encoder.to_vec();
+
+  let mut decoder = DecoderV1::from(data.as_slice());
+  let frames = CollabFrameReader::new(&mut decoder)
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(frames.len(), 3);
+  assert!(matches!(
+    frames[0],
+    CollabFrame::Message(Message::Sync(SyncMessage::SyncStep1(_)))
+  ));
+  assert!(matches!(frames[1], CollabFrame::Envelope(_)));
+  assert_eq!(frames[2], CollabFrame::Message(Message::Auth(None)));
+}
+
+#[test]
+fn old_peer_read_envelope_as_custom_message_test() {
+  let mut encoder = EncoderV1::new();
+  CollabEnvelope::new(
+    "object_1",
+    client_origin(),
+    1,
+    Message::Sync(SyncMessage::Update(vec![1, 2, 3])),
+  )
+  .encode(&mut encoder);
+  Message::AwarenessQuery.encode(&mut encoder);
+  let data = encoder.to_vec();
+
+  // The peer that only knows about the [Message] can still read the messages after the envelope.
+  let mut decoder = DecoderV1::from(data.as_slice());
+  let messages = MessageReader::new(&mut decoder)
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(messages.len(), 2);
+  assert!(matches!(messages[0], Message::Custom(MSG_ENVELOPE, _)));
+  assert_eq!(messages[1], Message::AwarenessQuery);
+}
+
+#[test]
+fn msg_id_generator_test() {
+  let generator = MsgIdGenerator::new();
+  assert_eq!(generator.next_id(), 1);
+  assert_eq!(generator.next_id(), 2);
+
+  let generator = MsgIdGenerator::with_start(10);
+  assert_eq!(generator.next_id(), 11);
+}
+
+#[test]
+fn subscription_encode_decode_test() {
+  let subscribe = CollabSubscription::subscribe("object_1");
+  assert_eq!(subscribe.action, SubscriptionAction::Subscribe);
+  assert_eq!(
+    CollabFrame::decode_v1(&subscribe.encode_v1()).unwrap(),
+    CollabFrame::Subscription(subscribe)
+  );
+
+  let unsubscribe = CollabSubscription::unsubscribe("object_1");
+  assert_eq!(unsubscribe.action, SubscriptionAction::Unsubscribe);
+  assert_eq!(
+    CollabFrame::decode_v1(&unsubscribe.encode_v1()).unwrap(),
+    CollabFrame::Subscription(unsubscribe)
+  );
+}
@@ -1,2 +1,7 @@
+mod auth_test;
+mod encoding_test;
+mod envelope_test;
 mod observer_test;
+mod presence_test;
+mod server_test;
 mod state_vec_test;
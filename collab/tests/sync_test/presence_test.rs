@@ -0,0 +1,145 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::collab::MutexCollab;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::preclude::*;
+use collab::sync_protocol::presence::{
+  CollabPresence, PresenceConfig, PresenceEvent, PresenceSelection, PresenceUser,
+};
+use yrs::updates::decoder::Decode;
+
+#[tokio::test]
+async fn receive_remote_presence_test() {
+  let collab_1 = test_collab(1);
+  let collab_2 = test_collab(2);
+  let presence_1 = CollabPresence::new(&collab_1, PresenceConfig::default());
+  let presence_2 = CollabPresence::new(&collab_2, PresenceConfig::default());
+  let mut events = presence_2.subscribe();
+
+  presence_1.set_local_state(
+    test_user(1),
+    Some(PresenceSelection::path(vec!["row_1".into()])),
+  );
+  sync_awareness(&collab_1, &collab_2);
+
+  let client_id_1 = collab_1.lock().get_awareness().client_id();
+  let state = presence_1.local_state().unwrap();
+  assert_eq!(state.user, test_user(1));
+  assert_eq!(
+    events.recv().await.unwrap(),
+    PresenceEvent::Added(client_id_1, state.clone())
+  );
+  assert_eq!(presence_2.remote_states().get(&client_id_1), Some(&state));
+
+  presence_1.set_local_selection(None);
+  sync_awareness(&collab_1, &collab_2);
+  match events.recv().await.unwrap() {
+    PresenceEvent::Updated(client_id, state) => {
+      assert_eq!(client_id, client_id_1);
+      assert_eq!(state.selection, None);
+    },
+    event => panic!("unexpected event: {:?}", event),
+  }
+}
+
+#[tokio::test]
+async fn remote_cursor_follow_text_edits_test() {
+  let collab_1 = test_collab(1);
+  let collab_2 = test_collab(2);
+  let presence_1 = CollabPresence::new(&collab_1, PresenceConfig::default());
+  let presence_2 = CollabPresence::new(&collab_2, PresenceConfig::default());
+
+  // Put the cursor of the user 1 before the "world".
+  let selection = {
+    let collab = collab_1.lock();
+    let text = collab.insert("text", TextPrelim::new("hello world"));
+    collab.with_origin_transact_mut(|txn| PresenceSelection::cursor(txn, &text, 6))
+  };
+  presence_1.set_local_state(test_user(1), selection);
+  sync_doc(&collab_1, &collab_2);
+  sync_awareness(&collab_1, &collab_2);
+
+  let client_id_1 = collab_1.lock().get_awareness().client_id();
+  let state = presence_2.remote_states().remove(&client_id_1).unwrap();
+
+  // The user 2 inserts the text at the beginning.
+  let collab = collab_2.lock();
+  let text = collab.get("text").unwrap().to_ytext().unwrap();
+  collab.with_origin_transact_mut(|txn| text.insert(txn, 0, "abc"));
+  let range = state
+    .selection
+    .unwrap()
+    .text_range(&collab.transact())
+    .unwrap();
+  assert_eq!(range, (9, 9));
+}
+
+#[tokio::test]
+async fn remove_outdated_remote_presence_test() {
+  let config = PresenceConfig::default()
+    .with_timeout(Duration::from_millis(300))
+    .with_check_interval(Duration::from_millis(50));
+  let collab_1 = test_collab(1);
+  let collab_2 = test_collab(2);
+  let presence_1 = CollabPresence::new(&collab_1, config.clone());
+  let presence_2 = CollabPresence::new(&collab_2, config);
+  let mut events = presence_2.subscribe();
+
+  presence_1.set_local_state(test_user(1), None);
+  presence_2.set_local_state(test_user(2), None);
+  sync_awareness(&collab_1, &collab_2);
+
+  let client_id_1 = collab_1.lock().get_awareness().client_id();
+  let client_id_2 = collab_2.lock().get_awareness().client_id();
+  assert_eq!(presence_2.remote_states().len(), 1);
+
+  // The user 1 stops syncing its awareness, so it will be removed after the timeout.
+  let removed = tokio::time::timeout(Duration::from_secs(2), async {
+    loop {
+      if let PresenceEvent::Removed(client_id) = events.recv().await.unwrap() {
+        return client_id;
+      }
+    }
+  })
+  .await
+  .unwrap();
+  assert_eq!(removed, client_id_1);
+  assert!(presence_2.remote_states().is_empty());
+
+  // The local state is renewed, so it's never removed.
+  let local_state = presence_2.local_state().unwrap();
+  assert_eq!(local_state.user, test_user(2));
+  assert!(collab_2
+    .lock()
+    .get_awareness()
+    .clients()
+    .contains_key(&client_id_2));
+}
+
+fn test_collab(uid: i64) -> Arc<MutexCollab> {
+  let origin = CollabOrigin::Client(CollabClient::new(uid, uid.to_string()));
+  let collab = MutexCollab::new(origin, "1", vec![]);
+  collab.lock().initialize();
+  Arc::new(collab)
+}
+
+fn test_user(uid: i64) -> PresenceUser {
+  PresenceUser {
+    uid,
+    name: format!("user {}", uid),
+    color: "#FF0000".to_string(),
+  }
+}
+
+fn sync_awareness(from: &MutexCollab, to: &MutexCollab) {
+  let update = from.lock().get_awareness().update().unwrap();
+  to.lock().get_mut_awareness().apply_update(update).unwrap();
+}
+
+fn sync_doc(from: &MutexCollab, to: &MutexCollab) {
+  let (doc_state, _) = from.encode_as_update_v1();
+  let collab = to.lock();
+  let mut txn = collab.get_doc().transact_mut();
+  txn.apply_update(Update::decode_v1(&doc_state).unwrap());
+}
@@ -0,0 +1,179 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::collab::MutexCollab;
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::sync_protocol::message::{Message, MessageReader, SyncMessage};
+use collab::sync_protocol::server::{CollabServer, CollabServerConfig};
+use collab::sync_protocol::{handle_msg, ClientSyncProtocol, CollabSyncProtocol};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::StreamExt;
+use serde_json::json;
+use yrs::updates::decoder::DecoderV1;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+
+#[tokio::test]
+async fn two_clients_sync_through_server_test() {
+  let server = CollabServer::new(CollabServerConfig::default());
+  let client_1 = TestClient::connect(&server, "1", 1).await;
+  let client_2 = TestClient::connect(&server, "1", 2).await;
+
+  client_1.collab.lock().insert("1", "a");
+  wait_until(|| client_2.collab.to_json_value() == json!({"1": "a"})).await;
+
+  client_2.collab.lock().insert("2", "b");
+  wait_until(|| client_1.collab.to_json_value() == json!({"1": "a", "2": "b"})).await;
+
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.number_of_subscribers(), 2);
+  assert_eq!(group.collab().to_json_value(), json!({"1": "a", "2": "b"}));
+}
+
+#[tokio::test]
+async fn client_receive_server_data_test() {
+  let server = CollabServer::new(CollabServerConfig::default());
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().insert("name", "appflowy");
+  let (doc_state, _) = collab.encode_as_update_v1();
+  server.create_group("1", vec![doc_state]).await.unwrap();
+
+  let client = TestClient::connect(&server, "1", 1).await;
+  wait_until(|| client.collab.to_json_value() == json!({"name": "appflowy"})).await;
+}
+
+#[tokio::test]
+async fn client_push_offline_edits_test() {
+  let server = CollabServer::new(CollabServerConfig::default());
+  let mut client = TestClient::new("1", 1);
+  client.collab.lock().insert("offline", "edit");
+  client.connect_to(&server).await;
+
+  let group = server.get_group("1").unwrap();
+  wait_until(|| group.collab().to_json_value() == json!({"offline": "edit"})).await;
+}
+
+#[tokio::test]
+async fn remove_inactive_group_test() {
+  let server = CollabServer::new(
+    CollabServerConfig::default()
+      .with_idle_timeout(Duration::from_millis(100))
+      .with_eviction_interval(Duration::from_millis(50)),
+  );
+  let client = TestClient::connect(&server, "1", 1).await;
+  tokio::time::sleep(Duration::from_millis(300)).await;
+  // The group has an active subscriber, so it should not be removed.
+  assert!(server.get_group("1").is_some());
+
+  server.unsubscribe("1", &client.origin);
+  wait_until(|| server.get_group("1").is_none()).await;
+  assert_eq!(server.number_of_groups(), 0);
+}
+
+#[tokio::test]
+async fn unsubscribe_when_client_disconnect_test() {
+  let server = CollabServer::new(CollabServerConfig::default());
+  let client_1 = TestClient::connect(&server, "1", 1).await;
+  let client_2 = TestClient::connect(&server, "1", 2).await;
+  let group = server.get_group("1").unwrap();
+  assert_eq!(group.number_of_subscribers(), 2);
+
+  // Closing the channel ends the stream that the server reads from.
+  client_1.sink.close_channel();
+  wait_until(|| !group.contains_subscriber(&client_1.origin)).await;
+  assert!(group.contains_subscriber(&client_2.origin));
+}
+
+struct TestClient {
+  origin: CollabOrigin,
+  object_id: String,
+  collab: MutexCollab,
+  sink: UnboundedSender<Vec<u8>>,
+  // The data written to the sink will be sent to the server once the client is connected.
+  server_stream: Option<futures::channel::mpsc::UnboundedReceiver<Vec<u8>>>,
+}
+
+impl TestClient {
+  fn new(object_id: &str, uid: i64) -> Self {
+    let origin = CollabOrigin::Client(CollabClient::new(uid, uid.to_string()));
+    let (sink, server_stream) = unbounded();
+    let plugin = ForwardLocalUpdatePlugin { sink: sink.clone() };
+    let collab = MutexCollab::new(origin.clone(), object_id, vec![Arc::new(plugin)]);
+    collab.lock().initialize();
+    Self {
+      origin,
+      object_id: object_id.to_string(),
+      collab,
+      sink,
+      server_stream: Some(server_stream),
+    }
+  }
+
+  async fn connect(server: &CollabServer, object_id: &str, uid: i64) -> Self {
+    let mut client = Self::new(object_id, uid);
+    client.connect_to(server).await;
+    client
+  }
+
+  async fn connect_to(&mut self, server: &CollabServer) {
+    let (server_sink, mut client_stream) = unbounded::<Vec<u8>>();
+    server
+      .subscribe(
+        &self.object_id,
+        self.origin.clone(),
+        server_sink,
+        self.server_stream.take().unwrap().map(Ok::<_, Infallible>),
+      )
+      .await
+      .unwrap();
+
+    // Start the sync with the server.
+    let mut encoder = EncoderV1::new();
+    ClientSyncProtocol
+      .start(self.collab.lock().get_awareness(), &mut encoder)
+      .unwrap();
+    self.sink.unbounded_send(encoder.to_vec()).unwrap();
+
+    let collab = self.collab.clone();
+    let sink = self.sink.clone();
+    tokio::spawn(async move {
+      while let Some(data) = client_stream.next().await {
+        let mut decoder = DecoderV1::from(data.as_slice());
+        let messages = MessageReader::new(&mut decoder)
+          .flatten()
+          .collect::<Vec<_>>();
+        for msg in messages {
+          let origin = CollabOrigin::Server;
+          if let Some(reply) = handle_msg(&Some(&origin), &ClientSyncProtocol, &collab, msg)
+            .await
+            .unwrap()
+          {
+            let _ = sink.unbounded_send(reply);
+          }
+        }
+      }
+    });
+  }
+}
+
+struct ForwardLocalUpdatePlugin {
+  sink: UnboundedSender<Vec<u8>>,
+}
+
+impl CollabPlugin for ForwardLocalUpdatePlugin {
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    let msg = Message::Sync(SyncMessage::Update(update.to_vec()));
+    let _ = self.sink.unbounded_send(msg.encode_v1());
+  }
+}
+
+async fn wait_until<F: Fn() -> bool>(f: F) {
+  for _ in 0..50 {
+    if f() {
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  panic!("timeout");
+}